@@ -4,7 +4,103 @@ use proc_macro::TokenStream;
 use quote::quote;
 use syn::spanned::Spanned;
 
-#[proc_macro_derive(Params, attributes(id, persist))]
+#[proc_macro_derive(Enum, attributes(name))]
+pub fn derive_enum(input: TokenStream) -> TokenStream {
+    let ast = syn::parse_macro_input!(input as syn::DeriveInput);
+
+    let enum_name = &ast.ident;
+    let enum_span = ast.span();
+    let variants = match ast.data {
+        syn::Data::Enum(syn::DataEnum { variants, .. }) => variants,
+        _ => {
+            return syn::Error::new(ast.span(), "Deriving Enum is only supported on enums")
+                .to_compile_error()
+                .into()
+        }
+    };
+    if variants.is_empty() {
+        return syn::Error::new(enum_span, "Enums need at least one variant to derive Enum")
+            .to_compile_error()
+            .into();
+    }
+
+    // The display names default to the variants' identifiers
+    let mut variant_names = Vec::new();
+    let mut to_index_tokens = Vec::new();
+    let mut from_index_tokens = Vec::new();
+    let num_variants = variants.len();
+    for (variant_idx, variant) in variants.into_iter().enumerate() {
+        if !matches!(variant.fields, syn::Fields::Unit) {
+            return syn::Error::new(
+                variant.span(),
+                "Deriving Enum is only supported on enums with unit variants",
+            )
+            .to_compile_error()
+            .into();
+        }
+
+        let mut name_attr: Option<String> = None;
+        for attr in &variant.attrs {
+            if attr.path.is_ident("name") {
+                match attr.parse_meta() {
+                    Ok(syn::Meta::NameValue(syn::MetaNameValue {
+                        lit: syn::Lit::Str(s),
+                        ..
+                    })) => {
+                        if name_attr.is_none() {
+                            name_attr = Some(s.value());
+                        } else {
+                            return syn::Error::new(attr.span(), "Duplicate name attribute")
+                                .to_compile_error()
+                                .into();
+                        }
+                    }
+                    _ => {
+                        return syn::Error::new(
+                            attr.span(),
+                            "The name attribute should be a key-value pair with a string argument: #[name = \"Foo Bar\"]",
+                        )
+                        .to_compile_error()
+                        .into()
+                    }
+                };
+            }
+        }
+
+        let variant_ident = &variant.ident;
+        variant_names.push(name_attr.unwrap_or_else(|| variant_ident.to_string()));
+        to_index_tokens.push(quote! { #enum_name::#variant_ident => #variant_idx, });
+        // Out of range indices map to the last variant
+        if variant_idx == num_variants - 1 {
+            from_index_tokens.push(quote! { _ => #enum_name::#variant_ident, });
+        } else {
+            from_index_tokens.push(quote! { #variant_idx => #enum_name::#variant_ident, });
+        }
+    }
+
+    quote! {
+        impl ::nih_plug::param::Enum for #enum_name {
+            fn variants() -> &'static [&'static str] {
+                &[#(#variant_names),*]
+            }
+
+            fn to_index(self) -> usize {
+                match self {
+                    #(#to_index_tokens)*
+                }
+            }
+
+            fn from_index(index: usize) -> Self {
+                match index {
+                    #(#from_index_tokens)*
+                }
+            }
+        }
+    }
+    .into()
+}
+
+#[proc_macro_derive(Params, attributes(id, persist, display_index, group))]
 pub fn derive_params(input: TokenStream) -> TokenStream {
     let ast = syn::parse_macro_input!(input as syn::DeriveInput);
 
@@ -29,7 +125,9 @@ pub fn derive_params(input: TokenStream) -> TokenStream {
     // `persist` function we'll create functions that serialize and deserialize those fields
     // individually (so they can be added and removed independently of eachother) using JSON.
     let mut param_mapping_insert_tokens = Vec::new();
-    let mut param_id_string_tokens = Vec::new();
+    // The parameter IDs are sorted by their optional display index before they're emitted
+    let mut param_ids_display_indices: Vec<(String, Option<u32>)> = Vec::new();
+    let mut param_group_insert_tokens = Vec::new();
     let mut field_serialize_tokens = Vec::new();
     let mut field_deserialize_tokens = Vec::new();
     for field in fields.named {
@@ -41,6 +139,9 @@ pub fn derive_params(input: TokenStream) -> TokenStream {
         // These two attributes are mutually exclusive
         let mut id_attr: Option<String> = None;
         let mut persist_attr: Option<String> = None;
+        // These can only be used together with the `id` attribute
+        let mut display_index_attr: Option<u32> = None;
+        let mut group_attr: Option<String> = None;
         for attr in &field.attrs {
             if attr.path.is_ident("id") {
                 match attr.parse_meta() {
@@ -88,16 +189,91 @@ pub fn derive_params(input: TokenStream) -> TokenStream {
                         .into()
                     }
                 };
+            } else if attr.path.is_ident("display_index") {
+                match attr.parse_meta() {
+                    Ok(syn::Meta::NameValue(syn::MetaNameValue {
+                        lit: syn::Lit::Int(i),
+                        ..
+                    })) => {
+                        let display_index = match i.base10_parse::<u32>() {
+                            Ok(display_index) => display_index,
+                            Err(err) => return err.to_compile_error().into(),
+                        };
+
+                        if display_index_attr.is_none() {
+                            display_index_attr = Some(display_index);
+                        } else {
+                            return syn::Error::new(
+                                attr.span(),
+                                "Duplicate display_index attribute",
+                            )
+                            .to_compile_error()
+                            .into();
+                        }
+                    }
+                    _ => {
+                        return syn::Error::new(
+                            attr.span(),
+                            "The display_index attribute should be a key-value pair with an integer argument: #[display_index = 0]",
+                        )
+                        .to_compile_error()
+                        .into()
+                    }
+                };
+            } else if attr.path.is_ident("group") {
+                match attr.parse_meta() {
+                    Ok(syn::Meta::NameValue(syn::MetaNameValue {
+                        lit: syn::Lit::Str(s),
+                        ..
+                    })) => {
+                        if group_attr.is_none() {
+                            group_attr = Some(s.value());
+                        } else {
+                            return syn::Error::new(attr.span(), "Duplicate group attribute")
+                                .to_compile_error()
+                                .into();
+                        }
+                    }
+                    _ => {
+                        return syn::Error::new(
+                            attr.span(),
+                            "The group attribute should be a key-value pair with a string argument: #[group = \"Band 1\"]",
+                        )
+                        .to_compile_error()
+                        .into()
+                    }
+                };
             }
         }
 
+        if display_index_attr.is_some() && id_attr.is_none() {
+            return syn::Error::new(
+                field.span(),
+                "The display_index attribute can only be used on fields with an id attribute",
+            )
+            .to_compile_error()
+            .into();
+        }
+        if group_attr.is_some() && id_attr.is_none() {
+            return syn::Error::new(
+                field.span(),
+                "The group attribute can only be used on fields with an id attribute",
+            )
+            .to_compile_error()
+            .into();
+        }
+
         match (id_attr, persist_attr) {
             (Some(param_id), None) => {
                 // The specific parameter types know how to convert themselves into the correct ParamPtr
                 // variant
                 param_mapping_insert_tokens
                     .push(quote! { param_map.insert(#param_id, self.#field_name.as_ptr()); });
-                param_id_string_tokens.push(quote! { #param_id, });
+                if let Some(group) = group_attr {
+                    param_group_insert_tokens
+                        .push(quote! { param_groups.insert(#param_id, #group); });
+                }
+                param_ids_display_indices.push((param_id, display_index_attr));
             }
             (None, Some(stable_name)) => {
                 // We don't know anything about the field types, but because we can generate this
@@ -106,7 +282,7 @@ pub fn derive_params(input: TokenStream) -> TokenStream {
                 field_serialize_tokens.push(quote! {
                     match ::nih_plug::param::internals::PersistentField::map(
                         &self.#field_name,
-                        ::nih_plug::param::internals::serialize_field,
+                        |value| ::nih_plug::param::internals::serialize_field(value, format),
                     ) {
                         Ok(data) => {
                             serialized.insert(String::from(#stable_name), data);
@@ -118,7 +294,7 @@ pub fn derive_params(input: TokenStream) -> TokenStream {
                 });
                 field_deserialize_tokens.push(quote! {
                     #stable_name => {
-                        match ::nih_plug::param::internals::deserialize_field(&data) {
+                        match ::nih_plug::param::internals::deserialize_field(data, format) {
                             Ok(deserialized) => {
                                 ::nih_plug::param::internals::PersistentField::set(
                                     &self.#field_name,
@@ -148,6 +324,17 @@ pub fn derive_params(input: TokenStream) -> TokenStream {
         }
     }
 
+    // Parameters with a display index are listed first, ordered by that index. The other
+    // parameters follow in declaration order. This is a stable sort, so parameters with the same
+    // display index also stay in declaration order.
+    param_ids_display_indices.sort_by_key(|(_, display_index)| match display_index {
+        Some(display_index) => (0, *display_index),
+        None => (1, 0),
+    });
+    let param_id_string_tokens = param_ids_display_indices
+        .into_iter()
+        .map(|(param_id, _)| quote! { #param_id, });
+
     quote! {
         impl Params for #struct_name {
             fn param_map(
@@ -164,7 +351,23 @@ pub fn derive_params(input: TokenStream) -> TokenStream {
                 &[#(#param_id_string_tokens)*]
             }
 
-            fn serialize_fields(&self) -> ::std::collections::HashMap<String, String> {
+            fn param_groups(
+                self: std::pin::Pin<&Self>,
+            ) -> std::collections::HashMap<&'static str, &'static str> {
+                #[allow(unused_mut)]
+                let mut param_groups = std::collections::HashMap::new();
+
+                #(#param_group_insert_tokens)*
+
+                param_groups
+            }
+
+            // `format` is unused for structs without any persistent fields
+            #[allow(unused_variables)]
+            fn serialize_fields(
+                &self,
+                format: ::nih_plug::param::internals::StateFormat,
+            ) -> ::std::collections::HashMap<String, Vec<u8>> {
                 let mut serialized = ::std::collections::HashMap::new();
 
                 #(#field_serialize_tokens)*
@@ -172,7 +375,13 @@ pub fn derive_params(input: TokenStream) -> TokenStream {
                 serialized
             }
 
-            fn deserialize_fields(&self, serialized: &::std::collections::HashMap<String, String>) {
+            // `format` is unused for structs without any persistent fields
+            #[allow(unused_variables)]
+            fn deserialize_fields(
+                &self,
+                serialized: &::std::collections::HashMap<String, Vec<u8>>,
+                format: ::nih_plug::param::internals::StateFormat,
+            ) {
                 for (field_name, data) in serialized {
                     match field_name.as_str() {
                         #(#field_deserialize_tokens)*
@@ -22,8 +22,8 @@ use baseview::gl::GlConfig;
 use baseview::{Size, WindowHandle, WindowOpenOptions, WindowScalePolicy};
 use egui::CtxRef;
 use egui_baseview::EguiWindow;
-use nih_plug::{Editor, ParamSetter, ParentWindowHandle};
-use parking_lot::RwLock;
+use nih_plug::{Editor, GuiContext, ParamSetter, ParentWindowHandle};
+use parking_lot::{Mutex, RwLock};
 use std::sync::Arc;
 
 /// Re-export for convenience.
@@ -36,8 +36,13 @@ pub use egui;
 /// size, and this is kept in sync whenever the GUI gets resized. If you want this size to be
 /// persisted when restoring a plugin instance, then you can store it in a `#[persist]` field on
 /// your parameters struct.
+///
+/// The GUI is redrawn on every frame for as long as the editor is open, so meters and parameter
+/// values changed by the host or by the plugin are always up to date.
+///
+/// The GUI is scaled by the DPI scaling factor set by the host, or 1.0 if the host doesn't set one.
+/// The update function can query the current scaling factor through `egui_ctx.pixels_per_point()`.
 //
-// TODO: DPI scaling, this needs to be implemented on the framework level
 // TODO: Add some way for the plugin to check whether the GUI is open
 pub fn create_egui_editor<T, U>(
     size: Arc<AtomicCell<(u32, u32)>>,
@@ -50,14 +55,42 @@ where
 {
     Some(Box::new(EguiEditor {
         size,
+        scaling_factor: AtomicCell::new(1.0),
         state: Arc::new(RwLock::new(initial_state)),
         update: Arc::new(update),
     }))
 }
 
+/// Change the editor's size from the update function and ask the host to resize the editor's
+/// window. `size` is the size that was passed to [create_egui_editor()]. If the host rejects the
+/// new size, then the previous size is restored and this returns `false`.
+///
+/// baseview windows cannot be resized while they are open, so the egui window is reopened at the
+/// new size right before the next frame is drawn.
+pub fn resize_editor(
+    size: &AtomicCell<(u32, u32)>,
+    setter: &ParamSetter,
+    new_size: (u32, u32),
+) -> bool {
+    let old_size = size.swap(new_size);
+    if old_size == new_size {
+        return true;
+    }
+
+    if setter.request_resize() {
+        true
+    } else {
+        size.store(old_size);
+        false
+    }
+}
+
 /// An [Editor] implementation that calls an egui draw loop.
 struct EguiEditor<T> {
     size: Arc<AtomicCell<(u32, u32)>>,
+    /// The DPI scaling factor passed to [Editor::set_scale_factor()]. The window's physical size is
+    /// `size` multiplied by this factor.
+    scaling_factor: AtomicCell<f32>,
     /// The plugin's state. This is kept in between editor openenings.
     state: Arc<RwLock<T>>,
     update: Arc<dyn Fn(&CtxRef, &ParamSetter, &mut T) + 'static + Send + Sync>,
@@ -70,20 +103,65 @@ where
     fn spawn(
         &self,
         parent: ParentWindowHandle,
-        context: Arc<dyn nih_plug::GuiContext>,
+        context: Arc<dyn GuiContext>,
     ) -> Box<dyn std::any::Any> {
-        let update = self.update.clone();
-        let state = self.state.clone();
+        let window = Arc::new(EguiEditorWindow {
+            parent,
+            context,
+            size: self.size.clone(),
+            scaling_factor: self.scaling_factor.load(),
+            state: self.state.clone(),
+            update: self.update.clone(),
+            window: Mutex::new(None),
+        });
+        *window.window.lock() = Some(window.open());
+
+        Box::new(EguiEditorHandle { window })
+    }
+
+    fn size(&self) -> (u32, u32) {
+        self.size.load()
+    }
+
+    fn set_scale_factor(&self, factor: f32) -> bool {
+        self.scaling_factor.store(factor);
+        true
+    }
+}
+
+/// Everything needed to open the editor's egui window. The window is reopened when its size
+/// changes through [resize_editor()], since baseview windows can't be resized while they are open.
+struct EguiEditorWindow<T> {
+    parent: ParentWindowHandle,
+    context: Arc<dyn GuiContext>,
+    size: Arc<AtomicCell<(u32, u32)>>,
+    scaling_factor: f32,
+    state: Arc<RwLock<T>>,
+    update: Arc<dyn Fn(&CtxRef, &ParamSetter, &mut T) + 'static + Send + Sync>,
+    /// The currently open window. This is replaced when the window gets reopened.
+    window: Mutex<Option<WindowHandle>>,
+}
+
+/// The parent and window handles contain raw pointers. Is there a way around having this
+/// requirement?
+unsafe impl<T: Send + Sync> Send for EguiEditorWindow<T> {}
+unsafe impl<T: Send + Sync> Sync for EguiEditorWindow<T> {}
+
+impl<T> EguiEditorWindow<T>
+where
+    T: 'static + Send + Sync,
+{
+    /// Open a new egui window at the editor's current size.
+    fn open(self: &Arc<Self>) -> WindowHandle {
+        let this = self.clone();
 
         let (width, height) = self.size.load();
-        let window = EguiWindow::open_parented(
-            &parent,
+        EguiWindow::open_parented(
+            &self.parent,
             WindowOpenOptions {
                 title: String::from("egui window"),
                 size: Size::new(width as f64, height as f64),
-                // TODO: What happens when we use the system scale factor here? I'd assume this
-                //       would work everywhere, even if the window may be tiny in some cases.
-                scale: WindowScalePolicy::ScaleFactor(1.0),
+                scale: WindowScalePolicy::ScaleFactor(self.scaling_factor as f64),
                 gl_config: Some(GlConfig {
                     version: (3, 2),
                     red_bits: 8,
@@ -99,43 +177,46 @@ where
                     ..Default::default()
                 }),
             },
-            state,
+            self.state.clone(),
             |_, _, _| {},
             move |egui_ctx, queue, state| {
-                let setter = ParamSetter::new(context.as_ref());
+                // The editor has been resized through `resize_editor()`. Closing a window only
+                // happens after this function has returned, so the old window can be closed from
+                // here.
+                if this.size.load() != (width, height) {
+                    let new_window = this.open();
+                    if let Some(mut old_window) = this.window.lock().replace(new_window) {
+                        old_window.close();
+                    }
+
+                    return;
+                }
 
-                // For now, just always redraw. Most plugin GUIs have meters, and those almost always
-                // need a redraw. Later we can try to be a bit more sophisticated about this. Without
-                // this we would also have a blank GUI when it gets first opened because most DAWs open
-                // their GUI while the window is still unmapped.
+                let setter = ParamSetter::new(this.context.as_ref());
+
+                // For now, just always redraw while the editor is open. Most plugin GUIs have
+                // meters, and those almost always need a redraw. Without this we would also have a
+                // blank GUI when it gets first opened because most DAWs open their GUI while the
+                // window is still unmapped.
                 // TODO: Are there other useful parts of this queue we could pass to thep lugin?
                 queue.request_repaint();
-                (update)(egui_ctx, &setter, &mut state.write());
+                (this.update)(egui_ctx, &setter, &mut state.write());
             },
         )
-        .expect("We provided an OpenGL config, did we not?");
-
-        Box::new(EguiEditorHandle { window })
-    }
-
-    fn size(&self) -> (u32, u32) {
-        self.size.load()
+        .expect("We provided an OpenGL config, did we not?")
     }
 }
 
 /// The window handle used for [EguiEditor].
-struct EguiEditorHandle {
-    window: WindowHandle,
+struct EguiEditorHandle<T> {
+    window: Arc<EguiEditorWindow<T>>,
 }
 
-/// The window handle enum stored within 'WindowHandle' contains raw pointers. Is there a way around
-/// having this requirement?
-unsafe impl Send for EguiEditorHandle {}
-unsafe impl Sync for EguiEditorHandle {}
-
-impl Drop for EguiEditorHandle {
+impl<T> Drop for EguiEditorHandle<T> {
     fn drop(&mut self) {
         // XXX: This should automatically happen when the handle gets dropped, but apparently not
-        self.window.close();
+        if let Some(mut window) = self.window.window.lock().take() {
+            window.close();
+        }
     }
 }
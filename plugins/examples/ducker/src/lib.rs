@@ -0,0 +1,167 @@
+// nih-plug: plugins, but rewritten in Rust
+// Copyright (C) 2022 Robbert van der Helm
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+#[macro_use]
+extern crate nih_plug;
+
+use nih_plug::{
+    formatters, util, Buffer, BufferConfig, BusConfig, Plugin, ProcessContext, ProcessStatus,
+    Vst3Plugin, Vst3SubCategory,
+};
+use nih_plug::{FloatParam, Params, Range, Smoother, SmoothingStyle};
+use std::pin::Pin;
+
+/// The length of the window used to measure the sidechain's loudness. Shorter windows make the
+/// ducking react faster, at the cost of more distortion on low frequencies.
+const SIDECHAIN_WINDOW_MS: f32 = 20.0;
+
+/// A simple ducker that turns down the main input while a signal is present on the sidechain
+/// input, like a voice-over ducking background music.
+struct Ducker {
+    params: Pin<Box<DuckerParams>>,
+
+    /// Measures the sidechain's loudness. The sidechain is summed to mono first.
+    sidechain_rms: util::RmsWindow,
+}
+
+#[derive(Params)]
+struct DuckerParams {
+    /// The sidechain level above which the input gets ducked.
+    #[id = "threshold"]
+    pub threshold: FloatParam,
+    /// The maximum amount of gain reduction.
+    #[id = "depth"]
+    pub depth: FloatParam,
+}
+
+impl Default for Ducker {
+    fn default() -> Self {
+        Self {
+            params: Box::pin(DuckerParams::default()),
+
+            // This is resized during initialization
+            sidechain_rms: util::RmsWindow::new(1),
+        }
+    }
+}
+
+impl Default for DuckerParams {
+    fn default() -> Self {
+        Self {
+            threshold: FloatParam {
+                value: -30.0,
+                smoothed: Smoother::new(SmoothingStyle::Linear(50.0)),
+                range: Range::Linear {
+                    min: -60.0,
+                    max: 0.0,
+                },
+                name: "Threshold",
+                unit: " dB",
+                value_to_string: formatters::f32_rounded(1),
+                ..Default::default()
+            },
+            depth: FloatParam {
+                value: 12.0,
+                smoothed: Smoother::new(SmoothingStyle::Linear(50.0)),
+                range: Range::Linear {
+                    min: 0.0,
+                    max: 48.0,
+                },
+                name: "Depth",
+                unit: " dB",
+                value_to_string: formatters::f32_rounded(1),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+impl Plugin for Ducker {
+    const NAME: &'static str = "Ducker";
+    const VENDOR: &'static str = "Moist Plugins GmbH";
+    const URL: &'static str = "https://youtu.be/dQw4w9WgXcQ";
+    const EMAIL: &'static str = "info@example.com";
+
+    const VERSION: &'static str = "0.0.1";
+
+    const DEFAULT_NUM_INPUTS: u32 = 2;
+    const DEFAULT_NUM_OUTPUTS: u32 = 2;
+    // A single stereo sidechain input. The user needs to route a signal to this in their DAW.
+    const AUX_INPUT_CHANNELS: &'static [u32] = &[2];
+
+    fn params(&self) -> Pin<&dyn Params> {
+        self.params.as_ref()
+    }
+
+    fn accepts_bus_config(&self, config: &BusConfig) -> bool {
+        // This works with any symmetrical IO layout, and with both mono and stereo sidechains
+        config.num_input_channels == config.num_output_channels
+            && config.num_input_channels > 0
+            && matches!(config.aux_input_channels[..], [1 | 2])
+    }
+
+    fn initialize(
+        &mut self,
+        _bus_config: &BusConfig,
+        buffer_config: &BufferConfig,
+        _context: &mut impl ProcessContext,
+    ) -> bool {
+        self.sidechain_rms
+            .set_duration(buffer_config.sample_rate, SIDECHAIN_WINDOW_MS);
+
+        true
+    }
+
+    fn process(
+        &mut self,
+        buffer: &mut Buffer,
+        _context: &mut impl ProcessContext,
+    ) -> ProcessStatus {
+        // Copying these channel references out of the buffer lets us read from the sidechain while
+        // writing to the outputs. Mono sidechains are used for both channels.
+        let (sidechain_l, sidechain_r) = match *buffer.sidechain() {
+            [left, right] => (left, right),
+            [mono] => (mono, mono),
+            _ => return ProcessStatus::Normal,
+        };
+        for (sample_idx, samples) in buffer.iter_mut().enumerate() {
+            let threshold = self.params.threshold.smoothed.next();
+            let depth = self.params.depth.smoothed.next();
+
+            let sidechain_sample = (sidechain_l[sample_idx] + sidechain_r[sample_idx]) * 0.5;
+            self.sidechain_rms.push(sidechain_sample);
+
+            // Every decibel the sidechain goes over the threshold turns the input down by a
+            // decibel, up to the configured depth
+            let sidechain_db = util::gain_to_db(self.sidechain_rms.rms());
+            let gain_reduction = (sidechain_db - threshold).clamp(0.0, depth);
+            let gain = util::db_to_gain(-gain_reduction);
+            for sample in samples {
+                *sample *= gain;
+            }
+        }
+
+        ProcessStatus::Normal
+    }
+}
+
+impl Vst3Plugin for Ducker {
+    const VST3_CLASS_ID: [u8; 16] = *b"DuckerMoistPlugs";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] =
+        &[Vst3SubCategory::Fx, Vst3SubCategory::Dynamics];
+}
+
+nih_export_vst3!(Ducker);
@@ -20,7 +20,7 @@ extern crate nih_plug;
 use atomic_float::AtomicF32;
 use nih_plug::{
     formatters, util, Buffer, BufferConfig, BusConfig, Editor, Plugin, ProcessContext,
-    ProcessStatus, Vst3Plugin,
+    ProcessStatus, Vst3Plugin, Vst3SubCategory,
 };
 use nih_plug::{FloatParam, Param, Params, Range, Smoother, SmoothingStyle};
 use nih_plug_egui::{create_egui_editor, egui, AtomicCell};
@@ -28,7 +28,7 @@ use std::pin::Pin;
 use std::sync::Arc;
 
 /// This is mostly identical to the gain example, minus some fluff, and with a GUI.
-struct Gain {
+pub struct Gain {
     params: Pin<Arc<GainParams>>,
     editor_size: Arc<AtomicCell<(u32, u32)>>,
 
@@ -65,12 +65,16 @@ impl Default for GainParams {
         Self {
             gain: FloatParam {
                 value: 0.0,
+                default: None,
                 smoothed: Smoother::new(SmoothingStyle::Linear(50.0)),
+                modulation: Default::default(),
                 value_changed: None,
                 range: Range::Linear {
                     min: -30.0,
                     max: 30.0,
                 },
+                step_size: None,
+                step_count: None,
                 name: "Gain",
                 unit: " dB",
                 value_to_string: formatters::f32_rounded(2),
@@ -102,22 +106,27 @@ impl Plugin for Gain {
         let peak_meter = self.peak_meter.clone();
         create_egui_editor(
             self.editor_size.clone(),
-            (),
-            move |egui_ctx, setter, _state| {
+            // Whether the gain slider is currently being changed
+            false,
+            move |egui_ctx, setter, gesture_active| {
                 egui::CentralPanel::default().show(egui_ctx, |ui| {
                     ui.allocate_space(egui::Vec2::splat(3.0));
                     ui.label("Gain");
 
                     // TODO: Create a custom widget that can do all of the parameter handling and
                     //       works with nonlinear ranges
-                    ui.add(
+                    // The slider only reports new values, so the gesture starts with the first new
+                    // value and it ends once the slider is no longer being dragged. This way an
+                    // entire drag gets recorded as a single automation gesture.
+                    let response = ui.add(
                         egui::widgets::Slider::from_get_set(-30.0..=30.0, |new_value| {
                             match new_value {
                                 Some(new_value) => {
-                                    // TODO: Gestures?
-                                    setter.begin_set_parameter(&params.gain);
+                                    if !*gesture_active {
+                                        setter.begin_set_parameter(&params.gain);
+                                        *gesture_active = true;
+                                    }
                                     setter.set_parameter(&params.gain, new_value as f32);
-                                    setter.end_set_parameter(&params.gain);
                                     new_value
                                 }
                                 None => params.gain.value as f64,
@@ -125,6 +134,10 @@ impl Plugin for Gain {
                         })
                         .suffix(" dB"),
                     );
+                    if *gesture_active && !response.dragged() {
+                        setter.end_set_parameter(&params.gain);
+                        *gesture_active = false;
+                    }
 
                     // TODO: Add a proper custom widget instead of reusing a progress bar
                     let peak_meter =
@@ -197,7 +210,8 @@ impl Plugin for Gain {
 
 impl Vst3Plugin for Gain {
     const VST3_CLASS_ID: [u8; 16] = *b"GainGuiYeahBoyyy";
-    const VST3_CATEGORIES: &'static str = "Fx|Dynamics";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] =
+        &[Vst3SubCategory::Fx, Vst3SubCategory::Dynamics];
 }
 
 nih_export_vst3!(Gain);
@@ -18,8 +18,8 @@
 extern crate nih_plug;
 
 use nih_plug::{
-    formatters, util, Buffer, BufferConfig, BusConfig, Plugin, ProcessContext, ProcessStatus,
-    Vst3Plugin,
+    formatters, util, Buffer, BufferConfig, BusConfig, ClapPlugin, Plugin, ProcessContext,
+    ProcessStatus, Vst3Plugin, Vst3SubCategory,
 };
 use nih_plug::{BoolParam, FloatParam, Param, Params, Range, Smoother, SmoothingStyle};
 use parking_lot::RwLock;
@@ -57,7 +57,9 @@ impl Default for GainParams {
         Self {
             gain: FloatParam {
                 value: 0.0,
+                default: None,
                 smoothed: Smoother::new(SmoothingStyle::Linear(50.0)),
+                modulation: Default::default(),
                 value_changed: None,
                 // If, for instance, updating this parameter would require other parts of the
                 // plugin's internal state to be updated other values to also be updated, then you
@@ -68,6 +70,8 @@ impl Default for GainParams {
                     min: -30.0,
                     max: 30.0,
                 },
+                step_size: None,
+                step_count: None,
                 name: "Gain",
                 unit: " dB",
                 value_to_string: formatters::f32_rounded(2),
@@ -139,7 +143,15 @@ impl Plugin for Gain {
 
 impl Vst3Plugin for Gain {
     const VST3_CLASS_ID: [u8; 16] = *b"GainMoistestPlug";
-    const VST3_CATEGORIES: &'static str = "Fx|Dynamics";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] =
+        &[Vst3SubCategory::Fx, Vst3SubCategory::Dynamics];
 }
 
+impl ClapPlugin for Gain {
+    const CLAP_ID: &'static str = "com.moist-plugins-gmbh.gain";
+    const CLAP_DESCRIPTION: &'static str = "A smoothed gain parameter example plugin";
+    const CLAP_FEATURES: &'static [&'static str] = &["audio-effect", "mono", "stereo", "utility"];
+}
+
+nih_export_clap!(Gain);
 nih_export_vst3!(Gain);
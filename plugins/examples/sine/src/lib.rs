@@ -19,7 +19,7 @@ extern crate nih_plug;
 
 use nih_plug::{
     formatters, util, Buffer, BufferConfig, BusConfig, Plugin, ProcessContext, ProcessStatus,
-    Vst3Plugin,
+    Vst3Plugin, Vst3SubCategory,
 };
 use nih_plug::{BoolParam, FloatParam, Param, Params, Range, Smoother, SmoothingStyle};
 use std::f32::consts;
@@ -95,8 +95,8 @@ impl Default for SineParams {
                     factor: Range::skew_factor(-2.0),
                 },
                 name: "Frequency",
-                unit: " Hz",
-                value_to_string: formatters::f32_rounded(0),
+                value_to_string: formatters::f32_hz_then_khz(1),
+                string_to_value: formatters::from_f32_hz_then_khz(),
                 ..Default::default()
             },
             use_midi: BoolParam {
@@ -141,7 +141,7 @@ impl Plugin for Sine {
 
     fn accepts_bus_config(&self, config: &BusConfig) -> bool {
         // This can output to any number of channels, but it doesn't take any audio inputs
-        config.num_input_channels == 0 && config.num_input_channels > 0
+        config.num_input_channels == 0 && config.num_output_channels > 0
     }
 
     fn initialize(
@@ -156,7 +156,7 @@ impl Plugin for Sine {
     }
 
     fn process(&mut self, buffer: &mut Buffer, context: &mut impl ProcessContext) -> ProcessStatus {
-        let mut next_event = context.next_midi_event();
+        let mut note_events = context.note_events().peekable();
         for (sample_id, samples) in buffer.iter_mut().enumerate() {
             // Smoothing is optionally built into the parameters themselves
             let gain = self.params.gain.smoothed.next();
@@ -170,24 +170,22 @@ impl Plugin for Sine {
 
             // This plugin can be either triggered by MIDI or controleld by a parameter
             let sine = if self.params.use_midi.value {
-                // Act on the next MIDI event
-                'midi_events: loop {
-                    match next_event {
-                        Some(event) if event.timing() == sample_id as u32 => match event {
-                            nih_plug::NoteEvent::NoteOn { note, .. } => {
-                                self.midi_note_freq = util::midi_note_to_freq(note);
-                                self.midi_note_gain.set_target(self.sample_rate, 1.0);
+                // Act on the MIDI events for this sample. These are sorted by their timing.
+                while let Some(event) =
+                    note_events.next_if(|event| event.timing() <= sample_id as u32)
+                {
+                    match event {
+                        nih_plug::NoteEvent::NoteOn { note, .. } => {
+                            self.midi_note_freq = util::midi_note_to_freq(note);
+                            self.midi_note_gain.set_target(self.sample_rate, 1.0);
+                        }
+                        nih_plug::NoteEvent::NoteOff { note, .. } => {
+                            if self.midi_note_freq == util::midi_note_to_freq(note) {
+                                self.midi_note_gain.set_target(self.sample_rate, 0.0);
                             }
-                            nih_plug::NoteEvent::NoteOff { note, .. } => {
-                                if self.midi_note_freq == util::midi_note_to_freq(note) {
-                                    self.midi_note_gain.set_target(self.sample_rate, 0.0);
-                                }
-                            }
-                        },
-                        _ => break 'midi_events,
+                        }
+                        _ => (),
                     }
-
-                    next_event = context.next_midi_event();
                 }
 
                 // This gain envelope prevents clicks with new notes and with released notes
@@ -208,7 +206,11 @@ impl Plugin for Sine {
 
 impl Vst3Plugin for Sine {
     const VST3_CLASS_ID: [u8; 16] = *b"SineMoistestPlug";
-    const VST3_CATEGORIES: &'static str = "Instrument|Synth|Tools";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[
+        Vst3SubCategory::Instrument,
+        Vst3SubCategory::Synth,
+        Vst3SubCategory::Tools,
+    ];
 }
 
 nih_export_vst3!(Sine);
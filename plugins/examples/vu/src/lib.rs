@@ -20,13 +20,12 @@ extern crate nih_plug;
 use atomic_float::AtomicF32;
 use nih_plug::{
     formatters, util, Buffer, BufferConfig, BusConfig, Editor, Plugin, ProcessContext,
-    ProcessStatus, Vst3Plugin,
+    ProcessStatus, Vst3Plugin, Vst3SubCategory,
 };
 use nih_plug::{FloatParam, Param, Params, Range, Smoother, SmoothingStyle};
 use nih_plug_egui::egui::plot::{Polygon, Value, Values};
 use nih_plug_egui::egui::{vec2, Color32, Sense, Shape, Stroke, Vec2};
 use nih_plug_egui::{create_egui_editor, egui, AtomicCell};
-use std::collections::{vec_deque, VecDeque};
 use std::f32::consts::TAU;
 use std::pin::Pin;
 use std::sync::Arc;
@@ -36,21 +35,32 @@ struct Vu {
     params: Pin<Arc<VuParams>>,
     editor_size: Arc<AtomicCell<(u32, u32)>>,
 
-    history: [VecDeque<f32>; 2],
-    sums: Arc<AtomicCell<(f32, f32)>>,
+    rms_windows: [util::RmsWindow; 2],
+    rms_levels: Arc<AtomicCell<(f32, f32)>>,
+    /// Bumped whenever `rms_levels` changes so the editor only needs to repaint when the meter
+    /// moves.
+    rms_levels_version: Arc<util::VersionCounter>,
     last_rots: Arc<AtomicCell<(f32, f32)>>,
-    history_len: Arc<AtomicCell<f32>>,
 
-    last_inst: Arc<AtomicCell<quanta::Instant>>,
-    last_fps: Arc<AtomicCell<quanta::Instant>>,
-    counter: Arc<AtomicCell<usize>>,
-    fps: Arc<AtomicCell<usize>>,
+    frame_counter: Arc<util::FrameCounter>,
+
+    /// Needed to resize the RMS windows when the window length parameter changes.
+    sample_rate: f32,
+    /// The window length in milliseconds the RMS windows were last resized to.
+    window_ms: f32,
 }
 
+/// The longest RMS window the user can pick. Enough room for this is reserved during
+/// initialization so the window can be resized from the audio thread.
+const MAX_WINDOW_MS: f32 = 1000.0;
+
 #[derive(Params)]
 struct VuParams {
     #[id = "trim"]
     pub trim: FloatParam,
+    /// The meter's response time in milliseconds.
+    #[id = "window"]
+    pub window: FloatParam,
 }
 
 impl Default for Vu {
@@ -59,15 +69,16 @@ impl Default for Vu {
             params: Arc::pin(VuParams::default()),
             editor_size: Arc::new(AtomicCell::new((640, 254))),
 
-            history: [VecDeque::new(), VecDeque::new()],
-            sums: Arc::new(AtomicCell::new((0.0, 0.0))),
+            // These are resized during initialization
+            rms_windows: [util::RmsWindow::new(1), util::RmsWindow::new(1)],
+            rms_levels: Arc::new(AtomicCell::new((0.0, 0.0))),
+            rms_levels_version: Arc::new(util::VersionCounter::new()),
             last_rots: Arc::new(AtomicCell::new((0.0, 0.0))),
-            history_len: Arc::new(AtomicCell::new(256.0)),
 
-            last_inst: Arc::new(AtomicCell::new(quanta::Instant::now())),
-            last_fps: Arc::new(AtomicCell::new(quanta::Instant::now())),
-            counter: Arc::new(AtomicCell::new(0)),
-            fps: Arc::new(AtomicCell::new(0)),
+            frame_counter: Arc::new(util::FrameCounter::new()),
+
+            sample_rate: 1.0,
+            window_ms: 0.0,
         }
     }
 }
@@ -77,17 +88,39 @@ impl Default for VuParams {
         Self {
             trim: FloatParam {
                 value: 0.0,
+                default: None,
                 smoothed: Smoother::new(SmoothingStyle::Linear(50.0)),
+                modulation: Default::default(),
                 value_changed: None,
                 range: Range::Linear {
                     min: -30.0,
                     max: 30.0,
                 },
+                step_size: None,
+                step_count: None,
                 name: "Trim",
                 unit: " dB",
                 value_to_string: formatters::f32_rounded(2),
                 string_to_value: None,
             },
+            window: FloatParam {
+                value: 300.0,
+                default: None,
+                smoothed: Smoother::none(),
+                modulation: Default::default(),
+                value_changed: None,
+                range: Range::Skewed {
+                    min: 10.0,
+                    max: MAX_WINDOW_MS,
+                    factor: Range::skew_factor(-1.0),
+                },
+                step_size: None,
+                step_count: None,
+                name: "Window",
+                unit: " ms",
+                value_to_string: formatters::f32_rounded(0),
+                string_to_value: None,
+            },
         }
     }
 }
@@ -104,6 +137,8 @@ impl Plugin for Vu {
     const DEFAULT_NUM_OUTPUTS: u32 = 2;
 
     const ACCEPTS_MIDI: bool = false;
+    // The meter never modifies the audio, so it can read the host's input buffers directly
+    const ANALYSIS_ONLY: bool = true;
 
     fn params(&self) -> Pin<&dyn Params> {
         self.params.as_ref()
@@ -111,41 +146,33 @@ impl Plugin for Vu {
 
     fn editor(&self) -> Option<Box<dyn Editor>> {
         let params = self.params.clone();
-        let sums = self.sums.clone();
-        let history_len = self.history_len.clone();
+        let rms_levels = self.rms_levels.clone();
+        let rms_levels_version = self.rms_levels_version.clone();
         let last_rots = self.last_rots.clone();
 
-        let counter = self.counter.clone();
-        let fps = self.fps.clone();
-        let last_fps = self.last_fps.clone();
-        let last_inst = self.last_inst.clone();
+        let frame_counter = self.frame_counter.clone();
 
         create_egui_editor(
             self.editor_size.clone(),
-            (),
-            move |egui_ctx, setter, _state| {
+            0,
+            move |egui_ctx, setter, last_rms_levels_version| {
+                if rms_levels_version.has_changed_since(last_rms_levels_version) {
+                    egui_ctx.request_repaint();
+                }
+
                 egui::CentralPanel::default().show(egui_ctx, |ui| {
                     // Stats
-                    counter.store(counter.load() + 1);
-                    let now = quanta::Instant::now();
-                    let dur = now.duration_since(last_inst.load());
-                    let maybe_sec = now.duration_since(last_fps.load());
-                    if maybe_sec > std::time::Duration::from_secs(1) {
-                        fps.store(counter.load());
-                        counter.store(0);
-                        last_fps.store(now);
-                    }
-                    last_inst.store(now);
+                    frame_counter.tick();
                     // ui.allocate_space(egui::Vec2::splat(3.0));
-                    // ui.label(format!("Frame (us): {}", dur.as_micros()));
-                    // ui.label(format!("FPS: {}", fps.load()));
+                    // ui.label(format!("Frame (us): {}", frame_counter.last_frame_time().as_micros()));
+                    // ui.label(format!("FPS: {}", frame_counter.fps()));
 
                     // Hands
                     let mut new_rots = [0.0, 0.0];
                     let old_rots = last_rots.load();
                     let old_rots = [old_rots.0, old_rots.1];
-                    let sums = sums.load();
-                    let sums = [sums.0, sums.1];
+                    let rms_levels = rms_levels.load();
+                    let rms_levels = [rms_levels.0, rms_levels.1];
 
                     let size = ui.available_size();
                     let (response, painter) = ui.allocate_painter(size, Sense::hover());
@@ -157,11 +184,7 @@ impl Plugin for Vu {
                         let hand_color = Color32::from_gray(0xFF);
                         let stroke_h = Stroke::new(1.0, hand_color);
 
-                        // True RMS
-                        // let rms = ((1.0 / history_len.load()) * sums[chan_idx]).sqrt();
-
-                        // Fancy RMS
-                        let rms = (sums[chan_idx] / history_len.load()).sqrt();
+                        let rms = rms_levels[chan_idx];
 
                         let peak = 1.0 + rms.log10();
                         let range_radians = 47.0 * std::f32::consts::PI / 180.0;
@@ -208,11 +231,14 @@ impl Plugin for Vu {
         buffer_config: &BufferConfig,
         _context: &mut impl ProcessContext,
     ) -> bool {
-        // TODO: How do you tie this exponential decay to an actual time span?
-        // let window_len = (buffer_config.sample_rate as f32 * 0.3) as usize; // how much samples fit in 300 ms
-        let window_len = (buffer_config.sample_rate as f32 * 0.150) as usize; // how much samples fit in 150 ms
-        for history in &mut self.history {
-            history.resize(window_len, 0.0);
+        // A VU meter's needle takes 300 ms to reach its reading. Since the RMS window is a simple
+        // moving average, its response time is exactly the window's duration. The user can change
+        // this at runtime, so we'll reserve room for the longest possible window here.
+        self.sample_rate = buffer_config.sample_rate;
+        self.window_ms = self.params.window.value;
+        for rms_window in &mut self.rms_windows {
+            rms_window.reserve_duration(self.sample_rate, MAX_WINDOW_MS);
+            rms_window.set_duration(self.sample_rate, self.window_ms);
         }
         true
     }
@@ -223,26 +249,33 @@ impl Plugin for Vu {
         _context: &mut impl ProcessContext,
     ) -> ProcessStatus {
         // let gain = self.params.trim.smoothed.next();
-        let channels = buffer.as_raw();
-        let buf_size = channels[0].len();
-
-        let mut new_sums = [0.0, 0.0];
-
-        for (chan_idx, channel) in channels.iter().enumerate() {
-            let history = &mut self.history[chan_idx];
-            let size_of_slice_to_push = buf_size.min(history.len());
-            let slice = &channel[buf_size - size_of_slice_to_push..buf_size];
-            history.rotate_left(size_of_slice_to_push);
-            let _ = history.split_off(history.len() - size_of_slice_to_push);
-            history.extend(slice);
-            let sum: f32 = history.iter().map(|x| x.powf(2.0)).sum::<f32>();
-
-            self.history_len.store(history.len() as f32);
+        // This won't allocate since we've reserved enough room for the longest window in
+        // `initialize()`
+        if self.params.window.value != self.window_ms {
+            self.window_ms = self.params.window.value;
+            for rms_window in &mut self.rms_windows {
+                rms_window.set_duration(self.sample_rate, self.window_ms);
+            }
+        }
 
-            new_sums[chan_idx] = sum;
+        // This is an analysis-only plugin, so the inputs are passed through by the wrapper
+        let channels = buffer.inputs();
+
+        let mut new_rms_levels = [0.0, 0.0];
+        for ((channel, rms_window), new_rms_level) in channels
+            .iter()
+            .zip(self.rms_windows.iter_mut())
+            .zip(new_rms_levels.iter_mut())
+        {
+            rms_window.push_slice(channel);
+            *new_rms_level = rms_window.rms();
         }
 
-        self.sums.store((new_sums[0], new_sums[1]));
+        let new_rms_levels = (new_rms_levels[0], new_rms_levels[1]);
+        if new_rms_levels != self.rms_levels.load() {
+            self.rms_levels.store(new_rms_levels);
+            self.rms_levels_version.bump();
+        }
 
         ProcessStatus::Normal
     }
@@ -250,7 +283,8 @@ impl Plugin for Vu {
 
 impl Vst3Plugin for Vu {
     const VST3_CLASS_ID: [u8; 16] = *b"ItsSuperSmoothVu";
-    const VST3_CATEGORIES: &'static str = "Fx|Analyzer";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] =
+        &[Vst3SubCategory::Fx, Vst3SubCategory::Analyzer];
 }
 
 nih_export_vst3!(Vu);
@@ -28,12 +28,25 @@ pub struct Buffer<'a> {
     /// buffers, and it also cannot be stored in a field next to it because that would mean
     /// containing mutable references to data stored in a mutex.
     output_slices: Vec<&'a mut [f32]>,
+    /// Contains slices for the plugin's unmodified inputs if
+    /// [crate::plugin::Plugin::SEPARATE_INPUT_BUFFERS] or [crate::plugin::Plugin::ANALYSIS_ONLY]
+    /// is enabled. These stay intact while the plugin writes to the outputs. Like
+    /// `output_slices`, this is preallocated in the setup call.
+    input_slices: Vec<&'a [f32]>,
+    /// Contains slices for each channel of every auxiliary input bus declared in
+    /// [crate::plugin::Plugin::AUX_INPUT_CHANNELS]. Busses the host didn't connect contain
+    /// silence. Like `output_slices`, this is preallocated in the setup call.
+    aux_input_slices: Vec<Vec<&'a [f32]>>,
 }
 
 impl<'a> Buffer<'a> {
-    /// Returns true if this buffer does not contain any samples.
+    /// Returns true if this buffer does not contain any samples. For analysis-only plugins this
+    /// looks at the input channels since those don't have any output slices.
     pub fn is_empty(&self) -> bool {
-        self.output_slices.is_empty() || self.output_slices[0].is_empty()
+        match (self.output_slices.first(), self.input_slices.first()) {
+            (Some(channel), _) | (None, Some(channel)) => channel.is_empty(),
+            (None, None) => true,
+        }
     }
 
     /// Obtain the raw audio buffers.
@@ -41,6 +54,32 @@ impl<'a> Buffer<'a> {
         &mut self.output_slices
     }
 
+    /// The plugin's unmodified input channels. These still contain the original input after the
+    /// plugin has written to the output buffers. This is only populated when
+    /// [crate::plugin::Plugin::SEPARATE_INPUT_BUFFERS] or
+    /// [crate::plugin::Plugin::ANALYSIS_ONLY] is enabled. Analysis-only plugins read the host's
+    /// input buffers directly through these slices, and their buffers don't contain any output
+    /// slices.
+    pub fn inputs(&self) -> &[&'a [f32]] {
+        &self.input_slices
+    }
+
+    /// The channels of the plugin's first auxiliary input bus, which is usually its sidechain
+    /// input. This is empty if the plugin doesn't declare any auxiliary input busses in
+    /// [crate::plugin::Plugin::AUX_INPUT_CHANNELS]. If the user hasn't routed anything to the
+    /// sidechain, then these channels contain silence.
+    pub fn sidechain(&self) -> &[&'a [f32]] {
+        self.aux_input_slices
+            .first()
+            .map_or(&[], |channel_slices| channel_slices.as_slice())
+    }
+
+    /// The channels for all of the plugin's auxiliary input busses, in the same order as in
+    /// [crate::plugin::Plugin::AUX_INPUT_CHANNELS]. See [Self::sidechain()].
+    pub fn aux_inputs(&self) -> &[Vec<&'a [f32]>] {
+        &self.aux_input_slices
+    }
+
     /// Iterate over the samples, returning a channel iterator for each sample.
     pub fn iter_mut(&mut self) -> Samples<'_, 'a> {
         Samples {
@@ -49,6 +88,170 @@ impl<'a> Buffer<'a> {
         }
     }
 
+    /// Iterate over the buffer's channels, yielding a slice containing every sample for each
+    /// channel. Unlike [Self::iter_mut()] this iterates channel by channel, which suits filters and
+    /// vectorized code that process an entire channel at once.
+    ///
+    /// ```ignore
+    /// let mut energy = [0.0; 2];
+    /// for (channel_energy, channel) in energy.iter_mut().zip(buffer.channels_mut()) {
+    ///     *channel_energy = channel.iter().map(|sample| sample * sample).sum();
+    ///     self.filter.process(channel);
+    /// }
+    /// ```
+    pub fn channels_mut(&mut self) -> ChannelSlices<'_, 'a> {
+        ChannelSlices {
+            channels: self.output_slices.iter_mut(),
+        }
+    }
+
+    /// Iterate over the samples like [Self::iter_mut()], but also yield the unmodified input
+    /// samples for each sample. See [Self::inputs()] for when those are available. The input
+    /// iterator is empty if the plugin doesn't get separate input buffers.
+    pub fn iter_mut_with_input(&mut self) -> SamplesWithInput<'_, 'a> {
+        SamplesWithInput {
+            samples: Samples {
+                buffers: &mut self.output_slices,
+                current_sample: 0,
+            },
+            inputs: &self.input_slices,
+        }
+    }
+
+    /// Iterate over the samples like [Self::iter_mut()], but also split the buffer up in control
+    /// rate periods of `interval` samples. Every sample comes with a [ControlRatePosition] that
+    /// tells you whether this sample starts a new period, which is where things like LFOs and
+    /// envelopes can be updated, and how far into the period it is so the values can be
+    /// interpolated in between. The last period in the buffer may be shorter than `interval`.
+    ///
+    /// Control rate periods start over with every buffer, so an `interval` that cleanly divides
+    /// the host's usual buffer size will result in the most regular updates.
+    pub fn iter_control_rate(&mut self, interval: usize) -> ControlRateSamples<'_, 'a> {
+        nih_debug_assert!(interval > 0);
+
+        ControlRateSamples {
+            samples: self.iter_mut(),
+            interval: interval.max(1),
+        }
+    }
+
+    /// Iterate over the buffer in blocks of at most `max_block_size` samples. Each [Block] gives
+    /// access to that part of every channel as a slice, along with the block's offset within the
+    /// buffer. This is useful for processing that works on fixed size chunks, like oversampling or
+    /// SIMD code, and it lets per-block work like updating smoothed parameters happen once per
+    /// block instead of once per sample. The last block in the buffer may be shorter than
+    /// `max_block_size`.
+    ///
+    /// ```ignore
+    /// let mut gain = [0.0; MAX_BLOCK_SIZE];
+    /// for mut block in buffer.iter_blocks(MAX_BLOCK_SIZE) {
+    ///     let gain = &mut gain[..block.len()];
+    ///     self.params.gain.smoothed.next_block(gain, gain.len());
+    ///
+    ///     for channel in block.iter_mut() {
+    ///         for (sample, gain) in channel.iter_mut().zip(gain.iter()) {
+    ///             *sample *= util::db_to_gain(*gain);
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn iter_blocks(&mut self, max_block_size: usize) -> Blocks<'_, 'a> {
+        nih_debug_assert!(max_block_size > 0);
+
+        Blocks {
+            buffers: &mut self.output_slices,
+            max_block_size: max_block_size.max(1),
+            current_block_start: 0,
+        }
+    }
+
+    /// Set every sample in every channel to zero. Useful for instruments and generators that
+    /// write their output from scratch.
+    pub fn clear(&mut self) {
+        self.fill(0.0);
+    }
+
+    /// Set every sample in every channel to `value`.
+    pub fn fill(&mut self, value: f32) {
+        for channel_slice in self.output_slices.iter_mut() {
+            channel_slice.fill(value);
+        }
+    }
+
+    /// Set every sample in a single channel to `value`.
+    pub fn fill_channel(&mut self, channel: usize, value: f32) {
+        self.output_slices[channel].fill(value);
+    }
+
+    /// Silence a single channel for the entire block.
+    pub fn mute_channel(&mut self, channel: usize) {
+        self.fill_channel(channel, 0.0);
+    }
+
+    /// Silence every channel except for `channel` for the entire block.
+    pub fn solo_channel(&mut self, channel: usize) {
+        nih_debug_assert!(channel < self.output_slices.len());
+        for (channel_idx, channel_slice) in self.output_slices.iter_mut().enumerate() {
+            if channel_idx != channel {
+                channel_slice.fill(0.0);
+            }
+        }
+    }
+
+    /// Multiply all samples in a single channel by a voltage gain ratio. Use
+    /// [crate::util::db_to_gain()] if you want to specify the gain in decibels.
+    pub fn set_channel_gain(&mut self, channel: usize, gain: f32) {
+        for sample in self.output_slices[channel].iter_mut() {
+            *sample *= gain;
+        }
+    }
+
+    /// Deinterleave `interleaved` into this buffer's channels. `interleaved` should contain exactly
+    /// as many samples as this buffer, with the samples for all channels stored next to each other
+    /// (`[l0, r0, l1, r1, ...]` for a stereo buffer). This can be used together with
+    /// [Self::copy_to_interleaved()] to run a plugin's process function on interleaved audio.
+    pub fn copy_from_interleaved(&mut self, interleaved: &[f32]) {
+        let num_channels = self.output_slices.len();
+        if num_channels == 0 {
+            return;
+        }
+
+        nih_debug_assert_eq!(
+            interleaved.len(),
+            num_channels * self.output_slices[0].len()
+        );
+        for (channel_idx, channel_slice) in self.output_slices.iter_mut().enumerate() {
+            let interleaved_samples = interleaved.iter().skip(channel_idx).step_by(num_channels);
+            for (sample, interleaved_sample) in channel_slice.iter_mut().zip(interleaved_samples) {
+                *sample = *interleaved_sample;
+            }
+        }
+    }
+
+    /// Interleave this buffer's channels into `interleaved`. This is the inverse of
+    /// [Self::copy_from_interleaved()], and `interleaved` should again have room for exactly as many
+    /// samples as this buffer contains.
+    pub fn copy_to_interleaved(&self, interleaved: &mut [f32]) {
+        let num_channels = self.output_slices.len();
+        if num_channels == 0 {
+            return;
+        }
+
+        nih_debug_assert_eq!(
+            interleaved.len(),
+            num_channels * self.output_slices[0].len()
+        );
+        for (channel_idx, channel_slice) in self.output_slices.iter().enumerate() {
+            let interleaved_samples = interleaved
+                .iter_mut()
+                .skip(channel_idx)
+                .step_by(num_channels);
+            for (sample, interleaved_sample) in channel_slice.iter().zip(interleaved_samples) {
+                *interleaved_sample = *sample;
+            }
+        }
+    }
+
     /// Access the raw output slice vector. This neds to be resized to match the number of output
     /// channels during the plugin's initialization. Then during audio processing, these slices
     /// should be updated to point to the plugin's audio buffers.
@@ -61,6 +264,28 @@ impl<'a> Buffer<'a> {
     pub unsafe fn as_raw_vec(&mut self) -> &mut Vec<&'a mut [f32]> {
         &mut self.output_slices
     }
+
+    /// Access the raw input slice vector. This works the same way as [Self::as_raw_vec()], and the
+    /// input slices should contain the same number of samples as the output slices.
+    ///
+    /// # Safety
+    ///
+    /// The stored slices must point to live data when this object is passed to the plugins' process
+    /// function, and that data must not be written to while the plugin is processing audio.
+    pub unsafe fn as_raw_input_vec(&mut self) -> &mut Vec<&'a [f32]> {
+        &mut self.input_slices
+    }
+
+    /// Access the raw auxiliary input slice vectors, with one vector of channel slices per
+    /// auxiliary input bus. This works the same way as [Self::as_raw_input_vec()].
+    ///
+    /// # Safety
+    ///
+    /// The stored slices must point to live data when this object is passed to the plugins' process
+    /// function, and that data must not be written to while the plugin is processing audio.
+    pub unsafe fn as_raw_aux_input_vec(&mut self) -> &mut Vec<Vec<&'a [f32]>> {
+        &mut self.aux_input_slices
+    }
 }
 
 /// An iterator over all samples in the buffer, yielding iterators over each channel for every
@@ -75,7 +300,9 @@ impl<'outer, 'inner> Iterator for Samples<'outer, 'inner> {
     type Item = Channels<'outer, 'inner>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.current_sample < self.buffers[0].len() {
+        // Plugins without any audio channels, like MIDI effects, get an empty buffer
+        let num_samples = self.buffers.first().map_or(0, |channel| channel.len());
+        if self.current_sample < num_samples {
             // SAFETY: We guarantee that each sample is only mutably borrowed once in the channels
             // iterator
             let buffers: &'outer mut _ = unsafe { &mut *(self.buffers as *mut _) };
@@ -94,13 +321,308 @@ impl<'outer, 'inner> Iterator for Samples<'outer, 'inner> {
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        let remaining = self.buffers[0].len() - self.current_sample;
+        let num_samples = self.buffers.first().map_or(0, |channel| channel.len());
+        let remaining = num_samples - self.current_sample;
         (remaining, Some(remaining))
     }
 }
 
 impl<'outer, 'inner> ExactSizeIterator for Samples<'outer, 'inner> {}
 
+/// An iterator over all samples in the buffer, yielding both the unmodified input samples and an
+/// iterator over the output channels for every sample. See [Buffer::iter_mut_with_input()].
+pub struct SamplesWithInput<'outer, 'inner> {
+    pub(self) samples: Samples<'outer, 'inner>,
+    /// The raw input buffers.
+    pub(self) inputs: &'outer [&'inner [f32]],
+}
+
+impl<'outer, 'inner> Iterator for SamplesWithInput<'outer, 'inner> {
+    type Item = (InputChannels<'outer, 'inner>, Channels<'outer, 'inner>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let current_sample = self.samples.current_sample;
+        let channels = self.samples.next()?;
+        let input_channels = InputChannels {
+            buffers: self.inputs,
+            current_sample,
+            current_channel: 0,
+        };
+
+        Some((input_channels, channels))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.samples.size_hint()
+    }
+}
+
+impl<'outer, 'inner> ExactSizeIterator for SamplesWithInput<'outer, 'inner> {}
+
+/// An iterator over the unmodified input samples for every channel at a single point in time,
+/// yielded by [SamplesWithInput].
+pub struct InputChannels<'outer, 'inner> {
+    /// The raw input buffers.
+    pub(self) buffers: &'outer [&'inner [f32]],
+    pub(self) current_sample: usize,
+    pub(self) current_channel: usize,
+}
+
+impl<'outer, 'inner> Iterator for InputChannels<'outer, 'inner> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let sample = *self
+            .buffers
+            .get(self.current_channel)?
+            .get(self.current_sample)?;
+        self.current_channel += 1;
+
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // `next()` stops at the first channel that doesn't contain the current sample, which can
+        // only happen if the input slices are shorter than the output slices
+        let remaining = self.buffers[self.current_channel..]
+            .iter()
+            .take_while(|channel| self.current_sample < channel.len())
+            .count();
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'outer, 'inner> ExactSizeIterator for InputChannels<'outer, 'inner> {}
+
+/// The position of a sample within a control rate period, yielded by [ControlRateSamples].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ControlRatePosition {
+    /// The index of the sample within the current buffer.
+    pub sample_idx: usize,
+    /// The index of the sample within the current control rate period.
+    pub offset: usize,
+    /// The number of samples in the current control rate period. This is only shorter than the
+    /// requested interval for the last period in the buffer.
+    pub len: usize,
+}
+
+impl ControlRatePosition {
+    /// Whether this sample starts a new control rate period. Control rate values should be
+    /// updated on these samples.
+    pub fn is_tick(&self) -> bool {
+        self.offset == 0
+    }
+
+    /// How far into the current control rate period this sample is, in the range `[0, 1)`. This
+    /// can be used to linearly interpolate between the values computed on this period's tick and
+    /// the next one's.
+    pub fn fraction(&self) -> f32 {
+        self.offset as f32 / self.len as f32
+    }
+}
+
+/// An iterator over the channels in the buffer, yielding a slice for every channel. See
+/// [Buffer::channels_mut()].
+pub struct ChannelSlices<'outer, 'inner> {
+    pub(self) channels: std::slice::IterMut<'outer, &'inner mut [f32]>,
+}
+
+impl<'outer, 'inner> Iterator for ChannelSlices<'outer, 'inner> {
+    type Item = &'outer mut [f32];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.channels
+            .next()
+            .map(|channel_slice| &mut **channel_slice)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.channels.size_hint()
+    }
+}
+
+impl<'outer, 'inner> ExactSizeIterator for ChannelSlices<'outer, 'inner> {}
+
+/// An iterator over all samples in the buffer that also keeps track of control rate periods. See
+/// [Buffer::iter_control_rate()].
+pub struct ControlRateSamples<'outer, 'inner> {
+    pub(self) samples: Samples<'outer, 'inner>,
+    pub(self) interval: usize,
+}
+
+impl<'outer, 'inner> Iterator for ControlRateSamples<'outer, 'inner> {
+    type Item = (ControlRatePosition, Channels<'outer, 'inner>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let sample_idx = self.samples.current_sample;
+        let num_samples = self
+            .samples
+            .buffers
+            .first()
+            .map_or(0, |channel| channel.len());
+        let channels = self.samples.next()?;
+
+        let period_start = sample_idx - (sample_idx % self.interval);
+        let position = ControlRatePosition {
+            sample_idx,
+            offset: sample_idx - period_start,
+            len: self.interval.min(num_samples - period_start),
+        };
+
+        Some((position, channels))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.samples.size_hint()
+    }
+}
+
+impl<'outer, 'inner> ExactSizeIterator for ControlRateSamples<'outer, 'inner> {}
+
+/// An iterator over the buffer in blocks of at most a fixed number of samples. See
+/// [Buffer::iter_blocks()].
+pub struct Blocks<'outer, 'inner> {
+    /// The raw output buffers.
+    pub(self) buffers: &'outer mut [&'inner mut [f32]],
+    pub(self) max_block_size: usize,
+    pub(self) current_block_start: usize,
+}
+
+impl<'outer, 'inner> Iterator for Blocks<'outer, 'inner> {
+    type Item = Block<'outer, 'inner>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let num_samples = self.buffers.first().map_or(0, |channel| channel.len());
+        if self.current_block_start < num_samples {
+            let current_block_end =
+                (self.current_block_start + self.max_block_size).min(num_samples);
+            // SAFETY: The blocks cover disjoint ranges of samples, so each sample is only mutably
+            // borrowed by a single block
+            let buffers: &'outer mut _ = unsafe { &mut *(self.buffers as *mut _) };
+            let block = Block {
+                buffers,
+                current_block_start: self.current_block_start,
+                current_block_end,
+            };
+
+            self.current_block_start = current_block_end;
+
+            Some(block)
+        } else {
+            None
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let num_samples = self.buffers.first().map_or(0, |channel| channel.len());
+        let remaining_samples = num_samples - self.current_block_start;
+        let remaining = if remaining_samples > 0 {
+            (remaining_samples - 1) / self.max_block_size + 1
+        } else {
+            0
+        };
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'outer, 'inner> ExactSizeIterator for Blocks<'outer, 'inner> {}
+
+/// A block of samples within the buffer, yielded by [Blocks]. This gives access to the block's
+/// part of every channel.
+pub struct Block<'outer, 'inner> {
+    /// The raw output buffers.
+    pub(self) buffers: &'outer mut [&'inner mut [f32]],
+    pub(self) current_block_start: usize,
+    pub(self) current_block_end: usize,
+}
+
+impl<'outer, 'inner> Block<'outer, 'inner> {
+    /// The index of the block's first sample within the buffer.
+    pub fn offset(&self) -> usize {
+        self.current_block_start
+    }
+
+    /// The number of samples in this block. This is only shorter than the requested maximum block
+    /// size for the last block in the buffer.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.current_block_end - self.current_block_start
+    }
+
+    /// The number of channels in this block.
+    pub fn channels(&self) -> usize {
+        self.buffers.len()
+    }
+
+    /// The block's samples for a single channel, or `None` if the channel does not exist.
+    pub fn get(&self, channel: usize) -> Option<&[f32]> {
+        // SAFETY: The sample range has already been checked when creating the block
+        self.buffers.get(channel).map(|channel_slice| unsafe {
+            channel_slice.get_unchecked(self.current_block_start..self.current_block_end)
+        })
+    }
+
+    /// The block's samples for a single channel, or `None` if the channel does not exist.
+    pub fn get_mut(&mut self, channel: usize) -> Option<&mut [f32]> {
+        let (start, end) = (self.current_block_start, self.current_block_end);
+        // SAFETY: The sample range has already been checked when creating the block
+        self.buffers
+            .get_mut(channel)
+            .map(|channel_slice| unsafe { channel_slice.get_unchecked_mut(start..end) })
+    }
+
+    /// Iterate over the block's channels, yielding a slice containing this block's samples for
+    /// every channel.
+    pub fn iter_mut(&mut self) -> BlockChannels<'_, 'inner> {
+        BlockChannels {
+            buffers: self.buffers,
+            current_block_start: self.current_block_start,
+            current_block_end: self.current_block_end,
+            current_channel: 0,
+        }
+    }
+}
+
+/// An iterator over the channels in a block, yielded by [Block::iter_mut()].
+pub struct BlockChannels<'outer, 'inner> {
+    /// The raw output buffers.
+    pub(self) buffers: &'outer mut [&'inner mut [f32]],
+    pub(self) current_block_start: usize,
+    pub(self) current_block_end: usize,
+    pub(self) current_channel: usize,
+}
+
+impl<'outer, 'inner> Iterator for BlockChannels<'outer, 'inner> {
+    type Item = &'outer mut [f32];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.current_channel < self.buffers.len() {
+            // SAFETY: These bounds have already been checked
+            let slice = unsafe {
+                self.buffers
+                    .get_unchecked_mut(self.current_channel)
+                    .get_unchecked_mut(self.current_block_start..self.current_block_end)
+            };
+            // SAFETY: Every channel is only yielded once, so it is not possible to have multiple
+            // mutable references to the same samples at the same time
+            let slice: &'outer mut [f32] = unsafe { &mut *(slice as *mut [f32]) };
+
+            self.current_channel += 1;
+
+            Some(slice)
+        } else {
+            None
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.buffers.len() - self.current_channel;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'outer, 'inner> ExactSizeIterator for BlockChannels<'outer, 'inner> {}
+
 /// An iterator over the channel data for a sample, yielded by [Samples].
 pub struct Channels<'outer, 'inner> {
     /// The raw output buffers.
@@ -139,3 +661,218 @@ impl<'outer, 'inner> Iterator for Channels<'outer, 'inner> {
 }
 
 impl<'outer, 'inner> ExactSizeIterator for Channels<'outer, 'inner> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Create a buffer with its output channels pointing to `channels`.
+    fn buffer_from(channels: &mut [Vec<f32>]) -> Buffer<'_> {
+        let mut buffer = Buffer::default();
+        unsafe {
+            buffer
+                .as_raw_vec()
+                .extend(channels.iter_mut().map(|channel| channel.as_mut_slice()));
+        }
+
+        buffer
+    }
+
+    /// Point the buffer's separate input channels to `channels`.
+    fn add_inputs<'a>(buffer: &mut Buffer<'a>, channels: &'a [Vec<f32>]) {
+        unsafe {
+            buffer
+                .as_raw_input_vec()
+                .extend(channels.iter().map(|channel| channel.as_slice()));
+        }
+    }
+
+    #[test]
+    fn blocks_visit_every_sample_once() {
+        for (num_samples, max_block_size) in [(10, 4), (12, 4), (3, 8), (1, 1)] {
+            let mut channels = vec![vec![0.0f32; num_samples]; 2];
+            let mut buffer = buffer_from(&mut channels);
+
+            let mut blocks = buffer.iter_blocks(max_block_size);
+            let num_blocks = blocks.len();
+            let mut expected_offset = 0;
+            let mut visited_blocks = 0;
+            for mut block in &mut blocks {
+                assert_eq!(block.offset(), expected_offset);
+                assert!(block.len() > 0 && block.len() <= max_block_size);
+                assert_eq!(block.channels(), 2);
+                let block_len = block.len();
+                for channel in block.iter_mut() {
+                    assert_eq!(channel.len(), block_len);
+                    for sample in channel {
+                        *sample += 1.0;
+                    }
+                }
+
+                expected_offset += block_len;
+                visited_blocks += 1;
+            }
+
+            assert_eq!(expected_offset, num_samples);
+            assert_eq!(visited_blocks, num_blocks);
+            for channel in &channels {
+                assert!(channel.iter().all(|&sample| sample == 1.0));
+            }
+        }
+    }
+
+    #[test]
+    fn block_channel_access() {
+        let mut channels = vec![(0..5).map(|i| i as f32).collect::<Vec<_>>(); 2];
+        let mut buffer = buffer_from(&mut channels);
+
+        let mut blocks = buffer.iter_blocks(2);
+        let _ = blocks.next();
+        let mut block = blocks.next().unwrap();
+        assert_eq!(block.get(0), Some(&[2.0, 3.0][..]));
+        assert_eq!(block.get(2), None);
+        block.get_mut(1).unwrap().fill(0.0);
+
+        let last_block = blocks.next().unwrap();
+        assert_eq!(last_block.offset(), 4);
+        assert_eq!(last_block.len(), 1);
+        assert!(blocks.next().is_none());
+
+        assert_eq!(channels[1], [0.0, 1.0, 0.0, 0.0, 4.0]);
+    }
+
+    #[test]
+    fn channel_slices() {
+        let mut channels = [vec![1.0f32; 4], vec![2.0f32; 4]];
+        let mut buffer = buffer_from(&mut channels);
+
+        let channel_slices = buffer.channels_mut();
+        assert_eq!(channel_slices.len(), 2);
+        let energy: Vec<f32> = channel_slices
+            .map(|channel| {
+                let energy = channel.iter().map(|sample| sample * sample).sum();
+                channel.fill(0.0);
+                energy
+            })
+            .collect();
+
+        assert_eq!(energy, [4.0, 16.0]);
+        assert!(channels.iter().flatten().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn clear_and_fill() {
+        // The host may leave anything in the output buffers of a plugin without inputs
+        let mut channels = [vec![f32::NAN; 8], vec![0.75f32; 8]];
+        let mut buffer = buffer_from(&mut channels);
+
+        buffer.fill_channel(1, 0.25);
+        buffer.clear();
+        assert!(buffer
+            .as_raw()
+            .iter()
+            .all(|channel| channel.iter().all(|&sample| sample == 0.0)));
+
+        buffer.fill(0.5);
+        buffer.fill_channel(0, -1.0);
+        assert_eq!(channels[0], [-1.0; 8]);
+        assert_eq!(channels[1], [0.5; 8]);
+    }
+
+    #[test]
+    fn mute_solo_and_channel_gain() {
+        let mut channels = [vec![1.0f32; 4], vec![2.0f32; 4], vec![3.0f32; 4]];
+        let mut buffer = buffer_from(&mut channels);
+
+        buffer.set_channel_gain(2, 0.5);
+        buffer.mute_channel(0);
+        assert_eq!(buffer.as_raw()[0], [0.0; 4]);
+        assert_eq!(buffer.as_raw()[1], [2.0; 4]);
+        assert_eq!(buffer.as_raw()[2], [1.5; 4]);
+
+        buffer.fill(1.0);
+        buffer.solo_channel(1);
+        assert_eq!(channels[0], [0.0; 4]);
+        assert_eq!(channels[1], [1.0; 4]);
+        assert_eq!(channels[2], [0.0; 4]);
+    }
+
+    #[test]
+    fn analysis_buffers_only_contain_inputs() {
+        let channels = [vec![0.5f32; 4], vec![-0.5f32; 4]];
+        let mut buffer = Buffer::default();
+        add_inputs(&mut buffer, &channels);
+
+        assert!(!buffer.is_empty());
+        assert!(buffer.as_raw().is_empty());
+        assert_eq!(buffer.iter_mut().count(), 0);
+        assert_eq!(buffer.inputs()[1], [-0.5; 4]);
+    }
+
+    #[test]
+    fn inputs_stay_intact_while_writing_outputs() {
+        let inputs = [vec![1.0f32, 2.0, 3.0], vec![-1.0f32, -2.0, -3.0]];
+        let mut outputs = inputs.clone();
+        let mut buffer = buffer_from(&mut outputs);
+        add_inputs(&mut buffer, &inputs);
+
+        let mut num_samples = 0;
+        let samples = buffer.iter_mut_with_input();
+        assert_eq!(samples.len(), 3);
+        for (input_channels, output_channels) in samples {
+            assert_eq!(input_channels.len(), 2);
+            assert_eq!(output_channels.len(), 2);
+            for (input_sample, output_sample) in input_channels.zip(output_channels) {
+                *output_sample = input_sample * 0.5;
+            }
+
+            num_samples += 1;
+        }
+
+        assert_eq!(num_samples, 3);
+        assert_eq!(buffer.inputs()[0], [1.0, 2.0, 3.0]);
+        assert_eq!(outputs[1], [-0.5, -1.0, -1.5]);
+    }
+
+    #[test]
+    fn input_channels_size_hint_is_exact() {
+        // A shorter input slice ends the iterator early, and the size hint should reflect that
+        let inputs = [vec![1.0f32, 2.0], vec![3.0f32], vec![4.0f32, 5.0]];
+        let input_slices: Vec<&[f32]> = inputs.iter().map(|channel| channel.as_slice()).collect();
+        let mut input_channels = InputChannels {
+            buffers: &input_slices,
+            current_sample: 1,
+            current_channel: 0,
+        };
+
+        assert_eq!(input_channels.len(), 1);
+        assert_eq!(input_channels.next(), Some(2.0));
+        assert_eq!(input_channels.len(), 0);
+        assert_eq!(input_channels.next(), None);
+
+        let input_channels = InputChannels {
+            buffers: &input_slices,
+            current_sample: 0,
+            current_channel: 0,
+        };
+        assert_eq!(input_channels.len(), 3);
+        assert_eq!(input_channels.count(), 3);
+    }
+
+    #[test]
+    fn no_inputs_without_separate_input_buffers() {
+        let mut outputs = [vec![0.0f32; 4]];
+        let mut buffer = buffer_from(&mut outputs);
+
+        assert!(buffer.inputs().is_empty());
+        for (input_channels, _) in buffer.iter_mut_with_input() {
+            assert_eq!(input_channels.len(), 0);
+        }
+    }
+
+    #[test]
+    fn no_blocks_for_empty_buffers() {
+        let mut buffer = Buffer::default();
+        assert_eq!(buffer.iter_blocks(16).count(), 0);
+    }
+}
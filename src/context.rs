@@ -36,7 +36,158 @@ use crate::plugin::NoteEvent;
 
 pub(crate) const TASK_QUEUE_CAPACITY: usize = 512;
 
-// TODO: ProcessContext for parameter automation and sending events
+/// A single automation point sent by the host for the current block, see
+/// [ProcessContext::next_automation_point()]. The parameter has already been set to the last
+/// point's value before the plugin's process function gets called.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AutomationPoint {
+    /// The sample within the current buffer this automation point belongs to.
+    pub timing: u32,
+    /// The automated parameter. Compare this to [Param::as_ptr()] to find out which parameter it
+    /// is.
+    pub param: ParamPtr,
+    /// The normalized value sent by the host.
+    pub normalized: f32,
+    /// `normalized` converted to the parameter's plain value, as a float. This uses the exact same
+    /// conversion the wrapper uses when setting the parameter's value.
+    pub plain: f32,
+}
+
+/// Information about the host's transport, like the tempo and the current playback position. This
+/// is provided for every block through [ProcessContext::transport()]. Hosts aren't required to
+/// provide most of this information, so those fields are optional. Positions are always for the
+/// start of the current block, and beats are quarter notes regardless of the time signature.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transport {
+    /// Whether the host is currently playing or recording.
+    pub playing: bool,
+    /// Whether the host is currently recording.
+    pub recording: bool,
+    /// The current sample rate.
+    pub sample_rate: f32,
+    /// The tempo in beats per minute. This is never zero or negative.
+    pub tempo: Option<f64>,
+    /// The time signature's numerator, like the 3 in 3/4.
+    pub time_sig_numerator: Option<i32>,
+    /// The time signature's denominator, like the 4 in 3/4.
+    pub time_sig_denominator: Option<i32>,
+    /// The playhead's position in samples since the start of the project.
+    pub pos_samples: Option<i64>,
+    /// The playhead's position in beats since the start of the project. If the host doesn't report
+    /// this directly, then this is computed from [Self::pos_samples] and [Self::tempo].
+    pub pos_beats: Option<f64>,
+    /// The position of the start of the current bar in beats.
+    pub bar_start_pos_beats: Option<f64>,
+    /// The start and end of the loop in beats, if looping is enabled.
+    pub loop_range_beats: Option<(f64, f64)>,
+}
+
+impl Transport {
+    /// Create a transport without any information from the host, for the wrapper to fill in.
+    pub(crate) fn new(sample_rate: f32) -> Self {
+        Self {
+            playing: false,
+            recording: false,
+            sample_rate,
+            tempo: None,
+            time_sig_numerator: None,
+            time_sig_denominator: None,
+            pos_samples: None,
+            pos_beats: None,
+            bar_start_pos_beats: None,
+            loop_range_beats: None,
+        }
+    }
+
+    /// Discard values that don't make any sense, and fill in [Self::pos_beats] when it can be
+    /// derived from the other values. Some hosts report a tempo of zero when there is no tempo
+    /// information, which would otherwise cause divisions by zero in the plugin. This should be
+    /// called by the wrapper after filling in the host's values.
+    pub(crate) fn sanitize(mut self) -> Self {
+        if !matches!(self.tempo, Some(tempo) if tempo.is_finite() && tempo > 0.0) {
+            self.tempo = None;
+        }
+        if !matches!(
+            (self.time_sig_numerator, self.time_sig_denominator),
+            (Some(numerator), Some(denominator)) if numerator > 0 && denominator > 0
+        ) {
+            self.time_sig_numerator = None;
+            self.time_sig_denominator = None;
+        }
+
+        if self.pos_beats.is_none() {
+            if let (Some(pos_seconds), Some(tempo)) = (self.pos_seconds(), self.tempo) {
+                self.pos_beats = Some(pos_seconds * tempo / 60.0);
+            }
+        }
+
+        self
+    }
+
+    /// The playhead's position in seconds since the start of the project, computed from
+    /// [Self::pos_samples].
+    pub fn pos_seconds(&self) -> Option<f64> {
+        self.pos_samples
+            .map(|pos_samples| pos_samples as f64 / self.sample_rate as f64)
+    }
+
+    /// The length of a bar in beats according to the current time signature. A bar in 6/8 is three
+    /// beats long.
+    pub fn bar_length_beats(&self) -> Option<f64> {
+        match (self.time_sig_numerator, self.time_sig_denominator) {
+            (Some(numerator), Some(denominator)) => {
+                Some(numerator as f64 * 4.0 / denominator as f64)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The parts of the [Transport] a plugin needs, set through
+/// [crate::plugin::Plugin::TRANSPORT_REQUIREMENTS]. Some hosts do expensive work to compute the
+/// transport information, and VST3 hosts that support `IProcessContextRequirements` can skip the
+/// parts the plugin doesn't need. Hosts are free to provide more than what's requested, and hosts
+/// that don't support this may still provide less.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransportRequirements {
+    /// Whether the host is playing or recording, see [Transport::playing] and
+    /// [Transport::recording].
+    pub transport_state: bool,
+    /// The tempo, see [Transport::tempo].
+    pub tempo: bool,
+    /// The time signature, see [Transport::time_sig_numerator] and
+    /// [Transport::time_sig_denominator].
+    pub time_signature: bool,
+    /// The playhead's position in beats, see [Transport::pos_beats].
+    pub position_beats: bool,
+    /// The position of the start of the current bar, see [Transport::bar_start_pos_beats].
+    pub bar_position: bool,
+    /// The loop range, see [Transport::loop_range_beats].
+    pub loop_range: bool,
+}
+
+impl TransportRequirements {
+    /// Request all transport information. This is the default.
+    pub const ALL: Self = Self {
+        transport_state: true,
+        tempo: true,
+        time_signature: true,
+        position_beats: true,
+        bar_position: true,
+        loop_range: true,
+    };
+
+    /// Don't request any transport information, for plugins that don't use the transport at all.
+    /// The position in samples is always available.
+    pub const NONE: Self = Self {
+        transport_state: false,
+        tempo: false,
+        time_signature: false,
+        position_beats: false,
+        bar_position: false,
+        loop_range: false,
+    };
+}
 
 /// General callbacks the plugin can make during its lifetime. This is passed to the plugin during
 /// [crate::plugin::Plugin::initialize()] and as part of [crate::plugin::Plugin::process()].
@@ -46,21 +197,95 @@ pub(crate) const TASK_QUEUE_CAPACITY: usize = 512;
 // The implementing wrapper needs to be able to handle concurrent requests, and it should perform
 // the actual callback within [MainThreadQueue::do_maybe_async].
 pub trait ProcessContext {
-    /// Update the current latency of the plugin. If the plugin is currently processing audio, then
-    /// this may cause audio playback to be restarted.
+    /// Update the current latency of the plugin. The latency is zero until this is called. Hosts
+    /// query the plugin's latency after it has been initialized, so if the plugin's latency
+    /// depends on its parameters or on the sample rate then it should be set from
+    /// [crate::plugin::Plugin::initialize()]. That way the host will pick up the correct value
+    /// right away. Changing the latency after the host has queried it causes the host to restart
+    /// the plugin's processing, and some hosts only handle this poorly or not at all.
+    ///
+    /// This is realtime-safe, so a plugin whose latency depends on its parameters, like a lookahead
+    /// limiter with optional oversampling, can simply call this from its process function on every
+    /// block. The host is only notified when the value actually changes.
     fn set_latency_samples(&self, samples: u32);
 
-    /// Return the next note event, if there is one. The event contains the timing
+    /// Information about the host's transport for the current block, like the tempo and the
+    /// playback position. During [crate::plugin::Plugin::initialize()] this doesn't contain any
+    /// information from the host.
+    fn transport(&self) -> &Transport;
+
+    /// Return the next note event, if there is one. Events are returned in order of their timing
+    /// within the current block, see [NoteEvent::timing()]. Events with the same timing are
+    /// returned in the order the host sent them in. Any events left when the process function
+    /// returns are discarded. [Self::note_events()] provides the same events as an iterator.
     ///
     /// TODO: Rethink this API, both in terms of ergonomics, and if we can do this in a way that
     ///       doesn't require locks (because of the thread safe-ness, which we don't really need
     ///       here)
     fn next_midi_event(&mut self) -> Option<NoteEvent>;
 
-    // TODO: Add this, this works similar to [GuiContext::set_parameter] but it adds the parameter
-    //       change to a queue (or directly to the VST3 plugin's parameter output queues) instead of
-    //       using main thread host automation (and all the locks involved there).
-    // fn set_parameter<P: Param>(&self, param: &P, value: P::Plain);
+    /// Return the next note event if it happens at or before `sample_idx`, the index of the current
+    /// sample within the block. This lets a synth handle events sample-accurately from within its
+    /// sample loop by calling this until it returns `None` before processing each sample. Since
+    /// events are sorted by their timing, none of them get skipped this way. Events that were not
+    /// returned yet are left for later calls, and this consumes the events in the same way as
+    /// [Self::next_midi_event()].
+    fn next_event(&mut self, sample_idx: usize) -> Option<NoteEvent>;
+
+    /// The number of note events the plugin has consumed during the current block through
+    /// [Self::next_midi_event()], [Self::next_event()], or [Self::note_events()].
+    fn num_consumed_events(&self) -> usize;
+
+    /// Iterate over the remaining note events for the current block, sorted by their timing. This
+    /// consumes the events in the same way as [Self::next_midi_event()]. Use
+    /// [Iterator::peekable()] to only handle the events up to the current sample while iterating
+    /// over the buffer.
+    fn note_events(&mut self) -> NoteEvents<'_, Self>
+    where
+        Self: Sized,
+    {
+        NoteEvents { context: self }
+    }
+
+    /// Return the next automation point the host sent for this block, if there is one. These are
+    /// ordered by parameter and then by timing. The parameters have already been set to the last
+    /// value in the block, so this is only needed when the plugin wants to handle sample accurate
+    /// automation itself or needs to know the individual values the host sent. Any points left
+    /// when the process function returns are discarded.
+    fn next_automation_point(&mut self) -> Option<AutomationPoint>;
+
+    /// Send a note event to the host. This only does something when
+    /// [crate::plugin::Plugin::PRODUCES_MIDI] is set. The events are sorted by their timing before
+    /// they are passed to the host after the process function returns, so they don't need to be
+    /// sent in order. Events timed at or after the end of the current block are sent during the
+    /// next block instead, with their timing adjusted accordingly.
+    fn send_event(&mut self, event: NoteEvent);
+
+    /// Set a parameter to a new normalized value from within the process function, for instance
+    /// when an automatic gain staging feature needs to write back the detected gain. The new value
+    /// is used immediately, and the change is reported to the host from the main thread so it can
+    /// be recorded as automation. Use [Param::as_ptr()] and [Param::preview_normalized()] to get
+    /// the arguments for this function.
+    fn set_parameter(&self, param: ParamPtr, normalized: f32);
+
+    /// Ask the editor to repaint because something it displays has changed, for instance a value
+    /// computed from the incoming audio. Parameter changes already do this automatically. This is
+    /// lock-free and can be called on every block. See [GuiContext::editor_repaint_requested()].
+    fn request_editor_repaint(&self);
+}
+
+/// An iterator over the note events for the current block, sorted by their timing. Created using
+/// [ProcessContext::note_events()].
+pub struct NoteEvents<'a, C: ProcessContext> {
+    context: &'a mut C,
+}
+
+impl<C: ProcessContext> Iterator for NoteEvents<'_, C> {
+    type Item = NoteEvent;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.context.next_midi_event()
+    }
 }
 
 /// Callbacks the plugin can make when the user interacts with its GUI such as updating parameter
@@ -98,11 +323,62 @@ pub trait GuiContext: Send + Sync + 'static {
     /// The implementing function still needs to check if `param` actually exists. This function is
     /// mostly marked as unsafe for API reasons.
     unsafe fn raw_end_set_parameter(&self, param: ParamPtr);
+
+    /// Whether there is autosaved state left behind by an earlier session that did not shut down
+    /// cleanly. This is only ever the case when [crate::plugin::Plugin::AUTOSAVE_INTERVAL] is set.
+    fn has_autosaved_state(&self) -> bool;
+
+    /// Restore the plugin's state from the autosave file left behind by an earlier session that did
+    /// not shut down cleanly, and inform the host about the changed parameter values. The autosave
+    /// file is removed afterwards. Returns `false` if there was no such state or if it could not be
+    /// restored.
+    fn restore_autosaved_state(&self) -> bool;
+
+    /// Check whether the editor should repaint because a parameter's value has changed, for
+    /// instance through host automation, or because the plugin called
+    /// [ProcessContext::request_editor_repaint()] since the last time this was called with the
+    /// same `last_seen` value. `last_seen` is updated to the current state. Editors that don't
+    /// redraw on every frame can poll this cheaply to stay in sync with the plugin.
+    fn editor_repaint_requested(&self, last_seen: &mut u32) -> bool;
+
+    /// Limit how many edits per second are sent to the host for a single parameter while the user
+    /// is dragging it around in the editor. Edits in between are coalesced, and the last value is
+    /// always sent at the end of the gesture. This reduces the load on the host and keeps
+    /// automation lanes from getting cluttered during fast drags. Some hosts prefer dense
+    /// automation data while others struggle with it, so this is not limited by default. Passing
+    /// `None` removes the limit again. The parameter's value is always updated immediately while
+    /// the plugin isn't processing audio.
+    fn set_max_parameter_edit_rate(&self, edits_per_second: Option<f32>);
+
+    /// Ask the host to resize the editor's window to the size currently returned by
+    /// [crate::plugin::Editor::size()]. The editor should update its size before calling this.
+    /// Returns `false` if the host rejected the new size, or if the editor isn't open or the host
+    /// doesn't support resizing. The editor should then go back to its previous size.
+    fn request_resize(&self) -> bool;
 }
 
 /// A convenience helper for setting parameter values. Any changes made here will be broadcasted to
 /// the host and reflected in the plugin's [crate::param::internals::Params] object. These functions
 /// should only be called from the main thread.
+///
+/// Every change to a parameter should be part of a gesture, so the host can record a continuous
+/// movement of a knob or slider as a single automation edit. A widget should call
+/// [Self::begin_set_parameter()] when the user starts interacting with it, for instance on mouse
+/// down, then call [Self::set_parameter()] for every new value, and finally call
+/// [Self::end_set_parameter()] when the interaction ends, for instance on mouse up. Unbalanced
+/// calls are reported as debug assertion failures in debug builds.
+///
+/// ```ignore
+/// if response.drag_started() {
+///     setter.begin_set_parameter(&params.gain);
+/// }
+/// if response.dragged() {
+///     setter.set_parameter(&params.gain, new_value);
+/// }
+/// if response.drag_released() {
+///     setter.end_set_parameter(&params.gain);
+/// }
+/// ```
 pub struct ParamSetter<'a> {
     context: &'a dyn GuiContext,
 }
@@ -113,7 +389,8 @@ impl<'a> ParamSetter<'a> {
     }
 
     /// Inform the host that you will start automating a parmater. This needs to be called before
-    /// calling [Self::set_parameter()] for the specified parameter.
+    /// calling [Self::set_parameter()] for the specified parameter, and every call needs to be
+    /// matched by a call to [Self::end_set_parameter()].
     pub fn begin_set_parameter<P: Param>(&self, param: &P) {
         unsafe { self.context.raw_begin_set_parameter(param.as_ptr()) };
     }
@@ -138,6 +415,19 @@ impl<'a> ParamSetter<'a> {
     pub fn end_set_parameter<P: Param>(&self, param: &P) {
         unsafe { self.context.raw_end_set_parameter(param.as_ptr()) };
     }
+
+    /// Limit the number of edits per second sent to the host during a gesture, for instance to
+    /// `Some(200.0)`. See [GuiContext::set_max_parameter_edit_rate()]. This setting applies to the
+    /// entire plugin instance and it persists between `ParamSetter`s.
+    pub fn set_max_edit_rate(&self, edits_per_second: Option<f32>) {
+        self.context.set_max_parameter_edit_rate(edits_per_second);
+    }
+
+    /// Ask the host to resize the editor's window after the editor has changed its size. See
+    /// [GuiContext::request_resize()].
+    pub fn request_resize(&self) -> bool {
+        self.context.request_resize()
+    }
 }
 
 /// A trait describing the functionality of the platform-specific event loop that can execute tasks
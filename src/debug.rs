@@ -14,16 +14,138 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-/// Write something to the STDERR stream.
+//! Simple logging and debug assertion macros. Log messages are written to STDERR by default. Many
+//! DAWs swallow STDERR, so setting the `NIH_LOG` environment variable to a file path makes all
+//! messages get appended to that file instead. The log file can also be changed at runtime using
+//! [set_log_file()].
+
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// The environment variable containing the path to the log file. When this is not set, or when it
+/// is set to an empty string or `stderr`, messages are written to STDERR.
+pub const LOG_FILE_ENV_VAR: &str = "NIH_LOG";
+
+lazy_static! {
+    /// The file log messages are written to, or `None` if they should be written to STDERR. This
+    /// is initialized from [LOG_FILE_ENV_VAR] the first time something gets logged.
+    static ref LOG_FILE: Mutex<Option<File>> = Mutex::new(
+        std::env::var_os(LOG_FILE_ENV_VAR)
+            .filter(|path| !path.is_empty() && path != "stderr")
+            .and_then(|path| open_log_file(Path::new(&path)).ok())
+    );
+}
+
+/// The severity of a log message. Messages logged through [nih_log!] use [LogLevel::Info].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    /// Very detailed messages that are only logged in debug builds, see [nih_trace!].
+    Trace,
+    /// Regular messages, see [nih_log!].
+    Info,
+    /// Something unexpected happened that the plugin can recover from, see [nih_warn!].
+    Warn,
+    /// Something went wrong, see [nih_error!].
+    Error,
+}
+
+impl LogLevel {
+    /// The tag written in front of messages with this log level. Regular messages don't get a tag.
+    fn tag(&self) -> &'static str {
+        match self {
+            LogLevel::Trace => "TRACE: ",
+            LogLevel::Info => "",
+            LogLevel::Warn => "WARNING: ",
+            LogLevel::Error => "ERROR: ",
+        }
+    }
+}
+
+/// Write log messages to the file at `path` instead of to STDERR, or go back to writing to STDERR
+/// if `path` is `None`. Messages are appended to the end of the file. This overrides the
+/// [LOG_FILE_ENV_VAR] environment variable.
+pub fn set_log_file(path: Option<&Path>) -> io::Result<()> {
+    let file = match path {
+        Some(path) => Some(open_log_file(path)?),
+        None => None,
+    };
+    *LOG_FILE.lock() = file;
+
+    Ok(())
+}
+
+fn open_log_file(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// Write a message to the log file, or to STDERR if there is no log file. This is used by the
+/// logging macros, which also add the file and line number to the message. If writing to the log
+/// file fails, then the message is written to STDERR instead.
+#[doc(hidden)]
+pub fn log(level: LogLevel, message: fmt::Arguments) {
+    let mut log_file = LOG_FILE.lock();
+    if let Some(file) = log_file.as_mut() {
+        if writeln!(file, "{}{}", level.tag(), message).is_ok() {
+            return;
+        }
+    }
+
+    eprintln!("{}{}", level.tag(), message);
+}
+
+/// Write something to the log. This goes to STDERR, or to the file configured through the
+/// `NIH_LOG` environment variable or [crate::debug::set_log_file()].
 ///
 /// XXX: I don't think we need all of the log crate just for some simple logging, but maybe consider
-///      integrating some other crate with this function if we need to log to some other place than
-///      STDERR or if it needs to be done in release builds and we should thus try to avoid
-///      allocations.
+///      integrating some other crate with this function if it needs to be done in release builds
+///      and we should thus try to avoid allocations.
 #[macro_export]
 macro_rules! nih_log {
     ($format:expr $(, $($args:tt)*)?) => (
-        eprintln!(concat!("[", file!(), ":", line!(), "] ", $format), $($($args)*)?)
+        $crate::debug::log(
+            $crate::debug::LogLevel::Info,
+            format_args!(concat!("[", file!(), ":", line!(), "] ", $format), $($($args)*)?),
+        )
+    );
+}
+
+/// The same as [nih_log!], but for very detailed messages. These are only logged in debug builds,
+/// and the message's arguments are not evaluated in release builds.
+#[macro_export]
+macro_rules! nih_trace {
+    ($format:expr $(, $($args:tt)*)?) => (
+        if cfg!(debug_assertions) {
+            $crate::debug::log(
+                $crate::debug::LogLevel::Trace,
+                format_args!(concat!("[", file!(), ":", line!(), "] ", $format), $($($args)*)?),
+            )
+        }
+    );
+}
+
+/// The same as [nih_log!], but for warnings.
+#[macro_export]
+macro_rules! nih_warn {
+    ($format:expr $(, $($args:tt)*)?) => (
+        $crate::debug::log(
+            $crate::debug::LogLevel::Warn,
+            format_args!(concat!("[", file!(), ":", line!(), "] ", $format), $($($args)*)?),
+        )
+    );
+}
+
+/// The same as [nih_log!], but for errors.
+#[macro_export]
+macro_rules! nih_error {
+    ($format:expr $(, $($args:tt)*)?) => (
+        $crate::debug::log(
+            $crate::debug::LogLevel::Error,
+            format_args!(concat!("[", file!(), ":", line!(), "] ", $format), $($($args)*)?),
+        )
     );
 }
 
@@ -92,3 +214,32 @@ macro_rules! nih_debug_assert_ne {
         }
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_to_file() {
+        let path =
+            std::env::temp_dir().join(format!("nih-plug-log-test-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        set_log_file(Some(&path)).unwrap();
+        nih_log!("Regular message {}", 1);
+        nih_warn!("Warning {}", 2);
+        nih_error!("Error");
+        set_log_file(None).unwrap();
+        nih_log!("This goes to STDERR");
+
+        // Other tests may log at the same time, so only check for our own messages
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert!(contents.contains(&format!("[{}:", file!())));
+        assert!(contents.contains("] Regular message 1\n"));
+        assert!(contents.contains(&format!("WARNING: [{}:", file!())));
+        assert!(contents.contains("] Warning 2\n"));
+        assert!(contents.contains(&format!("ERROR: [{}:", file!())));
+        assert!(!contents.contains("This goes to STDERR"));
+    }
+}
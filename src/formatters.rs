@@ -18,7 +18,290 @@
 
 use std::sync::Arc;
 
+use crate::util;
+
 /// Round an `f32` value to always have a specific number of decimal digits.
 pub fn f32_rounded(digits: usize) -> Option<Arc<dyn Fn(f32) -> String + Send + Sync>> {
     Some(Arc::new(move |x| format!("{:.digits$}", x)))
 }
+
+/// Format a frequency in Hertz as a rounded `Hz` value below 1000 Hz, and as a rounded `kHz` value
+/// at or above 1000 Hz, with `digits` decimal digits in both cases. This already includes the unit,
+/// so the parameter's `unit` field should be left empty. The values are parsed back by
+/// [from_f32_hz_then_khz()].
+pub fn f32_hz_then_khz(digits: usize) -> Option<Arc<dyn Fn(f32) -> String + Send + Sync>> {
+    Some(Arc::new(move |x| {
+        // Values just below 1000 Hz may still round up to 1000 Hz, and those should be displayed
+        // as kHz instead
+        let hz = format!("{:.digits$}", x);
+        if hz.parse::<f32>().map_or(false, |hz| hz.abs() >= 1000.0) {
+            format!("{:.digits$} kHz", x / 1000.0)
+        } else {
+            format!("{} Hz", hz)
+        }
+    }))
+}
+
+/// Parse a frequency formatted by [f32_hz_then_khz()] back to Hertz. This accepts both `Hz` and
+/// `kHz` suffixes in any case, with or without a space before the unit. Values without a unit are
+/// treated as Hertz.
+pub fn from_f32_hz_then_khz() -> Option<Arc<dyn Fn(&str) -> Option<f32> + Send + Sync>> {
+    Some(Arc::new(|string| {
+        let string = string.trim().to_lowercase();
+        let (value, multiplier) = match string.strip_suffix("khz") {
+            Some(value) => (value, 1000.0),
+            None => (string.strip_suffix("hz").unwrap_or(&string), 1.0),
+        };
+
+        value
+            .trim_end()
+            .parse::<f32>()
+            .ok()
+            .map(|value| value * multiplier)
+    }))
+}
+
+/// Format a linear gain value as decibels with `digits` decimal digits, like `-6.0 dB`. Gains at or
+/// below [util::MINUS_INFINITY_DB] are shown as `-inf dB`. This already includes the unit, so the
+/// parameter's `unit` field should be left empty. The values are parsed back by [from_db()].
+pub fn f32_gain_to_db(digits: usize) -> Option<Arc<dyn Fn(f32) -> String + Send + Sync>> {
+    Some(Arc::new(move |x| {
+        let dbs = util::gain_to_db(x);
+        if dbs <= util::MINUS_INFINITY_DB {
+            String::from("-inf dB")
+        } else {
+            format!("{:.digits$} dB", dbs)
+        }
+    }))
+}
+
+/// Parse a decibel value formatted by [f32_gain_to_db()] back to a linear gain value. The `dB`
+/// suffix is optional and case insensitive. `-inf` and anything at or below
+/// [util::MINUS_INFINITY_DB] result in a gain of exactly 0.0.
+pub fn from_db() -> Option<Arc<dyn Fn(&str) -> Option<f32> + Send + Sync>> {
+    Some(Arc::new(|string| {
+        let string = string.trim().to_lowercase();
+        let dbs = string.strip_suffix("db").unwrap_or(&string).trim_end();
+
+        // Rust's float parser also accepts `inf` and `NaN`, but only minus infinity makes sense
+        // here
+        match dbs.parse::<f32>() {
+            Ok(dbs) if dbs == f32::NEG_INFINITY || dbs.is_finite() => Some(util::db_to_gain(dbs)),
+            _ => None,
+        }
+    }))
+}
+
+/// Format a normalized `[0, 1]` value as a percentage with `digits` decimal digits, like `50.0 %`.
+/// This already includes the unit, so the parameter's `unit` field should be left empty. The
+/// values are parsed back by [from_f32_percentage()].
+pub fn f32_percentage(digits: usize) -> Option<Arc<dyn Fn(f32) -> String + Send + Sync>> {
+    Some(Arc::new(move |x| format!("{:.digits$} %", x * 100.0)))
+}
+
+/// Parse a percentage formatted by [f32_percentage()] back to a normalized value. The `%` sign is
+/// optional, and values outside of `[0, 100]` percent are clamped to `[0, 1]`.
+pub fn from_f32_percentage() -> Option<Arc<dyn Fn(&str) -> Option<f32> + Send + Sync>> {
+    Some(Arc::new(|string| {
+        let string = string.trim();
+        let percentage = string.strip_suffix('%').unwrap_or(string).trim_end();
+
+        match percentage.parse::<f32>() {
+            Ok(percentage) if !percentage.is_nan() => Some((percentage / 100.0).clamp(0.0, 1.0)),
+            _ => None,
+        }
+    }))
+}
+
+/// Round an `f32` value to `digits` decimal digits and always include the sign, like `+12.0` and
+/// `-12.0`. This is useful for bipolar parameters like panning and detuning, for instance with a
+/// [crate::param::range::Range::SymmetricalSkewed] range. Values that round to zero are shown as
+/// `+0.0` if `plus_sign_on_zero` is set, and as `0.0` otherwise. The values are parsed back by
+/// [from_f32_signed()].
+pub fn f32_signed(
+    digits: usize,
+    plus_sign_on_zero: bool,
+) -> Option<Arc<dyn Fn(f32) -> String + Send + Sync>> {
+    Some(Arc::new(move |x| {
+        // Small negative values would otherwise show up as `-0.0`
+        let magnitude = format!("{:.digits$}", x.abs());
+        if magnitude
+            .parse::<f32>()
+            .map_or(false, |magnitude| magnitude == 0.0)
+        {
+            if plus_sign_on_zero {
+                format!("+{}", magnitude)
+            } else {
+                magnitude
+            }
+        } else if x > 0.0 {
+            format!("+{}", magnitude)
+        } else {
+            format!("-{}", magnitude)
+        }
+    }))
+}
+
+/// Parse a value formatted by [f32_signed()]. The value may start with an explicit plus sign.
+pub fn from_f32_signed() -> Option<Arc<dyn Fn(&str) -> Option<f32> + Send + Sync>> {
+    Some(Arc::new(|string| {
+        // Rust's float parser already accepts a leading plus sign
+        string
+            .trim()
+            .parse::<f32>()
+            .ok()
+            .filter(|value| value.is_finite())
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::param::range::Range;
+    use crate::param::{FloatParam, Param};
+
+    #[test]
+    fn hz_then_khz() {
+        let to_string = f32_hz_then_khz(1).unwrap();
+        assert_eq!(to_string(440.0), "440.0 Hz");
+        assert_eq!(to_string(999.9), "999.9 Hz");
+        assert_eq!(to_string(999.96), "1.0 kHz");
+        assert_eq!(to_string(1000.0), "1.0 kHz");
+        assert_eq!(to_string(20_000.0), "20.0 kHz");
+        assert_eq!(f32_hz_then_khz(2).unwrap()(1500.0), "1.50 kHz");
+    }
+
+    #[test]
+    fn hz_then_khz_round_trip() {
+        let to_string = f32_hz_then_khz(2).unwrap();
+        let from_string = from_f32_hz_then_khz().unwrap();
+        for value in [20.0, 440.0, 999.9, 1000.0, 1500.0, 20_000.0] {
+            let parsed = from_string(&to_string(value)).unwrap();
+            assert!(
+                (parsed - value).abs() < 0.01,
+                "{} was parsed back as {}",
+                value,
+                parsed
+            );
+        }
+    }
+
+    #[test]
+    fn parse_hz_then_khz() {
+        let from_string = from_f32_hz_then_khz().unwrap();
+        assert_eq!(from_string("440 Hz"), Some(440.0));
+        assert_eq!(from_string("440hz"), Some(440.0));
+        assert_eq!(from_string(" 440 "), Some(440.0));
+        assert_eq!(from_string("1.5kHz"), Some(1500.0));
+        assert_eq!(from_string("1.5 KHZ"), Some(1500.0));
+        assert_eq!(from_string("kHz"), None);
+        assert_eq!(from_string("loud"), None);
+    }
+
+    #[test]
+    fn gain_to_db() {
+        let to_string = f32_gain_to_db(1).unwrap();
+        assert_eq!(to_string(1.0), "0.0 dB");
+        assert_eq!(to_string(0.5), "-6.0 dB");
+        assert_eq!(to_string(util::db_to_gain(-99.9)), "-99.9 dB");
+        assert_eq!(
+            to_string(util::db_to_gain(util::MINUS_INFINITY_DB)),
+            "-inf dB"
+        );
+        assert_eq!(to_string(0.000_001), "-inf dB");
+        assert_eq!(to_string(0.0), "-inf dB");
+    }
+
+    #[test]
+    fn parse_db() {
+        let from_string = from_db().unwrap();
+        assert_eq!(from_string("0 dB"), Some(1.0));
+        assert_eq!(from_string("-6dB"), Some(util::db_to_gain(-6.0)));
+        assert_eq!(from_string("-6"), Some(util::db_to_gain(-6.0)));
+        assert_eq!(from_string("-inf dB"), Some(0.0));
+        assert_eq!(from_string("-inf"), Some(0.0));
+        assert_eq!(from_string("-100 dB"), Some(0.0));
+        assert_eq!(from_string("-120"), Some(0.0));
+        assert!(from_string("-99.9 dB").unwrap() > 0.0);
+
+        assert_eq!(from_string("inf dB"), None);
+        assert_eq!(from_string("NaN"), None);
+        assert_eq!(from_string("dB"), None);
+        assert_eq!(from_string("-6 dBFS"), None);
+        assert_eq!(from_string("loud"), None);
+    }
+
+    #[test]
+    fn db_round_trip() {
+        let to_string = f32_gain_to_db(2).unwrap();
+        let from_string = from_db().unwrap();
+        assert_eq!(to_string(from_string("-inf dB").unwrap()), "-inf dB");
+        for dbs in [-99.0, -6.0, 0.0, 12.0] {
+            let gain = util::db_to_gain(dbs);
+            let parsed = from_string(&to_string(gain)).unwrap();
+            assert!((util::gain_to_db(parsed) - dbs).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn percentage() {
+        let to_string = f32_percentage(1).unwrap();
+        assert_eq!(to_string(0.5), "50.0 %");
+        assert_eq!(to_string(1.0), "100.0 %");
+
+        let from_string = from_f32_percentage().unwrap();
+        assert_eq!(from_string("50%"), Some(0.5));
+        assert_eq!(from_string("50 %"), Some(0.5));
+        assert_eq!(from_string("50"), Some(0.5));
+        assert_eq!(from_string(&to_string(0.25)), Some(0.25));
+        assert_eq!(from_string("150%"), Some(1.0));
+        assert_eq!(from_string("-10 %"), Some(0.0));
+        assert_eq!(from_string("half"), None);
+        assert_eq!(from_string("%"), None);
+    }
+
+    #[test]
+    fn percentage_param() {
+        let param = FloatParam {
+            value: 0.5,
+            range: Range::Linear { min: 0.0, max: 1.0 },
+            name: "Mix",
+            value_to_string: f32_percentage(0),
+            string_to_value: from_f32_percentage(),
+            ..Default::default()
+        };
+
+        assert_eq!(param.normalized_value_to_string(0.5, true), "50 %");
+        assert_eq!(param.string_to_normalized_value("50%"), Some(0.5));
+        assert_eq!(param.string_to_normalized_value("200 %"), Some(1.0));
+    }
+
+    #[test]
+    fn signed() {
+        let to_string = f32_signed(1, false).unwrap();
+        assert_eq!(to_string(12.0), "+12.0");
+        assert_eq!(to_string(-12.0), "-12.0");
+        assert_eq!(to_string(0.0), "0.0");
+        assert_eq!(to_string(-0.0), "0.0");
+        assert_eq!(to_string(-0.04), "0.0");
+        assert_eq!(to_string(0.06), "+0.1");
+
+        let to_string = f32_signed(1, true).unwrap();
+        assert_eq!(to_string(0.0), "+0.0");
+        assert_eq!(to_string(-0.04), "+0.0");
+        assert_eq!(to_string(-3.5), "-3.5");
+    }
+
+    #[test]
+    fn parse_signed() {
+        let from_string = from_f32_signed().unwrap();
+        assert_eq!(from_string("+3.5"), Some(3.5));
+        assert_eq!(from_string("-3.5"), Some(-3.5));
+        assert_eq!(from_string(" 3.5 "), Some(3.5));
+        assert_eq!(from_string("+0.0"), Some(0.0));
+        assert_eq!(from_string("0"), Some(0.0));
+        assert_eq!(from_string("+-3.5"), None);
+        assert_eq!(from_string("+inf"), None);
+        assert_eq!(from_string("left"), None);
+    }
+}
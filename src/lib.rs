@@ -24,18 +24,21 @@ pub mod formatters;
 pub mod util;
 
 // Re-export our derive macros to make this a bit easier to use
-pub use nih_plug_derive::Params;
+pub use nih_plug_derive::{Enum, Params};
 
 // And also re-export anything you'd need to build a plugin
-pub use buffer::Buffer;
-pub use context::{GuiContext, ParamSetter, ProcessContext};
+pub use buffer::{Block, Buffer, ControlRatePosition};
+pub use context::{
+    AutomationPoint, GuiContext, NoteEvents, ParamSetter, ProcessContext, Transport,
+    TransportRequirements,
+};
 pub use param::internals::Params;
 pub use param::range::Range;
-pub use param::smoothing::{Smoother, SmoothingStyle};
-pub use param::{BoolParam, FloatParam, IntParam, Param};
+pub use param::smoothing::{Envelope, EnvelopeStage, Smoother, SmoothingScope, SmoothingStyle};
+pub use param::{BoolParam, Enum, EnumParam, FloatParam, IntParam, MeterParam, Param};
 pub use plugin::{
-    BufferConfig, BusConfig, Editor, NoteEvent, ParentWindowHandle, Plugin, ProcessStatus,
-    Vst3Plugin,
+    BufferConfig, BusConfig, ClapPlugin, Editor, NoteEvent, ParentWindowHandle, Plugin,
+    ProcessStatus, SamplePrecision, Vst3Plugin, Vst3SubCategory,
 };
 
 // The rest is either internal or already re-exported
@@ -16,13 +16,21 @@
 
 //! TODO: Document how to use the [Param] trait. For the moment, just look at the gain example.
 
+use atomic_float::AtomicF32;
 use std::fmt::Display;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
 use self::range::{NormalizebleRange, Range};
 use self::smoothing::Smoother;
 
+pub use self::enums::{Enum, EnumParam};
+
+pub mod enums;
+pub mod file_path;
 pub mod internals;
+pub mod linked;
+pub mod midi_learn;
 pub mod range;
 pub mod smoothing;
 
@@ -59,6 +67,15 @@ pub trait Param {
     /// Get the normalized `[0, 1]` value for this parameter.
     fn normalized_value(&self) -> f32;
 
+    /// Get the normalized `[0, 1]` value for this parameter's default value. Editors can use this
+    /// to reset a parameter to its default value.
+    fn default_normalized_value(&self) -> f32;
+
+    /// Get the plain, unnormalized values at the start and at the end of this parameter's
+    /// normalized `[0, 1]` range as a `(min, max)` pair. Editors can use this to draw the
+    /// parameter's range.
+    fn normalized_range(&self) -> (Self::Plain, Self::Plain);
+
     /// Set this parameter based on a normalized value.
     ///
     /// This does **not** update the smoother.
@@ -86,13 +103,23 @@ pub trait Param {
 }
 
 /// A numerical parameter that's stored unnormalized. The range is used for the normalization
-/// process.
+/// process. For values that only the plugin should be able to change, like a gain reduction
+/// reading the host can display but not automate, use a [MeterParam] instead.
 pub struct PlainParam<T> {
     /// The field's current plain, unnormalized value. Should be initialized with the default value.
     /// Storing parameter values like this instead of in a single contiguous array is bad for cache
     /// locality, but it does allow for a much nicer declarative API.
     pub value: T,
+    /// The parameter's default plain, unnormalized value. If this is left at `None`, then the
+    /// initial `value` is used as the default.
+    pub default: Option<T>,
+    /// Smooths changes to `value`. The smoother only ever sees the parameter's base value, the
+    /// modulation offset from `modulate()` is applied on top of the smoothed value with
+    /// `modulated()`.
     pub smoothed: Smoother<T>,
+    /// The parameter's current modulation offset in the normalized `[0, 1]` range, set with
+    /// `modulate()`. This should be initialized with zero.
+    pub modulation: AtomicF32,
     /// Optional callback for listening to value changes. The argument passed to this function is
     /// the parameter's new **plain** value. This should not do anything expensive as it may be
     /// called multiple times in rapid succession.
@@ -103,11 +130,29 @@ pub struct PlainParam<T> {
 
     /// The distribution of the parameter's values.
     pub range: Range<T>,
+    /// Optional step size for quantizing the parameter's values. When this is set, the parameter
+    /// can only take on values of `min + n * step_size` within its range, and hosts will snap
+    /// automation for this parameter to those values. For instance, `Some(0.5)` on a decibel gain
+    /// parameter results in 0.5 dB steps. If the range is not a multiple of the step size, then it
+    /// is divided into slightly smaller equal steps instead so the values match the steps the host
+    /// shows. This needs to be positive. With a skewed range the
+    /// values are still snapped, but the parameter is reported to the host as continuous since the
+    /// host would otherwise place the steps at equal distances in the normalized range.
+    pub step_size: Option<f32>,
+    /// Optionally quantize the parameter to `step_count + 1` evenly spaced positions in the
+    /// normalized range, like the notches on a 12-position knob with a `step_count` of 11. Unlike
+    /// `step_size`, these positions follow the range's skew, so the value can still be displayed
+    /// as a regular float. The host is informed about the steps so it can snap to them, and the
+    /// smoother still interpolates between the snapped values. This should not be combined with
+    /// `step_size`.
+    pub step_count: Option<usize>,
     /// The parameter's human readable display name.
     pub name: &'static str,
     /// The parameter value's unit, added after `value_to_string` if that is set.
     pub unit: &'static str,
-    /// Optional custom conversion function from a plain **unnormalized** value to a string.
+    /// Optional custom conversion function from a plain **unnormalized** value to a string. If an
+    /// integer parameter with at most 128 values has one of these, then the parameter is
+    /// reported to the host as a list of those strings, and hosts may show a dropdown for it.
     pub value_to_string: Option<Arc<dyn Fn(T) -> String + Send + Sync>>,
     /// Optional custom conversion function from a string to a plain **unnormalized** value. If the
     /// string cannot be parsed, then this should return a `None`. If this happens while the
@@ -119,6 +164,9 @@ pub struct PlainParam<T> {
 pub struct BoolParam {
     /// The field's current, normalized value. Should be initialized with the default value.
     pub value: bool,
+    /// The parameter's default value. If this is left at `None`, then the initial `value` is used
+    /// as the default.
+    pub default: Option<bool>,
 
     /// Optional callback for listening to value changes. The argument passed to this function is
     /// the parameter's new value. This should not do anything expensive as it may be called
@@ -135,6 +183,26 @@ pub struct BoolParam {
     pub string_to_value: Option<Arc<dyn Fn(&str) -> Option<bool> + Send + Sync>>,
 }
 
+/// A read-only parameter for reporting values from the plugin back to the host, like meter
+/// readings. The plugin updates the value from its process function using [Self::set()], and the
+/// wrapper then sends the new value to the host after every processing cycle. That way the value
+/// shows up in the host's automation lanes and generic UIs as a live meter. The host cannot change
+/// this parameter, and it is not stored as part of the plugin's state.
+pub struct MeterParam {
+    /// The meter's current plain, unnormalized value. This is stored as an atomic so it can be
+    /// updated from the process function. Use [Self::set()] and [Self::value()] to access it.
+    pub value: AtomicF32,
+
+    /// The distribution of the meter's values.
+    pub range: Range<f32>,
+    /// The parameter's human readable display name.
+    pub name: &'static str,
+    /// The parameter value's unit, added after `value_to_string` if that is set.
+    pub unit: &'static str,
+    /// Optional custom conversion function from a plain **unnormalized** value to a string.
+    pub value_to_string: Option<Arc<dyn Fn(f32) -> String + Send + Sync>>,
+}
+
 impl<T> Default for PlainParam<T>
 where
     T: Default,
@@ -143,9 +211,13 @@ where
     fn default() -> Self {
         Self {
             value: T::default(),
+            default: None,
             smoothed: Smoother::none(),
+            modulation: AtomicF32::new(0.0),
             value_changed: None,
             range: Range::default(),
+            step_size: None,
+            step_count: None,
             name: "",
             unit: "",
             value_to_string: None,
@@ -159,6 +231,7 @@ impl Default for BoolParam {
     fn default() -> Self {
         Self {
             value: false,
+            default: None,
             value_changed: None,
             name: "",
             value_to_string: None,
@@ -167,8 +240,104 @@ impl Default for BoolParam {
     }
 }
 
+impl Default for MeterParam {
+    fn default() -> Self {
+        Self {
+            value: AtomicF32::new(0.0),
+            range: Range::default(),
+            name: "",
+            unit: "",
+            value_to_string: None,
+        }
+    }
+}
+
+impl MeterParam {
+    /// Update the meter's value. This value will be clamped to the meter's range. Can safely be
+    /// called from the process function.
+    pub fn set(&self, plain: f32) {
+        let (min, max) = match self.range {
+            Range::Linear { min, max } => (min, max),
+            Range::Skewed { min, max, .. } => (min, max),
+            Range::SymmetricalSkewed { min, max, .. } => (min, max),
+        };
+
+        self.value.store(plain.clamp(min, max), Ordering::Relaxed);
+    }
+
+    /// Get the meter's current plain, unnormalized value.
+    pub fn value(&self) -> f32 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
 macro_rules! impl_plainparam {
     ($ty:ident, $plain:ty) => {
+        impl $ty {
+            /// Snap a plain value to this parameter's step size, if it has one. Step sizes that
+            /// aren't positive are ignored.
+            fn snap_to_step(&self, plain: $plain) -> $plain {
+                match self.step_size {
+                    Some(step_size) if step_size > 0.0 => self.range.snap_to_step(plain, step_size),
+                    Some(_) => {
+                        nih_debug_assert_failure!("Step sizes need to be positive");
+                        plain
+                    }
+                    None => plain,
+                }
+            }
+
+            /// Snap a normalized value to the nearest of this parameter's `step_count` steps, if
+            /// it has them.
+            fn snap_normalized_to_step(&self, normalized: f32) -> f32 {
+                match self.step_count {
+                    Some(step_count) if step_count > 0 => {
+                        let step_count = step_count as f32;
+                        ((normalized * step_count).round() / step_count).clamp(0.0, 1.0)
+                    }
+                    _ => normalized,
+                }
+            }
+
+            /// Modulate the parameter by offsetting its normalized value, for instance from an LFO
+            /// or a modulation matrix in the plugin's process function. This replaces the previous
+            /// offset instead of adding to it, and setting it to zero disables the modulation
+            /// again. The modulation is only applied when the plugin uses [Self::modulated()] or
+            /// [Self::modulated_plain_value()]. The parameter's value, its smoother, and the value
+            /// reported to the host are not affected, so host automation keeps working as usual.
+            /// This is wait-free, so it's safe to call from the audio thread.
+            pub fn modulate(&self, normalized_offset: f32) {
+                self.modulation.store(normalized_offset, Ordering::Relaxed);
+            }
+
+            /// The parameter's current modulation offset, as set by [Self::modulate()].
+            pub fn modulation(&self) -> f32 {
+                self.modulation.load(Ordering::Relaxed)
+            }
+
+            /// Apply the current modulation offset to a plain value. The offset is added to the
+            /// value's normalized value, and the result is clamped to the parameter's range and
+            /// snapped to its steps. Modulating along the normalized range means that modulation
+            /// follows the range's skew, just like host automation does. A plain value without any
+            /// modulation is returned as is. To modulate a smoothed parameter, pass the smoother's
+            /// value to this function: `param.modulated(param.smoothed.next())`. The smoother then
+            /// only smooths the base value, and the modulation is applied to that directly.
+            pub fn modulated(&self, plain: $plain) -> $plain {
+                let offset = self.modulation();
+                if offset == 0.0 {
+                    return plain;
+                }
+
+                self.preview_plain((self.range.normalize(plain) + offset).clamp(0.0, 1.0))
+            }
+
+            /// The parameter's current unsmoothed value with the modulation offset applied. This
+            /// is the same as `self.modulated(self.plain_value())`.
+            pub fn modulated_plain_value(&self) -> $plain {
+                self.modulated(self.value)
+            }
+        }
+
         impl Param for $ty {
             type Plain = $plain;
 
@@ -208,15 +377,25 @@ macro_rules! impl_plainparam {
             }
 
             fn normalized_value(&self) -> f32 {
-                self.range.normalize(self.value)
+                // Skewed ranges don't always round trip exactly, but stepped parameters should
+                // still report values that are exactly on a step
+                self.snap_normalized_to_step(self.range.normalize(self.value))
+            }
+
+            fn default_normalized_value(&self) -> f32 {
+                self.range.normalize(self.default.unwrap_or(self.value))
+            }
+
+            fn normalized_range(&self) -> (Self::Plain, Self::Plain) {
+                (self.preview_plain(0.0), self.preview_plain(1.0))
             }
 
             fn set_normalized_value(&mut self, normalized: f32) {
-                self.set_plain_value(self.range.unnormalize(normalized));
+                self.set_plain_value(self.preview_plain(normalized));
             }
 
             fn normalized_value_to_string(&self, normalized: f32, include_unit: bool) -> String {
-                let value = self.range.unnormalize(normalized);
+                let value = self.preview_plain(normalized);
                 match (&self.value_to_string, include_unit) {
                     (Some(f), true) => format!("{}{}", f(value), self.unit),
                     (Some(f), false) => format!("{}", f(value)),
@@ -232,15 +411,18 @@ macro_rules! impl_plainparam {
                     None => string.parse().ok(),
                 }?;
 
-                Some(self.range.normalize(value))
+                Some(self.preview_normalized(value))
             }
 
             fn preview_normalized(&self, plain: Self::Plain) -> f32 {
-                self.range.normalize(plain)
+                self.snap_normalized_to_step(self.range.normalize(self.snap_to_step(plain)))
             }
 
             fn preview_plain(&self, normalized: f32) -> Self::Plain {
-                self.range.unnormalize(normalized)
+                self.snap_to_step(
+                    self.range
+                        .unnormalize(self.snap_normalized_to_step(normalized)),
+                )
             }
 
             fn as_ptr(&self) -> internals::ParamPtr {
@@ -294,6 +476,18 @@ impl Param for BoolParam {
         }
     }
 
+    fn default_normalized_value(&self) -> f32 {
+        if self.default.unwrap_or(self.value) {
+            1.0
+        } else {
+            0.0
+        }
+    }
+
+    fn normalized_range(&self) -> (Self::Plain, Self::Plain) {
+        (false, true)
+    }
+
     fn set_normalized_value(&mut self, normalized: f32) {
         self.set_plain_value(normalized > 0.5);
     }
@@ -333,6 +527,72 @@ impl Param for BoolParam {
     }
 }
 
+impl Param for MeterParam {
+    type Plain = f32;
+
+    fn update_smoother(&mut self, _sample_rate: f32, _reset: bool) {
+        // Meters are set directly by the plugin, so there is nothing to smooth
+    }
+
+    fn set_from_string(&mut self, _string: &str) -> bool {
+        // The host cannot change a meter's value
+        false
+    }
+
+    fn plain_value(&self) -> Self::Plain {
+        self.value()
+    }
+
+    fn set_plain_value(&mut self, _plain: Self::Plain) {
+        // The host cannot change a meter's value, use `set()` instead
+    }
+
+    fn normalized_value(&self) -> f32 {
+        self.range.normalize(self.value())
+    }
+
+    fn default_normalized_value(&self) -> f32 {
+        // Meters start out at the bottom of their range
+        0.0
+    }
+
+    fn normalized_range(&self) -> (Self::Plain, Self::Plain) {
+        (self.preview_plain(0.0), self.preview_plain(1.0))
+    }
+
+    fn set_normalized_value(&mut self, _normalized: f32) {
+        // The host cannot change a meter's value
+    }
+
+    fn normalized_value_to_string(&self, normalized: f32, include_unit: bool) -> String {
+        let value = self.preview_plain(normalized);
+        match (&self.value_to_string, include_unit) {
+            (Some(f), true) => format!("{}{}", f(value), self.unit),
+            (Some(f), false) => f(value),
+            (None, true) => format!("{}{}", value, self.unit),
+            (None, false) => format!("{}", value),
+        }
+    }
+
+    fn string_to_normalized_value(&self, string: &str) -> Option<f32> {
+        let value: f32 = string.parse().ok()?;
+
+        Some(self.preview_normalized(value))
+    }
+
+    fn preview_normalized(&self, plain: Self::Plain) -> f32 {
+        self.range.normalize(plain)
+    }
+
+    fn preview_plain(&self, normalized: f32) -> Self::Plain {
+        self.range.unnormalize(normalized)
+    }
+
+    fn as_ptr(&self) -> internals::ParamPtr {
+        internals::ParamPtr::MeterParam(self as *const MeterParam as *mut MeterParam)
+    }
+}
+
 impl<T: Display + Copy> Display for PlainParam<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match &self.value_to_string {
@@ -351,3 +611,202 @@ impl Display for BoolParam {
         }
     }
 }
+
+impl Display for MeterParam {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.value_to_string {
+            Some(func) => write!(f, "{}{}", func(self.value()), self.unit),
+            None => write!(f, "{}{}", self.value(), self.unit),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::param::smoothing::SmoothingStyle;
+
+    fn stepped_float_param() -> FloatParam {
+        FloatParam {
+            value: 1.0,
+            default: Some(1.0),
+            range: Range::Skewed {
+                min: 1.0,
+                max: 20.0,
+                factor: Range::skew_factor(-1.0),
+            },
+            step_count: Some(11),
+            name: "Drive",
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn step_count_normalization_lands_on_steps() {
+        let param = stepped_float_param();
+        for i in 0..=100 {
+            let normalized = i as f32 / 100.0;
+            let expected = (normalized * 11.0).round() / 11.0;
+
+            assert_eq!(
+                param.preview_normalized(param.preview_plain(normalized)),
+                expected
+            );
+        }
+
+        assert_eq!(param.preview_plain(0.0), 1.0);
+        assert_eq!(param.preview_plain(1.0), 20.0);
+        assert_eq!(unsafe { param.as_ptr().step_count() }, 11);
+    }
+
+    #[test]
+    fn skewed_int_params_are_continuous() {
+        let param = IntParam {
+            value: 1,
+            default: Some(1),
+            range: Range::Skewed {
+                min: 1,
+                max: 64,
+                factor: Range::skew_factor(-1.0),
+            },
+            name: "Voices",
+            ..Default::default()
+        };
+
+        // The host would otherwise snap automation to linearly spaced steps
+        assert_eq!(unsafe { param.as_ptr().step_count() }, 0);
+        assert_eq!(param.preview_normalized(1), 0.0);
+        assert_eq!(param.preview_normalized(64), 1.0);
+        for plain in 1..=64 {
+            assert_eq!(param.preview_plain(param.preview_normalized(plain)), plain);
+        }
+    }
+
+    #[test]
+    fn step_sizes_snap_and_report_steps() {
+        let mut param = FloatParam {
+            value: 0.0,
+            range: Range::Linear {
+                min: -30.0,
+                max: 30.0,
+            },
+            step_size: Some(0.5),
+            name: "Gain",
+            ..Default::default()
+        };
+
+        assert_eq!(param.preview_plain(0.5 + 0.2 / 60.0), 0.0);
+        assert_eq!(unsafe { param.as_ptr().step_count() }, 120);
+
+        // With a skewed range the values are still snapped, but the host's linearly spaced steps
+        // would not line up with them
+        param.range = Range::Skewed {
+            min: -30.0,
+            max: 30.0,
+            factor: Range::skew_factor(-1.0),
+        };
+        assert_eq!(param.preview_plain(param.preview_normalized(1.2)), 1.0);
+        assert_eq!(unsafe { param.as_ptr().step_count() }, 0);
+
+        // Step sizes that aren't positive are ignored instead of dividing by zero
+        param.range = Range::Linear {
+            min: -30.0,
+            max: 30.0,
+        };
+        param.step_size = Some(0.0);
+        assert!((param.preview_plain(0.5 + 0.2 / 60.0) - 0.2).abs() < 1e-4);
+        assert_eq!(unsafe { param.as_ptr().step_count() }, 0);
+    }
+
+    #[test]
+    fn modulation_is_not_reported_to_host() {
+        let mut param = FloatParam {
+            value: 0.0,
+            smoothed: Smoother::new(SmoothingStyle::Linear(10.0)),
+            range: Range::Linear {
+                min: -10.0,
+                max: 10.0,
+            },
+            name: "Cutoff",
+            ..Default::default()
+        };
+        param.update_smoother(1000.0, true);
+
+        param.modulate(0.25);
+        assert_eq!(param.modulation(), 0.25);
+        assert_eq!(param.modulated_plain_value(), 5.0);
+        assert_eq!(param.plain_value(), 0.0);
+        assert_eq!(param.normalized_value(), 0.5);
+        assert_eq!(unsafe { param.as_ptr().normalized_value() }, 0.5);
+
+        // The modulation is applied on top of the smoothed base value and it's clamped to the
+        // parameter's range
+        param.set_plain_value(10.0);
+        param.update_smoother(1000.0, false);
+        let smoothed = param.smoothed.next();
+        assert_eq!(smoothed, 1.0);
+        assert_eq!(param.modulated(smoothed), 6.0);
+        assert_eq!(param.modulated_plain_value(), 10.0);
+        assert_eq!(param.normalized_value(), 1.0);
+
+        param.modulate(-2.0);
+        assert_eq!(param.modulated(smoothed), -10.0);
+
+        param.modulate(0.0);
+        assert_eq!(param.modulated(smoothed), smoothed);
+    }
+
+    #[test]
+    fn int_param_modulation_is_rounded() {
+        let param = IntParam {
+            value: 2,
+            range: Range::Linear { min: 0, max: 10 },
+            name: "Octave",
+            ..Default::default()
+        };
+
+        param.modulate(0.26);
+        assert_eq!(param.modulated_plain_value(), 5);
+        assert_eq!(param.plain_value(), 2);
+        assert_eq!(param.normalized_value(), 0.2);
+    }
+
+    #[test]
+    fn initial_value_is_default() {
+        let mut param = IntParam {
+            value: 8,
+            range: Range::Linear { min: 0, max: 10 },
+            name: "Taps",
+            ..Default::default()
+        };
+        let mut bool_param = BoolParam {
+            value: true,
+            name: "Enabled",
+            ..Default::default()
+        };
+        assert_eq!(param.default_normalized_value(), 0.8);
+        assert_eq!(bool_param.default_normalized_value(), 1.0);
+
+        // Once captured, the default no longer follows the parameter's value
+        unsafe {
+            param.as_ptr().capture_default();
+            bool_param.as_ptr().capture_default();
+        }
+        param.set_plain_value(2);
+        bool_param.set_plain_value(false);
+        assert_eq!(param.default, Some(8));
+        assert_eq!(param.default_normalized_value(), 0.8);
+        assert_eq!(bool_param.default_normalized_value(), 1.0);
+    }
+
+    #[test]
+    fn step_count_set_normalized_value() {
+        let mut param = stepped_float_param();
+        for step in 0..=11 {
+            // Anything close to a step should snap to exactly that step
+            param.set_normalized_value(step as f32 / 11.0 + 0.02);
+            assert_eq!(param.normalized_value(), step as f32 / 11.0);
+            assert_eq!(param.plain_value(), param.preview_plain(step as f32 / 11.0));
+        }
+    }
+}
@@ -0,0 +1,327 @@
+// nih-plug: plugins, but rewritten in Rust
+// Copyright (C) 2022 Robbert van der Helm
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Parameters backed by Rust enums.
+
+use std::fmt::Display;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use super::internals::ParamPtr;
+use super::range::Range;
+use super::{IntParam, Param};
+
+/// An enum usable with [EnumParam]. This can be derived for enums with only unit variants using
+/// `#[derive(Enum)]`. The variants' display names default to their identifiers, and they can be
+/// changed with the `#[name = "..."]` attribute.
+pub trait Enum {
+    /// The display names for all of the enum's variants, in declaration order.
+    fn variants() -> &'static [&'static str];
+
+    /// The index of this variant in [Self::variants()].
+    fn to_index(self) -> usize;
+
+    /// The variant at `index` in [Self::variants()]. Out of range indices should map to the last
+    /// variant.
+    fn from_index(index: usize) -> Self;
+}
+
+/// A discrete parameter that can take on any of the variants of the enum `T`. Hosts display this
+/// parameter as a list of the variants' names. The parameter is stored as the index of the
+/// selected variant, so reordering the enum's variants will break existing presets and
+/// automation.
+pub struct EnumParam<T: Enum> {
+    /// The type-erased parameter the wrappers interact with.
+    inner: EnumParamInner,
+    _marker: PhantomData<T>,
+}
+
+/// The type-erased part of an [EnumParam]. This is what [ParamPtr::EnumParam] points to, since the
+/// wrappers don't know about the enum type.
+pub struct EnumParamInner {
+    /// The variant's index, with a value to string conversion that yields the variants' names.
+    pub(crate) inner: IntParam,
+    /// The display names for all of the enum's variants.
+    pub(crate) variants: &'static [&'static str],
+}
+
+impl<T: Enum + 'static> EnumParam<T> {
+    /// Create a new parameter set to the `default` variant.
+    pub fn new(name: &'static str, default: T) -> Self {
+        let variants = T::variants();
+        nih_debug_assert!(!variants.is_empty());
+
+        let default = default.to_index() as i32;
+        Self {
+            inner: EnumParamInner {
+                inner: IntParam {
+                    value: default,
+                    default: Some(default),
+                    range: Range::Linear {
+                        min: 0,
+                        max: variants.len() as i32 - 1,
+                    },
+                    name,
+                    value_to_string: Some(Arc::new(move |index| {
+                        String::from(variant_name(variants, index))
+                    })),
+                    string_to_value: Some(Arc::new(move |string| {
+                        variants
+                            .iter()
+                            .position(|variant| variant.eq_ignore_ascii_case(string.trim()))
+                            .map(|index| index as i32)
+                    })),
+                    ..Default::default()
+                },
+                variants,
+            },
+            _marker: PhantomData,
+        }
+    }
+
+    /// Run a callback whenever this parameter's value changes. The argument passed to this
+    /// function is the parameter's new variant. This should not do anything expensive as it may
+    /// be called multiple times in rapid succession.
+    pub fn with_callback(mut self, callback: Arc<dyn Fn(T) + Send + Sync>) -> Self {
+        self.inner.inner.value_changed = Some(Arc::new(move |index| {
+            callback(T::from_index(index as usize))
+        }));
+        self
+    }
+
+    /// The currently selected variant.
+    pub fn value(&self) -> T {
+        T::from_index(self.inner.inner.value as usize)
+    }
+}
+
+impl<T: Enum + 'static> Param for EnumParam<T> {
+    type Plain = T;
+
+    fn update_smoother(&mut self, sample_rate: f32, reset: bool) {
+        self.inner.update_smoother(sample_rate, reset)
+    }
+
+    fn set_from_string(&mut self, string: &str) -> bool {
+        self.inner.set_from_string(string)
+    }
+
+    fn plain_value(&self) -> Self::Plain {
+        self.value()
+    }
+
+    fn set_plain_value(&mut self, plain: Self::Plain) {
+        self.inner.set_plain_value(plain.to_index() as i32)
+    }
+
+    fn normalized_value(&self) -> f32 {
+        self.inner.normalized_value()
+    }
+
+    fn default_normalized_value(&self) -> f32 {
+        self.inner.default_normalized_value()
+    }
+
+    fn normalized_range(&self) -> (Self::Plain, Self::Plain) {
+        (self.preview_plain(0.0), self.preview_plain(1.0))
+    }
+
+    fn set_normalized_value(&mut self, normalized: f32) {
+        self.inner.set_normalized_value(normalized)
+    }
+
+    fn normalized_value_to_string(&self, normalized: f32, include_unit: bool) -> String {
+        self.inner
+            .normalized_value_to_string(normalized, include_unit)
+    }
+
+    fn string_to_normalized_value(&self, string: &str) -> Option<f32> {
+        self.inner.string_to_normalized_value(string)
+    }
+
+    fn preview_normalized(&self, plain: Self::Plain) -> f32 {
+        self.inner.preview_normalized(plain.to_index() as i32)
+    }
+
+    fn preview_plain(&self, normalized: f32) -> Self::Plain {
+        T::from_index(self.inner.preview_plain(normalized) as usize)
+    }
+
+    fn as_ptr(&self) -> ParamPtr {
+        self.inner.as_ptr()
+    }
+}
+
+impl EnumParamInner {
+    /// Clamp a variant index to the enum's variants.
+    fn clamp_index(&self, index: i32) -> i32 {
+        index.clamp(0, self.variants.len() as i32 - 1)
+    }
+}
+
+/// The name of the variant at `index`, with out of range indices mapping to the closest variant.
+fn variant_name(variants: &'static [&'static str], index: i32) -> &'static str {
+    variants[index.clamp(0, variants.len() as i32 - 1) as usize]
+}
+
+impl Param for EnumParamInner {
+    type Plain = i32;
+
+    fn update_smoother(&mut self, _sample_rate: f32, _reset: bool) {
+        // Switching between variants is never smoothed
+    }
+
+    fn set_from_string(&mut self, string: &str) -> bool {
+        self.inner.set_from_string(string)
+    }
+
+    fn plain_value(&self) -> Self::Plain {
+        self.inner.plain_value()
+    }
+
+    fn set_plain_value(&mut self, plain: Self::Plain) {
+        self.inner.set_plain_value(self.clamp_index(plain))
+    }
+
+    fn normalized_value(&self) -> f32 {
+        self.preview_normalized(self.inner.value)
+    }
+
+    fn default_normalized_value(&self) -> f32 {
+        self.preview_normalized(self.inner.default.unwrap_or(self.inner.value))
+    }
+
+    fn normalized_range(&self) -> (Self::Plain, Self::Plain) {
+        self.inner.normalized_range()
+    }
+
+    fn set_normalized_value(&mut self, normalized: f32) {
+        self.set_plain_value(self.preview_plain(normalized))
+    }
+
+    fn normalized_value_to_string(&self, normalized: f32, include_unit: bool) -> String {
+        self.inner
+            .normalized_value_to_string(normalized, include_unit)
+    }
+
+    fn string_to_normalized_value(&self, string: &str) -> Option<f32> {
+        let index = (self.inner.string_to_value.as_ref()?)(string)?;
+
+        Some(self.preview_normalized(index))
+    }
+
+    fn preview_normalized(&self, plain: Self::Plain) -> f32 {
+        // With a single variant the range is empty, and normalizing would divide by zero
+        if self.variants.len() == 1 {
+            0.0
+        } else {
+            self.inner.preview_normalized(self.clamp_index(plain))
+        }
+    }
+
+    fn preview_plain(&self, normalized: f32) -> Self::Plain {
+        if self.variants.len() == 1 {
+            0
+        } else {
+            self.inner.preview_plain(normalized)
+        }
+    }
+
+    fn as_ptr(&self) -> ParamPtr {
+        ParamPtr::EnumParam(self as *const EnumParamInner as *mut EnumParamInner)
+    }
+}
+
+impl<T: Enum> Display for EnumParam<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            variant_name(self.inner.variants, self.inner.inner.value)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum FilterType {
+        LowPass,
+        HighPass,
+        BandPass,
+    }
+
+    // The derive macro can't be used from within this crate
+    impl Enum for FilterType {
+        fn variants() -> &'static [&'static str] {
+            &["Low-pass", "High-pass", "Band-pass"]
+        }
+
+        fn to_index(self) -> usize {
+            match self {
+                FilterType::LowPass => 0,
+                FilterType::HighPass => 1,
+                FilterType::BandPass => 2,
+            }
+        }
+
+        fn from_index(index: usize) -> Self {
+            match index {
+                0 => FilterType::LowPass,
+                1 => FilterType::HighPass,
+                _ => FilterType::BandPass,
+            }
+        }
+    }
+
+    #[test]
+    fn string_round_trip() {
+        let param = EnumParam::new("Filter Type", FilterType::HighPass);
+        assert_eq!(param.value(), FilterType::HighPass);
+        assert_eq!(param.to_string(), "High-pass");
+
+        for (index, &name) in FilterType::variants().iter().enumerate() {
+            let normalized = param.string_to_normalized_value(name).unwrap();
+            assert_eq!(normalized, index as f32 / 2.0);
+            assert_eq!(param.normalized_value_to_string(normalized, true), name);
+            assert_eq!(
+                param.preview_plain(normalized),
+                FilterType::from_index(index)
+            );
+        }
+
+        assert_eq!(param.string_to_normalized_value("band-pass"), Some(1.0));
+        assert_eq!(param.string_to_normalized_value("Notch"), None);
+    }
+
+    #[test]
+    fn reported_as_list() {
+        let mut param = EnumParam::new("Filter Type", FilterType::LowPass);
+        let param_ptr = param.as_ptr();
+        unsafe {
+            assert_eq!(param_ptr.step_count(), 2);
+            assert_eq!(
+                param_ptr.normalized_value_to_string(1.0, false),
+                "Band-pass"
+            );
+        }
+
+        param.set_normalized_value(0.5);
+        assert_eq!(param.value(), FilterType::HighPass);
+    }
+}
@@ -0,0 +1,202 @@
+// nih-plug: plugins, but rewritten in Rust
+// Copyright (C) 2022 Robbert van der Helm
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! A persistable reference to a file that can be found again after a project has been moved to
+//! another machine.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// A reference to a file on disk, like a sampler's sample file. Absolute paths tend to break when
+/// a project is opened on another machine, so this also stores the path relative to a root
+/// directory, like the user's sample library, for when the file is selected. When the absolute
+/// path no longer exists, [Self::resolve()] looks for the file in other root directories. Store
+/// this in a persistent field on your `Params` struct:
+///
+/// ```ignore
+/// #[persist = "sample_path"]
+/// sample_path: RwLock<Option<FilePath>>,
+/// ```
+///
+/// After the plugin's state has been restored the wrapper calls
+/// [crate::plugin::Plugin::state_restored()], which is a good place to call [Self::resolve()]. If
+/// the file still cannot be found, then [Self::is_missing()] will return `true` and the editor can
+/// ask the user to locate the file so it can be updated using [Self::relocate()].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FilePath {
+    /// The absolute path to the file.
+    path: PathBuf,
+    /// The path relative to the root directory the file was in when it was selected, if it was in
+    /// one of the root directories passed to [Self::new()].
+    #[serde(default)]
+    relative_path: Option<PathBuf>,
+    /// Set by [Self::resolve()] if the file could not be found. This is not persisted.
+    #[serde(skip)]
+    missing: bool,
+}
+
+impl FilePath {
+    /// Refer to the file at `path`. If this file is located inside of any of the `roots`, then the
+    /// path relative to the first of these roots is also stored so the file can be found again if
+    /// those roots move.
+    pub fn new(path: impl Into<PathBuf>, roots: &[&Path]) -> Self {
+        let path = path.into();
+        let relative_path = roots
+            .iter()
+            .find_map(|root| path.strip_prefix(root).ok())
+            .map(Path::to_path_buf);
+
+        Self {
+            path,
+            relative_path,
+            missing: false,
+        }
+    }
+
+    /// The absolute path to the file. This may not exist if [Self::is_missing()] returns `true`.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The path to the file relative to the root directory that contained it, if any.
+    pub fn relative_path(&self) -> Option<&Path> {
+        self.relative_path.as_deref()
+    }
+
+    /// Whether the last call to [Self::resolve()] could not find the file.
+    pub fn is_missing(&self) -> bool {
+        self.missing
+    }
+
+    /// Try to find the file if it no longer exists at its stored path. This first checks the stored
+    /// absolute path, then the relative path within each of the `roots`, and finally the file's
+    /// name within each of the `roots`. If the file is found, then the stored path is updated to
+    /// point to it. Returns `false` and marks the file as missing if it could not be found.
+    pub fn resolve(&mut self, roots: &[&Path]) -> bool {
+        let mut candidates = self
+            .relative_path
+            .iter()
+            .flat_map(|relative_path| roots.iter().map(move |root| root.join(relative_path)))
+            .chain(
+                self.path
+                    .file_name()
+                    .into_iter()
+                    .flat_map(|file_name| roots.iter().map(move |root| root.join(file_name))),
+            );
+
+        if self.path.is_file() {
+            self.missing = false;
+            return true;
+        }
+
+        match candidates.find(|candidate| candidate.is_file()) {
+            Some(path) => {
+                nih_log!(
+                    "Relocated '{}' to '{}'",
+                    self.path.display(),
+                    path.display()
+                );
+                self.path = path;
+                self.missing = false;
+
+                true
+            }
+            None => {
+                self.missing = true;
+
+                false
+            }
+        }
+    }
+
+    /// Point this reference to a new location for the file, for instance after the user has
+    /// located a missing file. The relative path is recomputed using `roots` like in
+    /// [Self::new()].
+    pub fn relocate(&mut self, path: impl Into<PathBuf>, roots: &[&Path]) {
+        *self = Self::new(path, roots);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Create an empty directory in the system's temporary directory for a test.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "nih-plug-file-path-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    #[test]
+    fn new_stores_relative_path() {
+        let file_path = FilePath::new("/samples/drums/kick.wav", &[Path::new("/samples")]);
+        assert_eq!(file_path.path(), Path::new("/samples/drums/kick.wav"));
+        assert_eq!(file_path.relative_path(), Some(Path::new("drums/kick.wav")));
+
+        let file_path = FilePath::new("/elsewhere/kick.wav", &[Path::new("/samples")]);
+        assert_eq!(file_path.relative_path(), None);
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let file_path = FilePath::new("/samples/drums/kick.wav", &[Path::new("/samples")]);
+        let serialized = serde_json::to_string(&file_path).unwrap();
+        let deserialized: FilePath = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized, file_path);
+    }
+
+    #[test]
+    fn resolve_relocated_root() {
+        let old_root = temp_dir("old-root");
+        let new_root = temp_dir("new-root");
+        fs::create_dir_all(new_root.join("drums")).unwrap();
+        fs::write(new_root.join("drums/kick.wav"), b"").unwrap();
+
+        // The file was selected on another machine where the root directory was somewhere else
+        let mut file_path = FilePath::new(old_root.join("drums/kick.wav"), &[&old_root]);
+        assert!(file_path.resolve(&[&new_root]));
+        assert!(!file_path.is_missing());
+        assert_eq!(file_path.path(), new_root.join("drums/kick.wav"));
+
+        let _ = fs::remove_dir_all(&old_root);
+        let _ = fs::remove_dir_all(&new_root);
+    }
+
+    #[test]
+    fn resolve_missing() {
+        let root = temp_dir("missing");
+
+        let mut file_path = FilePath::new(root.join("kick.wav"), &[&root]);
+        assert!(!file_path.resolve(&[&root]));
+        assert!(file_path.is_missing());
+
+        // The user can then locate the file manually
+        fs::write(root.join("other-kick.wav"), b"").unwrap();
+        file_path.relocate(root.join("other-kick.wav"), &[&root]);
+        assert!(file_path.resolve(&[&root]));
+        assert_eq!(file_path.path(), root.join("other-kick.wav"));
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -16,15 +16,55 @@
 
 //! Implementation details for the parameter management.
 
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use std::collections::HashMap;
+use std::error::Error;
 use std::pin::Pin;
 
+use super::range::{NormalizebleRange, Range};
+use super::smoothing::SmoothingScope;
 use super::Param;
 
-/// Re-export for use in the [Params] proc-macro.
-pub use serde_json::from_str as deserialize_field;
-/// Re-export for use in the [Params] proc-macro.
-pub use serde_json::to_string as serialize_field;
+/// The format a plugin's state and its persistent fields are stored in. Plugins choose this with
+/// [crate::plugin::Plugin::BINARY_STATE]. States in either format can always be loaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateFormat {
+    /// Human readable JSON. The persistent fields are stored as JSON strings.
+    Json,
+    /// A compact binary representation using bincode. The persistent fields are stored as raw
+    /// bincode bytes within the state.
+    Binary,
+}
+
+impl Default for StateFormat {
+    fn default() -> Self {
+        StateFormat::Json
+    }
+}
+
+/// Serialize a persistent field in the given format for use in the [Params] proc-macro.
+pub fn serialize_field<T: Serialize + ?Sized>(
+    value: &T,
+    format: StateFormat,
+) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    match format {
+        StateFormat::Json => Ok(serde_json::to_vec(value)?),
+        StateFormat::Binary => Ok(bincode::serialize(value)?),
+    }
+}
+
+/// Deserialize a persistent field serialized with [serialize_field()] for use in the [Params]
+/// proc-macro. `format` is the format of the state the field was loaded from.
+pub fn deserialize_field<T: DeserializeOwned>(
+    serialized: &[u8],
+    format: StateFormat,
+) -> Result<T, Box<dyn Error + Send + Sync>> {
+    match format {
+        StateFormat::Json => Ok(serde_json::from_slice(serialized)?),
+        StateFormat::Binary => Ok(bincode::deserialize(serialized)?),
+    }
+}
 
 /// Describes a struct containing parameters and other persistent fields. The idea is that we can
 /// have a normal struct containing [super::FloatParam] and other parameter types with attributes
@@ -49,19 +89,31 @@ pub trait Params {
     fn param_map(self: Pin<&Self>) -> HashMap<&'static str, ParamPtr>;
 
     /// All parameter IDs from `param_map`, in a stable order. This order will be used to display
-    /// the parameters.
+    /// the parameters. When deriving this trait, parameters are listed in declaration order unless
+    /// they have a `#[display_index = 0]` attribute. Parameters with a display index are listed
+    /// first, ordered by that index.
     fn param_ids(self: Pin<&Self>) -> &'static [&'static str];
 
+    /// The groups parameters belong to, as a mapping from parameter IDs to group paths. Groups can
+    /// be nested by separating the group names with slashes, so `"Band 1/Filter"` is a group
+    /// called "Filter" inside of a group called "Band 1". Hosts may use these groups to organize
+    /// their parameter lists. Parameters that aren't in this map are not part of any group. When
+    /// deriving this trait, a parameter's group is set with the `#[group = "Band 1"]` attribute.
+    fn param_groups(self: Pin<&Self>) -> HashMap<&'static str, &'static str> {
+        HashMap::new()
+    }
+
     /// Serialize all fields marked with `#[persist = "stable_name"]` into a hash map containing
-    /// JSON-representations of those fields so they can be written to the plugin's state and
-    /// recalled later. This uses [serialize_field()] under the hood.
-    fn serialize_fields(&self) -> HashMap<String, String>;
+    /// serialized representations of those fields in `format` so they can be written to the
+    /// plugin's state and recalled later. This uses [serialize_field()] under the hood.
+    fn serialize_fields(&self, format: StateFormat) -> HashMap<String, Vec<u8>>;
 
     /// Restore all fields marked with `#[persist = "stable_name"]` from a hashmap created by
-    /// [Self::serialize_fields()]. All of thse fields should be wrapped in a [PersistentField] with
-    /// thread safe interior mutability, like an `RwLock` or a `Mutex`. This gets called when the
-    /// plugin's state is being restored. This uses [deserialize_field()] under the hood.
-    fn deserialize_fields(&self, serialized: &HashMap<String, String>);
+    /// [Self::serialize_fields()], with `format` being the format the fields were serialized in.
+    /// All of thse fields should be wrapped in a [PersistentField] with thread safe interior
+    /// mutability, like an `RwLock` or a `Mutex`. This gets called when the plugin's state is being
+    /// restored. This uses [deserialize_field()] under the hood.
+    fn deserialize_fields(&self, serialized: &HashMap<String, Vec<u8>>, format: StateFormat);
 }
 
 /// Internal pointers to parameters. This is an implementation detail used by the wrappers.
@@ -70,6 +122,8 @@ pub enum ParamPtr {
     FloatParam(*mut super::FloatParam),
     IntParam(*mut super::IntParam),
     BoolParam(*mut super::BoolParam),
+    MeterParam(*mut super::MeterParam),
+    EnumParam(*mut super::enums::EnumParamInner),
 }
 
 // These pointers only point to fields on pinned structs, and the caller always needs to make sure
@@ -104,6 +158,8 @@ impl ParamPtr {
             ParamPtr::FloatParam(p) => (**p).name,
             ParamPtr::IntParam(p) => (**p).name,
             ParamPtr::BoolParam(p) => (**p).name,
+            ParamPtr::MeterParam(p) => (**p).name,
+            ParamPtr::EnumParam(p) => (**p).inner.name,
         }
     }
 
@@ -118,6 +174,64 @@ impl ParamPtr {
             ParamPtr::FloatParam(p) => (**p).unit,
             ParamPtr::IntParam(p) => (**p).unit,
             ParamPtr::BoolParam(_) => "",
+            ParamPtr::MeterParam(p) => (**p).unit,
+            ParamPtr::EnumParam(_) => "",
+        }
+    }
+
+    /// Whether this is a read-only parameter that only reports values from the plugin to the host,
+    /// like a [super::MeterParam].
+    pub fn is_read_only(&self) -> bool {
+        matches!(self, ParamPtr::MeterParam(_))
+    }
+
+    /// The number of discrete steps in this parameter's range, or zero if the parameter is
+    /// continuous. A parameter with `n` steps can take on `n + 1` distinct values.
+    ///
+    /// # Safety
+    ///
+    /// Calling this function is only safe as long as the object this `ParamPtr` was created for is
+    /// still alive.
+    pub unsafe fn step_count(&self) -> i32 {
+        match &self {
+            ParamPtr::FloatParam(p) => match ((**p).step_count, (**p).step_size) {
+                (Some(step_count), _) => step_count as i32,
+                (None, Some(step_size)) if step_size > 0.0 => (**p).range.step_count(step_size),
+                (None, _) => 0,
+            },
+            ParamPtr::IntParam(p) => match ((**p).step_count, (**p).step_size) {
+                (Some(step_count), _) => step_count as i32,
+                (None, Some(step_size)) if step_size > 0.0 => (**p).range.step_count(step_size),
+                // Hosts place the steps of a discrete parameter at equal distances in the
+                // normalized range, so with a skewed range some of the integers would not be
+                // reachable through automation. These parameters are reported as continuous instead,
+                // and every integer then covers its own band of normalized values.
+                (None, _) => match (**p).range {
+                    Range::Linear { min, max } => max - min,
+                    Range::Skewed { .. } | Range::SymmetricalSkewed { .. } => 0,
+                },
+            },
+            ParamPtr::BoolParam(_) => 1,
+            ParamPtr::MeterParam(_) => 0,
+            ParamPtr::EnumParam(p) => (**p).variants.len() as i32 - 1,
+        }
+    }
+
+    /// Which parameter changes this parameter's smoother is used for. Parameters without a
+    /// smoother always return [SmoothingScope::All].
+    ///
+    /// # Safety
+    ///
+    /// Calling this function is only safe as long as the object this `ParamPtr` was created for is
+    /// still alive.
+    pub unsafe fn smoothing_scope(&self) -> SmoothingScope {
+        match &self {
+            ParamPtr::FloatParam(p) => (**p).smoothed.scope(),
+            ParamPtr::IntParam(p) => (**p).smoothed.scope(),
+            // These parameters are never smoothed
+            ParamPtr::BoolParam(_) => SmoothingScope::All,
+            ParamPtr::MeterParam(_) => SmoothingScope::All,
+            ParamPtr::EnumParam(_) => SmoothingScope::All,
         }
     }
 
@@ -134,6 +248,45 @@ impl ParamPtr {
             ParamPtr::FloatParam(p) => (**p).update_smoother(sample_rate, reset),
             ParamPtr::IntParam(p) => (**p).update_smoother(sample_rate, reset),
             ParamPtr::BoolParam(p) => (**p).update_smoother(sample_rate, reset),
+            ParamPtr::MeterParam(p) => (**p).update_smoother(sample_rate, reset),
+            ParamPtr::EnumParam(p) => (**p).update_smoother(sample_rate, reset),
+        }
+    }
+
+    /// Let the smoother adapt any smoothing that's still in progress to a new sample rate, without
+    /// resetting it. Used when the host only changes the sample rate.
+    ///
+    /// # Safety
+    ///
+    /// Calling this function is only safe as long as the object this `ParamPtr` was created for is
+    /// still alive.
+    pub unsafe fn set_smoother_sample_rate(&self, sample_rate: f32) {
+        match &self {
+            ParamPtr::FloatParam(p) => (**p).smoothed.set_sample_rate(sample_rate),
+            ParamPtr::IntParam(p) => (**p).smoothed.set_sample_rate(sample_rate),
+            // These parameters are never smoothed
+            ParamPtr::BoolParam(_) => (),
+            ParamPtr::MeterParam(_) => (),
+            ParamPtr::EnumParam(_) => (),
+        }
+    }
+
+    /// Stop any smoothing that's still in progress and have the smoother jump straight to the
+    /// parameter's current value. This is used when the host restarts playback so the plugin
+    /// doesn't glide from a stale value at the start of playback.
+    ///
+    /// # Safety
+    ///
+    /// Calling this function is only safe as long as the object this `ParamPtr` was created for is
+    /// still alive.
+    pub unsafe fn reset_smoother_to_current_target(&self) {
+        match &self {
+            ParamPtr::FloatParam(p) => (**p).smoothed.reset_to_target(),
+            ParamPtr::IntParam(p) => (**p).smoothed.reset_to_target(),
+            // These parameters are never smoothed
+            ParamPtr::BoolParam(_) => (),
+            ParamPtr::MeterParam(_) => (),
+            ParamPtr::EnumParam(_) => (),
         }
     }
 
@@ -149,6 +302,8 @@ impl ParamPtr {
             ParamPtr::FloatParam(p) => (**p).set_from_string(string),
             ParamPtr::IntParam(p) => (**p).set_from_string(string),
             ParamPtr::BoolParam(p) => (**p).set_from_string(string),
+            ParamPtr::MeterParam(p) => (**p).set_from_string(string),
+            ParamPtr::EnumParam(p) => (**p).set_from_string(string),
         }
     }
 
@@ -163,6 +318,51 @@ impl ParamPtr {
             ParamPtr::FloatParam(p) => (**p).normalized_value(),
             ParamPtr::IntParam(p) => (**p).normalized_value(),
             ParamPtr::BoolParam(p) => (**p).normalized_value(),
+            ParamPtr::MeterParam(p) => (**p).normalized_value(),
+            ParamPtr::EnumParam(p) => (**p).normalized_value(),
+        }
+    }
+
+    /// Get the normalized `[0, 1]` value for this parameter's default value.
+    ///
+    /// # Safety
+    ///
+    /// Calling this function is only safe as long as the object this `ParamPtr` was created for is
+    /// still alive.
+    pub unsafe fn default_normalized_value(&self) -> f32 {
+        match &self {
+            ParamPtr::FloatParam(p) => (**p).default_normalized_value(),
+            ParamPtr::IntParam(p) => (**p).default_normalized_value(),
+            ParamPtr::BoolParam(p) => (**p).default_normalized_value(),
+            ParamPtr::MeterParam(p) => (**p).default_normalized_value(),
+            ParamPtr::EnumParam(p) => (**p).default_normalized_value(),
+        }
+    }
+
+    /// Store the parameter's current value as its default if the parameter was created without an
+    /// explicit default. This is called once when the wrapper is created, before the host or the
+    /// plugin gets a chance to change any values.
+    ///
+    /// # Safety
+    ///
+    /// Calling this function is only safe as long as the object this `ParamPtr` was created for is
+    /// still alive.
+    pub unsafe fn capture_default(&self) {
+        match &self {
+            ParamPtr::FloatParam(p) => {
+                (**p).default.get_or_insert((**p).value);
+            }
+            ParamPtr::IntParam(p) => {
+                (**p).default.get_or_insert((**p).value);
+            }
+            ParamPtr::BoolParam(p) => {
+                (**p).default.get_or_insert((**p).value);
+            }
+            // Meters are never set by the host, so they don't have a default
+            ParamPtr::MeterParam(_) => (),
+            ParamPtr::EnumParam(p) => {
+                (**p).inner.default.get_or_insert((**p).inner.value);
+            }
         }
     }
 
@@ -179,6 +379,8 @@ impl ParamPtr {
             ParamPtr::FloatParam(p) => (**p).set_normalized_value(normalized),
             ParamPtr::IntParam(p) => (**p).set_normalized_value(normalized),
             ParamPtr::BoolParam(p) => (**p).set_normalized_value(normalized),
+            ParamPtr::MeterParam(p) => (**p).set_normalized_value(normalized),
+            ParamPtr::EnumParam(p) => (**p).set_normalized_value(normalized),
         }
     }
 
@@ -194,6 +396,8 @@ impl ParamPtr {
             ParamPtr::FloatParam(p) => (**p).preview_normalized(plain),
             ParamPtr::IntParam(p) => (**p).preview_normalized(plain as i32),
             ParamPtr::BoolParam(_) => plain,
+            ParamPtr::MeterParam(p) => (**p).preview_normalized(plain),
+            ParamPtr::EnumParam(p) => (**p).preview_normalized(plain as i32),
         }
     }
 
@@ -209,6 +413,8 @@ impl ParamPtr {
             ParamPtr::FloatParam(p) => (**p).preview_plain(normalized),
             ParamPtr::IntParam(p) => (**p).preview_plain(normalized) as f32,
             ParamPtr::BoolParam(_) => normalized,
+            ParamPtr::MeterParam(p) => (**p).preview_plain(normalized),
+            ParamPtr::EnumParam(p) => (**p).preview_plain(normalized) as f32,
         }
     }
 
@@ -225,6 +431,8 @@ impl ParamPtr {
             ParamPtr::FloatParam(p) => (**p).normalized_value_to_string(normalized, include_unit),
             ParamPtr::IntParam(p) => (**p).normalized_value_to_string(normalized, include_unit),
             ParamPtr::BoolParam(p) => (**p).normalized_value_to_string(normalized, include_unit),
+            ParamPtr::MeterParam(p) => (**p).normalized_value_to_string(normalized, include_unit),
+            ParamPtr::EnumParam(p) => (**p).normalized_value_to_string(normalized, include_unit),
         }
     }
 
@@ -239,6 +447,8 @@ impl ParamPtr {
             ParamPtr::FloatParam(p) => (**p).string_to_normalized_value(string),
             ParamPtr::IntParam(p) => (**p).string_to_normalized_value(string),
             ParamPtr::BoolParam(p) => (**p).string_to_normalized_value(string),
+            ParamPtr::MeterParam(p) => (**p).string_to_normalized_value(string),
+            ParamPtr::EnumParam(p) => (**p).string_to_normalized_value(string),
         }
     }
 }
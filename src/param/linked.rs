@@ -0,0 +1,128 @@
+// nih-plug: plugins, but rewritten in Rust
+// Copyright (C) 2022 Robbert van der Helm
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Helpers for parameters that can be linked together, like the left and right channel versions of
+//! the same control.
+
+use super::{BoolParam, Param};
+use crate::context::ParamSetter;
+
+/// A pair of parameters that can be linked together with a [BoolParam], like the per-channel gain
+/// parameters of a stereo EQ. When the pair is linked, any change made from the editor through the
+/// methods on this object is applied to both parameters so the host records automation for both.
+/// During processing, use [Self::left()] and [Self::right()] to obtain the parameters that should
+/// be used for each channel. While linked, these both return the left parameter, so the right
+/// parameter's own automation is ignored until the pair is unlinked again.
+///
+/// This only borrows the parameters, so it can be created on the fly from the plugin's `Params`
+/// object whenever it's needed.
+pub struct LinkedParams<'a, P: Param> {
+    left: &'a P,
+    right: &'a P,
+    link: &'a BoolParam,
+}
+
+impl<'a, P: Param> LinkedParams<'a, P> {
+    pub fn new(left: &'a P, right: &'a P, link: &'a BoolParam) -> Self {
+        Self { left, right, link }
+    }
+
+    /// Whether the two parameters are currently linked.
+    pub fn is_linked(&self) -> bool {
+        self.link.value
+    }
+
+    /// The parameter that should be used for the left channel.
+    pub fn left(&self) -> &'a P {
+        self.left
+    }
+
+    /// The parameter that should be used for the right channel. This is the left parameter while
+    /// the pair is linked.
+    pub fn right(&self) -> &'a P {
+        if self.is_linked() {
+            self.left
+        } else {
+            self.right
+        }
+    }
+
+    /// The same as [ParamSetter::begin_set_parameter()], but this also starts a gesture for the
+    /// other parameter in the pair if the pair is linked. `param` should be one of the two
+    /// parameters in the pair.
+    pub fn begin_set_parameter(&self, setter: &ParamSetter, param: &P) {
+        setter.begin_set_parameter(param);
+        if let Some(other) = self.linked_counterpart(param) {
+            setter.begin_set_parameter(other);
+        }
+    }
+
+    /// The same as [ParamSetter::set_parameter()], but this also sets the other parameter in the
+    /// pair to the same value if the pair is linked.
+    pub fn set_parameter(&self, setter: &ParamSetter, param: &P, value: P::Plain)
+    where
+        P::Plain: Copy,
+    {
+        setter.set_parameter(param, value);
+        if let Some(other) = self.linked_counterpart(param) {
+            setter.set_parameter(other, value);
+        }
+    }
+
+    /// The same as [ParamSetter::end_set_parameter()], but this also ends the gesture for the other
+    /// parameter in the pair if the pair is linked.
+    pub fn end_set_parameter(&self, setter: &ParamSetter, param: &P) {
+        setter.end_set_parameter(param);
+        if let Some(other) = self.linked_counterpart(param) {
+            setter.end_set_parameter(other);
+        }
+    }
+
+    /// Set the link parameter, including the gesture. When the pair gets linked, the right
+    /// parameter is set to the left parameter's value so both parameters agree with what's being
+    /// processed.
+    pub fn set_linked(&self, setter: &ParamSetter, linked: bool)
+    where
+        P::Plain: Copy,
+    {
+        setter.begin_set_parameter(self.link);
+        setter.set_parameter(self.link, linked);
+        setter.end_set_parameter(self.link);
+
+        if linked {
+            setter.begin_set_parameter(self.right);
+            setter.set_parameter(self.right, self.left.plain_value());
+            setter.end_set_parameter(self.right);
+        }
+    }
+
+    /// The other parameter in the pair if `param` is part of this pair and the pair is linked.
+    fn linked_counterpart(&self, param: &P) -> Option<&'a P> {
+        if !self.is_linked() {
+            return None;
+        }
+
+        let ptr = param.as_ptr();
+        if ptr == self.left.as_ptr() {
+            Some(self.right)
+        } else if ptr == self.right.as_ptr() {
+            Some(self.left)
+        } else {
+            nih_debug_assert_failure!("The parameter is not part of this linked pair");
+            None
+        }
+    }
+}
@@ -0,0 +1,91 @@
+// nih-plug: plugins, but rewritten in Rust
+// Copyright (C) 2022 Robbert van der Helm
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! A mapping from MIDI CCs to parameters for plugins with a MIDI learn feature.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A mapping from MIDI CC numbers to the stable string IDs of the parameters they control, as
+/// created by a MIDI learn feature. Store this in a persistent field on your `Params` struct so
+/// the user's assignments are saved together with the rest of the plugin's state:
+///
+/// ```ignore
+/// #[persist = "midi_cc_map"]
+/// midi_cc_map: RwLock<MidiCcMap>,
+/// ```
+///
+/// The parameters are referred to by their IDs instead of by references or hashes, so the
+/// mappings remain valid when parameters are added or reordered in a later version of the plugin.
+/// Mappings for IDs that no longer exist can simply be ignored.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MidiCcMap {
+    /// The parameter ID assigned to each CC number. A `BTreeMap` keeps the serialized state
+    /// deterministic.
+    mappings: BTreeMap<u8, String>,
+}
+
+impl MidiCcMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Assign `cc` to the parameter with ID `param_id`, replacing any existing mapping for that
+    /// CC. Returns the ID of the parameter that was previously mapped to `cc`, if any.
+    pub fn assign(&mut self, cc: u8, param_id: impl Into<String>) -> Option<String> {
+        nih_debug_assert!(cc < 128, "MIDI CC numbers are in the range [0, 127]");
+
+        self.mappings.insert(cc, param_id.into())
+    }
+
+    /// Remove the mapping for `cc`. Returns the ID of the parameter it was mapped to, if any.
+    pub fn unassign(&mut self, cc: u8) -> Option<String> {
+        self.mappings.remove(&cc)
+    }
+
+    /// Remove all mappings to the parameter with ID `param_id`.
+    pub fn unassign_param(&mut self, param_id: &str) {
+        self.mappings.retain(|_, id| id != param_id);
+    }
+
+    /// Remove all mappings.
+    pub fn clear(&mut self) {
+        self.mappings.clear();
+    }
+
+    /// Get the ID of the parameter mapped to `cc`, if any.
+    pub fn get(&self, cc: u8) -> Option<&str> {
+        self.mappings.get(&cc).map(String::as_str)
+    }
+
+    /// Get all CCs mapped to the parameter with ID `param_id`, in ascending order.
+    pub fn ccs_for_param<'a>(&'a self, param_id: &'a str) -> impl Iterator<Item = u8> + 'a {
+        self.mappings
+            .iter()
+            .filter(move |(_, id)| id.as_str() == param_id)
+            .map(|(cc, _)| *cc)
+    }
+
+    /// Iterate over all `(cc, param_id)` mappings, in ascending CC order.
+    pub fn iter(&self) -> impl Iterator<Item = (u8, &str)> {
+        self.mappings.iter().map(|(cc, id)| (*cc, id.as_str()))
+    }
+
+    /// Returns true if no CCs have been mapped.
+    pub fn is_empty(&self) -> bool {
+        self.mappings.is_empty()
+    }
+}
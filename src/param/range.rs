@@ -55,6 +55,31 @@ pub(crate) trait NormalizebleRange<T> {
     /// Unnormalize a normalized value. Will be clamped to `[0, 1]` if the plain, unnormalized value
     /// would exceed that range.
     fn unnormalize(&self, normalized: f32) -> T;
+
+    /// Snap a plain value to the nearest multiple of `step_size` counting from the start of the
+    /// range. The result is clamped to the range's bounds. `step_size` should be positive, the value
+    /// is only clamped otherwise. Linear ranges snap to the same evenly spaced steps from
+    /// [Self::step_count()] the host uses, so if the range is not a multiple of `step_size` then the
+    /// steps end up slightly smaller than `step_size`.
+    fn snap_to_step(&self, plain: T, step_size: f32) -> T;
+
+    /// The number of discrete steps in this range when snapping to `step_size`. This is the
+    /// smallest number of evenly spaced steps that are no larger than `step_size`. Hosts place
+    /// these steps at equal distances in the normalized range, which only lines up with the snapped
+    /// plain values for linear ranges. Skewed ranges are reported as continuous instead, with every
+    /// snapped value covering its own band of normalized values.
+    fn step_count(&self, step_size: f32) -> i32;
+}
+
+impl<T: Copy> Range<T> {
+    /// The range's `(min, max)` bounds.
+    fn bounds(&self) -> (T, T) {
+        match self {
+            Range::Linear { min, max }
+            | Range::Skewed { min, max, .. }
+            | Range::SymmetricalSkewed { min, max, .. } => (*min, *max),
+        }
+    }
 }
 
 impl Default for Range<f32> {
@@ -132,6 +157,31 @@ impl NormalizebleRange<f32> for Range<f32> {
             }
         }
     }
+
+    fn snap_to_step(&self, plain: f32, step_size: f32) -> f32 {
+        nih_debug_assert!(step_size > 0.0, "Step sizes need to be positive");
+        let (min, max) = self.bounds();
+        if step_size <= 0.0 {
+            return plain.clamp(min, max);
+        }
+
+        let step_size = match self {
+            Range::Linear { .. } => match self.step_count(step_size) {
+                0 => return plain.clamp(min, max),
+                step_count => (max - min) / step_count as f32,
+            },
+            Range::Skewed { .. } | Range::SymmetricalSkewed { .. } => step_size,
+        };
+        ((((plain - min) / step_size).round() * step_size) + min).clamp(min, max)
+    }
+
+    fn step_count(&self, step_size: f32) -> i32 {
+        nih_debug_assert!(step_size > 0.0, "Step sizes need to be positive");
+        match self {
+            Range::Linear { min, max } if step_size > 0.0 => num_steps(max - min, step_size),
+            _ => 0,
+        }
+    }
 }
 
 impl NormalizebleRange<i32> for Range<i32> {
@@ -147,17 +197,18 @@ impl NormalizebleRange<i32> for Range<i32> {
                 factor,
                 center,
             } => {
-                // See the comments in the float version
-                let unscaled_proportion = (plain - min) as f32 / (max - min) as f32;
-                let center_proportion = (center - min) as f32 / (max - min) as f32;
-                if unscaled_proportion > center_proportion {
-                    let scaled_proportion = (unscaled_proportion - center_proportion)
-                        * (1.0 - center_proportion).recip();
+                // See the comments in the float version. The proportions within the two halves are
+                // computed directly from the integer distances so the range's endpoints and the
+                // center map exactly to 0, 1, and 0.5.
+                if plain > *center {
+                    let scaled_proportion = (plain - center) as f32 / (max - center) as f32;
                     (scaled_proportion.powf(*factor) * 0.5) + 0.5
-                } else {
+                } else if plain < *center {
                     let inverted_scaled_proportion =
-                        (center_proportion - unscaled_proportion) * (center_proportion).recip();
+                        (center - plain) as f32 / (center - min) as f32;
                     (1.0 - inverted_scaled_proportion.powf(*factor)) * 0.5
+                } else {
+                    0.5
                 }
             }
         }
@@ -177,20 +228,59 @@ impl NormalizebleRange<i32> for Range<i32> {
                 factor,
                 center,
             } => {
-                let center_proportion = (center - min) as f32 / (max - min) as f32;
-                let skewed_proportion = if normalized > 0.5 {
+                // This is the inverse of the above, again working relative to the center
+                if normalized > 0.5 {
                     let scaled_proportion = (normalized - 0.5) * 2.0;
-                    (scaled_proportion.powf(factor.recip()) * (1.0 - center_proportion))
-                        + center_proportion
+                    center
+                        + (scaled_proportion.powf(factor.recip()) * (max - center) as f32).round()
+                            as i32
                 } else {
                     let inverted_scaled_proportion = (0.5 - normalized) * 2.0;
-                    (1.0 - inverted_scaled_proportion.powf(factor.recip())) * center_proportion
-                };
-
-                (skewed_proportion * (max - min) as f32).round() as i32 + min
+                    center
+                        - (inverted_scaled_proportion.powf(factor.recip()) * (center - min) as f32)
+                            .round() as i32
+                }
             }
         }
     }
+
+    fn snap_to_step(&self, plain: i32, step_size: f32) -> i32 {
+        nih_debug_assert!(step_size > 0.0, "Step sizes need to be positive");
+        // Integer parameters can't have steps smaller than a single integer, this matches
+        // `step_count()`
+        let step_size = step_size.max(1.0);
+        let (min, max) = self.bounds();
+        let step_size = match self {
+            Range::Linear { .. } => match self.step_count(step_size) {
+                0 => return plain.clamp(min, max),
+                step_count => (max - min) as f32 / step_count as f32,
+            },
+            Range::Skewed { .. } | Range::SymmetricalSkewed { .. } => step_size,
+        };
+        ((((plain - min) as f32 / step_size).round() * step_size).round() as i32 + min)
+            .clamp(min, max)
+    }
+
+    fn step_count(&self, step_size: f32) -> i32 {
+        nih_debug_assert!(step_size > 0.0, "Step sizes need to be positive");
+        match self {
+            Range::Linear { min, max } => num_steps((max - min) as f32, step_size.max(1.0)),
+            Range::Skewed { .. } | Range::SymmetricalSkewed { .. } => 0,
+        }
+    }
+}
+
+/// The smallest number of evenly spaced steps no larger than `step_size` needed to cover a range of
+/// `length`. Lengths that are only a multiple of `step_size` up to rounding errors are not rounded
+/// up to an extra step.
+fn num_steps(length: f32, step_size: f32) -> i32 {
+    let steps = length / step_size;
+    let rounded_steps = steps.round();
+    if (steps - rounded_steps).abs() < 1e-4 {
+        rounded_steps as i32
+    } else {
+        steps.ceil() as i32
+    }
 }
 
 #[cfg(test)]
@@ -350,6 +440,130 @@ mod tests {
         }
     }
 
+    /// Check that every integer in `range` maps to its own band of normalized values, with the
+    /// range's endpoints mapping to exactly 0 and 1.
+    fn assert_int_range_round_trips(range: &Range<i32>) {
+        let (min, max) = range.bounds();
+        assert_eq!(range.normalize(min), 0.0);
+        assert_eq!(range.normalize(max), 1.0);
+        assert_eq!(range.unnormalize(0.0), min);
+        assert_eq!(range.unnormalize(1.0), max);
+
+        let mut previous_normalized = range.normalize(min);
+        for plain in min + 1..=max {
+            let normalized = range.normalize(plain);
+            assert!(
+                normalized > previous_normalized,
+                "{plain} does not have its own band"
+            );
+            assert_eq!(range.unnormalize(normalized), plain);
+
+            // The band boundary lies somewhere between the two values
+            let midpoint = (previous_normalized + normalized) / 2.0;
+            assert!((plain - 1..=plain).contains(&range.unnormalize(midpoint)));
+
+            previous_normalized = normalized;
+        }
+    }
+
+    mod skewed_int {
+        use super::*;
+
+        #[test]
+        fn range_round_trips_every_int() {
+            for factor in [-2.0, -1.0, 1.0, 2.0] {
+                assert_int_range_round_trips(&Range::Skewed {
+                    min: 1,
+                    max: 64,
+                    factor: Range::skew_factor(factor),
+                });
+            }
+        }
+
+        #[test]
+        fn symmetrical_range_round_trips_every_int() {
+            for factor in [-2.0, -1.0, 1.0, 2.0] {
+                assert_int_range_round_trips(&Range::SymmetricalSkewed {
+                    min: 1,
+                    max: 64,
+                    factor: Range::skew_factor(factor),
+                    center: 16,
+                });
+            }
+        }
+    }
+
+    mod stepped {
+        use super::super::*;
+        use super::*;
+
+        #[test]
+        fn range_snap_to_step_float() {
+            let range = make_linear_float_range();
+            assert_eq!(range.snap_to_step(13.3, 0.5), 13.5);
+            assert_eq!(range.snap_to_step(13.2, 0.5), 13.0);
+        }
+
+        #[test]
+        fn range_snap_to_step_float_uneven() {
+            // 10..20 is not a multiple of 6, so this is divided into the same two 5 unit steps the
+            // host shows
+            let range = make_linear_float_range();
+            assert_eq!(range.step_count(6.0), 2);
+            assert_eq!(range.snap_to_step(16.0, 6.0), 15.0);
+            assert_eq!(range.snap_to_step(19.9, 6.0), 20.0);
+        }
+
+        #[test]
+        fn range_snap_to_step_int_uneven() {
+            // The host divides -10..10 into seven steps here, and the snapped values are rounded
+            // the same way the host rounds its steps
+            let range = make_linear_int_range();
+            assert_eq!(range.step_count(3.0), 7);
+            assert_eq!(range.snap_to_step(-6, 3.0), -7);
+            assert_eq!(range.snap_to_step(2, 3.0), 1);
+            assert_eq!(range.snap_to_step(9, 3.0), 10);
+        }
+
+        #[test]
+        fn range_snap_to_step_int() {
+            let range = make_linear_int_range();
+            assert_eq!(range.snap_to_step(3, 4.0), 2);
+        }
+
+        #[test]
+        fn range_step_count_float() {
+            let range = make_linear_float_range();
+            assert_eq!(range.step_count(0.5), 20);
+        }
+
+        #[test]
+        fn range_step_count_int() {
+            let range = make_linear_int_range();
+            assert_eq!(range.step_count(2.0), 10);
+        }
+
+        #[test]
+        fn range_snap_to_step_int_fractional() {
+            // Steps smaller than a single integer mean every integer is its own step, consistent
+            // with the step count
+            let range = make_linear_int_range();
+            assert_eq!(range.snap_to_step(3, 0.25), 3);
+            assert_eq!(range.step_count(0.25), 20);
+        }
+
+        #[test]
+        fn range_step_count_skewed() {
+            // The host's evenly spaced steps would not line up with the snapped values here
+            let range = make_skewed_float_range(Range::skew_factor(-1.0));
+            assert_eq!(range.step_count(0.5), 0);
+            assert_eq!(range.snap_to_step(13.3, 0.5), 13.5);
+
+            let range = make_symmetrical_skewed_int_range(Range::skew_factor(-1.0));
+            assert_eq!(range.step_count(2.0), 0);
+        }
+    }
+
     mod symmetrical_skewed {
         use super::super::*;
         use super::*;
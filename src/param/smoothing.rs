@@ -15,6 +15,7 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use atomic_float::AtomicF32;
+use crossbeam::atomic::AtomicCell;
 use std::sync::atomic::{AtomicU32, Ordering};
 
 /// Controls if and how parameters gets smoothed.
@@ -26,13 +27,37 @@ pub enum SmoothingStyle {
     /// rate.
     Linear(f32),
     /// Smooth parameter changes such that the rate matches the curve of a logarithmic function.
-    /// This is useful for smoothing things like frequencies and decibel gain value. **The caveat is
-    /// that the value may never reach 0**, or you will end up multiplying and dividing things by
-    /// zero. Make sure your value ranges don't include 0.
+    /// This is useful for smoothing things like frequencies and decibel gain value. A logarithmic
+    /// curve can't reach or cross zero, so when smoothing from or to zero, or between a positive
+    /// and a negative value, the first part of the smoothing period uses a short linear segment to
+    /// get past zero. The rest of the period is still smoothed logarithmically.
     Logarithmic(f32),
     // TODO: Sample-accurate modes
 }
 
+/// Which parameter changes a [Smoother] is used for. Changes that are not covered by the scope
+/// are applied instantly. Restoring the plugin's state never uses smoothing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmoothingScope {
+    /// All parameter changes are smoothed. This is the default.
+    All,
+    /// Only changes made by the host, like automation and the host's generic UI, are smoothed.
+    /// Changes made by the plugin from its editor or through the
+    /// [crate::context::ProcessContext] are applied instantly.
+    Host,
+    /// Only changes made by the plugin from its editor or through the
+    /// [crate::context::ProcessContext] are smoothed. Changes made by the host are applied
+    /// instantly.
+    Plugin,
+}
+
+/// The fraction of the smoothing period used for the linear segment when logarithmic smoothing needs
+/// to cross zero. See [SmoothingStyle::Logarithmic].
+const ZERO_CROSSING_LINEAR_FRACTION: f32 = 0.25;
+/// When logarithmic smoothing needs to cross zero, the linear segment ends at this fraction of the
+/// target value (or -60 dB) and the logarithmic smoothing continues from there.
+const ZERO_CROSSING_LOG_START: f32 = 0.001;
+
 /// A smoother, providing a smoothed value for each sample.
 //
 // TODO: We need to use atomics here so we can share the params object with the GUI. Is there a
@@ -40,12 +65,23 @@ pub enum SmoothingStyle {
 pub struct Smoother<T> {
     /// The kind of snoothing that needs to be applied, if any.
     style: SmoothingStyle,
+    /// Which parameter changes this smoother is used for.
+    scope: SmoothingScope,
     /// The number of steps of smoothing left to take.
     steps_left: AtomicU32,
+    /// The sample rate passed to the last call to `set_target()` or `set_sample_rate()`. Used to
+    /// convert the remaining steps to the new sample rate when the sample rate changes.
+    sample_rate: f32,
     /// The amount we should adjust the current value each sample to be able to reach the target in
     /// the specified tiem frame. This is also a floating point number to keep the smoothing
     /// uniform.
     step_size: f32,
+    /// The linear step size used for the first steps of logarithmic smoothing when the smoother
+    /// needs to cross zero. See [SmoothingStyle::Logarithmic].
+    zero_crossing_step_size: f32,
+    /// The number of steps at the end of the smoothing period that use logarithmic smoothing. When
+    /// `steps_left` is larger than this, [Self::zero_crossing_step_size] is used instead.
+    log_steps: u32,
     /// The value for the current sample. Always stored as floating point for obvious reasons.
     current: AtomicF32,
     /// The value we're smoothing towards
@@ -56,8 +92,12 @@ impl<T: Default> Default for Smoother<T> {
     fn default() -> Self {
         Self {
             style: SmoothingStyle::None,
+            scope: SmoothingScope::All,
             steps_left: AtomicU32::new(0),
+            sample_rate: 0.0,
             step_size: Default::default(),
+            zero_crossing_step_size: 0.0,
+            log_steps: 0,
             current: AtomicF32::new(0.0),
             target: Default::default(),
         }
@@ -78,11 +118,99 @@ impl<T: Default> Smoother<T> {
         Default::default()
     }
 
+    /// Only use this smoother for some parameter changes. For instance, a parameter could apply
+    /// automation instantly while still smoothing changes made from the editor.
+    pub fn with_scope(mut self, scope: SmoothingScope) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    /// Which parameter changes this smoother is used for.
+    pub fn scope(&self) -> SmoothingScope {
+        self.scope
+    }
+
     /// Whether calling [Self::next()] will yield a new value or an old value. Useful if you need to
     /// recompute something wheenver this parameter changes.
     pub fn is_smoothing(&self) -> bool {
         self.steps_left.load(Ordering::Relaxed) > 0
     }
+
+    /// Start smoothing towards `target` in `steps_left` steps, starting at the current value. The
+    /// step sizes are computed from the remaining distance, so this can also be used to change the
+    /// length of a smoothing period that's already in progress.
+    fn start_smoothing(&mut self, target: f32, steps_left: u32) {
+        self.steps_left.store(steps_left, Ordering::Relaxed);
+
+        let current = self.current.load(Ordering::Relaxed);
+        self.log_steps = steps_left;
+        self.step_size = match self.style {
+            SmoothingStyle::None => 0.0,
+            SmoothingStyle::Linear(_) => (target - current) / steps_left as f32,
+            SmoothingStyle::Logarithmic(_) => {
+                let (step_size, zero_crossing_step_size, log_steps) =
+                    logarithmic_step_sizes(current, target, steps_left);
+                self.zero_crossing_step_size = zero_crossing_step_size;
+                self.log_steps = log_steps;
+
+                step_size
+            }
+        };
+    }
+
+    /// The number of steps needed to smooth a value change at `sample_rate`.
+    fn smoothing_steps(&self, sample_rate: f32) -> u32 {
+        match self.style {
+            SmoothingStyle::None => 1,
+            // There should always be at least one step or we would end up dividing by zero
+            SmoothingStyle::Linear(time) | SmoothingStyle::Logarithmic(time) => {
+                ((sample_rate * time / 1000.0).round() as u32).max(1)
+            }
+        }
+    }
+
+    /// Take a single step from `current` towards `target`, with `steps_left` being the number of
+    /// steps left before taking this step. Integer smoothers also smooth in floating point and only
+    /// round the result.
+    fn step(&self, current: f32, target: f32, steps_left: u32) -> f32 {
+        // The number of steps usually won't fit exactly, so make sure we don't do weird things with
+        // overshoots or undershoots
+        if steps_left == 1 {
+            target
+        } else {
+            let new = match &self.style {
+                SmoothingStyle::None => target,
+                SmoothingStyle::Linear(_) => current + self.step_size,
+                SmoothingStyle::Logarithmic(_) if steps_left > self.log_steps => {
+                    current + self.zero_crossing_step_size
+                }
+                SmoothingStyle::Logarithmic(_) => current * self.step_size,
+            };
+
+            if target > current {
+                new.min(target)
+            } else {
+                new.max(target)
+            }
+        }
+    }
+
+    /// Adapt smoothing that's still in progress to a new sample rate, for instance when the host
+    /// changes the sample rate. The remaining time stays the same, and the smoother continues
+    /// from its current value so there are no jumps in the output.
+    fn adapt_to_sample_rate(&mut self, target: f32, sample_rate: f32) {
+        let old_sample_rate = self.sample_rate;
+        self.sample_rate = sample_rate;
+
+        let steps_left = self.steps_left.load(Ordering::Relaxed);
+        if steps_left == 0 || old_sample_rate <= 0.0 || old_sample_rate == sample_rate {
+            return;
+        }
+
+        let new_steps_left =
+            ((steps_left as f32 * sample_rate / old_sample_rate).round() as u32).max(1);
+        self.start_smoothing(target, new_steps_left);
+    }
 }
 
 // These are not iterators for the sole reason that this will always yield a value, and needing to
@@ -90,117 +218,327 @@ impl<T: Default> Smoother<T> {
 impl Smoother<f32> {
     /// Reset the smoother the specified value.
     pub fn reset(&mut self, value: f32) {
-        self.target = value;
-        self.current.store(value, Ordering::Relaxed);
+        // The step counter is cleared first so a concurrent call to `next()` won't take another
+        // step from the old value
         self.steps_left.store(0, Ordering::Relaxed);
+        self.current.store(value, Ordering::Relaxed);
+        self.target = value;
+    }
+
+    /// Stop smoothing and jump straight to the current target value.
+    pub fn reset_to_target(&mut self) {
+        self.reset(self.target);
     }
 
     /// Set the target value.
     pub fn set_target(&mut self, sample_rate: f32, target: f32) {
         self.target = target;
+        self.sample_rate = sample_rate;
+        self.start_smoothing(target, self.smoothing_steps(sample_rate));
+    }
 
-        let steps_left = match self.style {
-            SmoothingStyle::None => 1,
-            SmoothingStyle::Linear(time) | SmoothingStyle::Logarithmic(time) => {
-                (sample_rate * time / 1000.0).round() as u32
-            }
-        };
-        self.steps_left.store(steps_left, Ordering::Relaxed);
-
-        let current = self.current.load(Ordering::Relaxed);
-        self.step_size = match self.style {
-            SmoothingStyle::None => 0.0,
-            SmoothingStyle::Linear(_) => (self.target - current) / steps_left as f32,
-            SmoothingStyle::Logarithmic(_) => {
-                // We need to solve `current * (step_size ^ steps_left) = target` for
-                // `step_size`
-                nih_debug_assert_ne!(current, 0.0);
-                (self.target / current).powf((steps_left as f32).recip())
-            }
-        };
+    /// Update the sample rate used by the smoother. If the smoother is still smoothing towards a
+    /// target, then the remaining steps are recomputed so the target is still reached in the same
+    /// amount of time, continuing from the current value. This is called by the wrapper when the
+    /// host changes the sample rate.
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.adapt_to_sample_rate(self.target, sample_rate);
     }
 
     // Yes, Clippy, like I said, this was intentional
     #[allow(clippy::should_implement_trait)]
     pub fn next(&self) -> f32 {
-        if self.steps_left.load(Ordering::Relaxed) > 1 {
-            let current = self.current.load(Ordering::Relaxed);
-
-            // The number of steps usually won't fit exactly, so make sure we don't do weird things
-            // with overshoots or undershoots
-            let old_steps_left = self.steps_left.fetch_sub(1, Ordering::Relaxed);
-            let new = if old_steps_left == 1 {
-                self.target
-            } else {
-                match &self.style {
-                    SmoothingStyle::None => self.target,
-                    SmoothingStyle::Linear(_) => current + self.step_size,
-                    SmoothingStyle::Logarithmic(_) => current * self.step_size,
-                }
-            };
-            self.current.store(new, Ordering::Relaxed);
+        // This never lets the step counter wrap around, even if the smoother got reset in the
+        // meantime
+        match self
+            .steps_left
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |steps_left| {
+                steps_left.checked_sub(1)
+            }) {
+            Ok(old_steps_left) => {
+                let current = self.current.load(Ordering::Relaxed);
+                let new = self.step(current, self.target, old_steps_left);
+                self.current.store(new, Ordering::Relaxed);
 
-            new
-        } else {
-            self.target
+                new
+            }
+            Err(_) => self.target,
         }
     }
+
+    /// Compute the next `n` values at once and write them to the start of `out`. This produces the
+    /// exact same values as calling [Self::next()] `n` times, but the smoother's state is only
+    /// loaded once at the start and stored once at the end, so the loop itself doesn't touch any
+    /// atomics.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is larger than `out.len()`.
+    pub fn next_block(&self, out: &mut [f32], n: usize) {
+        let out = &mut out[..n];
+
+        let mut steps_left = self.steps_left.load(Ordering::Relaxed);
+        if steps_left == 0 {
+            out.fill(self.target);
+            return;
+        }
+
+        let mut current = self.current.load(Ordering::Relaxed);
+        for value in out.iter_mut() {
+            if steps_left > 0 {
+                current = self.step(current, self.target, steps_left);
+                steps_left -= 1;
+                *value = current;
+            } else {
+                *value = self.target;
+            }
+        }
+
+        // Just like in `next()`, the step counter is updated before the current value
+        self.steps_left.store(steps_left, Ordering::Relaxed);
+        self.current.store(current, Ordering::Relaxed);
+    }
 }
 
 impl Smoother<i32> {
     /// Reset the smoother the specified value.
     pub fn reset(&mut self, value: i32) {
-        self.target = value;
-        self.current.store(value as f32, Ordering::Relaxed);
+        // The step counter is cleared first so a concurrent call to `next()` won't take another
+        // step from the old value
         self.steps_left.store(0, Ordering::Relaxed);
+        self.current.store(value as f32, Ordering::Relaxed);
+        self.target = value;
+    }
+
+    /// Stop smoothing and jump straight to the current target value.
+    pub fn reset_to_target(&mut self) {
+        self.reset(self.target);
     }
 
     pub fn set_target(&mut self, sample_rate: f32, target: i32) {
         self.target = target;
+        self.sample_rate = sample_rate;
+        self.start_smoothing(target as f32, self.smoothing_steps(sample_rate));
+    }
 
-        let steps_left = match self.style {
-            SmoothingStyle::None => 1,
-            SmoothingStyle::Linear(time) | SmoothingStyle::Logarithmic(time) => {
-                (sample_rate * time / 1000.0).round() as u32
-            }
-        };
-        self.steps_left.store(steps_left, Ordering::Relaxed);
+    /// Update the sample rate used by the smoother. If the smoother is still smoothing towards a
+    /// target, then the remaining steps are recomputed so the target is still reached in the same
+    /// amount of time, continuing from the current value.
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.adapt_to_sample_rate(self.target as f32, sample_rate);
+    }
 
-        let current = self.current.load(Ordering::Relaxed);
-        self.step_size = match self.style {
-            SmoothingStyle::None => 0.0,
-            SmoothingStyle::Linear(_) => (self.target as f32 - current) / steps_left as f32,
-            SmoothingStyle::Logarithmic(_) => {
-                nih_debug_assert_ne!(current, 0.0);
-                (self.target as f32 / current).powf((steps_left as f32).recip())
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> i32 {
+        match self
+            .steps_left
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |steps_left| {
+                steps_left.checked_sub(1)
+            }) {
+            Ok(old_steps_left) => {
+                let current = self.current.load(Ordering::Relaxed);
+                let new = self.step(current, self.target as f32, old_steps_left);
+                self.current.store(new, Ordering::Relaxed);
+
+                new.round() as i32
             }
-        };
+            Err(_) => self.target,
+        }
     }
+}
+
+/// The sample rate an [Envelope] assumes until [Envelope::set_sample_rate()] gets called.
+const DEFAULT_ENVELOPE_SAMPLE_RATE: f32 = 44_100.0;
+
+/// A one-shot envelope for things like plucks and transient designers. Calling [Self::trigger()]
+/// ramps the envelope linearly from its current value to the peak value over the attack time, after
+/// which it ramps back down to the floor value over the release time. Calling [Self::release()]
+/// starts the release stage early. Unlike a [Smoother], all of the envelope's state is stored in
+/// atomics and every method takes `&self`, so the envelope can be shared with the editor using an
+/// `Arc` to display its current value.
+#[derive(Debug)]
+pub struct Envelope {
+    /// The time in milliseconds it takes to ramp from the floor value to the peak value.
+    attack_ms: f32,
+    /// The time in milliseconds it takes to ramp from the peak value to the floor value.
+    release_ms: f32,
+    /// The value the envelope rests at when it's not active.
+    floor: f32,
+    /// The value the attack stage ramps to.
+    peak: f32,
+    /// The sample rate used to convert the attack and release times to samples.
+    sample_rate: AtomicF32,
+    /// The stage the envelope is currently in.
+    stage: AtomicCell<EnvelopeStage>,
+    /// The number of steps left in the current stage.
+    steps_left: AtomicU32,
+    /// The amount the current value changes by every sample in the current stage.
+    step_size: AtomicF32,
+    /// The envelope's value for the current sample.
+    current: AtomicF32,
+}
 
+/// The stages of an [Envelope].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnvelopeStage {
+    /// The envelope is at its floor value and it's waiting to be triggered.
+    Idle,
+    /// The envelope is ramping up towards its peak value.
+    Attack,
+    /// The envelope is ramping down towards its floor value.
+    Release,
+}
+
+impl Envelope {
+    /// Create an envelope that ramps from zero to one and back again with the specified attack and
+    /// release times in milliseconds. Call [Self::set_sample_rate()] from the plugin's
+    /// initialization function to get the correct timings.
+    pub fn new(attack_ms: f32, release_ms: f32) -> Self {
+        Self {
+            attack_ms,
+            release_ms,
+            floor: 0.0,
+            peak: 1.0,
+            sample_rate: AtomicF32::new(DEFAULT_ENVELOPE_SAMPLE_RATE),
+            stage: AtomicCell::new(EnvelopeStage::Idle),
+            steps_left: AtomicU32::new(0),
+            step_size: AtomicF32::new(0.0),
+            current: AtomicF32::new(0.0),
+        }
+    }
+
+    /// Use different floor and peak values instead of zero and one. The envelope starts out at the
+    /// floor value.
+    pub fn with_range(mut self, floor: f32, peak: f32) -> Self {
+        self.floor = floor;
+        self.peak = peak;
+        self.current.store(floor, Ordering::Relaxed);
+        self
+    }
+
+    /// Update the sample rate used to compute the attack and release times. This should be called
+    /// from the plugin's initialization function. Stages that are already in progress keep their
+    /// old timing.
+    pub fn set_sample_rate(&self, sample_rate: f32) {
+        self.sample_rate.store(sample_rate, Ordering::Relaxed);
+    }
+
+    /// Start the attack stage, ramping from the current value to the peak value. Triggering the
+    /// envelope while it's still active continues from its current value, so this never causes a
+    /// jump.
+    pub fn trigger(&self) {
+        self.start_stage(EnvelopeStage::Attack, self.peak, self.attack_ms);
+    }
+
+    /// Start the release stage early, ramping from the current value down to the floor value. This
+    /// does nothing if the envelope is idle.
+    pub fn release(&self) {
+        if self.stage.load() != EnvelopeStage::Idle {
+            self.start_stage(EnvelopeStage::Release, self.floor, self.release_ms);
+        }
+    }
+
+    /// Stop the envelope and jump straight to the floor value.
+    pub fn reset(&self) {
+        self.stage.store(EnvelopeStage::Idle);
+        self.steps_left.store(0, Ordering::Relaxed);
+        self.current.store(self.floor, Ordering::Relaxed);
+    }
+
+    /// The stage the envelope is currently in.
+    pub fn stage(&self) -> EnvelopeStage {
+        self.stage.load()
+    }
+
+    /// Whether the envelope is in its attack or release stage.
+    pub fn is_active(&self) -> bool {
+        self.stage() != EnvelopeStage::Idle
+    }
+
+    /// The envelope's current value, without advancing it. This can be called from the editor.
+    pub fn value(&self) -> f32 {
+        self.current.load(Ordering::Relaxed)
+    }
+
+    /// Advance the envelope by one sample and return the new value. The last sample of the attack
+    /// stage is exactly the peak value, and the last sample of the release stage is exactly the
+    /// floor value.
     #[allow(clippy::should_implement_trait)]
-    pub fn next(&mut self) -> i32 {
-        if self.steps_left.load(Ordering::Relaxed) > 1 {
-            let current = self.current.load(Ordering::Relaxed);
-
-            // The number of steps usually won't fit exactly, so make sure we don't do weird things
-            // with overshoots or undershoots
-            let old_steps_left = self.steps_left.fetch_sub(1, Ordering::Relaxed);
-            let new = if old_steps_left == 1 {
-                self.target as f32
-            } else {
-                match &self.style {
-                    SmoothingStyle::None => self.target as f32,
-                    SmoothingStyle::Linear(_) => current + self.step_size,
-                    SmoothingStyle::Logarithmic(_) => current * self.step_size,
+    pub fn next(&self) -> f32 {
+        let stage = self.stage.load();
+        let (stage_target, next_stage) = match stage {
+            EnvelopeStage::Idle => return self.current.load(Ordering::Relaxed),
+            EnvelopeStage::Attack => (self.peak, EnvelopeStage::Release),
+            EnvelopeStage::Release => (self.floor, EnvelopeStage::Idle),
+        };
+
+        let steps_left = self.steps_left.load(Ordering::Relaxed).saturating_sub(1);
+        if steps_left == 0 {
+            // This snaps to the target to avoid accumulating rounding errors
+            self.current.store(stage_target, Ordering::Relaxed);
+            match next_stage {
+                EnvelopeStage::Release => {
+                    self.start_stage(EnvelopeStage::Release, self.floor, self.release_ms)
                 }
-            };
-            self.current.store(new, Ordering::Relaxed);
+                _ => self.stage.store(next_stage),
+            }
 
-            new.round() as i32
+            stage_target
         } else {
-            self.target
+            self.steps_left.store(steps_left, Ordering::Relaxed);
+            let new = self.current.load(Ordering::Relaxed) + self.step_size.load(Ordering::Relaxed);
+            self.current.store(new, Ordering::Relaxed);
+
+            new
         }
     }
+
+    /// Start ramping from the current value to `target` over `time_ms` milliseconds.
+    fn start_stage(&self, stage: EnvelopeStage, target: f32, time_ms: f32) {
+        // There should always be at least one step or we would end up dividing by zero
+        let steps =
+            ((self.sample_rate.load(Ordering::Relaxed) * time_ms / 1000.0).round() as u32).max(1);
+        let current = self.current.load(Ordering::Relaxed);
+        self.step_size
+            .store((target - current) / steps as f32, Ordering::Relaxed);
+        self.steps_left.store(steps, Ordering::Relaxed);
+        self.stage.store(stage);
+    }
+}
+
+/// Compute the step sizes for logarithmic smoothing from `current` to `target` in `steps` steps.
+/// Returns the multiplicative step size, the linear step size for crossing zero, and the number of
+/// steps at the end that are taken logarithmically. A logarithmic curve can't touch or cross zero,
+/// so if `current` and `target` are not both positive or both negative, then the first steps move
+/// linearly to a value close to zero with the same sign as the target, and the logarithmic part
+/// takes over from there.
+fn logarithmic_step_sizes(current: f32, target: f32, steps: u32) -> (f32, f32, u32) {
+    if current * target > 0.0 {
+        // We need to solve `current * (step_size ^ steps) = target` for `step_size`
+        return ((target / current).powf((steps as f32).recip()), 0.0, steps);
+    }
+
+    // Smoothing to zero can only be done linearly
+    let (log_start, linear_steps) = if target == 0.0 {
+        (target, steps)
+    } else {
+        (
+            target * ZERO_CROSSING_LOG_START,
+            ((steps as f32 * ZERO_CROSSING_LINEAR_FRACTION).round() as u32).clamp(1, steps),
+        )
+    };
+    let log_steps = steps - linear_steps;
+    let step_size = if log_steps > 0 {
+        (target / log_start).powf((log_steps as f32).recip())
+    } else {
+        1.0
+    };
+
+    (
+        step_size,
+        (log_start - current) / linear_steps as f32,
+        log_steps,
+    )
 }
 
 #[cfg(test)]
@@ -268,4 +606,231 @@ mod tests {
         assert_ne!(smoother.next(), 20);
         assert_eq!(smoother.next(), 20);
     }
+
+    #[test]
+    fn logarithmic_f32_smoothing_reaches_target() {
+        for (start, target) in [
+            (10.0, 20.0),
+            (20.0, 10.0),
+            (1.0, 20_000.0),
+            (20_000.0, 1.0),
+            (440.0, 441.0),
+            (0.001, 1000.0),
+        ] {
+            let mut smoother: Smoother<f32> = Smoother::new(SmoothingStyle::Logarithmic(100.0));
+            smoother.reset(start);
+            smoother.set_target(44_100.0, target);
+
+            // The smoother should move towards the target without ever overshooting it
+            let mut previous = start;
+            for _ in 0..4410 {
+                let current = smoother.next();
+                if target > start {
+                    assert!(current >= previous && current <= target);
+                } else {
+                    assert!(current <= previous && current >= target);
+                }
+
+                previous = current;
+            }
+
+            assert_eq!(previous, target);
+            assert!(!smoother.is_smoothing());
+            assert_eq!(smoother.next(), target);
+        }
+    }
+
+    #[test]
+    fn logarithmic_smoothing_crosses_zero() {
+        for (start, target) in [(0.0, 1.0), (1.0, -1.0), (-0.5, 2.0), (1.0, 0.0)] {
+            let mut smoother: Smoother<f32> = Smoother::new(SmoothingStyle::Logarithmic(100.0));
+            smoother.reset(start);
+            smoother.set_target(44_100.0, target);
+
+            // This should still move towards the target without producing NaN or infinite values
+            let mut previous = start;
+            for _ in 0..4410 {
+                let current = smoother.next();
+                assert!(current.is_finite());
+                if target > start {
+                    assert!(current >= previous && current <= target);
+                } else {
+                    assert!(current <= previous && current >= target);
+                }
+
+                previous = current;
+            }
+
+            assert_eq!(previous, target);
+            assert!(!smoother.is_smoothing());
+        }
+    }
+
+    #[test]
+    fn logarithmic_i32_smoothing_crosses_zero() {
+        let mut smoother: Smoother<i32> = Smoother::new(SmoothingStyle::Logarithmic(100.0));
+        smoother.reset(0);
+        smoother.set_target(100.0, 10);
+
+        let mut previous = 0;
+        for _ in 0..10 {
+            let current = smoother.next();
+            assert!((previous..=10).contains(&current));
+            previous = current;
+        }
+        assert_eq!(previous, 10);
+    }
+
+    #[test]
+    fn next_block_matches_next() {
+        let styles: [fn(f32) -> SmoothingStyle; 2] =
+            [SmoothingStyle::Linear, SmoothingStyle::Logarithmic];
+        for style in styles {
+            let mut block_smoother: Smoother<f32> = Smoother::new(style(100.0));
+            block_smoother.reset(10.0);
+            block_smoother.set_target(100.0, 20.0);
+            let mut smoother: Smoother<f32> = Smoother::new(style(100.0));
+            smoother.reset(10.0);
+            smoother.set_target(100.0, 20.0);
+
+            // The second block continues where the first one left off and runs past the end of
+            // the smoothing period. Only the first `n` values should be written.
+            for n in [4, 12] {
+                let mut block = [0.0; 16];
+                block_smoother.next_block(&mut block, n);
+                for value in &block[..n] {
+                    assert_eq!(*value, smoother.next());
+                }
+                assert!(block[n..].iter().all(|value| *value == 0.0));
+            }
+
+            assert!(!block_smoother.is_smoothing());
+            assert_eq!(block_smoother.next(), 20.0);
+        }
+    }
+
+    #[test]
+    fn sample_rate_change_during_smoothing() {
+        let styles: [fn(f32) -> SmoothingStyle; 2] =
+            [SmoothingStyle::Linear, SmoothingStyle::Logarithmic];
+        for style in styles {
+            let mut smoother: Smoother<f32> = Smoother::new(style(100.0));
+            smoother.reset(10.0);
+            smoother.set_target(100.0, 20.0);
+            let mut previous = 10.0;
+            let mut last_step_size = 0.0;
+            for _ in 0..5 {
+                let current = smoother.next();
+                last_step_size = current - previous;
+                previous = current;
+            }
+
+            // Halfway through the smoothing period the sample rate doubles. The remaining 50 ms
+            // now take 10 steps instead of 5, and the smoother should continue from where it was
+            // with smaller steps instead of jumping.
+            smoother.set_sample_rate(200.0);
+            for _ in 0..(10 - 1) {
+                let current = smoother.next();
+                assert!(current > previous && current < 20.0);
+                assert!(current - previous < last_step_size);
+                previous = current;
+            }
+            assert_eq!(smoother.next(), 20.0);
+            assert!(!smoother.is_smoothing());
+
+            // Without any smoothing in progress this only changes the sample rate used for the next
+            // target
+            smoother.set_sample_rate(1000.0);
+            assert!(!smoother.is_smoothing());
+            smoother.set_target(100.0, 10.0);
+            for _ in 0..10 {
+                assert!(smoother.is_smoothing());
+                smoother.next();
+            }
+            assert!(!smoother.is_smoothing());
+        }
+    }
+
+    #[test]
+    fn envelope_attack_reaches_peak() {
+        // At this sample rate the attack and release times are 10 and 20 samples
+        let envelope = Envelope::new(10.0, 20.0).with_range(0.5, 2.0);
+        envelope.set_sample_rate(1000.0);
+        assert_eq!(envelope.next(), 0.5);
+        assert!(!envelope.is_active());
+
+        envelope.trigger();
+        let mut previous = 0.5;
+        for _ in 0..(10 - 1) {
+            let current = envelope.next();
+            assert!(current > previous && current < 2.0);
+            assert_eq!(envelope.stage(), EnvelopeStage::Attack);
+            previous = current;
+        }
+        assert_eq!(envelope.next(), 2.0);
+        assert_eq!(envelope.value(), 2.0);
+        assert_eq!(envelope.stage(), EnvelopeStage::Release);
+    }
+
+    #[test]
+    fn envelope_release_reaches_floor() {
+        let envelope = Envelope::new(10.0, 20.0);
+        envelope.set_sample_rate(1000.0);
+        envelope.trigger();
+        for _ in 0..10 {
+            envelope.next();
+        }
+
+        // The release stage starts automatically once the peak has been reached
+        let mut previous = 1.0;
+        for _ in 0..(20 - 1) {
+            let current = envelope.next();
+            assert!(current < previous && current > 0.0);
+            previous = current;
+        }
+        assert_eq!(envelope.next(), 0.0);
+        assert_eq!(envelope.stage(), EnvelopeStage::Idle);
+        assert_eq!(envelope.next(), 0.0);
+    }
+
+    #[test]
+    fn envelope_early_release_and_retrigger() {
+        let envelope = Envelope::new(10.0, 20.0);
+        envelope.set_sample_rate(1000.0);
+        envelope.release();
+        assert!(!envelope.is_active());
+
+        // Releasing halfway through the attack ramps down from the current value over the full
+        // release time
+        envelope.trigger();
+        for _ in 0..5 {
+            envelope.next();
+        }
+        assert!((envelope.value() - 0.5).abs() < 1e-6);
+        envelope.release();
+        for _ in 0..(20 - 1) {
+            assert!(envelope.next() > 0.0);
+        }
+        assert!(envelope.is_active());
+
+        // Retriggering continues from the current value instead of jumping back to the floor
+        let value_before_trigger = envelope.value();
+        envelope.trigger();
+        let current = envelope.next();
+        assert!(current > value_before_trigger && current - value_before_trigger < 0.2);
+    }
+
+    #[test]
+    fn smoothing_stops_at_target() {
+        let mut smoother: Smoother<f32> = Smoother::new(SmoothingStyle::Linear(100.0));
+        smoother.reset(10.0);
+        smoother.set_target(100.0, 20.0);
+        for _ in 0..10 {
+            assert!(smoother.is_smoothing());
+            smoother.next();
+        }
+
+        assert!(!smoother.is_smoothing());
+        assert_eq!(smoother.next(), 20.0);
+    }
 }
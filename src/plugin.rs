@@ -18,10 +18,12 @@ use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
 use std::any::Any;
 use std::pin::Pin;
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::buffer::Buffer;
-use crate::context::{GuiContext, ProcessContext};
+use crate::context::{GuiContext, ProcessContext, TransportRequirements};
 use crate::param::internals::Params;
+use crate::wrapper::state::State;
 
 /// Basic functionality that needs to be implemented by a plugin. The wrappers will use this to
 /// expose the plugin in a particular plugin format.
@@ -29,17 +31,11 @@ use crate::param::internals::Params;
 /// This is super basic, and lots of things I didn't need or want to use yet haven't been
 /// implemented. Notable missing features include:
 ///
-/// - Sidechain inputs
 /// - Multiple output busses
 /// - Special handling for offline processing
-/// - Transport and other context information in the process call
 /// - Sample accurate automation (this would be great, but sadly few hosts even support it so until
 ///   they do we'll ignore that it's a thing)
-/// - Parameter hierarchies/groups
-/// - Bypass parameters, right now the VST3 wrapper generates one for you
-/// - Outputting parameter changes from the plugin
-/// - MIDI CC handling
-/// - Outputting MIDI events
+/// - Bypass parameters, right now the wrappers generate one for you
 #[allow(unused_variables)]
 pub trait Plugin: Default + Send + Sync + 'static {
     const NAME: &'static str;
@@ -58,10 +54,116 @@ pub trait Plugin: Default + Send + Sync + 'static {
     /// instead of setting up the busses properly.
     const DEFAULT_NUM_OUTPUTS: u32 = 2;
 
+    /// The default channel counts for the plugin's auxiliary input busses, like sidechain inputs.
+    /// These busses are reported to the host as auxiliary busses next to the main input bus, so
+    /// hosts will only automatically connect the main bus and leave these for the user to route.
+    /// They are also inactive by default. The number of auxiliary busses is fixed, but hosts can
+    /// propose different channel counts for them which are checked using
+    /// [Self::accepts_bus_config()]. During processing their audio can be accessed through
+    /// [Buffer::sidechain()] and [Buffer::aux_inputs()].
+    const AUX_INPUT_CHANNELS: &'static [u32] = &[];
+
     /// Whether the plugin accepts note events. If this is set to `false`, then the plugin won't
-    /// receive any note events.
+    /// receive any note events. MIDI effects that don't process any audio can use a bus
+    /// configuration without any input or output channels. The process function then still gets
+    /// called for every block, but with an empty buffer. VST3 hosts can also map MIDI CCs, channel
+    /// pressure, and pitch bend to automation lanes, and that automation is sent to the plugin as
+    /// the corresponding note events.
     const ACCEPTS_MIDI: bool = false;
 
+    /// Whether the plugin sends note events to the host, like a synth or an arpeggiator passing
+    /// notes on to the next plugin. If this is set to `true`, then the wrappers add a note or MIDI
+    /// output port, and events sent through [ProcessContext::send_event()] are passed on to the
+    /// host after the process function returns. Otherwise those events are discarded.
+    const PRODUCES_MIDI: bool = false;
+
+    /// If enabled, parameters that receive dense automation from the host will skip their
+    /// smoothers and jump straight to the automated value. The host's automation curve is already
+    /// smooth, so smoothing it again would only cause the parameter to lag behind the automation.
+    /// Automation is considered to be dense when the host sends more than one value for a parameter
+    /// during a single block. Sparse automation and changes made from the GUI are still smoothed.
+    const BYPASS_SMOOTHING_FOR_DENSE_AUTOMATION: bool = false;
+
+    /// Parameter changes from the host that differ less than this from the parameter's current
+    /// normalized value are ignored. Hosts often send the same value over and over again during
+    /// automation, and applying those would needlessly retarget the parameter's smoother and call
+    /// its `value_changed` callback. Setting this to `0.0` only ignores exactly identical values.
+    const PARAMETER_CHANGE_EPSILON: f32 = 1.0e-6;
+
+    /// The sample precision the plugin would prefer to process audio at. The wrapper will use
+    /// this precision if the host supports it, and it will fall back to single precision
+    /// otherwise. The precision that ended up being used is stored in
+    /// [BufferConfig::sample_precision]. nih-plug's buffers always contain single precision
+    /// samples, so at double precision the wrapper converts the host's buffers to and from single
+    /// precision around the process call. This only lets the plugin be used in hosts that only
+    /// support double precision processing, it does not increase the plugin's precision. Double
+    /// precision processing is currently only supported by the VST3 wrapper.
+    const PREFERRED_SAMPLE_PRECISION: SamplePrecision = SamplePrecision::Single;
+
+    /// The length of a linear fade-in applied to the plugin's output when the plugin gets
+    /// activated, in samples. Any nonzero initial state, like resonant filters ringing from their
+    /// initial state or smoothers that start below their target, could otherwise cause an audible
+    /// pop on the first block. Setting this to `0` disables the fade-in.
+    const ACTIVATION_FADE_IN_SAMPLES: u32 = 0;
+
+    /// If enabled, all parameter smoothers jump straight to their target values when the host
+    /// starts processing audio again, for instance after playback has been stopped. Otherwise a
+    /// smoother that was still in the middle of smoothing when processing stopped would cause a
+    /// brief glide at the start of playback. Plugins that want to keep their smoothers' state can
+    /// disable this.
+    const RESET_SMOOTHERS_ON_RESTART: bool = true;
+
+    /// Whether the wrapper enables the CPU's Flush To Zero and Denormals Are Zero modes while the
+    /// plugin processes audio, if the host has not already done so. Subnormal numbers are then
+    /// treated as zero, avoiding the massive slowdowns that come with processing them. Disabling
+    /// this leaves the host's floating point modes untouched, which can be useful for bit-exact
+    /// testing against a reference implementation. This is independent of the
+    /// `assert_process_allocs` feature, which only affects allocations in debug builds. See
+    /// [crate::util::ScopedFtz] for doing the same thing on other threads.
+    const ENABLE_FTZ: bool = true;
+
+    /// If set, the plugin's state is periodically written to a recovery file in the user's
+    /// configuration directory from a background thread. If the host crashes, then the editor
+    /// can offer to restore this state using [GuiContext::restore_autosaved_state()] the next time
+    /// the plugin is loaded. This is useful for plugins that hold state that's expensive to
+    /// recreate, like recorded loops or sampled audio.
+    const AUTOSAVE_INTERVAL: Option<Duration> = None;
+
+    /// The version of the plugin's state format. This is stored in the plugin's state, and it
+    /// should be incremented whenever the persistent fields change in a way that makes older
+    /// states incompatible. When an older state gets loaded, [Self::migrate_state()] is called to
+    /// convert those fields to the current format before they are restored. States written by a
+    /// newer version of the plugin are refused.
+    const STATE_VERSION: u32 = 1;
+
+    /// Set this to `true` to store the plugin's state and its persistent fields in a compact binary
+    /// format instead of JSON. This is useful for plugins that persist large amounts of data, like
+    /// sample data or impulse responses. States saved in either format can always be loaded, so
+    /// this can be changed without breaking existing presets.
+    const BINARY_STATE: bool = false;
+
+    /// Set this to `true` for plugins that only analyze their input, like meters, and never modify
+    /// the audio. The [Buffer] passed to [Self::process()] will then not contain any output
+    /// channels. Instead, [Buffer::inputs()] contains read-only slices that point directly to the
+    /// host's input channels, so the plugin never sees a copy of the audio. The wrapper takes care
+    /// of passing the input through to the outputs.
+    const ANALYSIS_ONLY: bool = false;
+
+    /// Set this to `true` if the plugin needs to read its unmodified input after it has started
+    /// writing to the output, like a parallel compressor mixing in the dry signal. The input is
+    /// then also available through [Buffer::inputs()] and [Buffer::iter_mut_with_input()]. Most
+    /// hosts process audio in place, in which case the wrapper needs to copy the inputs to a
+    /// separate buffer first, so this is disabled by default. This has no effect for
+    /// [Self::ANALYSIS_ONLY] plugins, since those always read the host's inputs.
+    const SEPARATE_INPUT_BUFFERS: bool = false;
+
+    /// The transport information the plugin reads through [ProcessContext::transport()]. VST3
+    /// hosts that support it only compute the requested information, so plugins that don't need
+    /// the tempo or the musical position can use [TransportRequirements::NONE] or only request the
+    /// parts they need. Everything is requested by default. The position in samples is always
+    /// available.
+    const TRANSPORT_REQUIREMENTS: TransportRequirements = TransportRequirements::ALL;
+
     /// The plugin's parameters. The host will update the parameter values before calling
     /// `process()`. These parameters are identified by strings that should never change when the
     /// plugin receives an update.
@@ -71,7 +173,9 @@ pub trait Plugin: Default + Send + Sync + 'static {
     /// [Editor::spawn]. A plugin editor likely wants to interact with the plugin's parameters and
     /// other shared data, so you'll need to move [Arc] pointing to any data you want to access into
     /// the editor. You can later modify the parameters through the [GuiContext] and [ParamSetter]
-    /// after the editor GUI has been created.
+    /// after the editor GUI has been created. This function is only called once when the plugin
+    /// gets instantiated, so use [Editor::is_available()] if the editor should not always be
+    /// available.
     fn editor(&self) -> Option<Box<dyn Editor>> {
         None
     }
@@ -81,9 +185,45 @@ pub trait Plugin: Default + Send + Sync + 'static {
     //
 
     /// Whether the plugin supports a bus config. This only acts as a check, and the plugin
-    /// shouldn't do anything beyond returning true or false.
+    /// shouldn't do anything beyond returning true or false. When the plugin rejects all of the
+    /// host's proposed configurations, the host falls back to [Self::DEFAULT_NUM_INPUTS] and
+    /// [Self::DEFAULT_NUM_OUTPUTS], so that configuration should always be accepted. Rejected
+    /// configurations are logged in debug builds to help with diagnosing hosts that fail to load
+    /// the plugin. Plugins with auxiliary input busses should also check
+    /// [BusConfig::aux_input_channels] when overriding this. The number of input and output
+    /// channels don't need to match. For a mono-to-stereo plugin the extra output channels start
+    /// out silent in [Self::process()], and for a stereo-to-mono plugin only the first input
+    /// channel gets copied to the output. Enable [Self::SEPARATE_INPUT_BUFFERS] to read all of the
+    /// input channels in that case.
     fn accepts_bus_config(&self, config: &BusConfig) -> bool {
-        config.num_input_channels == 2 && config.num_output_channels == 2
+        config.num_input_channels == 2
+            && config.num_output_channels == 2
+            && config.aux_input_channels == Self::AUX_INPUT_CHANNELS
+    }
+
+    /// Verify that the plugin's DSP works as expected before it gets initialized, for instance by
+    /// checking a filter's output for a known input. This catches issues like miscompiled SIMD code
+    /// or bad coefficient tables before any audio is processed. This is called once when the
+    /// plugin gets loaded, and if it returns an error then that error is logged and the plugin
+    /// refuses to initialize. This should be quick.
+    fn self_test(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Called after the plugin's state has been restored, for instance when a project is loaded.
+    /// The parameters and persistent fields contain their restored values at this point. This can
+    /// be used to check whether restored resources like
+    /// [crate::param::file_path::FilePath]s still exist. If the plugin has already been
+    /// initialized, then [Self::initialize()] is called again right after this.
+    fn state_restored(&mut self) {}
+
+    /// Convert a state saved with an older [Self::STATE_VERSION] to the current format. This is
+    /// called before the state gets restored, with `from_version` being that state's version. Both
+    /// the parameter values and the persistent fields can be changed here, for instance to rename
+    /// a parameter or to convert a field to a new format. Migrating across multiple versions needs
+    /// to happen in this single call. By default the state is left unchanged.
+    fn migrate_state(&self, from_version: u32, state: &mut State) {
+        let _ = (from_version, state);
     }
 
     /// Initialize the plugin for the given bus and buffer configurations. If the plugin is being
@@ -104,16 +244,28 @@ pub trait Plugin: Default + Send + Sync + 'static {
         true
     }
 
+    /// Called instead of [Self::initialize()] when the host changes the sample rate without
+    /// changing anything else about the plugin's bus or buffer configuration. This lets the plugin
+    /// adapt its existing state to the new sample rate, for instance by resampling its delay lines,
+    /// instead of resetting everything and causing an audible glitch. Return `true` if the plugin
+    /// handled the change. The default implementation returns `false`, in which case the plugin is
+    /// fully reinitialized like it normally would be.
+    fn sample_rate_changed(
+        &mut self,
+        old_sample_rate: f32,
+        new_sample_rate: f32,
+        context: &mut impl ProcessContext,
+    ) -> bool {
+        false
+    }
+
     /// Process audio. The host's input buffers have already been copied to the output buffers if
     /// they are not processing audio in place (most hosts do however). All channels are also
     /// guarenteed to contain the same number of samples. Lastly, denormals have already been taken
     /// case of by NIH-plug, and you can optionally enable the `assert_process_allocs` feature to
     /// abort the program when any allocation accurs in the process function while running in debug
-    /// mode.
-    ///
-    /// TODO: Provide a way to access auxiliary input channels if the IO configuration is
-    ///       assymetric
-    /// TODO: Pass transport and other context information to the plugin
+    /// mode. If the plugin panics during processing or initialization, then the panic is logged
+    /// and the plugin won't be initialized or processed again for the rest of its lifetime.
     fn process(&mut self, buffer: &mut Buffer, context: &mut impl ProcessContext) -> ProcessStatus;
 }
 
@@ -122,10 +274,119 @@ pub trait Vst3Plugin: Plugin {
     /// The unique class ID that identifies this particular plugin. You can use the
     /// `*b"fooofooofooofooo"` syntax for this.
     const VST3_CLASS_ID: [u8; 16];
-    /// One or more categories, separated by pipe characters (`|`), up to 127 characters. Anything
-    /// logner than that will be truncated. See the VST3 SDK for examples of common categories:
-    /// <https://github.com/steinbergmedia/vst3_pluginterfaces/blob/2ad397ade5b51007860bedb3b01b8afd2c5f6fba/vst/ivstaudioprocessor.h#L49-L90>
-    const VST3_CATEGORIES: &'static str;
+    /// Class IDs previously used by this plugin. If you change [Self::VST3_CLASS_ID] after
+    /// releasing a plugin, then adding the old IDs here lets hosts replace the old plugin with this
+    /// one when loading existing projects. This is reported through VST3's
+    /// `IPluginCompatibility` interface.
+    const VST3_COMPATIBILITY_IDS: &'static [[u8; 16]] = &[];
+    /// The plugin's categories. These should start with one of the main categories,
+    /// [Vst3SubCategory::Fx], [Vst3SubCategory::Instrument], or [Vst3SubCategory::Spatial],
+    /// followed by more specific categories. A synthesizer would use `&[Vst3SubCategory::Instrument,
+    /// Vst3SubCategory::Synth]`. The host receives these as a single string of categories
+    /// separated by pipe characters, and that string can't be longer than 127 characters. Any
+    /// categories that don't fit are left out.
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory];
+    /// Whether the plugin's processor and edit controller can run on different computers. This is
+    /// reported to the host as the `kDistributable` class flag.
+    const VST3_DISTRIBUTABLE: bool = false;
+    /// Whether the plugin supports VST3's simple IO mode. This is reported to the host as the
+    /// `kSimpleModeSupported` class flag.
+    const VST3_SIMPLE_MODE_SUPPORTED: bool = true;
+}
+
+/// A category for a VST3 plugin, used in [Vst3Plugin::VST3_SUBCATEGORIES]. Hosts use these to
+/// organize their plugin browsers. See the VST3 SDK for the exact meanings:
+/// <https://github.com/steinbergmedia/vst3_pluginterfaces/blob/2ad397ade5b51007860bedb3b01b8afd2c5f6fba/vst/ivstaudioprocessor.h#L49-L90>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Vst3SubCategory {
+    // These are the main categories, every plugin should start with one of these
+    Fx,
+    Instrument,
+    Spatial,
+    // These further describe what the plugin does
+    Analyzer,
+    Delay,
+    Distortion,
+    Drum,
+    Dynamics,
+    Eq,
+    External,
+    Filter,
+    Generator,
+    Mastering,
+    Modulation,
+    Network,
+    Piano,
+    PitchShift,
+    Restoration,
+    Reverb,
+    Sampler,
+    Synth,
+    Tools,
+    UpDownmix,
+    // These describe the channel configurations the plugin supports
+    Mono,
+    Stereo,
+    Surround,
+    Ambisonics,
+    // And these describe the processing modes the plugin supports
+    OnlyRealTime,
+    OnlyOfflineProcess,
+    NoOfflineProcess,
+    /// A category that's not listed here. This should not contain any pipe characters.
+    Custom(&'static str),
+}
+
+impl Vst3SubCategory {
+    /// The string the VST3 SDK uses for this category.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Vst3SubCategory::Fx => "Fx",
+            Vst3SubCategory::Instrument => "Instrument",
+            Vst3SubCategory::Spatial => "Spatial",
+            Vst3SubCategory::Analyzer => "Analyzer",
+            Vst3SubCategory::Delay => "Delay",
+            Vst3SubCategory::Distortion => "Distortion",
+            Vst3SubCategory::Drum => "Drum",
+            Vst3SubCategory::Dynamics => "Dynamics",
+            Vst3SubCategory::Eq => "EQ",
+            Vst3SubCategory::External => "External",
+            Vst3SubCategory::Filter => "Filter",
+            Vst3SubCategory::Generator => "Generator",
+            Vst3SubCategory::Mastering => "Mastering",
+            Vst3SubCategory::Modulation => "Modulation",
+            Vst3SubCategory::Network => "Network",
+            Vst3SubCategory::Piano => "Piano",
+            Vst3SubCategory::PitchShift => "Pitch Shift",
+            Vst3SubCategory::Restoration => "Restoration",
+            Vst3SubCategory::Reverb => "Reverb",
+            Vst3SubCategory::Sampler => "Sampler",
+            Vst3SubCategory::Synth => "Synth",
+            Vst3SubCategory::Tools => "Tools",
+            Vst3SubCategory::UpDownmix => "Up-Downmix",
+            Vst3SubCategory::Mono => "Mono",
+            Vst3SubCategory::Stereo => "Stereo",
+            Vst3SubCategory::Surround => "Surround",
+            Vst3SubCategory::Ambisonics => "Ambisonics",
+            Vst3SubCategory::OnlyRealTime => "OnlyRT",
+            Vst3SubCategory::OnlyOfflineProcess => "OnlyOfflineProcess",
+            Vst3SubCategory::NoOfflineProcess => "NoOfflineProcess",
+            Vst3SubCategory::Custom(category) => category,
+        }
+    }
+}
+
+/// Provides auxiliary metadata needed for a CLAP plugin.
+pub trait ClapPlugin: Plugin {
+    /// A unique ID that identifies this particular plugin. This is usually in reverse domain name
+    /// notation, e.g. `com.manufacturer.plugin-name`.
+    const CLAP_ID: &'static str;
+    /// A short description for the plugin.
+    const CLAP_DESCRIPTION: &'static str;
+    /// Keywords describing the plugin. The host may use these to categorize the plugin. See the
+    /// CLAP repository for a list of standard features:
+    /// <https://github.com/free-audio/clap/blob/main/include/clap/plugin-features.h>
+    const CLAP_FEATURES: &'static [&'static str];
 }
 
 /// An editor for a [Plugin].
@@ -148,15 +409,35 @@ pub trait Editor: Send + Sync {
     //       instance.
     fn spawn(&self, parent: ParentWindowHandle, context: Arc<dyn GuiContext>) -> Box<dyn Any>;
 
-    /// Return the (currnent) size of the editor in pixels as a `(width, height)` pair.
+    /// Return the (currnent) size of the editor in pixels as a `(width, height)` pair. This is the
+    /// size before any DPI scaling, the wrapper multiplies it by the scale factor passed to
+    /// [Self::set_scale_factor()] when reporting the size to the host.
     fn size(&self) -> (u32, u32);
 
+    /// Set the editor's DPI scaling factor. Hosts usually do this before the editor gets opened,
+    /// and any windows created by the editor should then have their sizes multiplied by this
+    /// factor. Hosts that don't support DPI scaling never call this, so editors should default to
+    /// a scale factor of 1.0. Return `false` if the editor doesn't support scaling, in which case
+    /// the host's scale factor is ignored. This is not called on macOS, where the operating system
+    /// takes care of the scaling. The default implementation returns `false`.
+    fn set_scale_factor(&self, factor: f32) -> bool {
+        let _ = factor;
+        false
+    }
+
+    /// Whether the editor can currently be opened. This is checked every time the host tries to
+    /// open the editor, so it can be used to only enable the editor under certain conditions, like
+    /// after a successful license check. Since this may be called while the plugin is processing
+    /// audio, any state needed for this decision should be shared with the editor through an
+    /// atomic or another thread safe type. The default implementation always returns `true`.
+    fn is_available(&self) -> bool {
+        true
+    }
+
     // TODO: Reconsider adding a tick function here for the Linux `IRunLoop`. To keep this platform
     //       and API agnostic, add a way to ask the GuiContext if the wrapper already provides a
     //       tick function. If it does not, then the Editor implementation must handle this by
     //       itself. This would also need an associated `PREFERRED_FRAME_RATE` constant.
-    // TODO: Add the things needed for DPI scaling
-    // TODO: Resizing
 }
 
 /// A raw window handle for platform and GUI framework agnostic editors.
@@ -170,13 +451,17 @@ unsafe impl HasRawWindowHandle for ParentWindowHandle {
     }
 }
 
-/// We only support a single main input and output bus at the moment.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The plugin's bus layout. We support a single main input and output bus, plus the auxiliary
+/// input busses declared in [Plugin::AUX_INPUT_CHANNELS].
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct BusConfig {
     /// The number of input channels for the plugin.
     pub num_input_channels: u32,
     /// The number of output channels for the plugin.
     pub num_output_channels: u32,
+    /// The number of channels for each of the plugin's auxiliary input busses. This always
+    /// contains one element for every bus in [Plugin::AUX_INPUT_CHANNELS].
+    pub aux_input_channels: Vec<u32>,
 }
 
 /// Configuration for (the host's) audio buffers.
@@ -187,9 +472,22 @@ pub struct BufferConfig {
     /// The maximum buffer size the host will use. The plugin should be able to accept variable
     /// sized buffers up to this size.
     pub max_buffer_size: u32,
+    /// The sample precision negotiated with the host, see [Plugin::PREFERRED_SAMPLE_PRECISION].
+    pub sample_precision: SamplePrecision,
 }
 
-/// Indicates the current situation after the plugin has processed audio.
+/// The precision of the samples in the audio buffers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SamplePrecision {
+    /// 32-bit floating point samples.
+    Single,
+    /// 64-bit floating point samples.
+    Double,
+}
+
+/// Indicates the current situation after the plugin has processed audio. The last returned status
+/// is reported to the host as the plugin's tail length. Instruments can use
+/// [crate::util::VoiceTracker] to end their tail once all voices have finished playing.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ProcessStatus {
     /// Something went wrong while processing audio.
@@ -205,26 +503,92 @@ pub enum ProcessStatus {
     KeepAlive,
 }
 
-/// Event for (incoming) notes. Right now this only supports a very small subset of the MIDI
-/// specification. See the util module for convenient conversion functions.
+/// Event for incoming and outgoing notes. Right now this only supports a very small subset of the
+/// MIDI specification. See the util module for convenient conversion functions.
 ///
 /// All of the timings are sample offsets withing the current buffer.
 ///
 /// TODO: Add more events as needed
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum NoteEvent {
+    /// A note on event. `note_id` is the host's identifier for this note if it provided one. The
+    /// polyphonic expression events below use the same identifier, so a synth can use it to find
+    /// the voice an expression event belongs to.
     NoteOn {
         timing: u32,
+        note_id: Option<i32>,
         channel: u8,
         note: u8,
         velocity: u8,
     },
+    /// A note off event. `note_id` matches the identifier of the corresponding
+    /// [NoteEvent::NoteOn] event, if the host provided one.
     NoteOff {
         timing: u32,
+        note_id: Option<i32>,
         channel: u8,
         note: u8,
         velocity: u8,
     },
+    /// A polyphonic key pressure (aftertouch) event for a single note. `pressure` is normalized to
+    /// `[0, 1]`. `note_id` is only set if the host sent this as a VST3 or CLAP event for a note
+    /// with an identifier, and it's `None` for MIDI polyphonic key pressure messages.
+    PolyPressure {
+        timing: u32,
+        note_id: Option<i32>,
+        channel: u8,
+        note: u8,
+        pressure: f32,
+    },
+    /// A per-note pitch bend, or tuning, for the note with the specified `note_id`, as used for
+    /// MIDI Polyphonic Expression. `semitones` is the offset from the note's original pitch, in
+    /// the range `[-120, 120]`.
+    PolyPitchBend {
+        timing: u32,
+        note_id: i32,
+        semitones: f32,
+    },
+    /// A per-note brightness change for the note with the specified `note_id`. This corresponds to
+    /// the MPE slide, which is sent as CC 74 in MIDI. `brightness` is normalized to `[0, 1]`.
+    PolyBrightness {
+        timing: u32,
+        note_id: i32,
+        brightness: f32,
+    },
+    /// A MIDI program change. Hosts may send the bank for the program using a
+    /// [NoteEvent::MidiBankSelect] event right before this event. VST3 plugins can only send these
+    /// events, since VST3 hosts don't pass program changes to plugins as MIDI.
+    MidiProgramChange {
+        timing: u32,
+        channel: u8,
+        program: u8,
+    },
+    /// A MIDI bank select, sent through CC 0 (the bank's most significant byte) and CC 32 (the
+    /// least significant byte). `bank` is the 14-bit bank number formed by the last received
+    /// values for both controllers on this channel, and it applies to the next
+    /// [NoteEvent::MidiProgramChange].
+    MidiBankSelect { timing: u32, channel: u8, bank: u16 },
+    /// A MIDI control change. `value` is the controller's value normalized to `[0, 1]`. Bank
+    /// selects are sent as [NoteEvent::MidiBankSelect] events instead.
+    MidiCC {
+        timing: u32,
+        channel: u8,
+        cc: u8,
+        value: f32,
+    },
+    /// A MIDI pitch bend. `value` is the 14-bit pitch bend value normalized to `[0, 1]`. The
+    /// center value that means no pitch bend is `8192 / 16383`, which is very close to `0.5`.
+    MidiPitchBend {
+        timing: u32,
+        channel: u8,
+        value: f32,
+    },
+    /// A MIDI channel pressure (aftertouch) message. `pressure` is normalized to `[0, 1]`.
+    MidiChannelPressure {
+        timing: u32,
+        channel: u8,
+        pressure: f32,
+    },
 }
 
 impl NoteEvent {
@@ -233,6 +597,30 @@ impl NoteEvent {
         match &self {
             NoteEvent::NoteOn { timing, .. } => *timing,
             NoteEvent::NoteOff { timing, .. } => *timing,
+            NoteEvent::PolyPressure { timing, .. } => *timing,
+            NoteEvent::PolyPitchBend { timing, .. } => *timing,
+            NoteEvent::PolyBrightness { timing, .. } => *timing,
+            NoteEvent::MidiProgramChange { timing, .. } => *timing,
+            NoteEvent::MidiBankSelect { timing, .. } => *timing,
+            NoteEvent::MidiCC { timing, .. } => *timing,
+            NoteEvent::MidiPitchBend { timing, .. } => *timing,
+            NoteEvent::MidiChannelPressure { timing, .. } => *timing,
+        }
+    }
+
+    /// Change the sample within the current buffer this event belongs to.
+    pub fn set_timing(&mut self, new_timing: u32) {
+        match self {
+            NoteEvent::NoteOn { timing, .. } => *timing = new_timing,
+            NoteEvent::NoteOff { timing, .. } => *timing = new_timing,
+            NoteEvent::PolyPressure { timing, .. } => *timing = new_timing,
+            NoteEvent::PolyPitchBend { timing, .. } => *timing = new_timing,
+            NoteEvent::PolyBrightness { timing, .. } => *timing = new_timing,
+            NoteEvent::MidiProgramChange { timing, .. } => *timing = new_timing,
+            NoteEvent::MidiBankSelect { timing, .. } => *timing = new_timing,
+            NoteEvent::MidiCC { timing, .. } => *timing = new_timing,
+            NoteEvent::MidiPitchBend { timing, .. } => *timing = new_timing,
+            NoteEvent::MidiChannelPressure { timing, .. } => *timing = new_timing,
         }
     }
 }
@@ -14,6 +14,21 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+use crossbeam::atomic::AtomicCell;
+use std::cmp;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::atomic::{self, AtomicPtr, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::param::Enum;
+use crate::plugin::ProcessStatus;
+
+pub use self::meter::{GainReductionMeter, PeakMeter};
+
+pub mod meter;
+
 pub const MINUS_INFINITY_DB: f32 = -100.0;
 
 /// Convert decibels to a voltage gain ratio, treating anything below -100 dB as minus infinity.
@@ -38,7 +53,1045 @@ pub fn gain_to_db(gain: f32) -> f32 {
 /// Convert a MIDI note ID to a frequency at A4 = 440 Hz equal temperament and middle C = note 60 =
 /// C4.
 pub fn midi_note_to_freq(pitch: u8) -> f32 {
-    2.0f32.powf((pitch as f32 - 69.0) / 12.0) * 440.0
+    midi_note_to_freq_with_tuning(pitch, 440.0)
+}
+
+/// The same as [midi_note_to_freq()], but with A4 tuned to `a4_hz` instead of 440 Hz.
+pub fn midi_note_to_freq_with_tuning(pitch: u8, a4_hz: f32) -> f32 {
+    2.0f32.powf((pitch as f32 - 69.0) / 12.0) * a4_hz
+}
+
+/// Convert a frequency to a fractional MIDI note number using the same A4 = 440 Hz reference as
+/// [midi_note_to_freq()], of which this is the exact inverse for integer note numbers. The
+/// fractional part can be used to compute how many cents a frequency is off from the nearest note.
+/// The frequency should be positive.
+pub fn freq_to_midi_note(freq: f32) -> f32 {
+    freq_to_midi_note_with_tuning(freq, 440.0)
+}
+
+/// The same as [freq_to_midi_note()], but with A4 tuned to `a4_hz` instead of 440 Hz. This is the
+/// inverse of [midi_note_to_freq_with_tuning()].
+pub fn freq_to_midi_note_with_tuning(freq: f32, a4_hz: f32) -> f32 {
+    ((freq / a4_hz).log2() * 12.0) + 69.0
+}
+
+/// A note length for tempo synced delay times and LFO rates. Use this together with
+/// [NoteModifier] and [note_division_to_samples()]. Both implement [Enum], so they can be exposed
+/// directly as [crate::param::EnumParam]s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoteDivision {
+    Whole,
+    Half,
+    Quarter,
+    Eighth,
+    Sixteenth,
+    ThirtySecond,
+    SixtyFourth,
+}
+
+/// A modifier for a [NoteDivision].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoteModifier {
+    /// The note's regular length.
+    Straight,
+    /// One and a half times the note's regular length.
+    Dotted,
+    /// Two thirds of the note's regular length, so three triplets fit in the space of two regular
+    /// notes.
+    Triplet,
+}
+
+impl NoteDivision {
+    /// The length of this division in quarter notes, or beats.
+    pub fn beats(self) -> f32 {
+        match self {
+            NoteDivision::Whole => 4.0,
+            NoteDivision::Half => 2.0,
+            NoteDivision::Quarter => 1.0,
+            NoteDivision::Eighth => 0.5,
+            NoteDivision::Sixteenth => 0.25,
+            NoteDivision::ThirtySecond => 0.125,
+            NoteDivision::SixtyFourth => 0.0625,
+        }
+    }
+}
+
+impl NoteModifier {
+    /// The factor a note's length gets multiplied by.
+    pub fn factor(self) -> f32 {
+        match self {
+            NoteModifier::Straight => 1.0,
+            NoteModifier::Dotted => 1.5,
+            NoteModifier::Triplet => 2.0 / 3.0,
+        }
+    }
+}
+
+impl Enum for NoteDivision {
+    fn variants() -> &'static [&'static str] {
+        &["1/1", "1/2", "1/4", "1/8", "1/16", "1/32", "1/64"]
+    }
+
+    fn to_index(self) -> usize {
+        match self {
+            NoteDivision::Whole => 0,
+            NoteDivision::Half => 1,
+            NoteDivision::Quarter => 2,
+            NoteDivision::Eighth => 3,
+            NoteDivision::Sixteenth => 4,
+            NoteDivision::ThirtySecond => 5,
+            NoteDivision::SixtyFourth => 6,
+        }
+    }
+
+    fn from_index(index: usize) -> Self {
+        match index {
+            0 => NoteDivision::Whole,
+            1 => NoteDivision::Half,
+            2 => NoteDivision::Quarter,
+            3 => NoteDivision::Eighth,
+            4 => NoteDivision::Sixteenth,
+            5 => NoteDivision::ThirtySecond,
+            _ => NoteDivision::SixtyFourth,
+        }
+    }
+}
+
+impl Enum for NoteModifier {
+    fn variants() -> &'static [&'static str] {
+        &["Straight", "Dotted", "Triplet"]
+    }
+
+    fn to_index(self) -> usize {
+        match self {
+            NoteModifier::Straight => 0,
+            NoteModifier::Dotted => 1,
+            NoteModifier::Triplet => 2,
+        }
+    }
+
+    fn from_index(index: usize) -> Self {
+        match index {
+            0 => NoteModifier::Straight,
+            1 => NoteModifier::Dotted,
+            _ => NoteModifier::Triplet,
+        }
+    }
+}
+
+/// Convert a note length to a number of samples at the specified tempo and sample rate. The tempo
+/// can be taken from [crate::context::Transport::tempo]. The result is not rounded so it can be
+/// used directly for fractional delay lines.
+pub fn note_division_to_samples(
+    division: NoteDivision,
+    modifier: NoteModifier,
+    tempo_bpm: f64,
+    sample_rate: f32,
+) -> f32 {
+    let seconds_per_beat = 60.0 / tempo_bpm;
+    (division.beats() as f64 * modifier.factor() as f64 * seconds_per_beat * sample_rate as f64)
+        as f32
+}
+
+/// Replace all subnormal values in a buffer with zeroes. The process function already runs with
+/// the CPU's Flush To Zero flag enabled, but that does not apply to other threads (see [ScopedFtz]
+/// for that), and repeatedly accumulating very quiet signals (like with a meter's history) can
+/// otherwise leave you with a buffer full of subnormals. Operations on these values can be orders
+/// of magnitude slower than operations on normal floating point values.
+pub fn flush_denormals(samples: &mut [f32]) {
+    for sample in samples {
+        if sample.is_subnormal() {
+            *sample = 0.0;
+        }
+    }
+}
+
+/// Enable the CPU's Flush To Zero and Denormals Are Zero modes for the current thread while this
+/// object is in scope. With these modes enabled, subnormal results and inputs are treated as zero,
+/// which avoids the massive slowdowns that come with processing very quiet signals. The wrapper
+/// already does this for the plugin's process function, but these modes are per-thread. If your
+/// plugin does DSP work on its own threads, like a background worker rendering wavetables, then
+/// those threads should create a `ScopedFtz` before doing that work.
+///
+/// If these modes were not already enabled, then they will be restored to their old values when
+/// this gets dropped so they don't leak into the host's code. This currently only does something on
+/// x86 and x86_64 CPUs with SSE2 support.
+pub struct ScopedFtz {
+    /// The old contents of the MXCSR register to restore the FTZ and DAZ bits from, if they were
+    /// not already both set.
+    #[allow(dead_code)]
+    old_mxcsr: Option<u32>,
+    /// We can't directly implement !Send and !Sync, but this will do the same thing. This object
+    /// affects the current thread's floating point registers, so it may only be dropped on the
+    /// current thread.
+    send_sync_marker: PhantomData<*const ()>,
+}
+
+/// The Flush To Zero and Denormals Are Zero bits in the MXCSR register.
+#[cfg(all(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    target_feature = "sse2"
+))]
+pub(crate) const MXCSR_FTZ_DAZ: u32 = 0x8040;
+
+impl ScopedFtz {
+    pub fn enable() -> Self {
+        #[cfg(all(
+            any(target_arch = "x86", target_arch = "x86_64"),
+            target_feature = "sse2"
+        ))]
+        {
+            let mxcsr = unsafe { get_mxcsr() };
+            if mxcsr & MXCSR_FTZ_DAZ != MXCSR_FTZ_DAZ {
+                unsafe { set_mxcsr(mxcsr | MXCSR_FTZ_DAZ) };
+
+                return Self {
+                    old_mxcsr: Some(mxcsr),
+                    send_sync_marker: PhantomData,
+                };
+            }
+        }
+
+        Self {
+            old_mxcsr: None,
+            send_sync_marker: PhantomData,
+        }
+    }
+}
+
+impl Drop for ScopedFtz {
+    fn drop(&mut self) {
+        // Only the FTZ and DAZ bits are restored, the rest of the register may have been changed
+        // for other reasons in the meantime
+        #[cfg(all(
+            any(target_arch = "x86", target_arch = "x86_64"),
+            target_feature = "sse2"
+        ))]
+        if let Some(old_mxcsr) = self.old_mxcsr {
+            unsafe {
+                set_mxcsr((get_mxcsr() & !MXCSR_FTZ_DAZ) | (old_mxcsr & MXCSR_FTZ_DAZ));
+            }
+        }
+    }
+}
+
+/// Read the current thread's MXCSR register. The `_mm_getcsr()` intrinsic is deprecated in favor
+/// of inline assembly.
+#[cfg(all(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    target_feature = "sse2"
+))]
+pub(crate) unsafe fn get_mxcsr() -> u32 {
+    let mut mxcsr = 0u32;
+    std::arch::asm!("stmxcsr [{}]", in(reg) &mut mxcsr, options(nostack, preserves_flags));
+
+    mxcsr
+}
+
+/// Write to the current thread's MXCSR register.
+#[cfg(all(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    target_feature = "sse2"
+))]
+unsafe fn set_mxcsr(mxcsr: u32) {
+    std::arch::asm!("ldmxcsr [{}]", in(reg) &mxcsr, options(nostack, readonly, preserves_flags));
+}
+
+/// Compute the root mean square of a block of samples. This is the square root of the average of
+/// the squared samples. Returns zero for empty blocks. This considers the block in isolation, so
+/// for a meter that should respond smoothly across blocks you'll want to use an [RmsWindow]
+/// instead.
+pub fn rms(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+
+    let sum_of_squares: f32 = samples.iter().map(|sample| sample * sample).sum();
+    (sum_of_squares / samples.len() as f32).sqrt()
+}
+
+/// A sliding window RMS, as used by VU meters. Samples are pushed one at a time or in blocks, and
+/// [Self::rms()] returns the RMS of the last `window_len` samples regardless of how the samples
+/// were split up into blocks. Samples before the first pushed sample are treated as silence. This
+/// keeps a running sum so computing the RMS is cheap, and it does not allocate after it has been
+/// created.
+///
+/// The window can also be sized in milliseconds using [Self::set_duration()], which gives the meter
+/// a predictable response time regardless of the sample rate. Call [Self::reserve_duration()] from
+/// the plugin's initialization function with the longest window you'll use to be able to change the
+/// duration from the audio thread later without allocating.
+#[derive(Debug, Clone)]
+pub struct RmsWindow {
+    /// The squares of the last `window_len` samples, used as a ring buffer.
+    squares: Vec<f32>,
+    /// The index in `squares` the next sample will be written to.
+    position: usize,
+    /// The sum of `squares`. This is stored at a higher precision and periodically recomputed to
+    /// prevent rounding errors from accumulating.
+    sum: f64,
+}
+
+impl RmsWindow {
+    /// Create a window covering `window_len` samples. This should be at least one sample long.
+    pub fn new(window_len: usize) -> Self {
+        nih_debug_assert!(window_len > 0);
+
+        Self {
+            squares: vec![0.0; window_len.max(1)],
+            position: 0,
+            sum: 0.0,
+        }
+    }
+
+    /// Change the window's length and reset it. This allocates if the window grows larger than it
+    /// has been before, so this should be done from the plugin's initialization function.
+    pub fn resize(&mut self, window_len: usize) {
+        nih_debug_assert!(window_len > 0);
+
+        self.squares.resize(window_len.max(1), 0.0);
+        self.reset();
+    }
+
+    /// Preallocate enough room for a window of `max_window_ms` milliseconds at `sample_rate`. After
+    /// this, [Self::set_duration()] and [Self::resize()] won't allocate for windows up to that
+    /// length. This does not change the window's current length.
+    pub fn reserve_duration(&mut self, sample_rate: f32, max_window_ms: f32) {
+        let max_window_len = duration_to_window_len(sample_rate, max_window_ms);
+        self.squares
+            .reserve(max_window_len.saturating_sub(self.squares.len()));
+    }
+
+    /// Change the window's length to cover `window_ms` milliseconds at `sample_rate`, and reset it.
+    /// The length is rounded to the nearest whole sample, see [Self::duration_ms()] for the
+    /// resulting window duration. Like [Self::resize()] this only allocates if the window grows
+    /// larger than it has been before or than what was reserved with [Self::reserve_duration()].
+    pub fn set_duration(&mut self, sample_rate: f32, window_ms: f32) {
+        self.resize(duration_to_window_len(sample_rate, window_ms));
+    }
+
+    /// The number of samples covered by the window.
+    pub fn window_len(&self) -> usize {
+        self.squares.len()
+    }
+
+    /// The effective duration of the window in milliseconds at `sample_rate`. A change in the input
+    /// signal's level is fully reflected in [Self::rms()] after this amount of time.
+    pub fn duration_ms(&self, sample_rate: f32) -> f32 {
+        self.squares.len() as f32 / sample_rate * 1000.0
+    }
+
+    /// Reset the window to silence.
+    pub fn reset(&mut self) {
+        self.squares.fill(0.0);
+        self.position = 0;
+        self.sum = 0.0;
+    }
+
+    /// Add a single sample to the window, pushing out the oldest sample. Very quiet samples would
+    /// otherwise leave subnormals in the window, so squares that end up subnormal are flushed to
+    /// zero, see [flush_denormals()].
+    pub fn push(&mut self, sample: f32) {
+        let mut square = sample * sample;
+        if square.is_subnormal() {
+            square = 0.0;
+        }
+        self.sum += square as f64 - self.squares[self.position] as f64;
+        self.squares[self.position] = square;
+
+        self.position += 1;
+        if self.position == self.squares.len() {
+            self.position = 0;
+            self.sum = self.squares.iter().map(|&square| square as f64).sum();
+        }
+    }
+
+    /// Add a block of samples to the window, see [Self::push()].
+    pub fn push_slice(&mut self, samples: &[f32]) {
+        for &sample in samples {
+            self.push(sample);
+        }
+    }
+
+    /// The RMS of the last `window_len` samples.
+    pub fn rms(&self) -> f32 {
+        (self.sum.max(0.0) / self.squares.len() as f64).sqrt() as f32
+    }
+}
+
+/// The number of samples in `window_ms` milliseconds at `sample_rate`, rounded to the nearest sample
+/// and at least one sample long.
+fn duration_to_window_len(sample_rate: f32, window_ms: f32) -> usize {
+    nih_debug_assert!(sample_rate > 0.0 && window_ms > 0.0);
+
+    ((sample_rate * window_ms / 1000.0).round() as usize).max(1)
+}
+
+/// A change counter for data shared between the audio thread and the editor. The audio thread
+/// calls [Self::bump()] whenever it has written new data, like new meter values, and the editor
+/// can then use [Self::has_changed_since()] to only repaint when something has actually changed
+/// instead of redrawing on every frame. This is completely lock-free.
+#[derive(Debug, Default)]
+pub struct VersionCounter {
+    version: AtomicU32,
+}
+
+impl VersionCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Signal that the observed data has changed. Call this after the new data has been written.
+    pub fn bump(&self) {
+        // This is allowed to wrap around, it only needs to be different from the last version
+        self.version.fetch_add(1, Ordering::Release);
+    }
+
+    /// The current version. This changes every time [Self::bump()] is called.
+    pub fn version(&self) -> u32 {
+        self.version.load(Ordering::Acquire)
+    }
+
+    /// Check whether the data has changed since the last time this was called with the same
+    /// `last_seen` version. `last_seen` is updated to the current version.
+    pub fn has_changed_since(&self, last_seen: &mut u32) -> bool {
+        let version = self.version();
+        let changed = version != *last_seen;
+        *last_seen = version;
+
+        changed
+    }
+}
+
+/// Measures an editor's frame rate. Call [Self::tick()] once per frame, and [Self::fps()] returns
+/// the number of frames drawn during the last full second. All operations are atomic, so this can
+/// safely be shared between threads and editor instances.
+#[derive(Debug)]
+pub struct FrameCounter {
+    /// The point in time all of the timestamps below are relative to.
+    epoch: Instant,
+    /// The number of frames counted in the current measuring period.
+    frames: AtomicU32,
+    /// The number of frames per second computed at the end of the last measuring period.
+    fps: AtomicU32,
+    /// When the current measuring period started, in nanoseconds since `epoch`.
+    period_start_nanos: AtomicU64,
+    /// When the last frame was drawn, in nanoseconds since `epoch`.
+    last_frame_nanos: AtomicU64,
+    /// The time between the last two frames, in nanoseconds.
+    last_frame_time_nanos: AtomicU64,
+}
+
+impl Default for FrameCounter {
+    fn default() -> Self {
+        Self {
+            epoch: Instant::now(),
+            frames: AtomicU32::new(0),
+            fps: AtomicU32::new(0),
+            period_start_nanos: AtomicU64::new(0),
+            last_frame_nanos: AtomicU64::new(0),
+            last_frame_time_nanos: AtomicU64::new(0),
+        }
+    }
+}
+
+impl FrameCounter {
+    /// The length of a measuring period.
+    const PERIOD_NANOS: u64 = 1_000_000_000;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count a new frame. Returns the time since the last frame.
+    pub fn tick(&self) -> Duration {
+        self.tick_at(self.epoch.elapsed().as_nanos() as u64)
+    }
+
+    /// The number of frames counted during the last full second. This is zero until the first
+    /// second has passed.
+    pub fn fps(&self) -> u32 {
+        self.fps.load(Ordering::Relaxed)
+    }
+
+    /// The time between the last two frames.
+    pub fn last_frame_time(&self) -> Duration {
+        Duration::from_nanos(self.last_frame_time_nanos.load(Ordering::Relaxed))
+    }
+
+    /// [Self::tick()], but with an explicit timestamp in nanoseconds since `epoch`.
+    fn tick_at(&self, now_nanos: u64) -> Duration {
+        self.frames.fetch_add(1, Ordering::Relaxed);
+        let frame_time_nanos =
+            now_nanos.saturating_sub(self.last_frame_nanos.swap(now_nanos, Ordering::Relaxed));
+        self.last_frame_time_nanos
+            .store(frame_time_nanos, Ordering::Relaxed);
+
+        // Only one thread gets to end the measuring period if multiple threads tick at the same
+        // time. The frame rate is scaled to the period's actual length since frames rarely line up
+        // exactly with the one second boundary.
+        let period_start_nanos = self.period_start_nanos.load(Ordering::Relaxed);
+        let period_nanos = now_nanos.saturating_sub(period_start_nanos);
+        if period_nanos >= Self::PERIOD_NANOS
+            && self
+                .period_start_nanos
+                .compare_exchange(
+                    period_start_nanos,
+                    now_nanos,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+        {
+            let frames = self.frames.swap(0, Ordering::Relaxed) as u64;
+            let fps = (frames * Self::PERIOD_NANOS + period_nanos / 2) / period_nanos;
+            self.fps.store(fps as u32, Ordering::Relaxed);
+        }
+
+        Duration::from_nanos(frame_time_nanos)
+    }
+}
+
+/// Keeps track of which voices are still producing sound so an instrument can tell the host when
+/// its output has ended. Call [Self::voice_started()] when a note starts a voice, and
+/// [Self::voice_finished()] once that voice has produced its final output. For a one-shot sampler
+/// that's when the sample has finished playing, and for a synth that's when the release envelope
+/// has reached zero, so this should not simply be called on note off. Returning
+/// [Self::process_status()] from [crate::Plugin::process()] then keeps the plugin alive while any
+/// voice is still playing and ends the tail as soon as the last voice has finished, allowing the
+/// host to suspend the plugin.
+///
+/// Voices are identified by their MIDI channel and note number.
+#[derive(Debug, Default, Clone)]
+pub struct VoiceTracker {
+    /// One bit per note for every MIDI channel.
+    active_voices: [u128; 16],
+}
+
+impl VoiceTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mark the voice for this note as playing.
+    pub fn voice_started(&mut self, channel: u8, note: u8) {
+        nih_debug_assert!(channel < 16 && note < 128);
+
+        self.active_voices[channel as usize & 15] |= 1 << (note & 127);
+    }
+
+    /// Mark the voice for this note as finished. See the struct's docs for when to call this.
+    pub fn voice_finished(&mut self, channel: u8, note: u8) {
+        nih_debug_assert!(channel < 16 && note < 128);
+
+        self.active_voices[channel as usize & 15] &= !(1 << (note & 127));
+    }
+
+    /// Whether the voice for this note is still playing.
+    pub fn is_voice_active(&self, channel: u8, note: u8) -> bool {
+        self.active_voices[channel as usize & 15] & (1 << (note & 127)) != 0
+    }
+
+    /// The number of voices that are still playing.
+    pub fn num_active_voices(&self) -> usize {
+        self.active_voices
+            .iter()
+            .map(|notes| notes.count_ones() as usize)
+            .sum()
+    }
+
+    /// Whether every voice has produced its final output.
+    pub fn all_voices_finished(&self) -> bool {
+        self.active_voices.iter().all(|&notes| notes == 0)
+    }
+
+    /// Mark all voices as finished, for instance when the plugin gets reset.
+    pub fn reset(&mut self) {
+        self.active_voices = [0; 16];
+    }
+
+    /// The status to return from the process function. This is [ProcessStatus::KeepAlive] while any
+    /// voice is still playing, and [ProcessStatus::Normal] once all of them have finished so the
+    /// host knows the plugin's tail has ended.
+    pub fn process_status(&self) -> ProcessStatus {
+        if self.all_voices_finished() {
+            ProcessStatus::Normal
+        } else {
+            ProcessStatus::KeepAlive
+        }
+    }
+}
+
+/// The curve used by a [Crossfader].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrossfadeStyle {
+    /// The gains change linearly. This keeps the amplitude constant for correlated signals, like
+    /// two slightly different versions of the same signal.
+    Linear,
+    /// The gains follow a quarter sine and cosine so the summed power stays constant. This works
+    /// better for uncorrelated signals, which would otherwise dip in volume halfway through the
+    /// fade.
+    EqualPower,
+}
+
+/// Crossfades between two processing paths over a fixed number of samples. This is useful for
+/// discrete mode changes that would otherwise cause clicks, like switching between two algorithms.
+/// Process the audio with both the old and the new path for as long as [Self::is_fading()] returns
+/// true, and use [Self::process()] to mix the two together.
+#[derive(Debug, Clone)]
+pub struct Crossfader {
+    style: CrossfadeStyle,
+    /// The length of a fade in samples.
+    length: u32,
+    /// The number of samples of the current fade that have already been processed. The fade is
+    /// complete when this is equal to `length`.
+    position: u32,
+}
+
+impl Crossfader {
+    /// Create a crossfader with the given curve that fades over `length` samples. The crossfader
+    /// starts out idle, call [Self::start()] to start a fade.
+    pub fn new(style: CrossfadeStyle, length: u32) -> Self {
+        Self {
+            style,
+            length,
+            position: length,
+        }
+    }
+
+    /// Change the fade's length in samples. This takes effect the next time [Self::start()] is
+    /// called.
+    pub fn set_length(&mut self, length: u32) {
+        self.length = length;
+        self.position = self.position.min(length);
+    }
+
+    /// Start a new fade from the old path to the new path. If a fade was already in progress, then
+    /// it starts over.
+    pub fn start(&mut self) {
+        self.position = 0;
+    }
+
+    /// Whether a fade is in progress. While this is the case both paths should be processed.
+    pub fn is_fading(&self) -> bool {
+        self.position < self.length
+    }
+
+    /// Mix the old path's output from `from` into the new path's output in `to`. Both should
+    /// contain the same number of channels and samples. Once the fade is complete `to` is left
+    /// untouched. Returns `true` when the fade has completed during or before this block.
+    pub fn process(&mut self, from: &[&mut [f32]], to: &mut [&mut [f32]]) -> bool {
+        nih_debug_assert_eq!(from.len(), to.len());
+
+        let num_samples = to.first().map(|channel| channel.len()).unwrap_or(0);
+        let num_fade_samples = cmp::min((self.length - self.position) as usize, num_samples);
+        for sample_idx in 0..num_fade_samples {
+            let t = (self.position as usize + sample_idx + 1) as f32 / self.length as f32;
+            let (from_gain, to_gain) = match self.style {
+                CrossfadeStyle::Linear => (1.0 - t, t),
+                CrossfadeStyle::EqualPower => {
+                    let angle = t * std::f32::consts::FRAC_PI_2;
+                    (angle.cos(), angle.sin())
+                }
+            };
+
+            for (from_channel, to_channel) in from.iter().zip(to.iter_mut()) {
+                to_channel[sample_idx] =
+                    from_channel[sample_idx] * from_gain + to_channel[sample_idx] * to_gain;
+            }
+        }
+
+        self.position += num_fade_samples as u32;
+
+        !self.is_fading()
+    }
+}
+
+/// What a plugin with a dry/wet control should output. Exposing this as an
+/// [crate::param::EnumParam] gives plugins a consistent control for parallel processing
+/// workflows, where the user mixes the wet signal with the dry signal themselves. See
+/// [DryWetMixer].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputMode {
+    /// Mix the dry signal with the wet signal using the plugin's mix control.
+    DryWet,
+    /// Only output the wet signal, regardless of the mix control.
+    WetOnly,
+    /// Only output the dry signal, delayed by the plugin's latency.
+    DryOnly,
+}
+
+impl Enum for OutputMode {
+    fn variants() -> &'static [&'static str] {
+        &["Dry/Wet", "Wet Only", "Dry Only"]
+    }
+
+    fn to_index(self) -> usize {
+        match self {
+            OutputMode::DryWet => 0,
+            OutputMode::WetOnly => 1,
+            OutputMode::DryOnly => 2,
+        }
+    }
+
+    fn from_index(index: usize) -> Self {
+        match index {
+            0 => OutputMode::DryWet,
+            1 => OutputMode::WetOnly,
+            _ => OutputMode::DryOnly,
+        }
+    }
+}
+
+/// Mixes a plugin's unprocessed dry signal back into its output. The dry signal is delayed by the
+/// plugin's latency so it lines up with the wet signal, since the host's latency compensation
+/// only applies to the plugin's output as a whole. Call [Self::write_dry()] with the input before
+/// processing it, and then [Self::mix_in_dry()] with the processed output.
+///
+/// All buffers are allocated in [Self::new()] and [Self::resize()], so this should be created or
+/// resized from the plugin's initialization function.
+#[derive(Debug, Clone)]
+pub struct DryWetMixer {
+    /// A ring buffer containing the dry signal for every channel. These can hold the maximum
+    /// latency plus the maximum block size.
+    delay_lines: Vec<Vec<f32>>,
+    /// The position in `delay_lines` the next block of dry samples will be written to.
+    position: usize,
+    /// The number of samples in the last block written with [Self::write_dry()].
+    num_dry_samples: usize,
+    /// The current latency in samples, at most `delay_lines.len() - max_block_size`.
+    latency: usize,
+    max_block_size: usize,
+}
+
+impl DryWetMixer {
+    /// Create a mixer for `num_channels` channels, blocks of up to `max_block_size` samples, and
+    /// latencies of up to `max_latency` samples. The latency starts out at zero.
+    pub fn new(num_channels: usize, max_block_size: usize, max_latency: usize) -> Self {
+        let mut mixer = Self {
+            delay_lines: Vec::new(),
+            position: 0,
+            num_dry_samples: 0,
+            latency: 0,
+            max_block_size: 0,
+        };
+        mixer.resize(num_channels, max_block_size, max_latency);
+
+        mixer
+    }
+
+    /// Change the mixer's channel count and maximum sizes, and reset it. This allocates, so it
+    /// should be done from the plugin's initialization function.
+    pub fn resize(&mut self, num_channels: usize, max_block_size: usize, max_latency: usize) {
+        self.delay_lines.resize_with(num_channels, Vec::new);
+        for delay_line in &mut self.delay_lines {
+            delay_line.resize(max_latency + max_block_size, 0.0);
+        }
+        self.max_block_size = max_block_size;
+        self.latency = self.latency.min(max_latency);
+
+        self.reset();
+    }
+
+    /// Set the latency the dry signal is delayed by. This should match the latency reported
+    /// through [crate::context::ProcessContext::set_latency_samples()]. Latencies above the
+    /// maximum latency passed to [Self::new()] are clamped.
+    pub fn set_latency(&mut self, latency: usize) {
+        let max_latency = self
+            .delay_lines
+            .first()
+            .map_or(0, |delay_line| delay_line.len() - self.max_block_size);
+        nih_debug_assert!(latency <= max_latency);
+
+        self.latency = latency.min(max_latency);
+    }
+
+    /// The latency the dry signal is delayed by.
+    pub fn latency(&self) -> usize {
+        self.latency
+    }
+
+    /// Clear the delay lines. Call this when the plugin gets reset.
+    pub fn reset(&mut self) {
+        for delay_line in &mut self.delay_lines {
+            delay_line.fill(0.0);
+        }
+        self.position = 0;
+        self.num_dry_samples = 0;
+    }
+
+    /// Store the dry input for the current block. This needs to be called before the buffer is
+    /// processed, and the buffer should not contain more samples than the maximum block size.
+    pub fn write_dry(&mut self, buffer: &[&mut [f32]]) {
+        nih_debug_assert_eq!(buffer.len(), self.delay_lines.len());
+
+        let num_samples = buffer.first().map_or(0, |channel| channel.len());
+        nih_debug_assert!(num_samples <= self.max_block_size);
+        self.num_dry_samples = num_samples.min(self.max_block_size);
+
+        for (channel, delay_line) in buffer.iter().zip(self.delay_lines.iter_mut()) {
+            let delay_line_len = delay_line.len();
+            for (sample_idx, &sample) in channel[..self.num_dry_samples].iter().enumerate() {
+                delay_line[(self.position + sample_idx) % delay_line_len] = sample;
+            }
+        }
+    }
+
+    /// Mix the dry signal stored with [Self::write_dry()] into the wet signal in `buffer`
+    /// according to `mode`. In [OutputMode::DryWet] mode, `mix` is the amount of wet signal
+    /// between zero and one, and `style` determines the mixing curve.
+    pub fn mix_in_dry(
+        &mut self,
+        buffer: &mut [&mut [f32]],
+        mode: OutputMode,
+        mix: f32,
+        style: CrossfadeStyle,
+    ) {
+        nih_debug_assert_eq!(buffer.len(), self.delay_lines.len());
+
+        let (dry_gain, wet_gain) = match mode {
+            OutputMode::DryWet => match style {
+                CrossfadeStyle::Linear => (1.0 - mix, mix),
+                CrossfadeStyle::EqualPower => {
+                    let angle = mix.clamp(0.0, 1.0) * std::f32::consts::FRAC_PI_2;
+                    (angle.cos(), angle.sin())
+                }
+            },
+            OutputMode::WetOnly => (0.0, 1.0),
+            OutputMode::DryOnly => (1.0, 0.0),
+        };
+
+        if dry_gain != 0.0 {
+            for (channel, delay_line) in buffer.iter_mut().zip(self.delay_lines.iter()) {
+                let delay_line_len = delay_line.len();
+                let read_position = self.position + delay_line_len - self.latency;
+                for (sample_idx, sample) in channel[..self.num_dry_samples].iter_mut().enumerate() {
+                    let dry_sample = delay_line[(read_position + sample_idx) % delay_line_len];
+                    *sample = dry_sample * dry_gain + *sample * wet_gain;
+                }
+            }
+        }
+
+        // The delay lines always keep advancing so switching modes doesn't cause a discontinuity
+        if let Some(delay_line_len) = self.delay_lines.first().map(|delay_line| delay_line.len()) {
+            if delay_line_len > 0 {
+                self.position = (self.position + self.num_dry_samples) % delay_line_len;
+            }
+        }
+        self.num_dry_samples = 0;
+    }
+
+    /// Copy the delayed dry signal for the block stored with [Self::write_dry()] to the start of
+    /// the buffers in `dry`, and advance the delay lines. This is used by the wrappers to crossfade
+    /// to the dry signal when the plugin gets bypassed.
+    pub(crate) fn read_dry(&mut self, dry: &mut [Vec<f32>]) {
+        nih_debug_assert_eq!(dry.len(), self.delay_lines.len());
+
+        for (dry_buffer, delay_line) in dry.iter_mut().zip(self.delay_lines.iter()) {
+            let delay_line_len = delay_line.len();
+            let read_position = self.position + delay_line_len - self.latency;
+            for (sample_idx, dry_sample) in
+                dry_buffer[..self.num_dry_samples].iter_mut().enumerate()
+            {
+                *dry_sample = delay_line[(read_position + sample_idx) % delay_line_len];
+            }
+        }
+
+        if let Some(delay_line_len) = self.delay_lines.first().map(|delay_line| delay_line.len()) {
+            if delay_line_len > 0 {
+                self.position = (self.position + self.num_dry_samples) % delay_line_len;
+            }
+        }
+        self.num_dry_samples = 0;
+    }
+}
+
+/// An [Arc] that can be atomically replaced, for sharing large read-only data like wavetables or
+/// impulse responses with the audio thread. A background thread loads the new data and publishes
+/// it with [Self::store()] or [Self::swap()], and the audio thread picks it up on its next block
+/// using [Self::load()]. Loading never blocks and never allocates, so it's safe to do from the
+/// audio thread.
+///
+/// The audio thread should only hold on to the loaded [Arc] for the duration of a single process
+/// call. If the audio thread ends up holding the last reference to old data, then that data will
+/// also be deallocated on the audio thread. To prevent this, use [Self::swap()] on the background
+/// thread and keep the old value around for a bit before dropping it there.
+///
+/// Replacing the value briefly spins until no [Self::load()] calls are in progress, so this
+/// should not be done from the audio thread.
+pub struct AtomicArcSwap<T> {
+    /// A pointer obtained from [Arc::into_raw()]. This stored pointer owns one strong reference.
+    ptr: AtomicPtr<T>,
+    /// The number of [Self::load()] calls that are currently in progress. The old value can only
+    /// be released after a swap once this drops to zero, because until that point a reader may
+    /// have read the old pointer without having incremented its reference count yet.
+    active_loads: AtomicUsize,
+}
+
+// SAFETY: This behaves like an `Arc<T>` that can be shared between threads
+unsafe impl<T: Send + Sync> Send for AtomicArcSwap<T> {}
+unsafe impl<T: Send + Sync> Sync for AtomicArcSwap<T> {}
+
+impl<T> AtomicArcSwap<T> {
+    pub fn new(value: Arc<T>) -> Self {
+        Self {
+            ptr: AtomicPtr::new(Arc::into_raw(value) as *mut T),
+            active_loads: AtomicUsize::new(0),
+        }
+    }
+
+    /// Get a reference to the current value. This only increments the [Arc]'s reference count, so
+    /// it's realtime-safe.
+    pub fn load(&self) -> Arc<T> {
+        self.active_loads.fetch_add(1, Ordering::SeqCst);
+        let ptr = self.ptr.load(Ordering::SeqCst);
+        // SAFETY: `ptr` came from `Arc::into_raw()`, and the swapping functions won't release this
+        //         pointer's reference while `active_loads` is nonzero
+        let value = unsafe {
+            Arc::increment_strong_count(ptr);
+            Arc::from_raw(ptr)
+        };
+        self.active_loads.fetch_sub(1, Ordering::SeqCst);
+
+        value
+    }
+
+    /// Replace the current value, dropping the old value on this thread if it's no longer used
+    /// elsewhere.
+    pub fn store(&self, value: Arc<T>) {
+        drop(self.swap(value));
+    }
+
+    /// Replace the current value and return the old value.
+    pub fn swap(&self, value: Arc<T>) -> Arc<T> {
+        let old_ptr = self
+            .ptr
+            .swap(Arc::into_raw(value) as *mut T, Ordering::SeqCst);
+
+        // Any load that could still have read the old pointer will be done soon
+        while self.active_loads.load(Ordering::SeqCst) != 0 {
+            std::hint::spin_loop();
+        }
+
+        // SAFETY: This takes over the reference that was owned by `self.ptr`
+        unsafe { Arc::from_raw(old_ptr) }
+    }
+}
+
+impl<T> Drop for AtomicArcSwap<T> {
+    fn drop(&mut self) {
+        // SAFETY: This releases the reference owned by `self.ptr`
+        drop(unsafe { Arc::from_raw(*self.ptr.get_mut()) });
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for AtomicArcSwap<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("AtomicArcSwap").field(&self.load()).finish()
+    }
+}
+
+/// A fixed size ring buffer for sending recent audio from the audio thread to an editor, for
+/// instance for drawing an oscilloscope or a spectrum analyzer. The audio thread writes samples
+/// with [Self::push_slice()], and the editor copies the most recent samples with
+/// [Self::read_latest()]. Neither function ever blocks or allocates. Reading does not consume any
+/// samples, and if the editor can't keep up then the old samples are simply overwritten.
+///
+/// There should only be a single thread pushing samples, but any number of threads can read from
+/// the buffer at the same time. `T` should be a small type like `f32` that can be stored atomically
+/// without locking, see [AtomicCell::is_lock_free()].
+pub struct RtRingBuffer<T> {
+    samples: Box<[AtomicCell<T>]>,
+    /// The total number of samples that have been written to the buffer. The sample with index `i`
+    /// is stored at `samples[i % samples.len()]`.
+    num_written: AtomicU64,
+    /// The total number of samples that will have been written once the current push has finished.
+    /// This is increased before writing any samples, so a reader can detect whether the samples it
+    /// just copied were overwritten while it was copying them.
+    num_writing: AtomicU64,
+}
+
+impl<T: Copy + Default> RtRingBuffer<T> {
+    /// Create a ring buffer that holds `capacity` samples. This allocates, so it should be done
+    /// outside of the audio thread.
+    pub fn new(capacity: usize) -> Self {
+        nih_debug_assert!(capacity > 0);
+        nih_debug_assert!(
+            AtomicCell::<T>::is_lock_free(),
+            "RtRingBuffer is used with a type that can't be stored atomically"
+        );
+
+        Self {
+            samples: (0..capacity)
+                .map(|_| AtomicCell::new(T::default()))
+                .collect(),
+            num_written: AtomicU64::new(0),
+            num_writing: AtomicU64::new(0),
+        }
+    }
+
+    /// The maximum number of samples that can be read back from the buffer.
+    pub fn capacity(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Write samples to the buffer, overwriting the oldest samples. This should only be called from
+    /// a single thread, usually the audio thread.
+    pub fn push_slice(&self, samples: &[T]) {
+        let capacity = self.samples.len();
+        // Only the last `capacity` samples would survive this push anyways
+        let skipped = samples.len().saturating_sub(capacity);
+        let num_written = self.num_written.load(Ordering::Relaxed);
+        let start = num_written + skipped as u64;
+        let end = num_written + samples.len() as u64;
+
+        self.num_writing.store(end, Ordering::Relaxed);
+        atomic::fence(Ordering::Release);
+        for (idx, sample) in (start..end).zip(&samples[skipped..]) {
+            self.samples[(idx % capacity as u64) as usize].store(*sample);
+        }
+
+        self.num_written.store(end, Ordering::Release);
+    }
+
+    /// Copy the most recent samples to `into`, with the newest sample at the end of the slice.
+    /// Returns the number of samples that have been copied. This can be less than the length of
+    /// `into` if it's longer than the buffer's capacity, if not enough samples have been written
+    /// yet, or if the writer overwrote part of the samples while they were being copied. In that
+    /// case the samples at the start of `into` are set to `T::default()`.
+    pub fn read_latest(&self, into: &mut [T]) -> usize {
+        let capacity = self.samples.len() as u64;
+        let end = self.num_written.load(Ordering::Acquire);
+        let num_samples = (into.len() as u64).min(capacity).min(end);
+        let start = end - num_samples;
+
+        let (missing, copied) = into.split_at_mut(into.len() - num_samples as usize);
+        missing.fill(T::default());
+        for (idx, sample) in (start..end).zip(copied.iter_mut()) {
+            *sample = self.samples[(idx % capacity) as usize].load();
+        }
+
+        // Any samples the writer may have started overwriting in the meantime are discarded. The
+        // fence makes sure that if we copied a sample written by a newer push, then we also see the
+        // updated `num_writing` from before that push.
+        atomic::fence(Ordering::Acquire);
+        let overwritten_start = self
+            .num_writing
+            .load(Ordering::Relaxed)
+            .saturating_sub(capacity);
+        let num_overwritten = overwritten_start.saturating_sub(start).min(num_samples) as usize;
+        copied[..num_overwritten].fill(T::default());
+
+        num_samples as usize - num_overwritten
+    }
+}
+
+impl<T> fmt::Debug for RtRingBuffer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RtRingBuffer")
+            .field("capacity", &self.samples.len())
+            .field("num_written", &self.num_written.load(Ordering::Relaxed))
+            .finish_non_exhaustive()
+    }
 }
 
 #[cfg(test)]
@@ -79,4 +1132,389 @@ mod tests {
     fn test_gain_to_db_minus_infinity_negative() {
         assert_eq!(gain_to_db(-2.0), MINUS_INFINITY_DB);
     }
+
+    #[test]
+    fn test_freq_to_midi_note() {
+        assert_eq!(freq_to_midi_note(midi_note_to_freq(69)), 69.0);
+        assert_eq!(freq_to_midi_note(880.0), 81.0);
+        for note in 0..=127 {
+            assert!((freq_to_midi_note(midi_note_to_freq(note)) - note as f32).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_midi_note_to_freq() {
+        assert_eq!(midi_note_to_freq(69), 440.0);
+        assert_eq!(midi_note_to_freq(81), 880.0);
+        assert_eq!(midi_note_to_freq(57), 220.0);
+    }
+
+    #[test]
+    fn test_note_tuning() {
+        for a4_hz in [432.0, 440.0, 442.0] {
+            assert_eq!(midi_note_to_freq_with_tuning(69, a4_hz), a4_hz);
+            assert_eq!(midi_note_to_freq_with_tuning(81, a4_hz), a4_hz * 2.0);
+            assert_eq!(freq_to_midi_note_with_tuning(a4_hz, a4_hz), 69.0);
+            for note in 0..=127 {
+                let freq = midi_note_to_freq_with_tuning(note, a4_hz);
+                assert!((freq_to_midi_note_with_tuning(freq, a4_hz) - note as f32).abs() < 1e-4);
+            }
+        }
+
+        for note in 0..=127 {
+            assert_eq!(
+                midi_note_to_freq(note),
+                midi_note_to_freq_with_tuning(note, 440.0)
+            );
+        }
+    }
+
+    #[test]
+    fn test_note_division_to_samples() {
+        assert_eq!(
+            note_division_to_samples(
+                NoteDivision::Quarter,
+                NoteModifier::Straight,
+                120.0,
+                48000.0
+            ),
+            24000.0
+        );
+        assert_eq!(
+            note_division_to_samples(NoteDivision::Eighth, NoteModifier::Dotted, 120.0, 48000.0),
+            18000.0
+        );
+        assert_eq!(
+            note_division_to_samples(NoteDivision::Quarter, NoteModifier::Triplet, 120.0, 48000.0),
+            16000.0
+        );
+        assert_eq!(
+            note_division_to_samples(NoteDivision::Whole, NoteModifier::Straight, 90.0, 44100.0),
+            117600.0
+        );
+    }
+
+    #[test]
+    fn test_flush_denormals() {
+        let mut samples = [
+            1.0,
+            f32::MIN_POSITIVE / 2.0,
+            -f32::MIN_POSITIVE / 4.0,
+            -0.5,
+            0.0,
+        ];
+        flush_denormals(&mut samples);
+        assert_eq!(samples, [1.0, 0.0, 0.0, -0.5, 0.0]);
+    }
+
+    #[test]
+    #[cfg(all(target_arch = "x86_64", target_feature = "sse2"))]
+    fn test_scoped_ftz() {
+        let modes = || unsafe { get_mxcsr() } & MXCSR_FTZ_DAZ;
+        let enabled_modes = MXCSR_FTZ_DAZ;
+        let original_modes = modes();
+
+        {
+            let _ftz_guard = ScopedFtz::enable();
+            assert_eq!(modes(), enabled_modes);
+
+            // Nested guards should leave the modes enabled for the outer guard
+            {
+                let _nested_ftz_guard = ScopedFtz::enable();
+                assert_eq!(modes(), enabled_modes);
+            }
+            assert_eq!(modes(), enabled_modes);
+
+            let subnormal = std::hint::black_box(f32::MIN_POSITIVE);
+            assert_eq!(subnormal / std::hint::black_box(2.0), 0.0);
+        }
+
+        assert_eq!(modes(), original_modes);
+    }
+
+    #[test]
+    fn test_frame_counter() {
+        let frame_counter = FrameCounter::new();
+        assert_eq!(frame_counter.fps(), 0);
+
+        // 60 frames per second, so the 60th frame completes the first second
+        let frame_nanos = 1_000_000_000 / 60 + 1;
+        for frame in 1..=60 {
+            let frame_time = frame_counter.tick_at(frame * frame_nanos);
+            assert_eq!(frame_time, Duration::from_nanos(frame_nanos));
+        }
+        assert_eq!(frame_counter.fps(), 60);
+
+        // Then the frame rate halves. This should only be picked up after the next full second.
+        let start_nanos = 60 * frame_nanos;
+        for frame in 1..30 {
+            frame_counter.tick_at(start_nanos + frame * 2 * frame_nanos);
+        }
+        assert_eq!(frame_counter.fps(), 60);
+        frame_counter.tick_at(start_nanos + 60 * frame_nanos);
+        assert_eq!(frame_counter.fps(), 30);
+        assert_eq!(
+            frame_counter.last_frame_time(),
+            Duration::from_nanos(2 * frame_nanos)
+        );
+    }
+
+    #[test]
+    fn test_voice_tracker() {
+        let mut voices = VoiceTracker::new();
+        assert!(voices.all_voices_finished());
+        assert_eq!(voices.process_status(), ProcessStatus::Normal);
+
+        voices.voice_started(0, 60);
+        voices.voice_started(15, 127);
+        assert!(voices.is_voice_active(0, 60));
+        assert!(!voices.is_voice_active(1, 60));
+        assert_eq!(voices.num_active_voices(), 2);
+        assert_eq!(voices.process_status(), ProcessStatus::KeepAlive);
+
+        // The tail only ends once the last voice has finished
+        voices.voice_finished(0, 60);
+        assert_eq!(voices.process_status(), ProcessStatus::KeepAlive);
+        voices.voice_finished(15, 127);
+        assert!(voices.all_voices_finished());
+        assert_eq!(voices.process_status(), ProcessStatus::Normal);
+    }
+
+    #[test]
+    fn test_rms() {
+        assert_eq!(rms(&[]), 0.0);
+        assert_eq!(rms(&[0.5, -0.5, 0.5, -0.5]), 0.5);
+        assert!((rms(&[1.0, 0.0]) - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_rms_window() {
+        let mut window = RmsWindow::new(4);
+        assert_eq!(window.rms(), 0.0);
+
+        // The samples before the first pushed sample count as silence
+        window.push_slice(&[1.0, -1.0]);
+        assert!((window.rms() - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-6);
+
+        // This should only consider the last four samples, regardless of the block sizes
+        window.push_slice(&[1.0, -1.0, 0.5]);
+        window.push_slice(&[-0.5, 0.5, -0.5]);
+        assert_eq!(window.rms(), rms(&[0.5, -0.5, 0.5, -0.5]));
+
+        window.reset();
+        assert_eq!(window.rms(), 0.0);
+
+        // Squares that would be subnormal should be flushed to zero
+        window.push_slice(&[1.0e-20; 4]);
+        assert!(window.squares.iter().all(|&square| square == 0.0));
+        assert_eq!(window.rms(), 0.0);
+    }
+
+    #[test]
+    fn test_dry_wet_mixer() {
+        let mut mixer = DryWetMixer::new(1, 4, 2);
+        mixer.set_latency(2);
+
+        // The dry signal is delayed by the latency, so it is still silent during the first two
+        // samples
+        let mut channel = [1.0, 2.0, 3.0, 4.0];
+        let mut buffer = [&mut channel[..]];
+        mixer.write_dry(&buffer);
+        buffer[0].fill(0.0);
+        mixer.mix_in_dry(
+            &mut buffer,
+            OutputMode::DryOnly,
+            0.0,
+            CrossfadeStyle::Linear,
+        );
+        assert_eq!(channel, [0.0, 0.0, 1.0, 2.0]);
+
+        let mut channel = [5.0, 6.0, 7.0, 8.0];
+        let mut buffer = [&mut channel[..]];
+        mixer.write_dry(&buffer);
+        buffer[0].fill(10.0);
+        mixer.mix_in_dry(&mut buffer, OutputMode::DryWet, 0.5, CrossfadeStyle::Linear);
+        assert_eq!(channel, [6.5, 7.0, 7.5, 8.0]);
+
+        let mut channel = [1.0, 1.0, 1.0, 1.0];
+        let mut buffer = [&mut channel[..]];
+        mixer.write_dry(&buffer);
+        buffer[0].fill(10.0);
+        mixer.mix_in_dry(
+            &mut buffer,
+            OutputMode::WetOnly,
+            0.5,
+            CrossfadeStyle::Linear,
+        );
+        assert_eq!(channel, [10.0; 4]);
+    }
+
+    #[test]
+    fn test_rms_window_duration() {
+        let mut window = RmsWindow::new(1);
+        window.reserve_duration(48000.0, 300.0);
+        let capacity = window.squares.capacity();
+        assert!(capacity >= 14400);
+
+        window.set_duration(48000.0, 150.0);
+        assert_eq!(window.window_len(), 7200);
+        assert_eq!(window.duration_ms(48000.0), 150.0);
+
+        // Changing the duration within the reserved length should not reallocate
+        window.set_duration(48000.0, 300.0);
+        assert_eq!(window.window_len(), 14400);
+        assert_eq!(window.squares.capacity(), capacity);
+
+        // This is rounded to whole samples, and the window is always at least one sample long
+        window.set_duration(44100.0, 0.01);
+        assert_eq!(window.window_len(), 1);
+        assert!((window.duration_ms(44100.0) - 0.022675737).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_version_counter() {
+        let counter = VersionCounter::new();
+        let mut last_seen = counter.version();
+        assert!(!counter.has_changed_since(&mut last_seen));
+
+        counter.bump();
+        counter.bump();
+        assert!(counter.has_changed_since(&mut last_seen));
+        assert!(!counter.has_changed_since(&mut last_seen));
+    }
+
+    #[test]
+    fn test_atomic_arc_swap() {
+        let first = Arc::new(vec![1.0f32; 4]);
+        let swap = AtomicArcSwap::new(first.clone());
+        assert_eq!(*swap.load(), [1.0; 4]);
+        assert_eq!(Arc::strong_count(&first), 2);
+
+        let old = swap.swap(Arc::new(vec![2.0; 4]));
+        assert!(Arc::ptr_eq(&old, &first));
+        assert_eq!(*swap.load(), [2.0; 4]);
+
+        // The old value is no longer referenced by the swap
+        drop(old);
+        assert_eq!(Arc::strong_count(&first), 1);
+    }
+
+    #[test]
+    fn test_rt_ring_buffer() {
+        let ring_buffer = RtRingBuffer::new(4);
+        let mut latest = [-1.0f32; 3];
+        assert_eq!(ring_buffer.read_latest(&mut latest), 0);
+        assert_eq!(latest, [0.0; 3]);
+
+        ring_buffer.push_slice(&[1.0, 2.0]);
+        assert_eq!(ring_buffer.read_latest(&mut latest), 2);
+        assert_eq!(latest, [0.0, 1.0, 2.0]);
+
+        ring_buffer.push_slice(&[3.0, 4.0, 5.0]);
+        assert_eq!(ring_buffer.read_latest(&mut latest), 3);
+        assert_eq!(latest, [3.0, 4.0, 5.0]);
+
+        // Slices longer than the buffer only keep the last samples, and reads are capped to the
+        // buffer's capacity
+        ring_buffer.push_slice(&[6.0, 7.0, 8.0, 9.0, 10.0, 11.0]);
+        let mut latest = [-1.0f32; 6];
+        assert_eq!(ring_buffer.read_latest(&mut latest), 4);
+        assert_eq!(latest, [0.0, 0.0, 8.0, 9.0, 10.0, 11.0]);
+    }
+
+    #[test]
+    fn test_rt_ring_buffer_concurrent() {
+        const NUM_SAMPLES: usize = 1 << 20;
+
+        let ring_buffer = Arc::new(RtRingBuffer::new(256));
+        let writer = {
+            let ring_buffer = ring_buffer.clone();
+            std::thread::spawn(move || {
+                // Every sample is its own index, so the reader can check for torn reads. These
+                // are all exactly representable as `f32`s.
+                let samples: Vec<f32> = (0..NUM_SAMPLES).map(|i| i as f32).collect();
+                for block in samples.chunks(61) {
+                    ring_buffer.push_slice(block);
+                }
+            })
+        };
+
+        let mut latest = [0.0f32; 100];
+        let mut last_newest_sample = -1.0;
+        loop {
+            let num_read = ring_buffer.read_latest(&mut latest);
+            let valid = &latest[latest.len() - num_read..];
+            for window in valid.windows(2) {
+                assert_eq!(window[1], window[0] + 1.0);
+            }
+
+            if let Some(&newest_sample) = valid.last() {
+                assert!(newest_sample >= last_newest_sample);
+                last_newest_sample = newest_sample;
+            }
+            if last_newest_sample == (NUM_SAMPLES - 1) as f32 {
+                break;
+            }
+        }
+
+        writer.join().unwrap();
+    }
+
+    mod crossfader {
+        use super::*;
+
+        #[test]
+        fn linear() {
+            let mut crossfader = Crossfader::new(CrossfadeStyle::Linear, 4);
+            assert!(!crossfader.is_fading());
+            crossfader.start();
+            assert!(crossfader.is_fading());
+
+            let mut from = [1.0; 6];
+            let mut to = [2.0; 6];
+            assert!(crossfader.process(&[&mut from], &mut [&mut to]));
+            assert_eq!(to, [1.25, 1.5, 1.75, 2.0, 2.0, 2.0]);
+            assert!(!crossfader.is_fading());
+        }
+
+        #[test]
+        fn equal_power() {
+            // Fading out a path with ones gives us the old path's gains, and fading in a path with
+            // ones gives us the new path's gains
+            let mut crossfader = Crossfader::new(CrossfadeStyle::EqualPower, 8);
+            let mut from_gains = [0.0; 8];
+            crossfader.start();
+            crossfader.process(&[&mut [1.0; 8]], &mut [&mut from_gains]);
+            let mut to_gains = [1.0; 8];
+            crossfader.start();
+            crossfader.process(&[&mut [0.0; 8]], &mut [&mut to_gains]);
+
+            for (from_gain, to_gain) in from_gains.iter().zip(to_gains.iter()) {
+                assert!((from_gain * from_gain + to_gain * to_gain - 1.0).abs() < 1e-6);
+            }
+            assert!((from_gains[3] - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-6);
+            assert!(from_gains[7].abs() < 1e-6);
+            assert!((to_gains[7] - 1.0).abs() < 1e-6);
+        }
+
+        #[test]
+        fn multiple_blocks() {
+            let mut crossfader = Crossfader::new(CrossfadeStyle::Linear, 4);
+            crossfader.start();
+
+            let mut from = [1.0; 2];
+            let mut to = [0.0; 2];
+            assert!(!crossfader.process(&[&mut from], &mut [&mut to]));
+            assert_eq!(to, [0.75, 0.5]);
+
+            let mut to = [0.0; 2];
+            assert!(crossfader.process(&[&mut from], &mut [&mut to]));
+            assert_eq!(to, [0.25, 0.0]);
+
+            // After the fade has completed the new path's output is left untouched
+            let mut to = [2.0; 2];
+            assert!(crossfader.process(&[&mut from], &mut [&mut to]));
+            assert_eq!(to, [2.0, 2.0]);
+        }
+    }
 }
@@ -0,0 +1,345 @@
+// nih-plug: plugins, but rewritten in Rust
+// Copyright (C) 2022 Robbert van der Helm
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Meters that are updated from the audio thread and read from the editor. These can be shared
+//! between the plugin and its editor using an `Arc`, and neither side ever needs to lock anything.
+//! The meters fall back at a rate determined by their release time, so the editor can simply
+//! display the latest reading. This release is based on the time that has passed since the last
+//! update, so the meters also fall back when the audio thread stops updating them, for instance
+//! because the transport has been stopped.
+
+use atomic_float::AtomicF32;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+use super::{gain_to_db, MINUS_INFINITY_DB};
+
+/// The sample rate the meters assume until [PeakMeter::set_sample_rate()] or
+/// [GainReductionMeter::set_sample_rate()] gets called.
+const DEFAULT_SAMPLE_RATE: f32 = 44_100.0;
+
+/// A peak meter. The meter jumps to a new peak immediately, and then falls back towards silence.
+/// The release time is the time it takes for the reading to fall by 20 dB. The audio thread calls
+/// [Self::push_sample()] or [Self::push_block()], and the editor reads the current level with
+/// [Self::read_db()].
+#[derive(Debug)]
+pub struct PeakMeter {
+    envelope: Envelope,
+}
+
+/// A gain reduction meter for dynamics processors. The audio thread pushes the gain the processor
+/// applied, and the meter shows the largest reduction, which is then released back towards 0 dB.
+/// The release time is the time it takes for the shown reduction to fall to a tenth of its value in
+/// decibels.
+#[derive(Debug)]
+pub struct GainReductionMeter {
+    /// The gain reduction in decibels, as a positive number.
+    envelope: Envelope,
+}
+
+/// A held value that decays exponentially towards zero, shared by the meters.
+#[derive(Debug)]
+struct Envelope {
+    /// The value at the time of the last update. Reading the envelope lets this decay based on the
+    /// time that has passed since then.
+    value: AtomicF32,
+    /// When `value` was last updated, in nanoseconds since `created`.
+    last_update_nanos: AtomicU64,
+    /// The point in time `last_update_nanos` is relative to.
+    created: Instant,
+    /// The time in milliseconds it takes for `value` to decay to a tenth of its value.
+    release_ms: AtomicF32,
+    /// The sample rate `decay_weight` has been computed for.
+    sample_rate: AtomicF32,
+    /// The factor values are multiplied by for every sample within a pushed block. Computed from
+    /// `release_ms` and `sample_rate`.
+    decay_weight: AtomicF32,
+}
+
+impl PeakMeter {
+    /// Create a meter with the specified release time in milliseconds. Call
+    /// [Self::set_sample_rate()] from the plugin's initialization function to get the correct
+    /// release time.
+    pub fn new(release_ms: f32) -> Self {
+        Self {
+            envelope: Envelope::new(release_ms),
+        }
+    }
+
+    /// Update the sample rate used to compute the release. This should be called from the plugin's
+    /// initialization function.
+    pub fn set_sample_rate(&self, sample_rate: f32) {
+        self.envelope.set_sample_rate(sample_rate);
+    }
+
+    /// Change the meter's release time in milliseconds.
+    pub fn set_release_time(&self, release_ms: f32) {
+        self.envelope.set_release_time(release_ms);
+    }
+
+    /// Reset the meter to silence.
+    pub fn reset(&self) {
+        self.envelope.reset();
+    }
+
+    /// Update the meter with a single sample. This should be called from the audio thread.
+    pub fn push_sample(&self, sample: f32) {
+        self.envelope.push(std::iter::once(sample.abs()));
+    }
+
+    /// Update the meter with a block of samples that ends now. The earlier samples in the block
+    /// have already been released by the time the block ends. This only touches the atomics once.
+    pub fn push_block(&self, samples: &[f32]) {
+        self.envelope
+            .push(samples.iter().map(|sample| sample.abs()));
+    }
+
+    /// The meter's current level as a linear gain value.
+    pub fn read(&self) -> f32 {
+        self.envelope.read()
+    }
+
+    /// The meter's current level in decibels. Silence is reported as [MINUS_INFINITY_DB].
+    pub fn read_db(&self) -> f32 {
+        gain_to_db(self.read()).max(MINUS_INFINITY_DB)
+    }
+}
+
+impl GainReductionMeter {
+    /// Create a meter with the specified release time in milliseconds. Call
+    /// [Self::set_sample_rate()] from the plugin's initialization function to get the correct
+    /// release time.
+    pub fn new(release_ms: f32) -> Self {
+        Self {
+            envelope: Envelope::new(release_ms),
+        }
+    }
+
+    /// Update the sample rate used to compute the release. This should be called from the plugin's
+    /// initialization function.
+    pub fn set_sample_rate(&self, sample_rate: f32) {
+        self.envelope.set_sample_rate(sample_rate);
+    }
+
+    /// Change the meter's release time in milliseconds.
+    pub fn set_release_time(&self, release_ms: f32) {
+        self.envelope.set_release_time(release_ms);
+    }
+
+    /// Reset the meter to show no gain reduction.
+    pub fn reset(&self) {
+        self.envelope.reset();
+    }
+
+    /// Update the meter with the linear gain applied to a single sample. Gains above 1.0 are
+    /// treated as no gain reduction. This should be called from the audio thread.
+    pub fn push_sample(&self, gain: f32) {
+        self.envelope.push(std::iter::once(gain_reduction_db(gain)));
+    }
+
+    /// Update the meter with the linear gains applied to a block of samples that ends now. The
+    /// earlier gain values in the block have already been released by the time the block ends.
+    /// This only touches the atomics once.
+    pub fn push_block(&self, gains: &[f32]) {
+        self.envelope
+            .push(gains.iter().map(|&gain| gain_reduction_db(gain)));
+    }
+
+    /// The current gain reduction in decibels. This is zero or negative, so a compressor reducing
+    /// the signal by 6 dB reads as -6 dB.
+    pub fn read_db(&self) -> f32 {
+        -self.envelope.read()
+    }
+}
+
+/// The amount of gain reduction in decibels as a positive number.
+fn gain_reduction_db(gain: f32) -> f32 {
+    (-gain_to_db(gain)).clamp(0.0, -MINUS_INFINITY_DB)
+}
+
+impl Envelope {
+    fn new(release_ms: f32) -> Self {
+        Self {
+            value: AtomicF32::new(0.0),
+            last_update_nanos: AtomicU64::new(0),
+            created: Instant::now(),
+            release_ms: AtomicF32::new(release_ms),
+            sample_rate: AtomicF32::new(DEFAULT_SAMPLE_RATE),
+            decay_weight: AtomicF32::new(decay_weight(DEFAULT_SAMPLE_RATE, release_ms)),
+        }
+    }
+
+    fn set_sample_rate(&self, sample_rate: f32) {
+        self.sample_rate.store(sample_rate, Ordering::Relaxed);
+        self.decay_weight.store(
+            decay_weight(sample_rate, self.release_ms.load(Ordering::Relaxed)),
+            Ordering::Relaxed,
+        );
+    }
+
+    fn set_release_time(&self, release_ms: f32) {
+        self.release_ms.store(release_ms, Ordering::Relaxed);
+        self.decay_weight.store(
+            decay_weight(self.sample_rate.load(Ordering::Relaxed), release_ms),
+            Ordering::Relaxed,
+        );
+    }
+
+    /// Update the envelope with a block of values that ends now. See [Self::push_at()].
+    fn push(&self, values: impl Iterator<Item = f32>) {
+        self.push_at(values, Instant::now());
+    }
+
+    /// Update the envelope with a block of values that ends at `now`. The values decay for every
+    /// sample within the block, and the envelope jumps to the block's decayed peak if that's higher
+    /// than the current reading. The time that has passed since the last update already accounts
+    /// for the block's duration, so the current reading is not decayed any further.
+    fn push_at(&self, values: impl Iterator<Item = f32>, now: Instant) {
+        let decay_weight = self.decay_weight.load(Ordering::Relaxed);
+        let block_peak = values.fold(0.0f32, |peak, value| (peak * decay_weight).max(value));
+        let envelope = self.read_at(now).max(block_peak);
+
+        self.last_update_nanos
+            .store(self.nanos_since_created(now), Ordering::Relaxed);
+        self.value.store(envelope, Ordering::Relaxed);
+    }
+
+    /// Reset the envelope to zero.
+    fn reset(&self) {
+        self.value.store(0.0, Ordering::Relaxed);
+    }
+
+    /// The envelope's current value. See [Self::read_at()].
+    fn read(&self) -> f32 {
+        self.read_at(Instant::now())
+    }
+
+    /// The envelope's value at `now`, released based on the time that has passed since the last
+    /// update.
+    fn read_at(&self, now: Instant) -> f32 {
+        let value = self.value.load(Ordering::Relaxed);
+        let elapsed_nanos = self
+            .nanos_since_created(now)
+            .saturating_sub(self.last_update_nanos.load(Ordering::Relaxed));
+
+        value
+            * release_factor(
+                elapsed_nanos as f32 / 1_000_000.0,
+                self.release_ms.load(Ordering::Relaxed),
+            )
+    }
+
+    fn nanos_since_created(&self, now: Instant) -> u64 {
+        now.saturating_duration_since(self.created).as_nanos() as u64
+    }
+}
+
+/// The factor to multiply the envelope by after `elapsed_ms` milliseconds so it falls to a tenth of
+/// its value after `release_ms` milliseconds.
+fn release_factor(elapsed_ms: f32, release_ms: f32) -> f32 {
+    if release_ms > 0.0 {
+        0.1f32.powf(elapsed_ms / release_ms)
+    } else if elapsed_ms > 0.0 {
+        0.0
+    } else {
+        1.0
+    }
+}
+
+/// The factor to multiply the envelope by for every sample so it falls to a tenth of its value
+/// after `release_ms` milliseconds.
+fn decay_weight(sample_rate: f32, release_ms: f32) -> f32 {
+    let release_samples = sample_rate * release_ms / 1000.0;
+    if release_samples > 0.0 {
+        0.1f32.powf(release_samples.recip())
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn peak_meter_decay() {
+        let meter = PeakMeter::new(100.0);
+        meter.set_sample_rate(1000.0);
+        assert_eq!(meter.read_db(), MINUS_INFINITY_DB);
+
+        let start = Instant::now();
+        meter.envelope.push_at(std::iter::once(1.0), start);
+        assert_eq!(meter.envelope.read_at(start), 1.0);
+
+        // The reading should fall by 20 dB for every release period without ever going back up,
+        // even when no new samples are pushed
+        let mut previous = 0.0;
+        for ms in 1..=300 {
+            let current = gain_to_db(meter.envelope.read_at(start + Duration::from_millis(ms)));
+            assert!(current < previous);
+            previous = current;
+
+            if ms % 100 == 0 {
+                assert!((current - (ms / 100) as f32 * -20.0).abs() < 0.01);
+            }
+        }
+
+        // New peaks are shown immediately
+        let later = start + Duration::from_millis(300);
+        meter.envelope.push_at(std::iter::once(0.5), later);
+        assert_eq!(meter.envelope.read_at(later), 0.5);
+    }
+
+    #[test]
+    fn peak_meter_push_block() {
+        // At this sample rate the 100 ms release time is exactly 100 samples
+        let meter = PeakMeter::new(100.0);
+        meter.set_sample_rate(1000.0);
+
+        // The peak at the start of this block has been released by the time the block ends
+        let start = Instant::now();
+        let mut block = [0.0; 101];
+        block[0] = 1.0;
+        meter.envelope.push_at(block.iter().copied(), start);
+        assert!((meter.envelope.read_at(start) - 0.1).abs() < 1e-4);
+
+        // A silent block that ends 100 ms later should only release the reading once
+        let end = start + Duration::from_millis(100);
+        meter.envelope.push_at([0.0; 100].iter().copied(), end);
+        assert!((meter.envelope.read_at(end) - 0.01).abs() < 1e-5);
+    }
+
+    #[test]
+    fn gain_reduction_meter_decay() {
+        let meter = GainReductionMeter::new(100.0);
+        meter.set_sample_rate(1000.0);
+        assert_eq!(meter.read_db(), 0.0);
+
+        // Gains above unity don't count as gain reduction
+        meter.push_sample(2.0);
+        assert_eq!(meter.read_db(), 0.0);
+
+        let start = Instant::now();
+        meter
+            .envelope
+            .push_at(std::iter::once(gain_reduction_db(0.5)), start);
+        assert!((-meter.envelope.read_at(start) - gain_to_db(0.5)).abs() < 0.001);
+
+        let later = start + Duration::from_millis(100);
+        assert!((-meter.envelope.read_at(later) - gain_to_db(0.5) / 10.0).abs() < 0.001);
+    }
+}
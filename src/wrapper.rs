@@ -17,6 +17,12 @@
 //! Wrappers for different plugin types. Each wrapper has an entry point macro that you can pass the
 //! name of a type that implements `Plugin` to. The macro will handle the rest.
 
-pub(crate) mod state;
+#[cfg(feature = "clap")]
+pub mod clap;
+mod context;
+pub(crate) mod inner;
+#[cfg(feature = "standalone")]
+pub mod standalone;
+pub mod state;
 pub(crate) mod util;
 pub mod vst3;
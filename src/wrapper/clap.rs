@@ -0,0 +1,2294 @@
+// nih-plug: plugins, but rewritten in Rust
+// Copyright (C) 2022 Robbert van der Helm
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! A CLAP wrapper. This uses the same [WrapperInner] as the VST3 wrapper for everything that isn't
+//! tied to the plugin format, like the parameter mappings, state handling, and the event queues, so
+//! both wrappers behave the same way.
+//!
+//! The `gui`, `audio-ports-config`, and `note-name` extensions are not yet supported, so plugins
+//! exported as CLAP plugins currently always use their default bus configuration and they can't
+//! show their editor.
+
+use clap_sys::audio_buffer::clap_audio_buffer;
+use clap_sys::events::{
+    clap_event_header, clap_event_midi, clap_event_note, clap_event_note_expression,
+    clap_event_param_gesture, clap_event_param_value, clap_event_transport, clap_input_events,
+    clap_output_events, CLAP_CORE_EVENT_SPACE_ID, CLAP_EVENT_MIDI, CLAP_EVENT_NOTE_EXPRESSION,
+    CLAP_EVENT_NOTE_OFF, CLAP_EVENT_NOTE_ON, CLAP_EVENT_PARAM_GESTURE_BEGIN,
+    CLAP_EVENT_PARAM_GESTURE_END, CLAP_EVENT_PARAM_VALUE, CLAP_NOTE_EXPRESSION_BRIGHTNESS,
+    CLAP_NOTE_EXPRESSION_TUNING, CLAP_TRANSPORT_HAS_BEATS_TIMELINE,
+    CLAP_TRANSPORT_HAS_SECONDS_TIMELINE, CLAP_TRANSPORT_HAS_TEMPO,
+    CLAP_TRANSPORT_HAS_TIME_SIGNATURE, CLAP_TRANSPORT_IS_LOOP_ACTIVE, CLAP_TRANSPORT_IS_PLAYING,
+    CLAP_TRANSPORT_IS_RECORDING,
+};
+use clap_sys::ext::audio_ports::{
+    clap_audio_port_info, clap_plugin_audio_ports, CLAP_AUDIO_PORT_IS_MAIN, CLAP_EXT_AUDIO_PORTS,
+    CLAP_PORT_MONO, CLAP_PORT_STEREO,
+};
+use clap_sys::ext::latency::{clap_plugin_latency, CLAP_EXT_LATENCY};
+use clap_sys::ext::note_ports::{
+    clap_note_port_info, clap_plugin_note_ports, CLAP_EXT_NOTE_PORTS, CLAP_NOTE_DIALECT_CLAP,
+    CLAP_NOTE_DIALECT_MIDI,
+};
+use clap_sys::ext::params::{
+    clap_host_params, clap_param_info, clap_plugin_params, CLAP_EXT_PARAMS,
+    CLAP_PARAM_IS_AUTOMATABLE, CLAP_PARAM_IS_BYPASS, CLAP_PARAM_IS_READONLY, CLAP_PARAM_IS_STEPPED,
+    CLAP_PARAM_RESCAN_VALUES,
+};
+use clap_sys::ext::state::{clap_plugin_state, CLAP_EXT_STATE};
+use clap_sys::ext::tail::{clap_plugin_tail, CLAP_EXT_TAIL};
+use clap_sys::factory::plugin_factory::{clap_plugin_factory, CLAP_PLUGIN_FACTORY_ID};
+use clap_sys::fixedpoint::{CLAP_BEATTIME_FACTOR, CLAP_SECTIME_FACTOR};
+use clap_sys::host::clap_host;
+use clap_sys::id::{clap_id, CLAP_INVALID_ID};
+use clap_sys::plugin::{clap_plugin, clap_plugin_descriptor};
+use clap_sys::process::{
+    clap_process, clap_process_status, CLAP_PROCESS_CONTINUE, CLAP_PROCESS_CONTINUE_IF_NOT_QUIET,
+    CLAP_PROCESS_ERROR, CLAP_PROCESS_TAIL,
+};
+use clap_sys::stream::{clap_istream, clap_ostream};
+use crossbeam::atomic::AtomicCell;
+use std::cmp;
+use std::ffi::{c_void, CStr, CString};
+use std::mem;
+use std::os::raw::c_char;
+use std::ptr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use super::inner::{PendingEdit, WrapperInner};
+use super::state;
+use super::util::{process_wrapper, queue_event, stable_input_slice, strlcpy, BYPASS_PARAM_HASH};
+use crate::context::{AutomationPoint, Transport};
+use crate::plugin::{BufferConfig, ClapPlugin, NoteEvent, ProcessStatus, SamplePrecision};
+
+/// Re-exports for the [nih_export_clap!] macro.
+#[doc(hidden)]
+pub use clap_sys::entry::clap_plugin_entry;
+#[doc(hidden)]
+pub use clap_sys::version::CLAP_VERSION;
+#[doc(hidden)]
+pub use lazy_static::lazy_static;
+
+/// Early exit out of a CLAP function when one of the passed pointers is null. CLAP functions don't
+/// have a common error type, so the value to return is passed as the first argument.
+macro_rules! check_null_ptr {
+    ($ret:expr, $ptr:expr $(, $ptrs:expr)* $(, )?) => {
+        if $ptr.is_null() $(|| $ptrs.is_null())* {
+            nih_debug_assert_failure!("Null pointer passed to function");
+            return $ret;
+        }
+    };
+}
+
+/// The CLAP plugin instance the host interacts with. The `clap_plugin` vtable is the first field so
+/// the `*const clap_plugin` pointers the host passes to the callbacks can be cast back to this
+/// struct.
+#[repr(C)]
+struct Wrapper<P: ClapPlugin> {
+    clap_plugin: clap_plugin,
+
+    /// The format-agnostic parts of the wrapper, shared with the VST3 wrapper.
+    inner: Arc<WrapperInner<P>>,
+    /// The host that created this instance.
+    host: *const clap_host,
+    /// The host's `clap_host_params` extension, if it supports it. CLAP doesn't allow querying host
+    /// extensions before `clap_plugin::init()` has been called, so this is set there. Null if the
+    /// host does not support the extension.
+    host_params: AtomicCell<*const clap_host_params>,
+
+    clap_plugin_audio_ports: clap_plugin_audio_ports,
+    clap_plugin_latency: clap_plugin_latency,
+    clap_plugin_note_ports: clap_plugin_note_ports,
+    clap_plugin_params: clap_plugin_params,
+    clap_plugin_state: clap_plugin_state,
+    clap_plugin_tail: clap_plugin_tail,
+}
+
+impl<P: ClapPlugin> Wrapper<P> {
+    pub fn new(host: *const clap_host, descriptor: *const clap_plugin_descriptor) -> Box<Self> {
+        let mut wrapper = Box::new(Self {
+            clap_plugin: clap_plugin {
+                desc: descriptor,
+                // This is set to the wrapper's address below, after it has been boxed
+                plugin_data: ptr::null_mut(),
+                init: Some(Self::init),
+                destroy: Some(Self::destroy),
+                activate: Some(Self::activate),
+                deactivate: Some(Self::deactivate),
+                start_processing: Some(Self::start_processing),
+                stop_processing: Some(Self::stop_processing),
+                reset: Some(Self::reset),
+                process: Some(Self::process),
+                get_extension: Some(Self::get_extension),
+                on_main_thread: Some(Self::on_main_thread),
+            },
+
+            inner: WrapperInner::new(),
+            host,
+            host_params: AtomicCell::new(ptr::null()),
+
+            clap_plugin_audio_ports: clap_plugin_audio_ports {
+                count: Some(Self::ext_audio_ports_count),
+                get: Some(Self::ext_audio_ports_get),
+            },
+            clap_plugin_latency: clap_plugin_latency {
+                get: Some(Self::ext_latency_get),
+            },
+            clap_plugin_note_ports: clap_plugin_note_ports {
+                count: Some(Self::ext_note_ports_count),
+                get: Some(Self::ext_note_ports_get),
+            },
+            clap_plugin_params: clap_plugin_params {
+                count: Some(Self::ext_params_count),
+                get_info: Some(Self::ext_params_get_info),
+                get_value: Some(Self::ext_params_get_value),
+                value_to_text: Some(Self::ext_params_value_to_text),
+                text_to_value: Some(Self::ext_params_text_to_value),
+                flush: Some(Self::ext_params_flush),
+            },
+            clap_plugin_state: clap_plugin_state {
+                save: Some(Self::ext_state_save),
+                load: Some(Self::ext_state_load),
+            },
+            clap_plugin_tail: clap_plugin_tail {
+                get: Some(Self::ext_tail_get),
+            },
+        });
+        wrapper.clap_plugin.plugin_data = &*wrapper as *const Self as *mut c_void;
+
+        wrapper
+    }
+
+    /// Get the wrapper from one of the `*const clap_plugin` pointers passed to the callbacks.
+    ///
+    /// # Safety
+    ///
+    /// `plugin` needs to point to the `clap_plugin` field of a live [Wrapper].
+    unsafe fn from_ptr<'a>(plugin: *const clap_plugin) -> &'a Self {
+        &*((*plugin).plugin_data as *const Self)
+    }
+
+    /// Stepped parameters are exposed to the host as their step index, so hosts can show them as
+    /// discrete values. Returns 0 for continuous and unknown parameters.
+    unsafe fn param_step_count(&self, hash: u32) -> i32 {
+        if hash == *BYPASS_PARAM_HASH {
+            1
+        } else {
+            match self.inner.param_by_hash.get(&hash) {
+                Some(param_ptr) => cmp::max(param_ptr.step_count(), 0),
+                None => 0,
+            }
+        }
+    }
+
+    /// Convert a normalized parameter value to the value the host sees. CLAP uses plain values, so
+    /// stepped parameters range from 0 to their step count and all other parameters range from 0 to
+    /// 1.
+    unsafe fn normalized_to_clap_value(&self, hash: u32, normalized: f32) -> f64 {
+        match self.param_step_count(hash) {
+            0 => normalized as f64,
+            step_count => (normalized as f64 * step_count as f64).round(),
+        }
+    }
+
+    /// The inverse of [Self::normalized_to_clap_value()].
+    unsafe fn clap_value_to_normalized(&self, hash: u32, value: f64) -> f32 {
+        let normalized = match self.param_step_count(hash) {
+            0 => value,
+            step_count => value / step_count as f64,
+        };
+
+        normalized.clamp(0.0, 1.0) as f32
+    }
+
+    /// Handle the host's input events. Parameter changes are applied right away, and note events
+    /// are queued for the plugin's next process call. During a parameter flush there is no audio
+    /// block for automation points to belong to, so those are not stored and note events are
+    /// delivered at the start of the next block instead.
+    unsafe fn handle_in_events(
+        &self,
+        in_events: *const clap_input_events,
+        is_parameter_flush: bool,
+    ) {
+        if in_events.is_null() {
+            return;
+        }
+
+        let in_events = &*in_events;
+        let (size_fn, get_fn) = match (in_events.size, in_events.get) {
+            (Some(size_fn), Some(get_fn)) => (size_fn, get_fn),
+            _ => {
+                nih_debug_assert_failure!("The host's input event list is missing callbacks");
+                return;
+            }
+        };
+
+        let sample_rate = self
+            .inner
+            .current_buffer_config
+            .load()
+            .map(|c| c.sample_rate);
+        let mut input_events = self.inner.input_events.write();
+        let mut input_automation = self.inner.input_automation.write();
+
+        // CLAP guarantees that these events are already sorted by their timing
+        let num_events = size_fn(in_events);
+        for event_idx in 0..num_events {
+            let event = get_fn(in_events, event_idx);
+            if event.is_null() || (*event).space_id != CLAP_CORE_EVENT_SPACE_ID {
+                continue;
+            }
+
+            let timing = if is_parameter_flush { 0 } else { (*event).time };
+            match (*event).type_ {
+                CLAP_EVENT_PARAM_VALUE => {
+                    let event = &*(event as *const clap_event_param_value);
+                    let param_hash = event.param_id;
+                    let normalized = self.clap_value_to_normalized(param_hash, event.value);
+
+                    // The plugin also gets to see the individual automation points, just like in
+                    // the VST3 wrapper
+                    let param_ptr = self.inner.param_by_hash.get(&param_hash);
+                    if let (Some(param_ptr), false) = (param_ptr, is_parameter_flush) {
+                        queue_event(
+                            &mut input_automation,
+                            AutomationPoint {
+                                timing,
+                                param: *param_ptr,
+                                normalized,
+                                plain: param_ptr.preview_plain(normalized),
+                            },
+                        );
+                    }
+
+                    // TODO: Handle sample accurate parameter changes, possibly in a similar way to
+                    //       the smoothing
+                    self.inner.set_normalized_value_by_hash(
+                        param_hash,
+                        normalized,
+                        sample_rate,
+                        self.inner.host_change_source(param_hash),
+                    );
+                }
+                CLAP_EVENT_NOTE_ON | CLAP_EVENT_NOTE_OFF if P::ACCEPTS_MIDI => {
+                    let note_event = &*(event as *const clap_event_note);
+                    // Negative keys and channels are wildcards, which don't mean anything for
+                    // individual notes
+                    if note_event.key < 0 || note_event.channel < 0 {
+                        continue;
+                    }
+
+                    // Negative note IDs mean that the host didn't assign an ID to this note
+                    let note_id = if note_event.note_id >= 0 {
+                        Some(note_event.note_id)
+                    } else {
+                        None
+                    };
+                    let channel = note_event.channel as u8 & 0x0F;
+                    let note = note_event.key as u8 & 0x7F;
+                    let velocity = (note_event.velocity.clamp(0.0, 1.0) * 127.0).round() as u8;
+                    queue_event(
+                        &mut input_events,
+                        if (*event).type_ == CLAP_EVENT_NOTE_ON {
+                            NoteEvent::NoteOn {
+                                timing,
+                                note_id,
+                                channel,
+                                note,
+                                velocity,
+                            }
+                        } else {
+                            NoteEvent::NoteOff {
+                                timing,
+                                note_id,
+                                channel,
+                                note,
+                                velocity,
+                            }
+                        },
+                    );
+                }
+                CLAP_EVENT_NOTE_EXPRESSION if P::ACCEPTS_MIDI => {
+                    let expression_event = &*(event as *const clap_event_note_expression);
+                    // Our polyphonic expression events are always tied to a specific note ID
+                    if expression_event.note_id < 0 {
+                        continue;
+                    }
+
+                    let note_id = expression_event.note_id;
+                    match expression_event.expression_id {
+                        // CLAP's tuning expression is already expressed in semitones
+                        CLAP_NOTE_EXPRESSION_TUNING => {
+                            queue_event(
+                                &mut input_events,
+                                NoteEvent::PolyPitchBend {
+                                    timing,
+                                    note_id,
+                                    semitones: expression_event.value as f32,
+                                },
+                            );
+                        }
+                        CLAP_NOTE_EXPRESSION_BRIGHTNESS => {
+                            queue_event(
+                                &mut input_events,
+                                NoteEvent::PolyBrightness {
+                                    timing,
+                                    note_id,
+                                    brightness: expression_event.value.clamp(0.0, 1.0) as f32,
+                                },
+                            );
+                        }
+                        _ => (),
+                    }
+                }
+                CLAP_EVENT_MIDI if P::ACCEPTS_MIDI => {
+                    let midi_event = &*(event as *const clap_event_midi);
+                    if let Some(note_event) = self.inner.midi_to_note_event(timing, midi_event.data)
+                    {
+                        queue_event(&mut input_events, note_event);
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Send the parameter edits queued up in [WrapperInner::pending_edits] to the host, followed by
+    /// the current values of the plugin's meters. CLAP wrappers never set a component handler, so
+    /// all edits made by the plugin end up in that queue.
+    unsafe fn handle_out_events(&self, out_events: *const clap_output_events) {
+        if out_events.is_null() {
+            return;
+        }
+
+        let out_events = &*out_events;
+        let try_push_fn = match out_events.try_push {
+            Some(try_push_fn) => try_push_fn,
+            None => {
+                nih_debug_assert_failure!("The host's output event list is missing callbacks");
+                return;
+            }
+        };
+        let push_event = |event: *const clap_event_header| {
+            let pushed = try_push_fn(out_events, event);
+            nih_debug_assert!(
+                pushed,
+                "The host's output event queue is full, dropping event..."
+            );
+        };
+        let header = |size: usize, type_: u16| clap_event_header {
+            size: size as u32,
+            time: 0,
+            space_id: CLAP_CORE_EVENT_SPACE_ID,
+            type_,
+            flags: 0,
+        };
+        let param_value = |param_hash: u32, normalized: f32| clap_event_param_value {
+            header: header(
+                mem::size_of::<clap_event_param_value>(),
+                CLAP_EVENT_PARAM_VALUE,
+            ),
+            param_id: param_hash,
+            cookie: ptr::null_mut(),
+            note_id: -1,
+            port_index: -1,
+            channel: -1,
+            key: -1,
+            value: self.normalized_to_clap_value(param_hash, normalized),
+        };
+        let param_gesture = |param_hash: u32, type_: u16| clap_event_param_gesture {
+            header: header(mem::size_of::<clap_event_param_gesture>(), type_),
+            param_id: param_hash,
+        };
+
+        while let Some(edit) = self.inner.pending_edits.pop() {
+            match edit {
+                PendingEdit::Begin(hash) => {
+                    let event = param_gesture(hash, CLAP_EVENT_PARAM_GESTURE_BEGIN);
+                    push_event(&event.header);
+                }
+                PendingEdit::Perform(hash, normalized) => {
+                    let event = param_value(hash, normalized);
+                    push_event(&event.header);
+                }
+                PendingEdit::End(hash) => {
+                    let event = param_gesture(hash, CLAP_EVENT_PARAM_GESTURE_END);
+                    push_event(&event.header);
+                }
+            }
+        }
+
+        // The plugin may have updated its meters during the process call
+        for param_hash in &self.inner.meter_param_hashes {
+            let normalized = self.inner.param_by_hash[param_hash].normalized_value();
+            let event = param_value(*param_hash, normalized);
+            push_event(&event.header);
+        }
+    }
+
+    /// Send the note events the plugin sent during this block of `num_samples` samples to the host.
+    /// Notes are sent as CLAP note events, and everything else is sent as raw MIDI.
+    unsafe fn handle_out_note_events(
+        &self,
+        out_events: *const clap_output_events,
+        num_samples: u32,
+    ) {
+        let try_push_fn = match out_events
+            .as_ref()
+            .and_then(|out_events| out_events.try_push)
+        {
+            Some(try_push_fn) => try_push_fn,
+            None => {
+                // Events that can't be sent are dropped so they don't pile up
+                self.inner.drain_output_events(num_samples, |_| ());
+                return;
+            }
+        };
+        let push_event = |event: *const clap_event_header| {
+            let pushed = try_push_fn(out_events, event);
+            nih_debug_assert!(
+                pushed,
+                "The host's output event queue is full, dropping event..."
+            );
+        };
+        let header = |size: usize, type_: u16, time: u32| clap_event_header {
+            size: size as u32,
+            time,
+            space_id: CLAP_CORE_EVENT_SPACE_ID,
+            type_,
+            flags: 0,
+        };
+        let push_midi = |time: u32, data: [u8; 3]| {
+            let mut event: clap_event_midi = mem::zeroed();
+            event.header = header(mem::size_of::<clap_event_midi>(), CLAP_EVENT_MIDI, time);
+            event.port_index = 0;
+            event.data = data;
+            push_event(&event.header);
+        };
+        let push_note_expression = |time: u32, note_id: i32, expression_id: i32, value: f64| {
+            let mut event: clap_event_note_expression = mem::zeroed();
+            event.header = header(
+                mem::size_of::<clap_event_note_expression>(),
+                CLAP_EVENT_NOTE_EXPRESSION,
+                time,
+            );
+            event.expression_id = expression_id;
+            // The note is identified purely by its note ID, the other fields are wildcards
+            event.note_id = note_id;
+            event.port_index = 0;
+            event.channel = -1;
+            event.key = -1;
+            event.value = value;
+            push_event(&event.header);
+        };
+
+        self.inner
+            .drain_output_events(num_samples, |event| match *event {
+                NoteEvent::NoteOn {
+                    timing,
+                    note_id,
+                    channel,
+                    note,
+                    velocity,
+                }
+                | NoteEvent::NoteOff {
+                    timing,
+                    note_id,
+                    channel,
+                    note,
+                    velocity,
+                } => {
+                    let type_ = if matches!(event, NoteEvent::NoteOn { .. }) {
+                        CLAP_EVENT_NOTE_ON
+                    } else {
+                        CLAP_EVENT_NOTE_OFF
+                    };
+
+                    let mut note_event: clap_event_note = mem::zeroed();
+                    note_event.header = header(mem::size_of::<clap_event_note>(), type_, timing);
+                    note_event.note_id = note_id.unwrap_or(-1);
+                    note_event.port_index = 0;
+                    note_event.channel = channel as _;
+                    note_event.key = note as _;
+                    note_event.velocity = velocity as f64 / 127.0;
+                    push_event(&note_event.header);
+                }
+                NoteEvent::PolyPressure {
+                    timing,
+                    channel,
+                    note,
+                    pressure,
+                    ..
+                } => push_midi(
+                    timing,
+                    [
+                        0xA0 | (channel & 0x0F),
+                        note & 0x7F,
+                        (pressure * 127.0).round() as u8 & 0x7F,
+                    ],
+                ),
+                NoteEvent::PolyPitchBend {
+                    timing,
+                    note_id,
+                    semitones,
+                } => push_note_expression(
+                    timing,
+                    note_id,
+                    CLAP_NOTE_EXPRESSION_TUNING,
+                    semitones as f64,
+                ),
+                NoteEvent::PolyBrightness {
+                    timing,
+                    note_id,
+                    brightness,
+                } => push_note_expression(
+                    timing,
+                    note_id,
+                    CLAP_NOTE_EXPRESSION_BRIGHTNESS,
+                    brightness as f64,
+                ),
+                NoteEvent::MidiProgramChange {
+                    timing,
+                    channel,
+                    program,
+                } => push_midi(timing, [0xC0 | (channel & 0x0F), program & 0x7F, 0]),
+                NoteEvent::MidiBankSelect {
+                    timing,
+                    channel,
+                    bank,
+                } => {
+                    let status = 0xB0 | (channel & 0x0F);
+                    push_midi(timing, [status, 0, ((bank >> 7) & 0x7F) as u8]);
+                    push_midi(timing, [status, 32, (bank & 0x7F) as u8]);
+                }
+                NoteEvent::MidiCC {
+                    timing,
+                    channel,
+                    cc,
+                    value,
+                } => push_midi(
+                    timing,
+                    [
+                        0xB0 | (channel & 0x0F),
+                        cc & 0x7F,
+                        (value * 127.0).round() as u8 & 0x7F,
+                    ],
+                ),
+                NoteEvent::MidiPitchBend {
+                    timing,
+                    channel,
+                    value,
+                } => {
+                    let value = (value * 16383.0).round() as u16;
+                    push_midi(
+                        timing,
+                        [
+                            0xE0 | (channel & 0x0F),
+                            (value & 0x7F) as u8,
+                            ((value >> 7) & 0x7F) as u8,
+                        ],
+                    );
+                }
+                NoteEvent::MidiChannelPressure {
+                    timing,
+                    channel,
+                    pressure,
+                } => push_midi(
+                    timing,
+                    [
+                        0xD0 | (channel & 0x0F),
+                        (pressure * 127.0).round() as u8 & 0x7F,
+                        0,
+                    ],
+                ),
+            });
+    }
+
+    /// Ask the host to call [Self::on_main_thread()] if the plugin requested a restart that hasn't
+    /// been handled yet. The actual restart needs to happen on the main thread.
+    unsafe fn request_pending_restart(&self) {
+        if self.inner.pending_restart_flags.load(Ordering::SeqCst) != 0 {
+            if let Some(request_callback) = (*self.host).request_callback {
+                request_callback(self.host);
+            }
+        }
+    }
+
+    unsafe extern "C" fn init(plugin: *const clap_plugin) -> bool {
+        check_null_ptr!(false, plugin);
+        let wrapper = Self::from_ptr(plugin);
+
+        // Host extensions can only be queried from here on
+        if let Some(get_extension) = (*wrapper.host).get_extension {
+            wrapper
+                .host_params
+                .store(get_extension(wrapper.host, CLAP_EXT_PARAMS.as_ptr())
+                    as *const clap_host_params);
+        }
+
+        // The edits made from the editor are only sent to the host from `process()` or from a
+        // parameter flush, so the host needs to be asked for a flush when it's not processing audio
+        let host_params = wrapper.host_params.load();
+        if let Some(request_flush) = host_params.as_ref().and_then(|p| p.request_flush) {
+            let host = HostPtr(wrapper.host);
+            *wrapper.inner.pending_edits_callback.write() =
+                Some(Box::new(move || request_flush(host.get())));
+        }
+
+        true
+    }
+
+    unsafe extern "C" fn destroy(plugin: *const clap_plugin) {
+        check_null_ptr!((), plugin);
+
+        drop(Box::from_raw((*plugin).plugin_data as *mut Self));
+    }
+
+    unsafe extern "C" fn activate(
+        plugin: *const clap_plugin,
+        sample_rate: f64,
+        _min_frames_count: u32,
+        max_frames_count: u32,
+    ) -> bool {
+        check_null_ptr!(false, plugin);
+        let wrapper = Self::from_ptr(plugin);
+
+        // CLAP also supports 64-bit audio, but just like in the VST3 wrapper we don't advertise
+        // that until [crate::Buffer] supports it
+        let buffer_config = BufferConfig {
+            sample_rate: sample_rate as f32,
+            max_buffer_size: max_frames_count,
+            sample_precision: SamplePrecision::Single,
+        };
+
+        wrapper.inner.initialize_plugin(buffer_config)
+    }
+
+    unsafe extern "C" fn deactivate(_plugin: *const clap_plugin) {
+        // We don't need any special handling here
+    }
+
+    unsafe extern "C" fn start_processing(plugin: *const clap_plugin) -> bool {
+        check_null_ptr!(false, plugin);
+        let wrapper = Self::from_ptr(plugin);
+
+        wrapper.inner.set_processing(true);
+
+        true
+    }
+
+    unsafe extern "C" fn stop_processing(plugin: *const clap_plugin) {
+        check_null_ptr!((), plugin);
+        let wrapper = Self::from_ptr(plugin);
+
+        wrapper.inner.set_processing(false);
+    }
+
+    unsafe extern "C" fn reset(plugin: *const clap_plugin) {
+        check_null_ptr!((), plugin);
+        let wrapper = Self::from_ptr(plugin);
+
+        // The plugin API doesn't have a way to clear the plugin's own state without reinitializing
+        // it yet, so this only resets the wrapper's processing state
+        wrapper.inner.reset();
+    }
+
+    unsafe extern "C" fn process(
+        plugin: *const clap_plugin,
+        process: *const clap_process,
+    ) -> clap_process_status {
+        check_null_ptr!(CLAP_PROCESS_ERROR, plugin, process);
+        let wrapper = Self::from_ptr(plugin);
+
+        // A plugin that panicked before is likely in an inconsistent state, so it won't be
+        // processed anymore and it only outputs silence
+        if wrapper.inner.panicked.load(Ordering::SeqCst) {
+            clear_outputs(&*process);
+            return CLAP_PROCESS_ERROR;
+        }
+
+        // Panic on allocations if the `assert_process_allocs` feature has been enabled, and make
+        // sure that FTZ is set up correctly unless the plugin opted out of that. Panics are caught
+        // here so they don't unwind into the host.
+        let status = process_wrapper::<P, _, _>(|| {
+            let process = &*process;
+
+            // All of the wrapper's buffers are allocated for the maximum block size passed to
+            // `activate()`, and hosts are not allowed to exceed it. If a host does so anyways, then
+            // only the first part of the block is processed.
+            let max_buffer_size = wrapper
+                .inner
+                .current_buffer_config
+                .load()
+                .map_or(0, |c| c.max_buffer_size);
+            nih_debug_assert!(
+                process.frames_count <= max_buffer_size,
+                "The host passed {} samples, but the maximum block size is {}",
+                process.frames_count,
+                max_buffer_size
+            );
+            let num_samples = process.frames_count.min(max_buffer_size) as usize;
+
+            // We need to handle incoming automation and note events first
+            wrapper.handle_in_events(process.in_events, false);
+
+            let audio_inputs: &[clap_audio_buffer] = if process.audio_inputs.is_null() {
+                &[]
+            } else {
+                std::slice::from_raw_parts(
+                    process.audio_inputs,
+                    process.audio_inputs_count as usize,
+                )
+            };
+            let audio_outputs: &[clap_audio_buffer] = if process.audio_outputs.is_null() {
+                &[]
+            } else {
+                std::slice::from_raw_parts(
+                    process.audio_outputs as *const clap_audio_buffer,
+                    process.audio_outputs_count as usize,
+                )
+            };
+
+            // The main input and output ports are only exposed when the plugin has main input or
+            // output channels, and any auxiliary input ports come after the main input port. See
+            // `ext_audio_ports_get()`.
+            let (has_main_input, has_main_output) = {
+                let bus_config = wrapper.inner.current_bus_config.read();
+                (
+                    bus_config.num_input_channels > 0,
+                    bus_config.num_output_channels > 0,
+                )
+            };
+            let main_input = audio_inputs
+                .first()
+                .filter(|_| has_main_input)
+                .filter(|port| !port.data32.is_null());
+            let main_output = audio_outputs
+                .first()
+                .filter(|_| has_main_output)
+                .filter(|port| !port.data32.is_null());
+            let aux_inputs = &audio_inputs[cmp::min(has_main_input as usize, audio_inputs.len())..];
+            let (output_channel_ptrs, num_output_channels) = match main_output {
+                Some(main_output) => (
+                    main_output.data32 as *const *const f32,
+                    main_output.channel_count as usize,
+                ),
+                None => (ptr::null(), 0),
+            };
+
+            // This vector has been reallocated to contain enough slices as there are output
+            // channels (or input channels for analysis-only plugins)
+            let mut output_buffer = wrapper.inner.output_buffer.write();
+            // Analysis-only plugins don't get any output slices, they only get to read the host's
+            // inputs. Those are set up after the inputs have been copied to the outputs below.
+            if !P::ANALYSIS_ONLY {
+                let output_slices = output_buffer.as_raw_vec();
+                nih_debug_assert_eq!(num_output_channels, output_slices.len());
+                for (output_channel_idx, output_channel_slice) in
+                    output_slices.iter_mut().enumerate()
+                {
+                    // SAFETY: These pointers may not be valid outside of this function even though
+                    // their lifetime is equal to this structs. This is still safe because they are
+                    // only dereferenced here later as part of this process function.
+                    *output_channel_slice = match main_output {
+                        Some(main_output) if output_channel_idx < num_output_channels => {
+                            std::slice::from_raw_parts_mut(
+                                *main_output.data32.add(output_channel_idx),
+                                num_samples,
+                            )
+                        }
+                        _ => &mut [],
+                    };
+                }
+            }
+
+            // The plugin may also need to read the unmodified inputs after writing to the
+            // outputs. This has to happen before the inputs get copied to the outputs below.
+            if P::SEPARATE_INPUT_BUFFERS && !P::ANALYSIS_ONLY {
+                let mut input_scratch_buffers = wrapper.inner.input_scratch_buffers.write();
+                let input_slices = output_buffer.as_raw_input_vec();
+                let num_input_channels = main_input.map_or(0, |port| port.channel_count as usize);
+                for (input_channel_idx, (input_channel_slice, scratch_buffer)) in input_slices
+                    .iter_mut()
+                    .zip(input_scratch_buffers.iter_mut())
+                    .enumerate()
+                {
+                    let input_channel_ptr = match main_input {
+                        Some(port) if input_channel_idx < num_input_channels => {
+                            *(port.data32 as *const *const f32).add(input_channel_idx)
+                        }
+                        _ => ptr::null(),
+                    };
+
+                    // SAFETY: `stable_input_slice()` only resizes this scratch buffer, which
+                    //         happens if the host exceeds the maximum buffer size, before creating
+                    //         the slice. The scratch buffers are not touched again until the next
+                    //         process call, so the slice stays valid while the plugin processes
+                    //         this block.
+                    *input_channel_slice = stable_input_slice(
+                        input_channel_ptr,
+                        output_channel_ptrs,
+                        num_output_channels,
+                        scratch_buffer,
+                        num_samples,
+                    );
+                }
+            }
+
+            // The same goes for the auxiliary inputs. Ports the host didn't connect are passed as
+            // silence.
+            {
+                let mut aux_input_scratch_buffers = wrapper.inner.aux_input_scratch_buffers.write();
+                let aux_input_slices = output_buffer.as_raw_aux_input_vec();
+                for (aux_input_idx, (channel_slices, scratch_buffers)) in aux_input_slices
+                    .iter_mut()
+                    .zip(aux_input_scratch_buffers.iter_mut())
+                    .enumerate()
+                {
+                    let aux_input = aux_inputs
+                        .get(aux_input_idx)
+                        .filter(|port| !port.data32.is_null());
+                    let num_aux_input_channels =
+                        aux_input.map_or(0, |port| port.channel_count as usize);
+
+                    for (channel_idx, (channel_slice, scratch_buffer)) in channel_slices
+                        .iter_mut()
+                        .zip(scratch_buffers.iter_mut())
+                        .enumerate()
+                    {
+                        let input_channel_ptr = match aux_input {
+                            Some(port) if channel_idx < num_aux_input_channels => {
+                                *(port.data32 as *const *const f32).add(channel_idx)
+                            }
+                            _ => ptr::null(),
+                        };
+
+                        // SAFETY: `stable_input_slice()` only resizes this scratch buffer, which
+                        //         happens if the host exceeds the maximum buffer size, before
+                        //         creating the slice. The scratch buffers are not touched again
+                        //         until the next process call, so the slice stays valid while the
+                        //         plugin processes this block.
+                        *channel_slice = stable_input_slice(
+                            input_channel_ptr,
+                            output_channel_ptrs,
+                            num_output_channels,
+                            scratch_buffer,
+                            num_samples,
+                        );
+                    }
+                }
+            }
+
+            // Hosts may process audio in place, in which case we don't need to do any copying
+            // ourselves. If the pointers do not alias, then we'll do the copy here and then the
+            // plugin can just do normal in place processing. When the plugin has more output
+            // channels than input channels the remaining outputs are cleared, and any extra input
+            // channels are dropped.
+            if let (Some(main_input), Some(main_output)) = (main_input, main_output) {
+                let num_input_channels = main_input.channel_count as usize;
+                let num_copied_channels = cmp::min(num_input_channels, num_output_channels);
+                for channel_idx in 0..num_copied_channels {
+                    let output_channel_ptr = *main_output.data32.add(channel_idx);
+                    let input_channel_ptr = *main_input.data32.add(channel_idx);
+                    if input_channel_ptr != output_channel_ptr {
+                        ptr::copy_nonoverlapping(
+                            input_channel_ptr,
+                            output_channel_ptr,
+                            num_samples,
+                        );
+                    }
+                }
+
+                for channel_idx in num_copied_channels..num_output_channels {
+                    ptr::write_bytes(*main_output.data32.add(channel_idx), 0, num_samples);
+                }
+            }
+
+            // Plugins that only analyze their input directly get to read the host's input buffers.
+            // Nothing writes to the outputs while the plugin is processing audio, so this is fine
+            // even when the host processes audio in place.
+            if P::ANALYSIS_ONLY {
+                let input_slices = output_buffer.as_raw_input_vec();
+                let num_input_channels = main_input.map_or(0, |port| port.channel_count as usize);
+                nih_debug_assert_eq!(num_input_channels, input_slices.len());
+                for (input_channel_idx, input_channel_slice) in input_slices.iter_mut().enumerate()
+                {
+                    *input_channel_slice = match main_input {
+                        Some(port) if input_channel_idx < num_input_channels => {
+                            std::slice::from_raw_parts(
+                                *(port.data32 as *const *const f32).add(input_channel_idx),
+                                num_samples,
+                            )
+                        }
+                        _ => &[],
+                    };
+                }
+            }
+
+            let plugin = &mut *wrapper.inner.plugin.data_ptr();
+            let transport = transport_from_clap(
+                process.transport,
+                wrapper
+                    .inner
+                    .current_buffer_config
+                    .load()
+                    .map_or(0.0, |c| c.sample_rate),
+            );
+            let mut context = wrapper.inner.make_process_context(transport);
+            let status =
+                wrapper
+                    .inner
+                    .process_bypassable(&mut output_buffer, num_samples, |buffer| {
+                        plugin.process(buffer, &mut context)
+                    });
+            // This is used to report the plugin's tail length to the host
+            wrapper.inner.last_process_status.store(status);
+            // Any events and automation points the plugin did not consume are meant for this block
+            // only
+            context.input_events_guard.clear();
+            context.input_automation_guard.clear();
+            drop(context);
+
+            wrapper
+                .inner
+                .apply_activation_fade_in(&mut output_buffer, num_samples as u32);
+
+            // Parameter changes made by the plugin and its meters' values are sent back to the
+            // host as output events
+            wrapper.handle_out_events(process.out_events);
+            if P::PRODUCES_MIDI {
+                wrapper.handle_out_note_events(process.out_events, num_samples as u32);
+            }
+            wrapper.request_pending_restart();
+
+            match status {
+                ProcessStatus::Error(err) => {
+                    nih_debug_assert_failure!("Process error: {}", err);
+
+                    CLAP_PROCESS_ERROR
+                }
+                ProcessStatus::Normal => CLAP_PROCESS_CONTINUE_IF_NOT_QUIET,
+                ProcessStatus::Tail(_) => CLAP_PROCESS_TAIL,
+                ProcessStatus::KeepAlive => CLAP_PROCESS_CONTINUE,
+            }
+        });
+
+        match status {
+            Some(status) => status,
+            None => {
+                // The plugin may have left anything in the output buffers, and the host should not
+                // get to play that back
+                wrapper.inner.panicked.store(true, Ordering::SeqCst);
+                clear_outputs(&*process);
+                CLAP_PROCESS_ERROR
+            }
+        }
+    }
+
+    unsafe extern "C" fn get_extension(
+        plugin: *const clap_plugin,
+        id: *const c_char,
+    ) -> *const c_void {
+        check_null_ptr!(ptr::null(), plugin, id);
+        let wrapper = Self::from_ptr(plugin);
+
+        let id = CStr::from_ptr(id);
+        if id == CLAP_EXT_AUDIO_PORTS {
+            &wrapper.clap_plugin_audio_ports as *const _ as *const c_void
+        } else if id == CLAP_EXT_LATENCY {
+            &wrapper.clap_plugin_latency as *const _ as *const c_void
+        } else if id == CLAP_EXT_NOTE_PORTS && (P::ACCEPTS_MIDI || P::PRODUCES_MIDI) {
+            &wrapper.clap_plugin_note_ports as *const _ as *const c_void
+        } else if id == CLAP_EXT_PARAMS {
+            &wrapper.clap_plugin_params as *const _ as *const c_void
+        } else if id == CLAP_EXT_STATE {
+            &wrapper.clap_plugin_state as *const _ as *const c_void
+        } else if id == CLAP_EXT_TAIL {
+            &wrapper.clap_plugin_tail as *const _ as *const c_void
+        } else {
+            ptr::null()
+        }
+    }
+
+    unsafe extern "C" fn on_main_thread(plugin: *const clap_plugin) {
+        check_null_ptr!((), plugin);
+        let wrapper = Self::from_ptr(plugin);
+
+        // The shared wrapper internals speak VST3's restart flags, so these need to be translated
+        // to the equivalent CLAP host callbacks
+        let restart_flags = wrapper
+            .inner
+            .pending_restart_flags
+            .swap(0, Ordering::SeqCst);
+        if restart_flags & vst3_sys::vst::RestartFlags::kLatencyChanged as i32 != 0 {
+            // CLAP hosts only requery the latency when the plugin gets reactivated
+            if let Some(request_restart) = (*wrapper.host).request_restart {
+                request_restart(wrapper.host);
+            }
+        }
+        if restart_flags & vst3_sys::vst::RestartFlags::kParamValuesChanged as i32 != 0 {
+            let host_params = wrapper.host_params.load();
+            if !host_params.is_null() {
+                if let Some(rescan) = (*host_params).rescan {
+                    rescan(wrapper.host, CLAP_PARAM_RESCAN_VALUES);
+                }
+            }
+        }
+    }
+
+    unsafe extern "C" fn ext_audio_ports_count(plugin: *const clap_plugin, is_input: bool) -> u32 {
+        check_null_ptr!(0, plugin);
+        let wrapper = Self::from_ptr(plugin);
+
+        let bus_config = wrapper.inner.current_bus_config.read();
+        if is_input {
+            (bus_config.num_input_channels > 0) as u32 + bus_config.aux_input_channels.len() as u32
+        } else {
+            (bus_config.num_output_channels > 0) as u32
+        }
+    }
+
+    unsafe extern "C" fn ext_audio_ports_get(
+        plugin: *const clap_plugin,
+        index: u32,
+        is_input: bool,
+        info: *mut clap_audio_port_info,
+    ) -> bool {
+        check_null_ptr!(false, plugin, info);
+        let wrapper = Self::from_ptr(plugin);
+
+        let bus_config = wrapper.inner.current_bus_config.read();
+        let has_main_input = bus_config.num_input_channels > 0;
+        let has_main_output = bus_config.num_output_channels > 0;
+        // CLAP only allows ports with the same channel count to be processed in place
+        let can_process_in_place = has_main_input
+            && has_main_output
+            && bus_config.num_input_channels == bus_config.num_output_channels;
+
+        // The main input and output ports share their buffers if the host processes in place
+        let (name, flags, channel_count, in_place_pair) = match (is_input, index) {
+            (true, 0) if has_main_input => (
+                String::from("Input"),
+                CLAP_AUDIO_PORT_IS_MAIN,
+                bus_config.num_input_channels,
+                if can_process_in_place {
+                    0
+                } else {
+                    CLAP_INVALID_ID
+                },
+            ),
+            (true, n) => {
+                let aux_input_idx = n as usize - has_main_input as usize;
+                let num_channels = match bus_config.aux_input_channels.get(aux_input_idx) {
+                    Some(&num_channels) => num_channels,
+                    None => return false,
+                };
+                let name = if bus_config.aux_input_channels.len() == 1 {
+                    String::from("Sidechain Input")
+                } else {
+                    format!("Sidechain Input {}", aux_input_idx + 1)
+                };
+
+                (name, 0, num_channels, CLAP_INVALID_ID)
+            }
+            (false, 0) if has_main_output => (
+                String::from("Output"),
+                CLAP_AUDIO_PORT_IS_MAIN,
+                bus_config.num_output_channels,
+                if can_process_in_place {
+                    0
+                } else {
+                    CLAP_INVALID_ID
+                },
+            ),
+            (false, _) => return false,
+        };
+
+        *info = mem::zeroed();
+
+        let info = &mut *info;
+        info.id = index;
+        strlcpy(&mut info.name, &name);
+        info.flags = flags;
+        info.channel_count = channel_count;
+        info.port_type = match channel_count {
+            1 => CLAP_PORT_MONO.as_ptr(),
+            2 => CLAP_PORT_STEREO.as_ptr(),
+            _ => ptr::null(),
+        };
+        info.in_place_pair = in_place_pair;
+
+        true
+    }
+
+    unsafe extern "C" fn ext_latency_get(plugin: *const clap_plugin) -> u32 {
+        check_null_ptr!(0, plugin);
+        let wrapper = Self::from_ptr(plugin);
+
+        // Any changes to the latency after this point need to be reported with a restart
+        wrapper.inner.latency_reported.store(true, Ordering::SeqCst);
+        wrapper.inner.current_latency.load(Ordering::SeqCst)
+    }
+
+    unsafe extern "C" fn ext_note_ports_count(plugin: *const clap_plugin, is_input: bool) -> u32 {
+        check_null_ptr!(0, plugin);
+
+        // This extension is only exposed when the plugin accepts or produces MIDI
+        if (is_input && P::ACCEPTS_MIDI) || (!is_input && P::PRODUCES_MIDI) {
+            1
+        } else {
+            0
+        }
+    }
+
+    unsafe extern "C" fn ext_note_ports_get(
+        plugin: *const clap_plugin,
+        index: u32,
+        is_input: bool,
+        info: *mut clap_note_port_info,
+    ) -> bool {
+        check_null_ptr!(false, plugin, info);
+
+        let has_port = if is_input {
+            P::ACCEPTS_MIDI
+        } else {
+            P::PRODUCES_MIDI
+        };
+        if !(has_port && index == 0) {
+            return false;
+        }
+
+        *info = mem::zeroed();
+
+        let info = &mut *info;
+        info.id = 0;
+        info.supported_dialects = CLAP_NOTE_DIALECT_CLAP | CLAP_NOTE_DIALECT_MIDI;
+        info.preferred_dialect = CLAP_NOTE_DIALECT_CLAP;
+        strlcpy(
+            &mut info.name,
+            if is_input {
+                "Note Input"
+            } else {
+                "Note Output"
+            },
+        );
+
+        true
+    }
+
+    unsafe extern "C" fn ext_params_count(plugin: *const clap_plugin) -> u32 {
+        check_null_ptr!(0, plugin);
+        let wrapper = Self::from_ptr(plugin);
+
+        // NOTE: We add a bypass parameter ourselves on index `wrapper.inner.param_hashes.len()`
+        wrapper.inner.param_hashes.len() as u32 + 1
+    }
+
+    unsafe extern "C" fn ext_params_get_info(
+        plugin: *const clap_plugin,
+        param_index: u32,
+        param_info: *mut clap_param_info,
+    ) -> bool {
+        check_null_ptr!(false, plugin, param_info);
+        let wrapper = Self::from_ptr(plugin);
+
+        let num_params = wrapper.inner.param_hashes.len();
+        if param_index as usize > num_params {
+            return false;
+        }
+
+        *param_info = mem::zeroed();
+
+        // The parameter IDs are the same hashes used by the VST3 wrapper, so the IDs stored in the
+        // hosts' project files stay stable
+        let info = &mut *param_info;
+        if param_index as usize == num_params {
+            info.id = *BYPASS_PARAM_HASH;
+            info.flags = CLAP_PARAM_IS_STEPPED | CLAP_PARAM_IS_BYPASS | CLAP_PARAM_IS_AUTOMATABLE;
+            strlcpy(&mut info.name, "Bypass");
+            strlcpy(&mut info.module, "");
+            info.min_value = 0.0;
+            info.max_value = 1.0;
+            info.default_value = 0.0;
+        } else {
+            let param_hash = wrapper.inner.param_hashes[param_index as usize];
+            let default_value = wrapper.inner.param_defaults_normalized[param_index as usize];
+            let param_ptr = &wrapper.inner.param_by_hash[&param_hash];
+            let step_count = wrapper.param_step_count(param_hash);
+
+            info.id = param_hash;
+            info.flags = if param_ptr.is_read_only() {
+                CLAP_PARAM_IS_READONLY
+            } else {
+                CLAP_PARAM_IS_AUTOMATABLE
+            };
+            if step_count > 0 {
+                info.flags |= CLAP_PARAM_IS_STEPPED;
+            }
+            strlcpy(&mut info.name, param_ptr.name());
+            strlcpy(&mut info.module, "");
+            info.min_value = 0.0;
+            info.max_value = if step_count > 0 {
+                step_count as f64
+            } else {
+                1.0
+            };
+            info.default_value = wrapper.normalized_to_clap_value(param_hash, default_value);
+        }
+
+        true
+    }
+
+    unsafe extern "C" fn ext_params_get_value(
+        plugin: *const clap_plugin,
+        param_id: clap_id,
+        value: *mut f64,
+    ) -> bool {
+        check_null_ptr!(false, plugin, value);
+        let wrapper = Self::from_ptr(plugin);
+
+        if param_id == *BYPASS_PARAM_HASH {
+            *value = if wrapper.inner.bypass_state.load(Ordering::SeqCst) {
+                1.0
+            } else {
+                0.0
+            };
+
+            true
+        } else if let Some(param_ptr) = wrapper.inner.param_by_hash.get(&param_id) {
+            *value = wrapper.normalized_to_clap_value(param_id, param_ptr.normalized_value());
+
+            true
+        } else {
+            false
+        }
+    }
+
+    unsafe extern "C" fn ext_params_value_to_text(
+        plugin: *const clap_plugin,
+        param_id: clap_id,
+        value: f64,
+        display: *mut c_char,
+        size: u32,
+    ) -> bool {
+        check_null_ptr!(false, plugin, display);
+        let wrapper = Self::from_ptr(plugin);
+
+        let dest = std::slice::from_raw_parts_mut(display, size as usize);
+        let normalized = wrapper.clap_value_to_normalized(param_id, value);
+        if param_id == *BYPASS_PARAM_HASH {
+            if normalized > 0.5 {
+                strlcpy(dest, "Bypassed")
+            } else {
+                strlcpy(dest, "Enabled")
+            }
+
+            true
+        } else if let Some(value_strings) = wrapper.inner.param_value_strings.get(&param_id) {
+            let step = (normalized * (value_strings.len() - 1) as f32).round();
+            strlcpy(dest, &value_strings[step as usize]);
+
+            true
+        } else if let Some(param_ptr) = wrapper.inner.param_by_hash.get(&param_id) {
+            // CLAP doesn't have a separate field for the unit, so it's included here
+            strlcpy(
+                dest,
+                &param_ptr.normalized_value_to_string(normalized, true),
+            );
+
+            true
+        } else {
+            false
+        }
+    }
+
+    unsafe extern "C" fn ext_params_text_to_value(
+        plugin: *const clap_plugin,
+        param_id: clap_id,
+        display: *const c_char,
+        value: *mut f64,
+    ) -> bool {
+        check_null_ptr!(false, plugin, display, value);
+        let wrapper = Self::from_ptr(plugin);
+
+        let string = match CStr::from_ptr(display).to_str() {
+            Ok(s) => s,
+            Err(_) => return false,
+        };
+
+        if param_id == *BYPASS_PARAM_HASH {
+            *value = match string {
+                "Bypassed" => 1.0,
+                "Enabled" => 0.0,
+                _ => return false,
+            };
+
+            true
+        } else if let Some(param_ptr) = wrapper.inner.param_by_hash.get(&param_id) {
+            let normalized = match param_ptr.string_to_normalized_value(string) {
+                Some(v) => v,
+                None => return false,
+            };
+            *value = wrapper.normalized_to_clap_value(param_id, normalized);
+
+            true
+        } else {
+            false
+        }
+    }
+
+    unsafe extern "C" fn ext_params_flush(
+        plugin: *const clap_plugin,
+        in_: *const clap_input_events,
+        out: *const clap_output_events,
+    ) {
+        check_null_ptr!((), plugin);
+        let wrapper = Self::from_ptr(plugin);
+
+        // This is the CLAP equivalent of a VST3 parameter flush, it's only called when the plugin
+        // is not processing audio
+        wrapper.handle_in_events(in_, true);
+        wrapper.handle_out_events(out);
+        wrapper.request_pending_restart();
+    }
+
+    unsafe extern "C" fn ext_state_save(
+        plugin: *const clap_plugin,
+        stream: *const clap_ostream,
+    ) -> bool {
+        check_null_ptr!(false, plugin, stream);
+        let wrapper = Self::from_ptr(plugin);
+
+        let write_fn = match (*stream).write {
+            Some(write_fn) => write_fn,
+            None => return false,
+        };
+
+        let plugin_state = wrapper.inner.get_state_object();
+        let serialized = match state::serialize(&plugin_state) {
+            Ok(serialized) => serialized,
+            Err(err) => {
+                nih_debug_assert_failure!("Could not save state: {}", err);
+                return false;
+            }
+        };
+
+        // The host may not write everything at once
+        let mut num_bytes_written = 0;
+        while num_bytes_written < serialized.len() {
+            let result = write_fn(
+                stream,
+                serialized.as_ptr().add(num_bytes_written) as *const c_void,
+                (serialized.len() - num_bytes_written) as u64,
+            );
+            if result <= 0 {
+                nih_debug_assert_failure!("Could not write the state to the host's stream");
+                return false;
+            }
+
+            num_bytes_written += result as usize;
+        }
+
+        true
+    }
+
+    unsafe extern "C" fn ext_state_load(
+        plugin: *const clap_plugin,
+        stream: *const clap_istream,
+    ) -> bool {
+        check_null_ptr!(false, plugin, stream);
+        let wrapper = Self::from_ptr(plugin);
+
+        let read_fn = match (*stream).read {
+            Some(read_fn) => read_fn,
+            None => return false,
+        };
+
+        // CLAP streams don't have a length, so we'll need to keep reading until the end of the
+        // stream
+        let mut read_buffer: Vec<u8> = Vec::new();
+        let mut chunk = [0u8; 4096];
+        loop {
+            let result = read_fn(
+                stream,
+                chunk.as_mut_ptr() as *mut c_void,
+                chunk.len() as u64,
+            );
+            match result {
+                0 => break,
+                n if n > 0 => read_buffer.extend_from_slice(&chunk[..n as usize]),
+                _ => {
+                    nih_debug_assert_failure!("Could not read the state from the host's stream");
+                    return false;
+                }
+            }
+        }
+
+        let state = match state::deserialize(&read_buffer) {
+            Ok(s) => s,
+            Err(err) => {
+                nih_debug_assert_failure!("Error while deserializing state: {}", err);
+                return false;
+            }
+        };
+
+        wrapper.inner.set_state_object(state)
+    }
+
+    unsafe extern "C" fn ext_tail_get(plugin: *const clap_plugin) -> u32 {
+        check_null_ptr!(0, plugin);
+        let wrapper = Self::from_ptr(plugin);
+
+        match wrapper.inner.last_process_status.load() {
+            ProcessStatus::Tail(samples) => samples,
+            ProcessStatus::KeepAlive => u32::MAX,
+            _ => 0,
+        }
+    }
+}
+
+/// A `Send + Sync` wrapper around the host's `clap_host` pointer, so it can be used from the
+/// [WrapperInner::pending_edits_callback].
+struct HostPtr(*const clap_host);
+
+impl HostPtr {
+    fn get(&self) -> *const clap_host {
+        self.0
+    }
+}
+
+/// SAFETY: CLAP requires the host's callbacks to be thread safe unless stated otherwise, and the
+/// host outlives the plugin instance.
+unsafe impl Send for HostPtr {}
+unsafe impl Sync for HostPtr {}
+
+/// Fill all of the host's output buffers in `process` with silence. This is used when the plugin
+/// can't be processed because it panicked.
+unsafe fn clear_outputs(process: &clap_process) {
+    if process.audio_outputs.is_null() {
+        return;
+    }
+
+    let num_samples = process.frames_count as usize;
+    let audio_outputs =
+        std::slice::from_raw_parts_mut(process.audio_outputs, process.audio_outputs_count as usize);
+    for port in audio_outputs {
+        for channel_idx in 0..port.channel_count as usize {
+            if !port.data32.is_null() {
+                ptr::write_bytes(*port.data32.add(channel_idx) as *mut f32, 0, num_samples);
+            }
+            if !port.data64.is_null() {
+                ptr::write_bytes(*port.data64.add(channel_idx) as *mut f64, 0, num_samples);
+            }
+        }
+        port.constant_mask = u64::MAX;
+    }
+}
+
+/// Convert the host's CLAP transport event to a [Transport]. The transport is optional, so
+/// `transport` may be a null pointer, in which case the transport won't contain any information
+/// from the host.
+unsafe fn transport_from_clap(
+    transport: *const clap_event_transport,
+    sample_rate: f32,
+) -> Transport {
+    let mut result = Transport::new(sample_rate);
+    let transport = match transport.as_ref() {
+        Some(transport) => transport,
+        None => return result,
+    };
+
+    result.playing = transport.flags & CLAP_TRANSPORT_IS_PLAYING != 0;
+    result.recording = transport.flags & CLAP_TRANSPORT_IS_RECORDING != 0;
+    if transport.flags & CLAP_TRANSPORT_HAS_TEMPO != 0 {
+        result.tempo = Some(transport.tempo);
+    }
+    if transport.flags & CLAP_TRANSPORT_HAS_TIME_SIGNATURE != 0 {
+        result.time_sig_numerator = Some(transport.tsig_num as i32);
+        result.time_sig_denominator = Some(transport.tsig_denom as i32);
+    }
+    // CLAP positions are fixed point numbers
+    if transport.flags & CLAP_TRANSPORT_HAS_SECONDS_TIMELINE != 0 {
+        let pos_seconds = transport.song_pos_seconds as f64 / CLAP_SECTIME_FACTOR as f64;
+        result.pos_samples = Some((pos_seconds * sample_rate as f64).round() as i64);
+    }
+    if transport.flags & CLAP_TRANSPORT_HAS_BEATS_TIMELINE != 0 {
+        result.pos_beats = Some(transport.song_pos_beats as f64 / CLAP_BEATTIME_FACTOR as f64);
+        result.bar_start_pos_beats = Some(transport.bar_start as f64 / CLAP_BEATTIME_FACTOR as f64);
+        if transport.flags & CLAP_TRANSPORT_IS_LOOP_ACTIVE != 0 {
+            result.loop_range_beats = Some((
+                transport.loop_start_beats as f64 / CLAP_BEATTIME_FACTOR as f64,
+                transport.loop_end_beats as f64 / CLAP_BEATTIME_FACTOR as f64,
+            ));
+        }
+    }
+
+    result.sanitize()
+}
+
+/// Everything the [Factory] needs to know about a plugin, with the plugin's type erased so a single
+/// factory can export multiple plugins.
+#[doc(hidden)]
+pub struct PluginDescriptor {
+    // These strings are referenced by `clap_descriptor`, so they need to stay alive for as long as
+    // the descriptor does
+    _id: CString,
+    _name: CString,
+    _vendor: CString,
+    _url: CString,
+    _version: CString,
+    _description: CString,
+    _features: Vec<CString>,
+    /// Pointers to the strings in `features`, followed by a null pointer.
+    _feature_ptrs: Vec<*const c_char>,
+
+    clap_descriptor: clap_plugin_descriptor,
+    /// Creates a new [Wrapper] for the plugin and returns a pointer to its `clap_plugin` vtable.
+    create_instance: fn(*const clap_host, *const clap_plugin_descriptor) -> *const clap_plugin,
+}
+
+impl PluginDescriptor {
+    pub fn for_plugin<P: ClapPlugin>() -> Self {
+        let c_string = |string: &str| {
+            CString::new(string).expect("Plugin metadata should not contain null bytes")
+        };
+
+        let id = c_string(P::CLAP_ID);
+        let name = c_string(P::NAME);
+        let vendor = c_string(P::VENDOR);
+        let url = c_string(P::URL);
+        let version = c_string(P::VERSION);
+        let description = c_string(P::CLAP_DESCRIPTION);
+        let features: Vec<CString> = P::CLAP_FEATURES
+            .iter()
+            .map(|feature| c_string(feature))
+            .collect();
+        let feature_ptrs: Vec<*const c_char> = features
+            .iter()
+            .map(|feature| feature.as_ptr())
+            .chain(std::iter::once(ptr::null()))
+            .collect();
+
+        // The strings' heap allocations don't move when they're moved into this struct, so these
+        // pointers remain valid
+        let clap_descriptor = clap_plugin_descriptor {
+            clap_version: CLAP_VERSION,
+            id: id.as_ptr(),
+            name: name.as_ptr(),
+            vendor: vendor.as_ptr(),
+            url: url.as_ptr(),
+            manual_url: url.as_ptr(),
+            support_url: url.as_ptr(),
+            version: version.as_ptr(),
+            description: description.as_ptr(),
+            features: feature_ptrs.as_ptr(),
+        };
+
+        Self {
+            _id: id,
+            _name: name,
+            _vendor: vendor,
+            _url: url,
+            _version: version,
+            _description: description,
+            _features: features,
+            _feature_ptrs: feature_ptrs,
+
+            clap_descriptor,
+            create_instance: |host, descriptor| {
+                let wrapper = Wrapper::<P>::new(host, descriptor);
+                Box::into_raw(wrapper) as *const clap_plugin
+            },
+        }
+    }
+}
+
+/// The plugin factory exported through the `clap_entry` symbol defined by [nih_export_clap!]. The
+/// `clap_plugin_factory` vtable is the first field so the pointer the host passes to the callbacks
+/// can be cast back to this struct.
+#[doc(hidden)]
+#[repr(C)]
+pub struct Factory {
+    clap_plugin_factory: clap_plugin_factory,
+
+    /// The plugins exported by this factory.
+    plugins: Vec<PluginDescriptor>,
+}
+
+// The descriptors only contain pointers to data owned by the factory, and none of this is ever
+// modified after the factory has been created
+unsafe impl Send for Factory {}
+unsafe impl Sync for Factory {}
+
+impl Factory {
+    pub fn new(plugins: Vec<PluginDescriptor>) -> Self {
+        nih_debug_assert!(
+            !plugins.is_empty(),
+            "The factory does not contain any plugins"
+        );
+        for (i, plugin) in plugins.iter().enumerate() {
+            nih_debug_assert!(
+                plugins[i + 1..].iter().all(|other| other._id != plugin._id),
+                "Multiple plugins use the same CLAP ID as '{:?}'",
+                plugin._name
+            );
+        }
+
+        Self {
+            clap_plugin_factory: clap_plugin_factory {
+                get_plugin_count: Some(Self::get_plugin_count),
+                get_plugin_descriptor: Some(Self::get_plugin_descriptor),
+                create_plugin: Some(Self::create_plugin),
+            },
+            plugins,
+        }
+    }
+
+    /// Return a pointer to this factory if `factory_id` refers to the CLAP plugin factory, as
+    /// requested through `clap_plugin_entry::get_factory()`.
+    ///
+    /// # Safety
+    ///
+    /// `factory_id` needs to be either null or a null terminated C-string.
+    pub unsafe fn get(&'static self, factory_id: *const c_char) -> *const c_void {
+        if !factory_id.is_null() && CStr::from_ptr(factory_id) == CLAP_PLUGIN_FACTORY_ID {
+            self as *const Self as *const c_void
+        } else {
+            ptr::null()
+        }
+    }
+
+    unsafe extern "C" fn get_plugin_count(factory: *const clap_plugin_factory) -> u32 {
+        check_null_ptr!(0, factory);
+        let factory = &*(factory as *const Self);
+
+        factory.plugins.len() as u32
+    }
+
+    unsafe extern "C" fn get_plugin_descriptor(
+        factory: *const clap_plugin_factory,
+        index: u32,
+    ) -> *const clap_plugin_descriptor {
+        check_null_ptr!(ptr::null(), factory);
+        let factory = &*(factory as *const Self);
+
+        match factory.plugins.get(index as usize) {
+            Some(plugin) => &plugin.clap_descriptor,
+            None => ptr::null(),
+        }
+    }
+
+    unsafe extern "C" fn create_plugin(
+        factory: *const clap_plugin_factory,
+        host: *const clap_host,
+        plugin_id: *const c_char,
+    ) -> *const clap_plugin {
+        check_null_ptr!(ptr::null(), factory, host, plugin_id);
+        let factory = &*(factory as *const Self);
+
+        let plugin_id = CStr::from_ptr(plugin_id);
+        match factory
+            .plugins
+            .iter()
+            .find(|plugin| plugin._id.as_c_str() == plugin_id)
+        {
+            Some(plugin) => (plugin.create_instance)(host, &plugin.clap_descriptor),
+            None => ptr::null(),
+        }
+    }
+}
+
+/// Export one or more CLAP plugins from this library using the provided plugin types. Multiple
+/// plugins can be bundled in a single library using `nih_export_clap!(PluginA, PluginB)`. The
+/// plugins should all have their own CLAP IDs. This requires the `clap` feature.
+#[macro_export]
+macro_rules! nih_export_clap {
+    ($($plugin_ty:ty),+ $(,)?) => {
+        #[no_mangle]
+        #[allow(non_upper_case_globals)]
+        pub static clap_entry: ::nih_plug::wrapper::clap::clap_plugin_entry = {
+            // The host keeps pointers to the plugins' descriptors, so the factory needs to live
+            // for as long as the library is loaded
+            ::nih_plug::wrapper::clap::lazy_static! {
+                static ref FACTORY: ::nih_plug::wrapper::clap::Factory =
+                    ::nih_plug::wrapper::clap::Factory::new(vec![
+                        $(::nih_plug::wrapper::clap::PluginDescriptor::for_plugin::<$plugin_ty>()),+
+                    ]);
+            }
+
+            unsafe extern "C" fn init(_plugin_path: *const ::std::os::raw::c_char) -> bool {
+                true
+            }
+
+            unsafe extern "C" fn deinit() {}
+
+            unsafe extern "C" fn get_factory(
+                factory_id: *const ::std::os::raw::c_char,
+            ) -> *const ::std::ffi::c_void {
+                FACTORY.get(factory_id)
+            }
+
+            ::nih_plug::wrapper::clap::clap_plugin_entry {
+                clap_version: ::nih_plug::wrapper::clap::CLAP_VERSION,
+                init: Some(init),
+                deinit: Some(deinit),
+                get_factory: Some(get_factory),
+            }
+        };
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use parking_lot::{Mutex, RwLock};
+    use std::collections::HashMap;
+    use std::pin::Pin;
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+    use crate::buffer::Buffer;
+    use crate::context::{GuiContext, ProcessContext};
+    use crate::param::internals::{
+        deserialize_field, serialize_field, ParamPtr, Params, StateFormat,
+    };
+    use crate::param::range::Range;
+    use crate::param::smoothing::{Smoother, SmoothingStyle};
+    use crate::param::{FloatParam, IntParam, Param};
+    use crate::plugin::Plugin;
+    use crate::wrapper::util::hash_param_id;
+
+    /// A host that only supports the `clap_host_params` extension, and that keeps track of how
+    /// often the plugin asked it to flush the parameters. The `clap_host` is the first field so the
+    /// pointers passed to the callbacks can be cast back to this struct.
+    #[repr(C)]
+    struct TestHost {
+        host: clap_host,
+        host_params: clap_host_params,
+        num_flush_requests: AtomicUsize,
+    }
+
+    impl TestHost {
+        fn new() -> Box<Self> {
+            let mut host = Box::new(Self {
+                host: unsafe { mem::zeroed() },
+                host_params: unsafe { mem::zeroed() },
+                num_flush_requests: AtomicUsize::new(0),
+            });
+            host.host.get_extension = Some(Self::get_extension);
+            host.host_params.request_flush = Some(Self::request_flush);
+
+            host
+        }
+
+        unsafe extern "C" fn get_extension(
+            host: *const clap_host,
+            extension_id: *const c_char,
+        ) -> *const c_void {
+            let host = &*(host as *const Self);
+            if CStr::from_ptr(extension_id) == CLAP_EXT_PARAMS {
+                &host.host_params as *const _ as *const c_void
+            } else {
+                ptr::null()
+            }
+        }
+
+        unsafe extern "C" fn request_flush(host: *const clap_host) {
+            let host = &*(host as *const Self);
+            host.num_flush_requests.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// An input event list containing parameter changes.
+    #[repr(C)]
+    struct TestInputEvents {
+        list: clap_input_events,
+        events: Vec<clap_event_param_value>,
+    }
+
+    impl TestInputEvents {
+        fn new(changes: &[(u32, clap_id, f64)]) -> Box<Self> {
+            let mut list: clap_input_events = unsafe { mem::zeroed() };
+            list.size = Some(Self::size);
+            list.get = Some(Self::get);
+
+            Box::new(Self {
+                list,
+                events: changes
+                    .iter()
+                    .map(|&(time, param_id, value)| clap_event_param_value {
+                        header: clap_event_header {
+                            size: mem::size_of::<clap_event_param_value>() as u32,
+                            time,
+                            space_id: CLAP_CORE_EVENT_SPACE_ID,
+                            type_: CLAP_EVENT_PARAM_VALUE,
+                            flags: 0,
+                        },
+                        param_id,
+                        cookie: ptr::null_mut(),
+                        note_id: -1,
+                        port_index: -1,
+                        channel: -1,
+                        key: -1,
+                        value,
+                    })
+                    .collect(),
+            })
+        }
+
+        unsafe extern "C" fn size(list: *const clap_input_events) -> u32 {
+            (*(list as *const Self)).events.len() as u32
+        }
+
+        unsafe extern "C" fn get(
+            list: *const clap_input_events,
+            index: u32,
+        ) -> *const clap_event_header {
+            match (*(list as *const Self)).events.get(index as usize) {
+                Some(event) => &event.header,
+                None => ptr::null(),
+            }
+        }
+    }
+
+    /// An output event list that stores the type, the parameter, and the value of every parameter
+    /// event the plugin sends.
+    #[repr(C)]
+    struct TestOutputEvents {
+        list: clap_output_events,
+        events: Mutex<Vec<(u16, clap_id, Option<f64>)>>,
+    }
+
+    impl TestOutputEvents {
+        fn new() -> Box<Self> {
+            let mut list: clap_output_events = unsafe { mem::zeroed() };
+            list.try_push = Some(Self::try_push);
+
+            Box::new(Self {
+                list,
+                events: Mutex::new(Vec::new()),
+            })
+        }
+
+        unsafe extern "C" fn try_push(
+            list: *const clap_output_events,
+            event: *const clap_event_header,
+        ) -> bool {
+            let events = &(*(list as *const Self)).events;
+            match (*event).type_ {
+                CLAP_EVENT_PARAM_VALUE => {
+                    let event = &*(event as *const clap_event_param_value);
+                    events
+                        .lock()
+                        .push((CLAP_EVENT_PARAM_VALUE, event.param_id, Some(event.value)));
+                }
+                type_ @ (CLAP_EVENT_PARAM_GESTURE_BEGIN | CLAP_EVENT_PARAM_GESTURE_END) => {
+                    let event = &*(event as *const clap_event_param_gesture);
+                    events.lock().push((type_, event.param_id, None));
+                }
+                _ => (),
+            }
+
+            true
+        }
+    }
+
+    /// An in-memory stream that can be used both as a `clap_ostream` and as a `clap_istream`. The
+    /// host's streams only read or write a couple bytes at a time to make sure the wrapper handles
+    /// partial reads and writes.
+    #[repr(C)]
+    struct TestStream {
+        ostream: clap_ostream,
+        istream: clap_istream,
+        data: Mutex<Vec<u8>>,
+        position: AtomicUsize,
+    }
+
+    impl TestStream {
+        const MAX_CHUNK_SIZE: usize = 7;
+
+        fn new() -> Box<Self> {
+            let mut stream = Box::new(Self {
+                ostream: unsafe { mem::zeroed() },
+                istream: unsafe { mem::zeroed() },
+                data: Mutex::new(Vec::new()),
+                position: AtomicUsize::new(0),
+            });
+            let stream_ptr = &*stream as *const Self as *mut c_void;
+            stream.ostream.ctx = stream_ptr;
+            stream.ostream.write = Some(Self::write);
+            stream.istream.ctx = stream_ptr;
+            stream.istream.read = Some(Self::read);
+
+            stream
+        }
+
+        unsafe extern "C" fn write(
+            stream: *const clap_ostream,
+            buffer: *const c_void,
+            size: u64,
+        ) -> i64 {
+            let this = &*((*stream).ctx as *const Self);
+            let size = cmp::min(size as usize, Self::MAX_CHUNK_SIZE);
+            this.data
+                .lock()
+                .extend_from_slice(std::slice::from_raw_parts(buffer as *const u8, size));
+
+            size as i64
+        }
+
+        unsafe extern "C" fn read(
+            stream: *const clap_istream,
+            buffer: *mut c_void,
+            size: u64,
+        ) -> i64 {
+            let this = &*((*stream).ctx as *const Self);
+            let data = this.data.lock();
+            let position = this.position.load(Ordering::SeqCst);
+            let size = cmp::min(
+                cmp::min(size as usize, Self::MAX_CHUNK_SIZE),
+                data.len() - position,
+            );
+            ptr::copy_nonoverlapping(data[position..].as_ptr(), buffer as *mut u8, size);
+            this.position.store(position + size, Ordering::SeqCst);
+
+            size as i64
+        }
+    }
+
+    /// A gain plugin with a stepped parameter and a persistent field. The [Params] trait is
+    /// implemented by hand because the derive macro can't be used from within this crate.
+    struct TestPlugin {
+        params: Pin<Box<TestParams>>,
+    }
+
+    struct TestParams {
+        gain: FloatParam,
+        mode: IntParam,
+        field: RwLock<Vec<u32>>,
+    }
+
+    impl Default for TestPlugin {
+        fn default() -> Self {
+            Self {
+                params: Box::pin(TestParams {
+                    gain: FloatParam {
+                        value: 1.0,
+                        range: Range::Linear { min: 0.0, max: 2.0 },
+                        smoothed: Smoother::new(SmoothingStyle::Linear(100.0)),
+                        name: "Gain",
+                        ..Default::default()
+                    },
+                    mode: IntParam {
+                        value: 0,
+                        range: Range::Linear { min: 0, max: 3 },
+                        name: "Mode",
+                        ..Default::default()
+                    },
+                    field: RwLock::new(Vec::new()),
+                }),
+            }
+        }
+    }
+
+    impl Params for TestParams {
+        fn param_map(self: Pin<&Self>) -> HashMap<&'static str, ParamPtr> {
+            let mut param_map = HashMap::new();
+            param_map.insert("gain", self.gain.as_ptr());
+            param_map.insert("mode", self.mode.as_ptr());
+
+            param_map
+        }
+
+        fn param_ids(self: Pin<&Self>) -> &'static [&'static str] {
+            &["gain", "mode"]
+        }
+
+        fn serialize_fields(&self, format: StateFormat) -> HashMap<String, Vec<u8>> {
+            let mut serialized = HashMap::new();
+            serialized.insert(
+                String::from("field"),
+                serialize_field(&*self.field.read(), format).unwrap(),
+            );
+
+            serialized
+        }
+
+        fn deserialize_fields(&self, serialized: &HashMap<String, Vec<u8>>, format: StateFormat) {
+            if let Some(data) = serialized.get("field") {
+                *self.field.write() = deserialize_field(data, format).unwrap();
+            }
+        }
+    }
+
+    impl Plugin for TestPlugin {
+        const NAME: &'static str = "Test Plugin";
+        const VENDOR: &'static str = "NIH-plug";
+        const URL: &'static str = "https://example.com";
+        const EMAIL: &'static str = "info@example.com";
+
+        const VERSION: &'static str = "0.0.1";
+
+        fn params(&self) -> Pin<&dyn Params> {
+            self.params.as_ref()
+        }
+
+        fn process(
+            &mut self,
+            buffer: &mut Buffer,
+            _context: &mut impl ProcessContext,
+        ) -> ProcessStatus {
+            for samples in buffer.iter_mut() {
+                for sample in samples {
+                    *sample *= self.params.gain.value;
+                }
+            }
+
+            ProcessStatus::Normal
+        }
+    }
+
+    impl ClapPlugin for TestPlugin {
+        const CLAP_ID: &'static str = "org.nih-plug.test-plugin";
+        const CLAP_DESCRIPTION: &'static str = "A plugin for testing the CLAP wrapper";
+        const CLAP_FEATURES: &'static [&'static str] = &["audio_effect"];
+    }
+
+    /// Create an initialized and activated wrapper for [TestPlugin] that's ready to process audio.
+    unsafe fn new_active_wrapper(host: &TestHost) -> Box<Wrapper<TestPlugin>> {
+        let wrapper = Wrapper::<TestPlugin>::new(&host.host, ptr::null());
+        assert!(Wrapper::<TestPlugin>::init(&wrapper.clap_plugin));
+        assert!(Wrapper::<TestPlugin>::activate(
+            &wrapper.clap_plugin,
+            44_100.0,
+            1,
+            64
+        ));
+        assert!(Wrapper::<TestPlugin>::start_processing(
+            &wrapper.clap_plugin
+        ));
+
+        wrapper
+    }
+
+    /// Process `channels` in place, with `in_events` as the input events. Returns the output
+    /// events.
+    unsafe fn process_in_place(
+        wrapper: &Wrapper<TestPlugin>,
+        channels: &mut [Vec<f32>],
+        in_events: &TestInputEvents,
+    ) -> (clap_process_status, Vec<(u16, clap_id, Option<f64>)>) {
+        let mut channel_ptrs: Vec<*mut f32> = channels
+            .iter_mut()
+            .map(|channel| channel.as_mut_ptr())
+            .collect();
+        // The input and the output ports point to the same buffers
+        let num_channels = channel_ptrs.len() as u32;
+        let channel_ptrs = channel_ptrs.as_mut_ptr();
+        let port = || {
+            let mut port: clap_audio_buffer = mem::zeroed();
+            port.data32 = channel_ptrs;
+            port.channel_count = num_channels;
+            port
+        };
+        let audio_inputs = [port()];
+        let mut audio_outputs = [port()];
+        let out_events = TestOutputEvents::new();
+
+        let mut process: clap_process = mem::zeroed();
+        process.frames_count = channels[0].len() as u32;
+        process.audio_inputs = audio_inputs.as_ptr();
+        process.audio_inputs_count = 1;
+        process.audio_outputs = audio_outputs.as_mut_ptr();
+        process.audio_outputs_count = 1;
+        process.in_events = &in_events.list;
+        process.out_events = &out_events.list;
+
+        let status = Wrapper::<TestPlugin>::process(&wrapper.clap_plugin, &process);
+        let out_events = out_events.events.lock().clone();
+
+        (status, out_events)
+    }
+
+    #[test]
+    fn process_applies_parameter_changes() {
+        unsafe {
+            let host = TestHost::new();
+            let wrapper = new_active_wrapper(&host);
+            let gain_hash = hash_param_id("gain");
+
+            let in_events = TestInputEvents::new(&[(0, gain_hash, 0.25)]);
+            let mut channels = vec![vec![1.0f32; 4]; 2];
+            let (status, out_events) = process_in_place(&wrapper, &mut channels, &in_events);
+            assert_eq!(status, CLAP_PROCESS_CONTINUE_IF_NOT_QUIET);
+            assert!(out_events.is_empty());
+            assert_eq!(channels, vec![vec![0.5f32; 4]; 2]);
+        }
+    }
+
+    #[test]
+    fn stepped_parameters_use_their_step_index() {
+        unsafe {
+            let host = TestHost::new();
+            let wrapper = Wrapper::<TestPlugin>::new(&host.host, ptr::null());
+            let mode_hash = hash_param_id("mode");
+
+            // The plugin's own parameters, followed by the bypass parameter
+            assert_eq!(
+                Wrapper::<TestPlugin>::ext_params_count(&wrapper.clap_plugin),
+                3
+            );
+            let mut info: clap_param_info = mem::zeroed();
+            assert!(Wrapper::<TestPlugin>::ext_params_get_info(
+                &wrapper.clap_plugin,
+                1,
+                &mut info
+            ));
+            assert_eq!(info.id, mode_hash);
+            assert_eq!(
+                info.flags,
+                CLAP_PARAM_IS_AUTOMATABLE | CLAP_PARAM_IS_STEPPED
+            );
+            assert_eq!((info.min_value, info.max_value), (0.0, 3.0));
+
+            let in_events = TestInputEvents::new(&[(0, mode_hash, 2.0)]);
+            Wrapper::<TestPlugin>::ext_params_flush(
+                &wrapper.clap_plugin,
+                &in_events.list,
+                ptr::null(),
+            );
+            assert_eq!(wrapper.inner.plugin.read().params.mode.value, 2);
+            let mut value = 0.0;
+            assert!(Wrapper::<TestPlugin>::ext_params_get_value(
+                &wrapper.clap_plugin,
+                mode_hash,
+                &mut value
+            ));
+            assert_eq!(value, 2.0);
+        }
+    }
+
+    #[test]
+    fn editor_edits_request_a_flush() {
+        unsafe {
+            let host = TestHost::new();
+            let wrapper = Wrapper::<TestPlugin>::new(&host.host, ptr::null());
+            assert!(Wrapper::<TestPlugin>::init(&wrapper.clap_plugin));
+            let gain_ptr = wrapper.inner.plugin.read().params.gain.as_ptr();
+            let gain_hash = hash_param_id("gain");
+
+            wrapper.inner.raw_begin_set_parameter(gain_ptr);
+            wrapper.inner.raw_set_parameter_normalized(gain_ptr, 0.75);
+            wrapper.inner.raw_end_set_parameter(gain_ptr);
+            assert_eq!(host.num_flush_requests.load(Ordering::SeqCst), 3);
+
+            // The host then flushes the parameters, and the edits are sent as output events
+            let out_events = TestOutputEvents::new();
+            Wrapper::<TestPlugin>::ext_params_flush(
+                &wrapper.clap_plugin,
+                ptr::null(),
+                &out_events.list,
+            );
+            assert_eq!(
+                *out_events.events.lock(),
+                [
+                    (CLAP_EVENT_PARAM_GESTURE_BEGIN, gain_hash, None),
+                    (CLAP_EVENT_PARAM_VALUE, gain_hash, Some(0.75)),
+                    (CLAP_EVENT_PARAM_GESTURE_END, gain_hash, None),
+                ]
+            );
+            assert!(wrapper.inner.pending_edits.is_empty());
+        }
+    }
+
+    #[test]
+    fn reset_clears_processing_state() {
+        unsafe {
+            let host = TestHost::new();
+            let wrapper = new_active_wrapper(&host);
+            let gain_hash = hash_param_id("gain");
+
+            let in_events = TestInputEvents::new(&[(0, gain_hash, 0.25)]);
+            Wrapper::<TestPlugin>::ext_params_flush(
+                &wrapper.clap_plugin,
+                &in_events.list,
+                ptr::null(),
+            );
+            wrapper
+                .inner
+                .input_events
+                .write()
+                .push_back(NoteEvent::NoteOn {
+                    timing: 0,
+                    note_id: None,
+                    channel: 0,
+                    note: 60,
+                    velocity: 100,
+                });
+            assert!(wrapper
+                .inner
+                .plugin
+                .read()
+                .params
+                .gain
+                .smoothed
+                .is_smoothing());
+
+            Wrapper::<TestPlugin>::reset(&wrapper.clap_plugin);
+            assert!(!wrapper
+                .inner
+                .plugin
+                .read()
+                .params
+                .gain
+                .smoothed
+                .is_smoothing());
+            assert!(wrapper.inner.input_events.read().is_empty());
+        }
+    }
+
+    #[test]
+    fn state_is_saved_and_restored() {
+        unsafe {
+            let host = TestHost::new();
+            let gain_hash = hash_param_id("gain");
+
+            let wrapper = Wrapper::<TestPlugin>::new(&host.host, ptr::null());
+            let in_events = TestInputEvents::new(&[(0, gain_hash, 0.25)]);
+            Wrapper::<TestPlugin>::ext_params_flush(
+                &wrapper.clap_plugin,
+                &in_events.list,
+                ptr::null(),
+            );
+            *wrapper.inner.plugin.read().params.field.write() = vec![1, 2, 3];
+
+            let stream = TestStream::new();
+            assert!(Wrapper::<TestPlugin>::ext_state_save(
+                &wrapper.clap_plugin,
+                &stream.ostream
+            ));
+
+            let restored_wrapper = Wrapper::<TestPlugin>::new(&host.host, ptr::null());
+            assert!(Wrapper::<TestPlugin>::ext_state_load(
+                &restored_wrapper.clap_plugin,
+                &stream.istream
+            ));
+            let plugin = restored_wrapper.inner.plugin.read();
+            assert_eq!(plugin.params.gain.value, 0.5);
+            assert_eq!(*plugin.params.field.read(), [1, 2, 3]);
+        }
+    }
+}
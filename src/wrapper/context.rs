@@ -0,0 +1,113 @@
+// nih-plug: plugins, but rewritten in Rust
+// Copyright (C) 2022 Robbert van der Helm
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use parking_lot::RwLockWriteGuard;
+use std::collections::VecDeque;
+use std::sync::atomic::Ordering;
+
+use super::inner::{ParamChangeSource, WrapperInner};
+use super::util::queue_event;
+use crate::context::{AutomationPoint, EventLoop, ProcessContext, Transport};
+use crate::param::internals::ParamPtr;
+use crate::plugin::{NoteEvent, Plugin};
+
+/// A [ProcessContext] implementation for the wrapper. This is a separate object so it can hold on
+/// to lock guards for event queues. Otherwise reading these events would require constant
+/// unnecessary atomic operations to lock the uncontested RwLocks.
+pub(crate) struct WrapperProcessContext<'a, P: Plugin> {
+    pub inner: &'a WrapperInner<P>,
+    pub input_events_guard: RwLockWriteGuard<'a, VecDeque<NoteEvent>>,
+    pub input_automation_guard: RwLockWriteGuard<'a, VecDeque<AutomationPoint>>,
+    pub output_events_guard: RwLockWriteGuard<'a, VecDeque<NoteEvent>>,
+    pub transport: Transport,
+    /// The number of events from `input_events_guard` the plugin has consumed during this block.
+    pub num_consumed_events: usize,
+}
+
+impl<P: Plugin> ProcessContext for WrapperProcessContext<'_, P> {
+    fn set_latency_samples(&self, samples: u32) {
+        // Only trigger a restart if it's actually needed. If the host hasn't queried the old
+        // latency yet, then it will simply see the new value when it does. The new value is always
+        // kept, and if the host could not be notified then that is retried from the next process
+        // call.
+        let old_latency = self.inner.current_latency.swap(samples, Ordering::SeqCst);
+        if old_latency != samples && self.inner.latency_reported.swap(false, Ordering::SeqCst) {
+            self.inner.notify_latency_changed();
+        }
+    }
+
+    fn transport(&self) -> &Transport {
+        &self.transport
+    }
+
+    fn next_midi_event(&mut self) -> Option<NoteEvent> {
+        let event = self.input_events_guard.pop_front();
+        if event.is_some() {
+            self.num_consumed_events += 1;
+        }
+
+        event
+    }
+
+    fn next_event(&mut self, sample_idx: usize) -> Option<NoteEvent> {
+        match self.input_events_guard.front() {
+            Some(event) if event.timing() as usize <= sample_idx => self.next_midi_event(),
+            _ => None,
+        }
+    }
+
+    fn num_consumed_events(&self) -> usize {
+        self.num_consumed_events
+    }
+
+    fn next_automation_point(&mut self) -> Option<AutomationPoint> {
+        self.input_automation_guard.pop_front()
+    }
+
+    fn send_event(&mut self, event: NoteEvent) {
+        if P::PRODUCES_MIDI {
+            queue_event(&mut self.output_events_guard, event);
+        }
+    }
+
+    fn set_parameter(&self, param: ParamPtr, normalized: f32) {
+        match self.inner.param_ptr_to_hash.get(&param) {
+            Some(hash) => {
+                let sample_rate = self
+                    .inner
+                    .current_buffer_config
+                    .load()
+                    .map(|c| c.sample_rate);
+                unsafe {
+                    self.inner.set_normalized_value_by_hash(
+                        *hash,
+                        normalized,
+                        sample_rate,
+                        ParamChangeSource::Plugin,
+                    )
+                };
+
+                // The host's component handler may only be used from the main thread
+                self.inner.queue_process_edit(*hash);
+            }
+            None => nih_debug_assert_failure!("Unknown parameter: {:?}", param),
+        }
+    }
+
+    fn request_editor_repaint(&self) {
+        self.inner.editor_repaint_version.bump();
+    }
+}
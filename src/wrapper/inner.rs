@@ -0,0 +1,1631 @@
+// nih-plug: plugins, but rewritten in Rust
+// Copyright (C) 2022 Robbert van der Helm
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use crossbeam::atomic::AtomicCell;
+use crossbeam::channel::{self, RecvTimeoutError};
+use crossbeam::queue::ArrayQueue;
+use parking_lot::{Mutex, RwLock};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::mem::MaybeUninit;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU16, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+use vst3_sys::base::{kInvalidArgument, kResultFalse, kResultOk, tresult};
+use vst3_sys::vst::{IComponentHandler, RestartFlags};
+
+use super::context::WrapperProcessContext;
+use super::vst3::param_units::ParamUnits;
+use super::vst3::util::{
+    midi_cc_default_normalized_value, SinglePrecisionBuffers, VstPtr, VST3_MIDI_CCS,
+    VST3_MIDI_CHANNELS, VST3_MIDI_PARAMS_END, VST3_MIDI_PARAMS_START,
+};
+use super::vst3::view::ViewPtr;
+use crate::buffer::Buffer;
+use crate::context::{
+    AutomationPoint, EventLoop, GuiContext, MainThreadExecutor, OsEventLoop, Transport,
+};
+use crate::param::internals::{ParamPtr, StateFormat};
+use crate::param::smoothing::SmoothingScope;
+use crate::param::Param;
+use crate::plugin::{BufferConfig, BusConfig, Editor, NoteEvent, Plugin, ProcessStatus};
+use crate::util::{DryWetMixer, VersionCounter};
+use crate::wrapper::state::{self, ParamValue, State};
+use crate::wrapper::util::{
+    catch_plugin_panic, hash_param_id, sort_note_events, BYPASS_PARAM_HASH, BYPASS_PARAM_ID,
+    EVENT_QUEUE_CAPACITY,
+};
+
+/// The actual wrapper bits, shared between the VST3, CLAP, and standalone wrappers. We need this as
+/// an `Arc<T>` so we can safely use our event loop API. Since we can't combine that with VST3's
+/// interior reference counting this just has to be moved to its own struct. The VST3 specific
+/// fields are simply left unused by the other wrappers.
+pub(crate) struct WrapperInner<P: Plugin> {
+    /// The wrapped plugin instance.
+    pub plugin: RwLock<P>,
+    /// The plugin's editor, if it has one. This object does not do anything on its own, but we need
+    /// to instantiate this in advance so we don't need to lock the entire [Plugin] object when
+    /// creating an editor.
+    pub editor: Option<Arc<dyn Editor>>,
+
+    /// The host's `IComponentHandler` instance, if passed through
+    /// `IEditController::set_component_handler`.
+    pub component_handler: RwLock<Option<VstPtr<dyn IComponentHandler>>>,
+    /// The editor's view while the editor is open, used to ask the host to resize the editor. The
+    /// view sets this when the editor gets opened and clears it again before the editor is closed.
+    pub plug_view: RwLock<Option<ViewPtr<P>>>,
+    /// Parameter edits made from the editor before the host has set the component handler. Some
+    /// hosts only set the handler after the editor has already been opened. These edits are sent
+    /// to the host once the component handler gets set so they are not lost. The CLAP and
+    /// standalone wrappers drain this queue from the audio thread, so this needs to be lock-free.
+    pub pending_edits: ArrayQueue<PendingEdit>,
+    /// Called after an edit has been added to `pending_edits`. The CLAP wrapper uses this to ask
+    /// the host to flush the parameters so the edits get sent even when the plugin is not
+    /// processing audio.
+    pub pending_edits_callback: RwLock<Option<Box<dyn Fn() + Send + Sync>>>,
+    /// Restart flags from [Task::TriggerRestart] that could not be sent to the host yet because it
+    /// has not set the component handler. The CLAP wrapper never sets a component handler, and it
+    /// instead translates these flags to the equivalent CLAP host callbacks.
+    pub pending_restart_flags: AtomicI32,
+
+    /// A realtime-safe task queue so the plugin can schedule tasks that need to be run later on the
+    /// GUI thread.
+    ///
+    /// This RwLock is only needed because it has to be initialized late. There is no reason to
+    /// mutably borrow the event loop, so reads will never be contested.
+    ///
+    /// TODO: Is there a better type for Send+Sync late initializaiton?
+    pub event_loop: RwLock<MaybeUninit<OsEventLoop<Task, Self>>>,
+
+    /// Whether the plugin is currently processing audio. In other words, the last state
+    /// `IAudioProcessor::setActive()` has been called with.
+    pub is_processing: AtomicBool,
+    /// The current bus configuration, modified through `IAudioProcessor::setBusArrangements()`.
+    pub current_bus_config: RwLock<BusConfig>,
+    /// The current buffer configuration, containing the sample rate and the maximum block size.
+    /// Will be set in `IAudioProcessor::setupProcessing()`.
+    pub current_buffer_config: AtomicCell<Option<BufferConfig>>,
+    /// The bus configuration the plugin was last successfully initialized with. Used together with
+    /// [Self::current_buffer_config] to detect when only the sample rate changes.
+    pub initialized_bus_config: RwLock<Option<BusConfig>>,
+    /// Whether the plugin is currently bypassed. This is not yet integrated with the `Plugin`
+    /// trait, and the wrapper handles the bypass itself in [Self::process_bypassable()].
+    pub bypass_state: AtomicBool,
+    /// How far the output has been crossfaded from the plugin's output to the unprocessed input.
+    /// This is 0.0 when the plugin is not bypassed, and 1.0 when it's fully bypassed. Only used on
+    /// the audio thread.
+    pub bypass_mix: AtomicCell<f32>,
+    /// The last process status returned by the plugin. This is used for tail handling.
+    pub last_process_status: AtomicCell<ProcessStatus>,
+    /// Set when the plugin panicked while processing audio or while being initialized. The plugin
+    /// is then likely in an inconsistent state, so it won't be initialized or processed anymore to
+    /// prevent it from crashing over and over again. See [Self::catch_panic()].
+    pub panicked: AtomicBool,
+    /// Whether [Plugin::self_test()] succeeded. The self-test only runs once when the plugin gets
+    /// loaded, and if it failed then the plugin refuses to initialize.
+    pub self_test_passed: bool,
+    /// The current latency in samples, as set by the plugin through the [ProcessContext].
+    pub current_latency: AtomicU32,
+    /// Whether the host has queried the plugin's latency since it was last changed. Until that
+    /// happens, changing the latency does not require a restart since the host will pick up the new
+    /// value when it queries the latency anyways. This lets a plugin set its initial latency during
+    /// [Plugin::initialize()] without causing a restart.
+    pub latency_reported: AtomicBool,
+    /// Set when the task notifying the host about a latency change could not be posted because the
+    /// task queue was full. The notification is then retried at the start of the next process
+    /// call, see [Self::notify_latency_changed()].
+    pub latency_notification_pending: AtomicBool,
+    /// The number of samples left in the fade-in that's applied to the plugin's output after it
+    /// has been activated, see [Plugin::ACTIVATION_FADE_IN_SAMPLES].
+    pub activation_fade_in_samples_remaining: AtomicU32,
+    /// Contains slices for the plugin's outputs. You can't directly create a nested slice form
+    /// apointer to pointers, so this needs to be preallocated in the setup call and kept around
+    /// between process calls. This buffer owns the vector, because otherwise it would need to store
+    /// a mutable reference to the data contained in this mutex.
+    pub output_buffer: RwLock<Buffer<'static>>,
+    /// Scratch buffers for the plugin's inputs if `P::SEPARATE_INPUT_BUFFERS` is set. When the
+    /// host processes audio in place, the inputs are copied here before the plugin gets to
+    /// overwrite them. These are allocated in the setup call to fit the maximum buffer size.
+    pub input_scratch_buffers: RwLock<Vec<Vec<f32>>>,
+    /// Scratch buffers for every channel of the plugin's auxiliary input busses. These are used
+    /// the same way as `input_scratch_buffers`, and they're also used to pass silence to the
+    /// plugin for busses the host didn't connect.
+    pub aux_input_scratch_buffers: RwLock<Vec<Vec<Vec<f32>>>>,
+    /// Copies of the unprocessed audio for every channel in `output_buffer`, delayed by the
+    /// plugin's latency. These are used to crossfade between the plugin's output and the
+    /// unprocessed input when the bypass changes, and they are output as is while the plugin is
+    /// bypassed. These are allocated in the setup call to fit the maximum buffer size.
+    pub bypass_dry_buffers: RwLock<Vec<Vec<f32>>>,
+    /// Delays the unprocessed audio by the plugin's latency so it lines up with the plugin's
+    /// output. This is sized in the setup call for the latency reported during the plugin's
+    /// initialization. Hosts reinitialize the plugin when its latency changes.
+    pub bypass_dry_delay: RwLock<DryWetMixer>,
+    /// Single precision copies of the host's buffers. These are only allocated and used when the
+    /// host processes audio at double precision, see [Plugin::PREFERRED_SAMPLE_PRECISION].
+    pub single_precision_buffers: RwLock<SinglePrecisionBuffers>,
+    /// The incoming events for the plugin, if `P::ACCEPTS_MIDI` is set.
+    ///
+    /// TODO: Maybe load these lazily at some point instead of needing to spool them all to this
+    ///       queue first
+    pub input_events: RwLock<VecDeque<NoteEvent>>,
+    /// The automation points the host sent for the current block, see
+    /// [crate::context::ProcessContext::next_automation_point()].
+    pub input_automation: RwLock<VecDeque<AutomationPoint>>,
+    /// The events sent by the plugin through [crate::context::ProcessContext::send_event()], if
+    /// `P::PRODUCES_MIDI` is set. These are passed to the host by
+    /// [Self::drain_output_events()].
+    pub output_events: RwLock<VecDeque<NoteEvent>>,
+    /// The last bank selected through MIDI CC 0 and 32 for each of the 16 MIDI channels. These
+    /// controllers are sent separately, so both halves of the bank number need to be remembered to
+    /// be able to send a [NoteEvent::MidiBankSelect] with the full bank number.
+    pub midi_banks: [AtomicU16; 16],
+    /// Bumped whenever a parameter's value changes or when the plugin asks for the editor to be
+    /// repainted, see [GuiContext::editor_repaint_requested()].
+    pub editor_repaint_version: VersionCounter,
+
+    /// The keys from `param_map` in a stable order.
+    pub param_hashes: Vec<u32>,
+    /// A mapping from parameter ID hashes (obtained from the string parameter IDs) to pointers to
+    /// parameters belonging to the plugin. As long as `plugin` does not get recreated, these
+    /// addresses will remain stable, as they are obtained from a pinned object.
+    pub param_by_hash: HashMap<u32, ParamPtr>,
+    /// The default normalized parameter value for every parameter in `param_ids`. We need to store
+    /// this in case the host requeries the parmaeter later.
+    pub param_defaults_normalized: Vec<f32>,
+    /// The display strings for every value of discrete parameters that should be shown as a list,
+    /// indexed by the parameter's step. See [MAX_LIST_PARAM_VALUES].
+    pub param_value_strings: HashMap<u32, Vec<String>>,
+    /// Mappings from string parameter indentifiers to parameter hashes. Useful for debug logging
+    /// and when storing and restorign plugin state.
+    pub param_id_to_hash: HashMap<&'static str, u32>,
+    /// The inverse mapping from [Self::param_by_hash]. This is needed to be able to have an
+    /// ergonomic parameter setting API that uses references to the parameters instead of having to
+    /// add a setter function to the parameter (or even worse, have it be completely untyped).
+    pub param_ptr_to_hash: HashMap<ParamPtr, u32>,
+    /// Whether the editor is currently in the middle of a gesture for a parameter, indexed by the
+    /// parameter's hash. While processing audio, the editor's changes reach the plugin through the
+    /// host, so this is used to tell them apart from the host's own changes.
+    pub param_edit_gestures: HashMap<u32, AtomicBool>,
+    /// Whether the plugin has changed a parameter from its process function since the host was
+    /// last informed about it, indexed by the parameter's hash. See [Task::PerformEdits].
+    pub process_edited_params: HashMap<u32, AtomicBool>,
+    /// Set while a [Task::PerformEdits] task has been posted but not yet executed. A plugin that
+    /// sets a parameter on every sample or on every block then still only posts a single task.
+    pub process_edits_task_posted: AtomicBool,
+    /// The VST3 units for the plugin's parameter groups, and the unit each parameter belongs to.
+    /// See [crate::param::internals::Params::param_groups()].
+    pub param_units: ParamUnits,
+    /// The maximum number of edits per second the editor sends to the host for a single parameter
+    /// during a gesture, see [GuiContext::set_max_parameter_edit_rate()]. `None` means that every
+    /// edit is sent.
+    pub max_edit_rate: AtomicCell<Option<f32>>,
+    /// When the last edit was sent to the host for parameters that are currently being edited from
+    /// the editor while `max_edit_rate` is set, along with the last edit that has been held back,
+    /// if any. Indexed by the parameter's hash.
+    pub throttled_edits: Mutex<HashMap<u32, (Instant, Option<f32>)>>,
+
+    /// The file this instance periodically writes its state to if [Plugin::AUTOSAVE_INTERVAL] is
+    /// set. This file is removed again when the plugin instance gets dropped, so any remaining
+    /// autosave files belong to sessions that did not shut down cleanly.
+    pub autosave_path: Option<PathBuf>,
+    /// Used to hand the state snapshots taken on the main thread to the autosave thread, which then
+    /// writes them to `autosave_path`. This is dropped before the autosave file is removed to stop
+    /// the autosave thread.
+    autosave_sender: Option<channel::Sender<State>>,
+    /// The autosave thread, if autosaving is enabled. This is joined when the wrapper gets dropped.
+    autosave_thread: Mutex<Option<JoinHandle<()>>>,
+    /// The hashes of all read-only meter parameters in `param_hashes`. The values of these
+    /// parameters are sent to the host after every process call.
+    pub meter_param_hashes: Vec<u32>,
+    /// The current values of the hidden MIDI controller parameters the host maps MIDI CCs to
+    /// through `IMidiMapping`, indexed by `param_hash - VST3_MIDI_PARAMS_START`. This is empty if
+    /// the plugin doesn't accept MIDI.
+    pub midi_cc_values: Vec<AtomicCell<f32>>,
+}
+
+/// The length of the crossfade between the plugin's output and the unprocessed input when the
+/// plugin gets bypassed or unbypassed, in milliseconds.
+pub const BYPASS_RAMP_MS: f32 = 10.0;
+
+/// Integer parameters with a custom `value_to_string` function and at most this many values are
+/// reported to the host as a list of values, so hosts can display them as a dropdown menu.
+pub const MAX_LIST_PARAM_VALUES: i32 = 128;
+
+/// The maximum number of edits that can be queued up in [WrapperInner::pending_edits]. Any edits
+/// beyond this are dropped.
+pub const PENDING_EDITS_CAPACITY: usize = 4096;
+
+/// The number of slots in [WrapperInner::pending_edits] that are kept free for the starts and ends
+/// of gestures. Value changes are dropped before these, since a gesture that never ends would
+/// leave the host stuck recording automation for that parameter.
+pub const PENDING_GESTURE_EDITS_RESERVED: usize = 256;
+
+/// Where a parameter change came from. Parameters can choose to only smooth changes from one of
+/// these sources, see [crate::param::smoothing::SmoothingScope].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamChangeSource {
+    /// The change was made by the host, for instance through automation.
+    Host,
+    /// The change was made by the plugin, either from its editor or from the process function.
+    Plugin,
+}
+
+/// A parameter edit from the editor that still needs to be sent to the host's component handler.
+/// These contain the parameter's hash.
+#[derive(Debug, Clone, Copy)]
+pub enum PendingEdit {
+    Begin(u32),
+    Perform(u32, f32),
+    End(u32),
+}
+
+/// Tasks that can be sent from the plugin to be executed on the main thread in a non-blocking
+/// realtime safe way (either a random thread or `IRunLoop` on Linux, the OS' message loop on
+/// Windows and macOS).
+#[derive(Debug, Clone)]
+pub enum Task {
+    /// Trigger a restart with the given restart flags. This is a bit set of the flags from
+    /// [vst3_sys::vst::RestartFlags].
+    TriggerRestart(i32),
+    /// Inform the host about the parameters the plugin has changed from within the process
+    /// function, see [WrapperInner::process_edited_params]. Each parameter is only sent once with
+    /// its current value, no matter how often it was changed since the last time.
+    PerformEdits,
+    /// Take a snapshot of the plugin's state and send it to the autosave thread. Snapshots are only
+    /// taken on the main thread so they can't race with the host loading state or with the audio
+    /// thread.
+    Autosave,
+}
+
+impl<P: Plugin> WrapperInner<P> {
+    // XXX: The unsafe blocks in this function are unnecessary. but rust-analyzer gets a bit
+    //      confused by all of these vtables
+    #[allow(unused_unsafe)]
+    pub fn new() -> Arc<Self> {
+        let plugin = RwLock::new(P::default());
+        let editor = plugin.read().editor().map(Arc::from);
+
+        // Refuse to initialize the plugin later if it doesn't work as expected. This is also logged
+        // in release builds so it can be caught in the field.
+        let self_test_result =
+            catch_plugin_panic("running its self-test", || plugin.read().self_test());
+        let self_test_passed = match self_test_result {
+            Some(Ok(())) => true,
+            Some(Err(err)) => {
+                nih_error!(
+                    "The plugin's self-test failed, it won't be initialized: {}",
+                    err
+                );
+                false
+            }
+            // The panic has already been logged
+            None => false,
+        };
+
+        let autosave_path = P::AUTOSAVE_INTERVAL.and_then(|_| state::new_autosave_path(P::NAME));
+        // Only the most recent snapshot is useful, so there's no need to queue up any more of them
+        let (autosave_sender, autosave_receiver) = match autosave_path {
+            Some(_) => {
+                let (sender, receiver) = channel::bounded(1);
+                (Some(sender), Some(receiver))
+            }
+            None => (None, None),
+        };
+
+        // Hosts fall back to this configuration when the plugin rejects all of their proposed
+        // configurations, so the plugin should always support it
+        let default_bus_config = BusConfig {
+            num_input_channels: P::DEFAULT_NUM_INPUTS,
+            num_output_channels: P::DEFAULT_NUM_OUTPUTS,
+            aux_input_channels: P::AUX_INPUT_CHANNELS.to_vec(),
+        };
+        nih_debug_assert!(
+            plugin.read().accepts_bus_config(&default_bus_config),
+            "The plugin does not accept its own default bus config {:?}, so hosts may not be able \
+             to load it",
+            default_bus_config
+        );
+
+        let mut wrapper = Self {
+            plugin,
+            editor,
+
+            component_handler: RwLock::new(None),
+            plug_view: RwLock::new(None),
+            pending_edits: ArrayQueue::new(PENDING_EDITS_CAPACITY),
+            pending_edits_callback: RwLock::new(None),
+            pending_restart_flags: AtomicI32::new(0),
+
+            event_loop: RwLock::new(MaybeUninit::uninit()),
+
+            is_processing: AtomicBool::new(false),
+            // Some hosts, like the current version of Bitwig and Ardour at the time of writing,
+            // will try using the plugin's default not yet initialized bus arrangement. Because of
+            // that, we'll always initialize this configuration even before the host requests a
+            // channel layout.
+            current_bus_config: RwLock::new(default_bus_config),
+            current_buffer_config: AtomicCell::new(None),
+            initialized_bus_config: RwLock::new(None),
+            bypass_state: AtomicBool::new(false),
+            bypass_mix: AtomicCell::new(0.0),
+            last_process_status: AtomicCell::new(ProcessStatus::Normal),
+            panicked: AtomicBool::new(false),
+            self_test_passed,
+            current_latency: AtomicU32::new(0),
+            latency_reported: AtomicBool::new(false),
+            latency_notification_pending: AtomicBool::new(false),
+            activation_fade_in_samples_remaining: AtomicU32::new(0),
+            output_buffer: RwLock::new(Buffer::default()),
+            input_scratch_buffers: RwLock::new(Vec::new()),
+            aux_input_scratch_buffers: RwLock::new(Vec::new()),
+            bypass_dry_buffers: RwLock::new(Vec::new()),
+            bypass_dry_delay: RwLock::new(DryWetMixer::new(0, 0, 0)),
+            single_precision_buffers: RwLock::new(SinglePrecisionBuffers::default()),
+            input_events: RwLock::new(VecDeque::with_capacity(EVENT_QUEUE_CAPACITY)),
+            input_automation: RwLock::new(VecDeque::with_capacity(EVENT_QUEUE_CAPACITY)),
+            output_events: RwLock::new(VecDeque::with_capacity(EVENT_QUEUE_CAPACITY)),
+            midi_banks: Default::default(),
+            editor_repaint_version: VersionCounter::new(),
+
+            param_hashes: Vec::new(),
+            param_by_hash: HashMap::new(),
+            param_defaults_normalized: Vec::new(),
+            param_value_strings: HashMap::new(),
+            param_id_to_hash: HashMap::new(),
+            param_ptr_to_hash: HashMap::new(),
+            param_edit_gestures: HashMap::new(),
+            process_edited_params: HashMap::new(),
+            process_edits_task_posted: AtomicBool::new(false),
+            param_units: ParamUnits::default(),
+            max_edit_rate: AtomicCell::new(None),
+            throttled_edits: Mutex::new(HashMap::new()),
+            meter_param_hashes: Vec::new(),
+            midi_cc_values: if P::ACCEPTS_MIDI {
+                (0..VST3_MIDI_CHANNELS * VST3_MIDI_CCS)
+                    .map(|idx| AtomicCell::new(midi_cc_default_normalized_value(idx)))
+                    .collect()
+            } else {
+                Vec::new()
+            },
+
+            autosave_path,
+            autosave_sender,
+            autosave_thread: Mutex::new(None),
+        };
+
+        // This is a mapping from the parameter IDs specified by the plugin to pointers to thsoe
+        // parameters. Since the object returned by `params()` is pinned, these pointers are safe to
+        // dereference as long as `wrapper.plugin` is alive
+        // XXX: This unsafe block is unnecessary. rust-analyzer gets a bit confused and this this
+        //      `read()` function is from `IBStream` which it definitely is not.
+        let param_map = unsafe { wrapper.plugin.read() }.params().param_map();
+        let param_ids = unsafe { wrapper.plugin.read() }.params().param_ids();
+        let param_groups = unsafe { wrapper.plugin.read() }.params().param_groups();
+        nih_debug_assert!(
+            !param_map.contains_key(BYPASS_PARAM_ID),
+            "The wrapper alread yadds its own bypass parameter"
+        );
+
+        // Only calculate these hashes once, and in the stable order defined by the plugin. The
+        // parameter indices exposed to the host are the indices into `param_hashes`, so they
+        // never depend on the iteration order of any of the hash maps below.
+        let param_id_hashes_ptrs: Vec<_> = param_ids
+            .iter()
+            .filter_map(|id| {
+                let param_ptr = param_map.get(id)?;
+                Some((id, hash_param_id(id), param_ptr))
+            })
+            .collect();
+        if cfg!(debug_assertions) {
+            let mut seen_hashes = HashMap::with_capacity(param_id_hashes_ptrs.len() + 1);
+            seen_hashes.insert(*BYPASS_PARAM_HASH, BYPASS_PARAM_ID);
+            for &(id, hash, _) in &param_id_hashes_ptrs {
+                if let Some(other_id) = seen_hashes.insert(hash, *id) {
+                    nih_debug_assert_failure!(
+                        "The parameter IDs \"{}\" and \"{}\" have the same hash, one of them should be renamed",
+                        other_id,
+                        id
+                    );
+                }
+
+                // Host changes to these parameters would otherwise be mistaken for MIDI CCs
+                if P::ACCEPTS_MIDI {
+                    nih_debug_assert!(
+                        !(VST3_MIDI_PARAMS_START..VST3_MIDI_PARAMS_END).contains(&hash),
+                        "The parameter ID \"{}\" has a hash in the range reserved for MIDI CCs, it should be renamed",
+                        id
+                    );
+                }
+            }
+        }
+        wrapper.param_hashes = param_id_hashes_ptrs
+            .iter()
+            .map(|&(_, hash, _)| hash)
+            .collect();
+        wrapper.param_by_hash = param_id_hashes_ptrs
+            .iter()
+            .map(|&(_, hash, ptr)| (hash, *ptr))
+            .collect();
+        wrapper.param_defaults_normalized = param_id_hashes_ptrs
+            .iter()
+            .map(|&(_, _, ptr)| unsafe {
+                ptr.capture_default();
+                ptr.default_normalized_value()
+            })
+            .collect();
+        wrapper.param_value_strings = param_id_hashes_ptrs
+            .iter()
+            .filter_map(|&(_, hash, ptr)| unsafe {
+                let value_strings = list_param_value_strings(ptr)?;
+                Some((hash, value_strings))
+            })
+            .collect();
+        wrapper.param_id_to_hash = param_id_hashes_ptrs
+            .iter()
+            .map(|&(id, hash, _)| (*id, hash))
+            .collect();
+        wrapper.meter_param_hashes = param_id_hashes_ptrs
+            .iter()
+            .filter(|&&(_, _, ptr)| ptr.is_read_only())
+            .map(|&(_, hash, _)| hash)
+            .collect();
+        wrapper.param_edit_gestures = param_id_hashes_ptrs
+            .iter()
+            .map(|&(_, hash, _)| (hash, AtomicBool::new(false)))
+            .collect();
+        wrapper.process_edited_params = param_id_hashes_ptrs
+            .iter()
+            .map(|&(_, hash, _)| (hash, AtomicBool::new(false)))
+            .collect();
+        wrapper.param_units = ParamUnits::from_param_groups(
+            param_id_hashes_ptrs.iter().filter_map(|&(id, hash, _)| {
+                let group_path = param_groups.get(id)?;
+                Some((hash, *group_path))
+            }),
+        );
+        wrapper.param_ptr_to_hash = param_id_hashes_ptrs
+            .into_iter()
+            .map(|(_, hash, ptr)| (*ptr, hash))
+            .collect();
+
+        // FIXME: Right now this is safe, but if we are going to have a singleton main thread queue
+        //        serving multiple plugin instances, Arc can't be used because its reference count
+        //        is separate from the internal COM-style reference count.
+        let wrapper: Arc<WrapperInner<P>> = wrapper.into();
+        // XXX: This unsafe block is unnecessary. rust-analyzer gets a bit confused and this this
+        //      `write()` function is from `IBStream` which it definitely is not.
+        *unsafe { wrapper.event_loop.write() } =
+            MaybeUninit::new(OsEventLoop::new_and_spawn(Arc::downgrade(&wrapper)));
+
+        // The state is written to the autosave file from a separate thread so it never interferes
+        // with the audio thread or the GUI. The snapshots themselves are taken on the main thread,
+        // see [Task::Autosave]. This thread only holds a weak reference to the wrapper, and it
+        // stops once the wrapper has been dropped.
+        if let (Some(interval), Some(autosave_path), Some(autosave_receiver)) = (
+            P::AUTOSAVE_INTERVAL,
+            wrapper.autosave_path.clone(),
+            autosave_receiver,
+        ) {
+            let weak_wrapper = Arc::downgrade(&wrapper);
+            let spawn_result = thread::Builder::new()
+                .name(format!("{} autosave", P::NAME))
+                .spawn(move || loop {
+                    match autosave_receiver.recv_timeout(interval) {
+                        Ok(state) => {
+                            if let Err(err) = state::write_autosave(&autosave_path, &state) {
+                                nih_log!(
+                                    "Could not write the autosave file to '{}': {}",
+                                    autosave_path.display(),
+                                    err
+                                );
+                            }
+                        }
+                        // The wrapper is only upgraded for as long as it takes to post the task
+                        Err(RecvTimeoutError::Timeout) => match weak_wrapper.upgrade() {
+                            Some(wrapper) => {
+                                let _ = unsafe { wrapper.event_loop.read().assume_init_ref() }
+                                    .do_maybe_async(Task::Autosave);
+                            }
+                            None => break,
+                        },
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                });
+            match spawn_result {
+                Ok(autosave_thread) => *wrapper.autosave_thread.lock() = Some(autosave_thread),
+                Err(err) => {
+                    nih_debug_assert_failure!("Could not spawn the autosave thread: {}", err)
+                }
+            }
+        }
+
+        wrapper
+    }
+
+    /// Create a process context for a process call. This also retries a latency change notification
+    /// that could not be posted during an earlier call.
+    pub fn make_process_context(&self, transport: Transport) -> WrapperProcessContext<'_, P> {
+        if self
+            .latency_notification_pending
+            .swap(false, Ordering::SeqCst)
+        {
+            self.notify_latency_changed();
+        }
+
+        WrapperProcessContext {
+            inner: self,
+            input_events_guard: self.input_events.write(),
+            input_automation_guard: self.input_automation.write(),
+            output_events_guard: self.output_events.write(),
+            transport,
+            num_consumed_events: 0,
+        }
+    }
+
+    /// Inform the host about a parameter change made by the plugin from within the process
+    /// function. This should be called after the parameter has been set. Changes are coalesced so
+    /// only a single [Task::PerformEdits] is ever posted at a time. If the task queue is full, then
+    /// this is retried on the next change.
+    pub fn queue_process_edit(&self, hash: u32) {
+        if let Some(edited) = self.process_edited_params.get(&hash) {
+            edited.store(true, Ordering::SeqCst);
+        }
+
+        if !self.process_edits_task_posted.swap(true, Ordering::SeqCst) {
+            let task_posted = unsafe { self.event_loop.read().assume_init_ref() }
+                .do_maybe_async(Task::PerformEdits);
+            nih_debug_assert!(
+                task_posted,
+                "The task queue is full, retrying the parameter change notification later..."
+            );
+            if !task_posted {
+                self.process_edits_task_posted
+                    .store(false, Ordering::SeqCst);
+            }
+        }
+    }
+
+    /// Ask the host to requery the plugin's latency. If the task queue is full, then this is retried
+    /// from the next process call instead so the host doesn't keep using the old latency.
+    pub fn notify_latency_changed(&self) {
+        let task_posted = unsafe { self.event_loop.read().assume_init_ref() }.do_maybe_async(
+            Task::TriggerRestart(vst3_sys::vst::RestartFlags::kLatencyChanged as i32),
+        );
+        nih_debug_assert!(
+            task_posted,
+            "The task queue is full, retrying the latency change notification later..."
+        );
+        if !task_posted {
+            self.latency_notification_pending
+                .store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// Pass the events the plugin sent during a block of `num_samples` samples to `send_event` in
+    /// order of their timing. Events timed past the end of the block are kept around for the next
+    /// block, with `num_samples` subtracted from their timing.
+    pub fn drain_output_events(&self, num_samples: u32, mut send_event: impl FnMut(&NoteEvent)) {
+        let mut output_events = self.output_events.write();
+        sort_note_events(&mut output_events);
+
+        while let Some(event) = output_events.front() {
+            if event.timing() >= num_samples {
+                break;
+            }
+
+            send_event(event);
+            output_events.pop_front();
+        }
+
+        for event in output_events.iter_mut() {
+            event.set_timing(event.timing() - num_samples);
+        }
+    }
+
+    /// Run `f`, catching any panics from the plugin. If the plugin panicked, then the panic gets
+    /// logged, [Self::panicked] is set, and `None` is returned.
+    pub fn catch_panic<T, F: FnOnce() -> T>(&self, description: &str, f: F) -> Option<T> {
+        let result = catch_plugin_panic(description, f);
+        if result.is_none() {
+            self.panicked.store(true, Ordering::SeqCst);
+        }
+
+        result
+    }
+
+    /// Initialize the plugin for the current bus configuration and `buffer_config`, and allocate
+    /// everything needed to process audio. This is called by the wrappers when the host sets up
+    /// audio processing. If only the sample rate changed since the last time, then the plugin gets
+    /// a chance to handle that through [Plugin::sample_rate_changed()] instead. Returns `false` if
+    /// the plugin could not be initialized, if its self-test failed, or if it panicked now or at
+    /// some point in the past.
+    pub unsafe fn initialize_plugin(&self, buffer_config: BufferConfig) -> bool {
+        // The self-test has already been run when the plugin was loaded
+        if self.panicked.load(Ordering::SeqCst) || !self.self_test_passed {
+            return false;
+        }
+
+        self.catch_panic("being initialized", || {
+            self.initialize_plugin_unguarded(buffer_config)
+        })
+        .unwrap_or(false)
+    }
+
+    /// The actual implementation of [Self::initialize_plugin()], without the panic handling.
+    unsafe fn initialize_plugin_unguarded(&self, buffer_config: BufferConfig) -> bool {
+        let bus_config = self.current_bus_config.read().clone();
+
+        // If only the sample rate changed, then the plugin may be able to adapt its existing state
+        // instead of being reinitialized from scratch
+        let old_buffer_config = self.current_buffer_config.load();
+        let only_sample_rate_changed = match old_buffer_config {
+            Some(old_buffer_config) => {
+                old_buffer_config.sample_rate != buffer_config.sample_rate
+                    && old_buffer_config.max_buffer_size == buffer_config.max_buffer_size
+                    && self.initialized_bus_config.read().as_ref() == Some(&bus_config)
+            }
+            None => false,
+        };
+
+        // Befure initializing the plugin, make sure all smoothers are set the the default values.
+        // When only the sample rate changes, smoothing that's still in progress is adapted to the
+        // new sample rate instead so the parameter values don't jump.
+        for param in self.param_by_hash.values() {
+            if only_sample_rate_changed {
+                param.set_smoother_sample_rate(buffer_config.sample_rate);
+            } else {
+                param.update_smoother(buffer_config.sample_rate, true);
+            }
+        }
+
+        let sample_rate_change_handled = match old_buffer_config {
+            Some(old_buffer_config) if only_sample_rate_changed => {
+                self.plugin.write().sample_rate_changed(
+                    old_buffer_config.sample_rate,
+                    buffer_config.sample_rate,
+                    &mut self.make_process_context(Transport::new(buffer_config.sample_rate)),
+                )
+            }
+            _ => false,
+        };
+
+        // If the plugin did not handle the sample rate change then it gets fully reinitialized, so
+        // the smoothers that were adapted above need to be reset like they would be otherwise
+        if only_sample_rate_changed && !sample_rate_change_handled {
+            for param in self.param_by_hash.values() {
+                param.update_smoother(buffer_config.sample_rate, true);
+            }
+        }
+
+        if sample_rate_change_handled
+            || self.plugin.write().initialize(
+                &bus_config,
+                &buffer_config,
+                &mut self.make_process_context(Transport::new(buffer_config.sample_rate)),
+            )
+        {
+            // Preallocate enough room in the output slices vector so we can convert a `*mut *mut
+            // f32` to a `&mut [&mut f32]` in the process call. Analysis-only plugins only get
+            // read-only input slices instead.
+            let num_buffer_channels = if P::ANALYSIS_ONLY {
+                0
+            } else {
+                bus_config.num_output_channels
+            };
+            let mut output_buffer = self.output_buffer.write();
+            output_buffer
+                .as_raw_vec()
+                .resize_with(num_buffer_channels as usize, || &mut []);
+            if P::SEPARATE_INPUT_BUFFERS || P::ANALYSIS_ONLY {
+                output_buffer
+                    .as_raw_input_vec()
+                    .resize_with(bus_config.num_input_channels as usize, || &[]);
+            }
+
+            // Plugins that want to read their unmodified inputs get scratch buffers to copy those
+            // inputs to when the host processes audio in place
+            if P::SEPARATE_INPUT_BUFFERS && !P::ANALYSIS_ONLY {
+                let num_input_channels = bus_config.num_input_channels as usize;
+                let mut input_scratch_buffers = self.input_scratch_buffers.write();
+                input_scratch_buffers.resize_with(num_input_channels, Vec::new);
+                for scratch_buffer in input_scratch_buffers.iter_mut() {
+                    scratch_buffer.resize(buffer_config.max_buffer_size as usize, 0.0);
+                }
+            }
+
+            // The auxiliary inputs always get scratch buffers, since the host may not have
+            // connected them or it may reuse their buffers for the outputs
+            let aux_input_slices = output_buffer.as_raw_aux_input_vec();
+            let mut aux_input_scratch_buffers = self.aux_input_scratch_buffers.write();
+            aux_input_slices.resize_with(bus_config.aux_input_channels.len(), Vec::new);
+            aux_input_scratch_buffers.resize_with(bus_config.aux_input_channels.len(), Vec::new);
+            for ((channel_slices, scratch_buffers), &num_channels) in aux_input_slices
+                .iter_mut()
+                .zip(aux_input_scratch_buffers.iter_mut())
+                .zip(&bus_config.aux_input_channels)
+            {
+                channel_slices.resize_with(num_channels as usize, || &[]);
+                scratch_buffers.resize_with(num_channels as usize, Vec::new);
+                for scratch_buffer in scratch_buffers.iter_mut() {
+                    scratch_buffer.resize(buffer_config.max_buffer_size as usize, 0.0);
+                }
+            }
+            drop(aux_input_scratch_buffers);
+            drop(output_buffer);
+
+            // The output is crossfaded with the unprocessed audio when the bypass changes
+            let mut bypass_dry_buffers = self.bypass_dry_buffers.write();
+            bypass_dry_buffers.resize_with(num_buffer_channels as usize, Vec::new);
+            for dry_buffer in bypass_dry_buffers.iter_mut() {
+                dry_buffer.resize(buffer_config.max_buffer_size as usize, 0.0);
+            }
+            drop(bypass_dry_buffers);
+            let mut bypass_dry_delay = self.bypass_dry_delay.write();
+            bypass_dry_delay.resize(
+                num_buffer_channels as usize,
+                buffer_config.max_buffer_size as usize,
+                self.current_latency.load(Ordering::SeqCst) as usize,
+            );
+            drop(bypass_dry_delay);
+
+            // Also store this for later, so we can reinitialize the plugin after restoring state
+            self.current_buffer_config.store(Some(buffer_config));
+            *self.initialized_bus_config.write() = Some(bus_config);
+
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Update the processing state after the host has started or stopped processing audio. The
+    /// plugin's output is faded in after it starts processing if
+    /// [Plugin::ACTIVATION_FADE_IN_SAMPLES] is set.
+    pub fn set_processing(&self, is_processing: bool) {
+        // Always reset the processing status when the plugin gets activated or deactivated
+        self.last_process_status.store(ProcessStatus::Normal);
+        self.is_processing.store(is_processing, Ordering::SeqCst);
+        if is_processing {
+            // There's no previous output to crossfade from
+            self.bypass_mix
+                .store(if self.bypass_state.load(Ordering::SeqCst) {
+                    1.0
+                } else {
+                    0.0
+                });
+            self.activation_fade_in_samples_remaining
+                .store(P::ACTIVATION_FADE_IN_SAMPLES, Ordering::SeqCst);
+
+            if P::RESET_SMOOTHERS_ON_RESTART {
+                for param_ptr in self.param_by_hash.values() {
+                    unsafe { param_ptr.reset_smoother_to_current_target() };
+                }
+            }
+        } else {
+            // Events deferred to a block that never came would otherwise show up out of nowhere
+            // when processing resumes
+            self.output_events.write().clear();
+        }
+    }
+
+    /// Reset the wrapper's processing state when the host asks the plugin to clear its state
+    /// without reinitializing it, for instance after the transport jumps. The smoothers jump to
+    /// their current targets, any queued events and automation points are discarded, and the
+    /// output is faded in again if [Plugin::ACTIVATION_FADE_IN_SAMPLES] is set.
+    pub fn reset(&self) {
+        self.last_process_status.store(ProcessStatus::Normal);
+        self.bypass_mix
+            .store(if self.bypass_state.load(Ordering::SeqCst) {
+                1.0
+            } else {
+                0.0
+            });
+        self.activation_fade_in_samples_remaining
+            .store(P::ACTIVATION_FADE_IN_SAMPLES, Ordering::SeqCst);
+
+        for param_ptr in self.param_by_hash.values() {
+            unsafe { param_ptr.reset_smoother_to_current_target() };
+        }
+
+        self.input_events.write().clear();
+        self.input_automation.write().clear();
+        self.output_events.write().clear();
+        self.bypass_dry_delay.write().reset();
+    }
+
+    /// Run the plugin's process function `process` on `output_buffer`, which contains `num_samples`
+    /// samples of unprocessed audio, while handling the wrapper's bypass parameter. When the bypass
+    /// changes, the output is crossfaded between the plugin's output and the unprocessed audio over
+    /// [BYPASS_RAMP_MS] milliseconds. Once the plugin is fully bypassed `process` is not called at
+    /// all and the unprocessed audio is passed through. The unprocessed audio is delayed by the
+    /// plugin's latency so it lines up with the plugin's output, since the host still compensates
+    /// for that latency while the plugin is bypassed. Analysis-only plugins never touch the output,
+    /// so for those bypassing only skips `process`.
+    pub fn process_bypassable(
+        &self,
+        output_buffer: &mut Buffer,
+        num_samples: usize,
+        process: impl FnOnce(&mut Buffer) -> ProcessStatus,
+    ) -> ProcessStatus {
+        let bypassed = self.bypass_state.load(Ordering::SeqCst);
+        let target_mix = if bypassed { 1.0 } else { 0.0 };
+        let mix = self.bypass_mix.load();
+        if P::ANALYSIS_ONLY {
+            self.bypass_mix.store(target_mix);
+
+            return if bypassed {
+                ProcessStatus::Normal
+            } else {
+                process(output_buffer)
+            };
+        }
+
+        // The unprocessed audio always needs to go through the delay line, even when the plugin is
+        // not bypassed, so it's already delayed by the right amount when the bypass changes
+        let mut bypass_dry_delay = self.bypass_dry_delay.write();
+        let mut bypass_dry_buffers = self.bypass_dry_buffers.write();
+        bypass_dry_delay.set_latency(self.current_latency.load(Ordering::Relaxed) as usize);
+        bypass_dry_delay.write_dry(output_buffer.as_raw());
+        bypass_dry_delay.read_dry(&mut bypass_dry_buffers);
+
+        if mix == target_mix {
+            // A bypassed plugin doesn't produce any tail
+            return if bypassed {
+                for (channel, dry_buffer) in output_buffer
+                    .as_raw()
+                    .iter_mut()
+                    .zip(bypass_dry_buffers.iter())
+                {
+                    channel[..num_samples].copy_from_slice(&dry_buffer[..num_samples]);
+                }
+
+                ProcessStatus::Normal
+            } else {
+                process(output_buffer)
+            };
+        }
+
+        let status = process(output_buffer);
+
+        let sample_rate = self
+            .current_buffer_config
+            .load()
+            .map_or(44_100.0, |c| c.sample_rate);
+        let ramp_length_samples = BYPASS_RAMP_MS / 1000.0 * sample_rate;
+        let step_size = if bypassed {
+            ramp_length_samples.recip()
+        } else {
+            -ramp_length_samples.recip()
+        };
+        for (channel, dry_buffer) in output_buffer
+            .as_raw()
+            .iter_mut()
+            .zip(bypass_dry_buffers.iter())
+        {
+            for (sample_idx, (sample, dry_sample)) in channel
+                .iter_mut()
+                .zip(&dry_buffer[..num_samples])
+                .enumerate()
+            {
+                let sample_mix = (mix + step_size * (sample_idx + 1) as f32).clamp(0.0, 1.0);
+                *sample += (dry_sample - *sample) * sample_mix;
+            }
+        }
+        self.bypass_mix
+            .store((mix + step_size * num_samples as f32).clamp(0.0, 1.0));
+
+        status
+    }
+
+    /// Fade in the output after the plugin has been activated to prevent startup pops. This should
+    /// be called after the plugin has processed a block of `num_samples` samples. Analysis-only
+    /// plugins don't touch the output, so there's nothing to fade in there.
+    pub fn apply_activation_fade_in(&self, output_buffer: &mut Buffer, num_samples: u32) {
+        let fade_in_samples_remaining = self
+            .activation_fade_in_samples_remaining
+            .load(Ordering::Relaxed);
+        if fade_in_samples_remaining > 0 && !P::ANALYSIS_ONLY {
+            let fade_in_length = P::ACTIVATION_FADE_IN_SAMPLES;
+            let fade_in_start = fade_in_length - fade_in_samples_remaining;
+            for channel in output_buffer.as_raw() {
+                for (sample_idx, sample) in channel
+                    .iter_mut()
+                    .take(fade_in_samples_remaining as usize)
+                    .enumerate()
+                {
+                    *sample *= (fade_in_start + sample_idx as u32) as f32 / fade_in_length as f32;
+                }
+            }
+
+            self.activation_fade_in_samples_remaining.store(
+                fade_in_samples_remaining.saturating_sub(num_samples),
+                Ordering::Relaxed,
+            );
+        }
+    }
+
+    /// Update the MIDI bank for `channel` after receiving a bank select MSB (`cc` 0) or LSB (`cc`
+    /// 32) message, and return the channel's new 14-bit bank number.
+    pub fn update_midi_bank(&self, channel: u8, cc: u8, value: u8) -> u16 {
+        nih_debug_assert!(cc == 0 || cc == 32);
+
+        let value = value as u16 & 0x7F;
+        let bank_atomic = &self.midi_banks[channel as usize & 0x0F];
+        let old_bank = bank_atomic.load(Ordering::SeqCst);
+        let bank = if cc == 0 {
+            (value << 7) | (old_bank & 0x7F)
+        } else {
+            (old_bank & !0x7F) | value
+        };
+        bank_atomic.store(bank, Ordering::SeqCst);
+
+        bank
+    }
+
+    /// Convert a raw three byte MIDI message to a [NoteEvent]. Returns `None` for messages that
+    /// don't have a [NoteEvent] equivalent. This is used by the wrappers that receive raw MIDI
+    /// instead of already parsed events.
+    pub fn midi_to_note_event(&self, timing: u32, data: [u8; 3]) -> Option<NoteEvent> {
+        let channel = data[0] & 0x0F;
+        match data[0] & 0xF0 {
+            0x80 => Some(NoteEvent::NoteOff {
+                timing,
+                note_id: None,
+                channel,
+                note: data[1] & 0x7F,
+                velocity: data[2] & 0x7F,
+            }),
+            // Note on events with a zero velocity are note off events
+            0x90 if data[2] & 0x7F == 0 => Some(NoteEvent::NoteOff {
+                timing,
+                note_id: None,
+                channel,
+                note: data[1] & 0x7F,
+                velocity: 0,
+            }),
+            0x90 => Some(NoteEvent::NoteOn {
+                timing,
+                note_id: None,
+                channel,
+                note: data[1] & 0x7F,
+                velocity: data[2] & 0x7F,
+            }),
+            0xA0 => Some(NoteEvent::PolyPressure {
+                timing,
+                note_id: None,
+                channel,
+                note: data[1] & 0x7F,
+                pressure: (data[2] & 0x7F) as f32 / 127.0,
+            }),
+            0xB0 => match data[1] & 0x7F {
+                // Bank select MSB and LSB
+                cc @ (0 | 32) => Some(NoteEvent::MidiBankSelect {
+                    timing,
+                    channel,
+                    bank: self.update_midi_bank(channel, cc, data[2]),
+                }),
+                cc => Some(NoteEvent::MidiCC {
+                    timing,
+                    channel,
+                    cc,
+                    value: (data[2] & 0x7F) as f32 / 127.0,
+                }),
+            },
+            0xC0 => Some(NoteEvent::MidiProgramChange {
+                timing,
+                channel,
+                program: data[1] & 0x7F,
+            }),
+            0xD0 => Some(NoteEvent::MidiChannelPressure {
+                timing,
+                channel,
+                pressure: (data[1] & 0x7F) as f32 / 127.0,
+            }),
+            // The least significant byte comes first
+            0xE0 => Some(NoteEvent::MidiPitchBend {
+                timing,
+                channel,
+                value: (((data[2] as u16 & 0x7F) << 7) | (data[1] as u16 & 0x7F)) as f32 / 16383.0,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Get the plugin's current state, containing the parameter values and the persistent fields.
+    /// This is used when saving the plugin's state and when autosaving.
+    pub unsafe fn get_state_object(&self) -> State {
+        // We'll serialize parmaeter values as a simple `string_param_id: display_value` map.
+        let mut params: HashMap<_, _> = self
+            .param_id_to_hash
+            .iter()
+            .filter_map(|(param_id_str, hash)| {
+                let param_ptr = self.param_by_hash.get(hash)?;
+                Some((param_id_str, param_ptr))
+            })
+            .filter_map(|(&param_id_str, &param_ptr)| match param_ptr {
+                ParamPtr::FloatParam(p) => Some((
+                    param_id_str.to_string(),
+                    ParamValue::F32((*p).plain_value()),
+                )),
+                ParamPtr::IntParam(p) => Some((
+                    param_id_str.to_string(),
+                    ParamValue::I32((*p).plain_value()),
+                )),
+                ParamPtr::BoolParam(p) => Some((
+                    param_id_str.to_string(),
+                    ParamValue::Bool((*p).plain_value()),
+                )),
+                // Enums are stored as the selected variant's index
+                ParamPtr::EnumParam(p) => Some((
+                    param_id_str.to_string(),
+                    ParamValue::I32((*p).plain_value()),
+                )),
+                // Meters only report values to the host, so there's nothing to restore later
+                ParamPtr::MeterParam(_) => None,
+            })
+            .collect();
+
+        // Don't forget about the bypass parameter
+        params.insert(
+            BYPASS_PARAM_ID.to_string(),
+            ParamValue::Bool(self.bypass_state.load(Ordering::SeqCst)),
+        );
+
+        // The plugin can also persist arbitrary fields alongside its parameters. This is useful for
+        // storing things like sample data.
+        let format = if P::BINARY_STATE {
+            StateFormat::Binary
+        } else {
+            StateFormat::Json
+        };
+        let fields = self.plugin.read().params().serialize_fields(format);
+
+        State {
+            version: P::STATE_VERSION,
+            params,
+            fields,
+            format,
+        }
+    }
+
+    /// Restore the plugin's state from a [State] object, and reinitialize the plugin afterwards if
+    /// it has already been initialized before. States saved with an older
+    /// [Plugin::STATE_VERSION] are migrated first. Returns `false` without changing anything if
+    /// the state was saved by a newer version of the plugin, and it also returns `false` if the
+    /// plugin panicked while restoring the state.
+    pub unsafe fn set_state_object(&self, state: State) -> bool {
+        self.catch_panic("restoring its state", || {
+            self.set_state_object_unguarded(state)
+        })
+        .unwrap_or(false)
+    }
+
+    /// The actual implementation of [Self::set_state_object()], without the panic handling.
+    unsafe fn set_state_object_unguarded(&self, mut state: State) -> bool {
+        if state.version > P::STATE_VERSION {
+            nih_debug_assert_failure!(
+                "Refusing to load state version {}, the plugin's state version is {}",
+                state.version,
+                P::STATE_VERSION
+            );
+            return false;
+        }
+        if state.version < P::STATE_VERSION {
+            let from_version = state.version;
+            self.plugin.read().migrate_state(from_version, &mut state);
+        }
+
+        let sample_rate = self.current_buffer_config.load().map(|c| c.sample_rate);
+        for (param_id_str, param_value) in state.params {
+            // Handle the bypass parameter separately
+            if param_id_str == BYPASS_PARAM_ID {
+                match param_value {
+                    ParamValue::Bool(b) => self.bypass_state.store(b, Ordering::SeqCst),
+                    _ => nih_debug_assert_failure!(
+                        "Invalid serialized value {:?} for parameter \"{}\"",
+                        param_value,
+                        param_id_str,
+                    ),
+                };
+                continue;
+            }
+
+            let param_ptr = match self
+                .param_id_to_hash
+                .get(param_id_str.as_str())
+                .and_then(|hash| self.param_by_hash.get(hash))
+            {
+                Some(ptr) => ptr,
+                None => {
+                    nih_debug_assert_failure!("Unknown parameter: {}", param_id_str);
+                    continue;
+                }
+            };
+
+            match (param_ptr, param_value) {
+                (ParamPtr::FloatParam(p), ParamValue::F32(v)) => (**p).set_plain_value(v),
+                (ParamPtr::IntParam(p), ParamValue::I32(v)) => (**p).set_plain_value(v),
+                (ParamPtr::BoolParam(p), ParamValue::Bool(v)) => (**p).set_plain_value(v),
+                (ParamPtr::EnumParam(p), ParamValue::I32(v)) => (**p).set_plain_value(v),
+                (param_ptr, param_value) => {
+                    nih_debug_assert_failure!(
+                        "Invalid serialized value {:?} for parameter \"{}\" ({:?})",
+                        param_value,
+                        param_id_str,
+                        param_ptr,
+                    );
+                }
+            }
+        }
+
+        // Make sure everything starts out in sync. This is done for every parameter, including the
+        // ones missing from the state, so no smoothing started before the state was loaded is left
+        // over. If the plugin has not been set up for processing yet, then this happens in
+        // `setup_processing()` instead.
+        if let Some(sample_rate) = sample_rate {
+            for param_ptr in self.param_by_hash.values() {
+                param_ptr.update_smoother(sample_rate, true);
+            }
+        }
+
+        // The plugin can also persist arbitrary fields alongside its parameters. This is useful for
+        // storing things like sample data.
+        self.plugin
+            .read()
+            .params()
+            .deserialize_fields(&state.fields, state.format);
+        self.plugin.write().state_restored();
+        self.editor_repaint_version.bump();
+
+        // Reinitialize the plugin after loading state so it can respond to the new parmaeters
+        let bus_config = self.current_bus_config.read().clone();
+        if let Some(buffer_config) = self.current_buffer_config.load() {
+            self.plugin.write().initialize(
+                &bus_config,
+                &buffer_config,
+                &mut self.make_process_context(Transport::new(buffer_config.sample_rate)),
+            );
+        }
+
+        true
+    }
+
+    /// Send a parameter edit from the editor to the host. If the host has not yet set the
+    /// component handler, then the edit is queued until [Self::flush_pending_edits()] gets called.
+    unsafe fn send_edit(&self, edit: PendingEdit) {
+        match &*self.component_handler.read() {
+            Some(handler) => match edit {
+                PendingEdit::Begin(hash) => {
+                    handler.begin_edit(hash);
+                }
+                PendingEdit::Perform(hash, normalized) => {
+                    handler.perform_edit(hash, normalized as f64);
+                }
+                PendingEdit::End(hash) => {
+                    handler.end_edit(hash);
+                }
+            },
+            None => {
+                let has_room = match edit {
+                    PendingEdit::Perform(_, _) => {
+                        self.pending_edits.len()
+                            < PENDING_EDITS_CAPACITY - PENDING_GESTURE_EDITS_RESERVED
+                    }
+                    PendingEdit::Begin(_) | PendingEdit::End(_) => true,
+                };
+                let pushed = has_room && self.pending_edits.push(edit).is_ok();
+                nih_debug_assert!(pushed, "The pending edits queue is full, dropping edit...");
+
+                if let Some(callback) = &*self.pending_edits_callback.read() {
+                    callback();
+                }
+            }
+        }
+    }
+
+    /// Whether an edit made from the editor should be sent to the host right away. When the edit
+    /// rate is limited and the last edit for this parameter during the current gesture was sent too
+    /// recently, then the new value is held back until the next edit or until the end of the
+    /// gesture instead.
+    fn should_send_edit(&self, hash: u32, normalized: f32) -> bool {
+        let max_edit_rate = match self.max_edit_rate.load() {
+            Some(max_edit_rate) if max_edit_rate > 0.0 => max_edit_rate,
+            _ => return true,
+        };
+        let gesture_active = self
+            .param_edit_gestures
+            .get(&hash)
+            .map_or(false, |gesture_active| {
+                gesture_active.load(Ordering::Relaxed)
+            });
+        if !gesture_active {
+            return true;
+        }
+
+        let now = Instant::now();
+        let mut throttled_edits = self.throttled_edits.lock();
+        match throttled_edits.get_mut(&hash) {
+            Some((last_sent, held_back))
+                if now.duration_since(*last_sent).as_secs_f32() < max_edit_rate.recip() =>
+            {
+                *held_back = Some(normalized);
+                false
+            }
+            _ => {
+                throttled_edits.insert(hash, (now, None));
+                true
+            }
+        }
+    }
+
+    /// Send the parameter edits and restarts that were requested before the component handler was
+    /// set to the host. This should be called after setting the component handler.
+    pub unsafe fn flush_pending_edits(&self) {
+        // If there's still no component handler then these edits are added to the end of the queue
+        // again, so only the edits that are currently in the queue are handled here
+        for _ in 0..self.pending_edits.len() {
+            match self.pending_edits.pop() {
+                Some(edit) => self.send_edit(edit),
+                None => break,
+            }
+        }
+
+        let pending_restart_flags = self.pending_restart_flags.swap(0, Ordering::SeqCst);
+        if pending_restart_flags != 0 {
+            self.trigger_restart(pending_restart_flags);
+        }
+    }
+
+    /// Ask the host to restart the plugin with the given [vst3_sys::vst::RestartFlags]. If the host
+    /// has not yet set the component handler, then the flags are stored in
+    /// [Self::pending_restart_flags] instead.
+    unsafe fn trigger_restart(&self, flags: i32) {
+        match &*self.component_handler.read() {
+            Some(handler) => {
+                handler.restart_component(flags);
+            }
+            None => {
+                self.pending_restart_flags.fetch_or(flags, Ordering::SeqCst);
+            }
+        }
+    }
+
+    /// Snapshot the plugin's current state and send it to the autosave thread, if autosaving is
+    /// enabled. This must only be called from the main thread.
+    unsafe fn autosave(&self) {
+        if let Some(autosave_sender) = &self.autosave_sender {
+            // If the autosave thread hasn't written the last snapshot yet, then this one can be
+            // skipped
+            let _ = autosave_sender.try_send(self.get_state_object());
+        }
+    }
+
+    /// The source of a parameter change the host sent to the plugin. If the editor is currently in
+    /// the middle of a gesture for the parameter, then this is the host passing the editor's change
+    /// back to the plugin.
+    pub fn host_change_source(&self, hash: u32) -> ParamChangeSource {
+        match self.param_edit_gestures.get(&hash) {
+            Some(gesture_active) if gesture_active.load(Ordering::Relaxed) => {
+                ParamChangeSource::Plugin
+            }
+            _ => ParamChangeSource::Host,
+        }
+    }
+
+    /// Convenience function for setting a value for a parameter as triggered by a VST3 parameter
+    /// update. The same rate is for updating parameter smoothing. Values that are within
+    /// [Plugin::PARAMETER_CHANGE_EPSILON] of the parameter's current value are ignored. The change's
+    /// source determines whether the parameter's smoother is used. Read-only parameters like
+    /// [crate::param::MeterParam]s can only be changed by the plugin, so changes to those are
+    /// rejected.
+    pub unsafe fn set_normalized_value_by_hash(
+        &self,
+        hash: u32,
+        normalized_value: f32,
+        sample_rate: Option<f32>,
+        source: ParamChangeSource,
+    ) -> tresult {
+        if hash == *BYPASS_PARAM_HASH {
+            self.bypass_state
+                .store(normalized_value >= 0.5, Ordering::SeqCst);
+            self.editor_repaint_version.bump();
+
+            kResultOk
+        } else if let Some(param_ptr) = self.param_by_hash.get(&hash) {
+            if param_ptr.is_read_only() {
+                return kResultFalse;
+            }
+
+            // Hosts tend to resend the current value during automation, and that should not
+            // retarget the smoother or trigger the parameter's `value_changed` callback
+            if (param_ptr.normalized_value() - normalized_value).abs()
+                <= P::PARAMETER_CHANGE_EPSILON
+            {
+                return kResultOk;
+            }
+
+            // Also update the parameter's smoothing if applicable. Changes outside of the smoother's
+            // scope jump straight to the new value.
+            param_ptr.set_normalized_value(normalized_value);
+            if let Some(sample_rate) = sample_rate {
+                let smooth = match param_ptr.smoothing_scope() {
+                    SmoothingScope::All => true,
+                    SmoothingScope::Host => source == ParamChangeSource::Host,
+                    SmoothingScope::Plugin => source == ParamChangeSource::Plugin,
+                };
+                param_ptr.update_smoother(sample_rate, !smooth);
+            }
+            self.editor_repaint_version.bump();
+
+            kResultOk
+        } else {
+            kInvalidArgument
+        }
+    }
+}
+
+// We can't use a nice standalone context object for this as we don't have any control over the
+// lifetime since we currently try to stay GUI framework agnostic. Because of that, the only
+// alternative is to pass an `Arc<Self as GuiContext>` to the plugin and hope it doesn't do anything
+// weird with it.
+impl<P: Plugin> GuiContext for WrapperInner<P> {
+    // All of these functions are supposed to be called from the main thread, so we'll put some
+    // trust in the caller and assume that this is indeed the case
+    unsafe fn raw_begin_set_parameter(&self, param: ParamPtr) {
+        match self.param_ptr_to_hash.get(&param) {
+            Some(hash) => {
+                if let Some(gesture_active) = self.param_edit_gestures.get(hash) {
+                    let was_active = gesture_active.swap(true, Ordering::Relaxed);
+                    nih_debug_assert!(
+                        !was_active,
+                        "Parameter {:?} was already being set, call \
+                         ParamSetter::end_set_parameter() before starting a new gesture",
+                        param
+                    );
+                }
+
+                self.send_edit(PendingEdit::Begin(*hash));
+            }
+            None => nih_debug_assert_failure!("Unknown parameter: {:?}", param),
+        }
+    }
+
+    unsafe fn raw_set_parameter_normalized(&self, param: ParamPtr, normalized: f32) {
+        match self.param_ptr_to_hash.get(&param) {
+            Some(hash) => {
+                nih_debug_assert!(
+                    self.param_edit_gestures
+                        .get(hash)
+                        .map_or(true, |gesture_active| gesture_active
+                            .load(Ordering::Relaxed)),
+                    "Parameter {:?} was set outside of a gesture, call \
+                     ParamSetter::begin_set_parameter() first",
+                    param
+                );
+
+                // Only update the parameters manually if the host is not processing audio. If the
+                // plugin is currently processing audio, the host will pass this change back to the
+                // plugin in the audio callback. This also prevents the values from changing in the
+                // middle of the process callback, which would be unsound.
+                if !self.is_processing.load(Ordering::SeqCst) {
+                    self.set_normalized_value_by_hash(
+                        *hash,
+                        normalized,
+                        self.current_buffer_config.load().map(|c| c.sample_rate),
+                        ParamChangeSource::Plugin,
+                    );
+                }
+
+                if self.should_send_edit(*hash, normalized) {
+                    self.send_edit(PendingEdit::Perform(*hash, normalized));
+                }
+            }
+            None => nih_debug_assert_failure!("Unknown parameter: {:?}", param),
+        }
+    }
+
+    unsafe fn raw_end_set_parameter(&self, param: ParamPtr) {
+        match self.param_ptr_to_hash.get(&param) {
+            Some(hash) => {
+                if let Some(gesture_active) = self.param_edit_gestures.get(hash) {
+                    let was_active = gesture_active.swap(false, Ordering::Relaxed);
+                    nih_debug_assert!(
+                        was_active,
+                        "Parameter {:?} was not being set, ParamSetter::end_set_parameter() needs \
+                         to be preceded by ParamSetter::begin_set_parameter()",
+                        param
+                    );
+                }
+
+                // The final value always reaches the host, even if it was held back
+                if let Some((_, Some(normalized))) = self.throttled_edits.lock().remove(hash) {
+                    self.send_edit(PendingEdit::Perform(*hash, normalized));
+                }
+                self.send_edit(PendingEdit::End(*hash));
+            }
+            None => nih_debug_assert_failure!("Unknown parameter: {:?}", param),
+        }
+    }
+
+    fn editor_repaint_requested(&self, last_seen: &mut u32) -> bool {
+        self.editor_repaint_version.has_changed_since(last_seen)
+    }
+
+    fn set_max_parameter_edit_rate(&self, edits_per_second: Option<f32>) {
+        nih_debug_assert!(edits_per_second.map_or(true, |rate| rate > 0.0));
+
+        self.max_edit_rate.store(edits_per_second);
+    }
+
+    fn request_resize(&self) -> bool {
+        // The lock is held for the entire call so the view cannot be removed or dropped while it's
+        // asking the host for a new size
+        match &*self.plug_view.read() {
+            Some(plug_view) => unsafe { plug_view.request_resize() },
+            None => false,
+        }
+    }
+
+    fn has_autosaved_state(&self) -> bool {
+        P::AUTOSAVE_INTERVAL.is_some() && state::find_recovery_file(P::NAME).is_some()
+    }
+
+    fn restore_autosaved_state(&self) -> bool {
+        if P::AUTOSAVE_INTERVAL.is_none() {
+            return false;
+        }
+
+        let recovery_path = match state::find_recovery_file(P::NAME) {
+            Some(path) => path,
+            None => return false,
+        };
+        let state = match state::read_autosave(&recovery_path) {
+            Ok(state) => state,
+            Err(err) => {
+                nih_log!(
+                    "Could not read the autosave file at '{}': {}",
+                    recovery_path.display(),
+                    err
+                );
+                return false;
+            }
+        };
+
+        if !unsafe { self.set_state_object(state) } {
+            return false;
+        }
+
+        // This state has now been recovered, so it shouldn't be offered again
+        let _ = fs::remove_file(&recovery_path);
+
+        unsafe { self.trigger_restart(RestartFlags::kParamValuesChanged as i32) };
+
+        true
+    }
+}
+
+impl<P: Plugin> Drop for WrapperInner<P> {
+    fn drop(&mut self) {
+        // The autosave thread needs to be stopped first so it can't write the file again after it
+        // has been removed. This drop may also happen on the autosave thread itself.
+        self.autosave_sender = None;
+        if let Some(autosave_thread) = self.autosave_thread.get_mut().take() {
+            if autosave_thread.thread().id() != thread::current().id() {
+                let _ = autosave_thread.join();
+            }
+        }
+
+        // This instance shut down cleanly, so there's nothing to recover
+        if let Some(autosave_path) = &self.autosave_path {
+            let _ = fs::remove_file(autosave_path);
+        }
+    }
+}
+
+/// Precompute the display strings for all values of an integer parameter that should be shown as
+/// a list, see [MAX_LIST_PARAM_VALUES]. Returns `None` for all other parameters.
+///
+/// # Safety
+///
+/// `param_ptr` needs to point to a live parameter.
+unsafe fn list_param_value_strings(param_ptr: &ParamPtr) -> Option<Vec<String>> {
+    let has_value_to_string = match param_ptr {
+        ParamPtr::IntParam(p) => (**p).value_to_string.is_some(),
+        ParamPtr::EnumParam(_) => true,
+        _ => false,
+    };
+    let step_count = param_ptr.step_count();
+    if !has_value_to_string || step_count <= 0 || step_count >= MAX_LIST_PARAM_VALUES {
+        return None;
+    }
+
+    // The label is computed from the plain value each step snaps to, normalized again from that
+    // plain value. Formatting the step's evenly spaced normalized value directly could otherwise
+    // round to a neighbouring value depending on the parameter's range and snapping.
+    Some(
+        (0..=step_count)
+            .map(|step| {
+                let plain = param_ptr.preview_plain(step as f32 / step_count as f32);
+                param_ptr.normalized_value_to_string(param_ptr.preview_normalized(plain), false)
+            })
+            .collect(),
+    )
+}
+
+impl<P: Plugin> MainThreadExecutor<Task> for WrapperInner<P> {
+    unsafe fn execute(&self, task: Task) {
+        // This function is always called from the main thread
+        // TODO: When we add GUI resizing and context menus, this should propagate those events to
+        //       `IRunLoop` on Linux to keep REAPER happy. That does mean a double spool, but we can
+        //       come up with a nicer solution to handle that later (can always add a separate
+        //       function for checking if a to be scheduled task can be handled right ther and
+        //       then).
+        match task {
+            Task::TriggerRestart(flags) => self.trigger_restart(flags),
+            Task::PerformEdits => {
+                // Changes made after this point will post a new task
+                self.process_edits_task_posted
+                    .store(false, Ordering::SeqCst);
+                for (hash, edited) in &self.process_edited_params {
+                    if !edited.swap(false, Ordering::SeqCst) {
+                        continue;
+                    }
+
+                    if let Some(param_ptr) = self.param_by_hash.get(hash) {
+                        let normalized = param_ptr.normalized_value();
+                        self.send_edit(PendingEdit::Begin(*hash));
+                        self.send_edit(PendingEdit::Perform(*hash, normalized));
+                        self.send_edit(PendingEdit::End(*hash));
+                    }
+                }
+            }
+            Task::Autosave => self.autosave(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::param::range::Range;
+    use crate::param::IntParam;
+
+    #[test]
+    fn list_value_strings_use_the_snapped_plain_values() {
+        let param = IntParam {
+            value: 0,
+            range: Range::Linear { min: 0, max: 10 },
+            step_size: Some(4.0),
+            name: "Octave",
+            value_to_string: Some(Arc::new(|value| format!("{}", value))),
+            ..Default::default()
+        };
+
+        // 0..10 is not a multiple of 4, so the range is divided into three equal steps instead
+        let value_strings = unsafe { list_param_value_strings(&param.as_ptr()) };
+        assert_eq!(
+            value_strings,
+            Some(vec![
+                String::from("0"),
+                String::from("3"),
+                String::from("7"),
+                String::from("10")
+            ])
+        );
+    }
+}
@@ -0,0 +1,544 @@
+// nih-plug: plugins, but rewritten in Rust
+// Copyright (C) 2022 Robbert van der Helm
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! A wrapper that runs a plugin as a standalone application, without a plugin host. Audio is
+//! processed through CPAL, which can also talk to JACK when the `standalone_jack` feature is
+//! enabled, and MIDI input is received through midir. This uses the same [WrapperInner] as the
+//! plugin wrappers, with this wrapper taking on the host's role of passing the editor's parameter
+//! changes back to the plugin.
+//!
+//! This is mostly meant for quickly testing plugins without having to load them in a DAW. Since
+//! there are no sample accurate MIDI timestamps, all MIDI events are delivered at the start of the
+//! next block.
+
+use baseview::{
+    Event, EventStatus, Size, Window, WindowHandler, WindowOpenOptions, WindowScalePolicy,
+};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use crossbeam::queue::ArrayQueue;
+use midir::{MidiInput, MidiInputConnection};
+use raw_window_handle::HasRawWindowHandle;
+use std::any::Any;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread;
+
+use super::inner::{ParamChangeSource, PendingEdit, WrapperInner};
+use super::util::{process_wrapper, queue_event};
+use crate::context::Transport;
+use crate::plugin::{BufferConfig, ParentWindowHandle, Plugin, SamplePrecision};
+
+mod config;
+
+pub use self::config::{WrapperConfig, USAGE_STRING};
+
+/// The number of raw MIDI messages that can be queued up between two audio callbacks.
+const MIDI_QUEUE_CAPACITY: usize = 1024;
+
+/// Run the plugin `P` as a standalone application using the options passed on the command line.
+/// This is what [nih_export_standalone!] calls from the generated `main()` function. Exits the
+/// process with a non-zero exit code if the plugin could not be started.
+pub fn nih_export_standalone<P: Plugin>() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        eprintln!("{}\n\n{USAGE_STRING}", P::NAME);
+        return;
+    }
+
+    let config = match WrapperConfig::from_args(args) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{err}\n\n{USAGE_STRING}");
+            std::process::exit(1);
+        }
+    };
+
+    if let Err(err) = run::<P>(config) {
+        eprintln!("Could not run '{}': {err}", P::NAME);
+        std::process::exit(1);
+    }
+}
+
+/// Run the plugin `P` with the audio and MIDI devices from `config`. If the plugin has an editor,
+/// then this returns after the editor's window has been closed. Otherwise this keeps running until
+/// the process gets terminated.
+pub fn run<P: Plugin>(config: WrapperConfig) -> Result<(), String> {
+    let inner = WrapperInner::<P>::new();
+
+    // There is no host to negotiate a bus layout with, so the plugin's default layout is used
+    let bus_config = inner.current_bus_config.read().clone();
+    if !inner.plugin.read().accepts_bus_config(&bus_config) {
+        return Err(format!(
+            "The plugin does not accept its default bus configuration {bus_config:?}"
+        ));
+    }
+
+    let host = match &config.backend {
+        Some(backend) => {
+            let host_id = cpal::available_hosts()
+                .into_iter()
+                .find(|host_id| host_id.name().eq_ignore_ascii_case(backend))
+                .ok_or_else(|| format!("Unknown or unavailable audio backend '{backend}'"))?;
+
+            cpal::host_from_id(host_id)
+                .map_err(|err| format!("Could not use the '{backend}' backend: {err}"))?
+        }
+        None => cpal::default_host(),
+    };
+
+    let output_device = match &config.output_device {
+        Some(name) => host
+            .output_devices()
+            .map_err(|err| format!("Could not list the output devices: {err}"))?
+            .find(|device| {
+                device
+                    .name()
+                    .map_or(false, |device_name| &device_name == name)
+            })
+            .ok_or_else(|| format!("Unknown output device '{name}'"))?,
+        None => host
+            .default_output_device()
+            .ok_or("There is no default output device")?,
+    };
+    // Plugins without inputs, or with inputs they don't need right now, simply get silence
+    let input_device = if bus_config.num_input_channels == 0 || config.no_input {
+        None
+    } else {
+        match &config.input_device {
+            Some(name) => Some(
+                host.input_devices()
+                    .map_err(|err| format!("Could not list the input devices: {err}"))?
+                    .find(|device| {
+                        device
+                            .name()
+                            .map_or(false, |device_name| &device_name == name)
+                    })
+                    .ok_or_else(|| format!("Unknown input device '{name}'"))?,
+            ),
+            None => {
+                let device = host.default_input_device();
+                if device.is_none() {
+                    nih_log!("There is no default input device, the plugin will receive silence");
+                }
+
+                device
+            }
+        }
+    };
+
+    let buffer_config = BufferConfig {
+        sample_rate: config.sample_rate as f32,
+        max_buffer_size: config.period_size,
+        sample_precision: SamplePrecision::Single,
+    };
+    if !unsafe { inner.initialize_plugin(buffer_config) } {
+        return Err(String::from("The plugin failed to initialize"));
+    }
+
+    // Audio devices don't have a notion of a MIDI-only plugin, so the output stream always has at
+    // least a single channel to drive the plugin's processing
+    let num_input_channels = bus_config.num_input_channels as usize;
+    let num_output_channels = bus_config.num_output_channels as usize;
+    let num_device_output_channels = num_output_channels.max(1);
+    let stream_config = |num_channels: usize| cpal::StreamConfig {
+        channels: num_channels as u16,
+        sample_rate: cpal::SampleRate(config.sample_rate),
+        buffer_size: cpal::BufferSize::Fixed(config.period_size),
+    };
+
+    // The input stream's samples are handed to the output stream's callback through this queue.
+    // This leaves room for a couple of periods in case the two streams aren't perfectly in sync.
+    let input_queue = Arc::new(ArrayQueue::new(
+        (config.period_size as usize * num_input_channels * 4).max(1),
+    ));
+    let input_stream = match input_device {
+        Some(input_device) => {
+            let input_queue = input_queue.clone();
+            let stream = input_device
+                .build_input_stream(
+                    &stream_config(num_input_channels),
+                    move |data: &[f32], _| {
+                        // If the output stream is lagging behind, then entire frames are dropped so
+                        // the channels never get out of sync. This is the only thread pushing to
+                        // the queue, so the free space can only grow while a frame is pushed.
+                        for frame in data.chunks_exact(num_input_channels) {
+                            if input_queue.capacity() - input_queue.len() < num_input_channels {
+                                break;
+                            }
+
+                            for sample in frame {
+                                let _ = input_queue.push(*sample);
+                            }
+                        }
+                    },
+                    |err| nih_log!("Error in the audio input stream: {}", err),
+                )
+                .map_err(|err| format!("Could not open the input device: {err}"))?;
+
+            Some(stream)
+        }
+        None => None,
+    };
+
+    let midi_queue = Arc::new(ArrayQueue::new(MIDI_QUEUE_CAPACITY));
+    let _midi_connection = if P::ACCEPTS_MIDI {
+        connect_midi_input::<P>(&config, midi_queue.clone())?
+    } else {
+        None
+    };
+
+    let mut processor = Processor {
+        inner: inner.clone(),
+        sample_rate: buffer_config.sample_rate,
+        max_buffer_size: config.period_size as usize,
+        num_input_channels,
+        num_output_channels,
+        num_device_output_channels,
+        // The plugin processes audio in place, just like in most hosts
+        main_buffers: vec![
+            vec![0.0; config.period_size as usize];
+            num_input_channels.max(num_output_channels)
+        ],
+        input_queue,
+        midi_queue,
+    };
+    let output_stream = output_device
+        .build_output_stream(
+            &stream_config(num_device_output_channels),
+            move |data: &mut [f32], _| processor.process(data),
+            |err| nih_log!("Error in the audio output stream: {}", err),
+        )
+        .map_err(|err| format!("Could not open the output device: {err}"))?;
+
+    inner.set_processing(true);
+    if let Some(input_stream) = &input_stream {
+        input_stream
+            .play()
+            .map_err(|err| format!("Could not start the input stream: {err}"))?;
+    }
+    output_stream
+        .play()
+        .map_err(|err| format!("Could not start the output stream: {err}"))?;
+
+    match inner.editor.clone() {
+        Some(editor) if editor.is_available() => {
+            let (width, height) = editor.size();
+            let gui_inner = inner.clone();
+            Window::open_blocking(
+                WindowOpenOptions {
+                    title: String::from(P::NAME),
+                    size: Size::new(width as f64, height as f64),
+                    scale: WindowScalePolicy::SystemScaleFactor,
+                    gl_config: None,
+                },
+                move |window| {
+                    let parent = ParentWindowHandle {
+                        handle: window.raw_window_handle(),
+                    };
+
+                    EditorWindow {
+                        _editor_handle: editor.spawn(parent, gui_inner),
+                    }
+                },
+            );
+        }
+        _ => {
+            eprintln!("Running '{}', press Ctrl+C to exit", P::NAME);
+            loop {
+                thread::park();
+            }
+        }
+    }
+
+    // The streams stop when they get dropped
+    drop(output_stream);
+    drop(input_stream);
+    inner.set_processing(false);
+
+    Ok(())
+}
+
+/// Connect to the MIDI input port from `config`, or create a virtual MIDI input port if no port was
+/// specified and the platform supports it. The incoming messages are pushed to `midi_queue`. The
+/// connection stays open until the returned object gets dropped.
+fn connect_midi_input<P: Plugin>(
+    config: &WrapperConfig,
+    midi_queue: Arc<ArrayQueue<[u8; 3]>>,
+) -> Result<Option<MidiInputConnection<()>>, String> {
+    let midi_input = MidiInput::new(P::NAME)
+        .map_err(|err| format!("Could not initialize the MIDI input: {err}"))?;
+    let on_message = move |_timestamp: u64, message: &[u8], _: &mut ()| {
+        // Only channel voice messages are passed to the plugin, and those fit in three bytes
+        if (1..=3).contains(&message.len()) {
+            let mut data = [0u8; 3];
+            data[..message.len()].copy_from_slice(message);
+            let _ = midi_queue.push(data);
+        }
+    };
+
+    match &config.midi_input {
+        Some(name) => {
+            let port = midi_input
+                .ports()
+                .into_iter()
+                .find(|port| {
+                    midi_input
+                        .port_name(port)
+                        .map_or(false, |port_name| &port_name == name)
+                })
+                .ok_or_else(|| format!("Unknown MIDI input port '{name}'"))?;
+            let connection = midi_input
+                .connect(&port, P::NAME, on_message, ())
+                .map_err(|err| format!("Could not connect to the MIDI input port: {err}"))?;
+
+            Ok(Some(connection))
+        }
+        #[cfg(unix)]
+        None => {
+            use midir::os::unix::VirtualInput;
+
+            let connection = midi_input
+                .create_virtual(P::NAME, on_message, ())
+                .map_err(|err| format!("Could not create a virtual MIDI input port: {err}"))?;
+
+            Ok(Some(connection))
+        }
+        #[cfg(not(unix))]
+        None => {
+            nih_log!("Virtual MIDI ports are not supported on this platform, use --midi-input");
+
+            Ok(None)
+        }
+    }
+}
+
+/// The state for the output stream's callback, which drives the plugin's processing. Everything
+/// used in the callback is allocated up front.
+struct Processor<P: Plugin> {
+    inner: Arc<WrapperInner<P>>,
+    sample_rate: f32,
+    max_buffer_size: usize,
+    num_input_channels: usize,
+    num_output_channels: usize,
+    /// The number of channels in the output stream. This can be larger than `num_output_channels`
+    /// for plugins without any outputs.
+    num_device_output_channels: usize,
+
+    /// The plugin's main input and output channels, each containing `max_buffer_size` samples. The
+    /// inputs are written to these buffers before the plugin processes them in place.
+    main_buffers: Vec<Vec<f32>>,
+    /// Interleaved samples from the input stream. This always contains whole frames.
+    input_queue: Arc<ArrayQueue<f32>>,
+    /// Raw MIDI messages from the MIDI input port.
+    midi_queue: Arc<ArrayQueue<[u8; 3]>>,
+}
+
+impl<P: Plugin> Processor<P> {
+    /// Process and write the plugin's output to the output stream's `interleaved_output` buffer. If
+    /// the device requests more samples than the plugin's maximum buffer size, then this is done in
+    /// multiple smaller blocks.
+    fn process(&mut self, interleaved_output: &mut [f32]) {
+        // A plugin that panicked before is likely in an inconsistent state, so it only outputs
+        // silence from then on
+        if self.inner.panicked.load(Ordering::SeqCst) {
+            interleaved_output.fill(0.0);
+            return;
+        }
+
+        // Panic on allocations if the `assert_process_allocs` feature has been enabled, and make
+        // sure that FTZ is set up correctly unless the plugin opted out of that. Panics are caught
+        // here so they don't unwind into the audio backend.
+        let result = process_wrapper::<P, _, _>(|| {
+            for interleaved_block in interleaved_output
+                .chunks_mut(self.max_buffer_size * self.num_device_output_channels)
+            {
+                let num_samples = interleaved_block.len() / self.num_device_output_channels;
+
+                // Any frames that haven't arrived yet are replaced with silence. The input stream
+                // only pushes whole frames and this is the only thread popping from the queue, so
+                // a frame is either entirely available or not at all.
+                for sample_idx in 0..num_samples {
+                    let frame_available = self.input_queue.len() >= self.num_input_channels;
+                    for channel in &mut self.main_buffers[..self.num_input_channels] {
+                        channel[sample_idx] = if frame_available {
+                            self.input_queue.pop().unwrap_or(0.0)
+                        } else {
+                            0.0
+                        };
+                    }
+                }
+                for channel in &mut self.main_buffers[self.num_input_channels..] {
+                    channel[..num_samples].fill(0.0);
+                }
+
+                unsafe { self.process_block(num_samples) };
+
+                for (frame_idx, frame) in interleaved_block
+                    .chunks_exact_mut(self.num_device_output_channels)
+                    .enumerate()
+                {
+                    for (channel_idx, sample) in frame.iter_mut().enumerate() {
+                        *sample = if channel_idx < self.num_output_channels {
+                            self.main_buffers[channel_idx][frame_idx]
+                        } else {
+                            0.0
+                        };
+                    }
+                }
+            }
+        });
+
+        if result.is_none() {
+            self.inner.panicked.store(true, Ordering::SeqCst);
+            interleaved_output.fill(0.0);
+        }
+    }
+
+    /// Let the plugin process the first `num_samples` samples in `main_buffers` in place.
+    unsafe fn process_block(&mut self, num_samples: usize) {
+        let inner = &*self.inner;
+
+        // A host would normally pass the editor's parameter changes back to the plugin during the
+        // next process call, so without a host that needs to happen here. The queue is lock-free, so
+        // this doesn't block the audio thread.
+        while let Some(edit) = inner.pending_edits.pop() {
+            if let PendingEdit::Perform(hash, normalized) = edit {
+                inner.set_normalized_value_by_hash(
+                    hash,
+                    normalized,
+                    Some(self.sample_rate),
+                    ParamChangeSource::Plugin,
+                );
+            }
+        }
+
+        if P::ACCEPTS_MIDI {
+            let mut input_events = inner.input_events.write();
+            while let Some(data) = self.midi_queue.pop() {
+                if let Some(event) = inner.midi_to_note_event(0, data) {
+                    queue_event(&mut input_events, event);
+                }
+            }
+        }
+
+        // This vector has been reallocated to contain enough slices as there are output channels
+        // (or input channels for analysis-only plugins)
+        let mut output_buffer = inner.output_buffer.write();
+        for (channel_slice, channel) in output_buffer
+            .as_raw_vec()
+            .iter_mut()
+            .zip(self.main_buffers.iter_mut())
+        {
+            // SAFETY: `main_buffers` is never resized after it has been allocated, and these
+            //         slices are only dereferenced as part of this function
+            *channel_slice = std::slice::from_raw_parts_mut(channel.as_mut_ptr(), num_samples);
+        }
+
+        // Since the audio is processed in place, the unmodified inputs need to be copied to the
+        // scratch buffers allocated during initialization
+        if P::SEPARATE_INPUT_BUFFERS && !P::ANALYSIS_ONLY {
+            let mut input_scratch_buffers = inner.input_scratch_buffers.write();
+            for ((input_slice, scratch_buffer), channel) in output_buffer
+                .as_raw_input_vec()
+                .iter_mut()
+                .zip(input_scratch_buffers.iter_mut())
+                .zip(self.main_buffers.iter())
+            {
+                let scratch_slice = &mut scratch_buffer[..num_samples];
+                scratch_slice.copy_from_slice(&channel[..num_samples]);
+                *input_slice = std::slice::from_raw_parts(scratch_slice.as_ptr(), num_samples);
+            }
+        }
+
+        // Analysis-only plugins don't get any output slices, they can only read the audio that's
+        // passed through unmodified
+        if P::ANALYSIS_ONLY {
+            for (input_slice, channel) in output_buffer
+                .as_raw_input_vec()
+                .iter_mut()
+                .zip(self.main_buffers.iter())
+            {
+                *input_slice = std::slice::from_raw_parts(channel.as_ptr(), num_samples);
+            }
+        }
+
+        // There are no sidechain inputs, so the auxiliary inputs are always silent
+        {
+            let mut aux_input_scratch_buffers = inner.aux_input_scratch_buffers.write();
+            for (channel_slices, scratch_buffers) in output_buffer
+                .as_raw_aux_input_vec()
+                .iter_mut()
+                .zip(aux_input_scratch_buffers.iter_mut())
+            {
+                for (channel_slice, scratch_buffer) in
+                    channel_slices.iter_mut().zip(scratch_buffers.iter_mut())
+                {
+                    let scratch_slice = &mut scratch_buffer[..num_samples];
+                    scratch_slice.fill(0.0);
+                    *channel_slice =
+                        std::slice::from_raw_parts(scratch_slice.as_ptr(), num_samples);
+                }
+            }
+        }
+
+        let plugin = &mut *inner.plugin.data_ptr();
+        // There is no host transport, so the plugin only gets to know the sample rate
+        let mut context = inner.make_process_context(Transport::new(self.sample_rate));
+        let status = plugin.process(&mut output_buffer, &mut context);
+        inner.last_process_status.store(status);
+        // Any events and automation points the plugin did not consume are meant for this block
+        // only
+        context.input_events_guard.clear();
+        context.input_automation_guard.clear();
+        drop(context);
+
+        // There is no MIDI output port yet, so the plugin's output events are discarded
+        if P::PRODUCES_MIDI {
+            inner.drain_output_events(num_samples as u32, |_| ());
+        }
+
+        inner.apply_activation_fade_in(&mut output_buffer, num_samples as u32);
+    }
+}
+
+/// The window the plugin's editor gets embedded in. The editor creates its own child window, so
+/// this doesn't need to do anything on its own.
+struct EditorWindow {
+    /// The handle returned by [crate::plugin::Editor::spawn()]. The editor is closed when this is
+    /// dropped.
+    _editor_handle: Box<dyn Any>,
+}
+
+impl WindowHandler for EditorWindow {
+    fn on_frame(&mut self, _window: &mut Window) {}
+
+    fn on_event(&mut self, _window: &mut Window, _event: Event) -> EventStatus {
+        EventStatus::Ignored
+    }
+}
+
+/// Export a `main()` function that runs the plugin as a standalone application, see
+/// [crate::wrapper::standalone]. This is meant to be used in a plugin crate's `src/main.rs`. The
+/// plugin can then be run with `cargo run`, passing `--help` for a list of options. This requires
+/// the `standalone` feature.
+#[macro_export]
+macro_rules! nih_export_standalone {
+    ($plugin_ty:ty) => {
+        fn main() {
+            ::nih_plug::wrapper::standalone::nih_export_standalone::<$plugin_ty>();
+        }
+    };
+}
@@ -0,0 +1,162 @@
+// nih-plug: plugins, but rewritten in Rust
+// Copyright (C) 2022 Robbert van der Helm
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Command line options for standalone plugins.
+
+/// The usage string printed for `--help` and when the command line options could not be parsed.
+pub const USAGE_STRING: &str = "Options:
+  --backend <name>        The audio backend to use, e.g. 'alsa', 'jack', or 'wasapi'
+  --input-device <name>   The name of the audio input device to use
+  --output-device <name>  The name of the audio output device to use
+  --no-input              Don't open an input device, the plugin will receive silence
+  --midi-input <name>     The name of the MIDI input port to connect to
+  --sample-rate <hz>      The sample rate to run the plugin at (default: 48000)
+  --period-size <frames>  The audio device's period size and the plugin's maximum buffer size
+                          (default: 512)
+  --help                  Print this message";
+
+/// Configuration for a standalone plugin. A plugin host would normally provide these settings.
+/// The system's default devices are used for all devices that aren't explicitly set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WrapperConfig {
+    /// The name of the audio backend to use. Matched case insensitively against CPAL's host names.
+    pub backend: Option<String>,
+    /// The name of the audio input device to use.
+    pub input_device: Option<String>,
+    /// The name of the audio output device to use.
+    pub output_device: Option<String>,
+    /// If set, no input device gets opened and the plugin's inputs only contain silence.
+    pub no_input: bool,
+    /// The name of the MIDI input port to connect to. If this is not set, then a virtual MIDI port
+    /// is created instead on platforms that support it.
+    pub midi_input: Option<String>,
+    /// The sample rate to open the audio devices with.
+    pub sample_rate: u32,
+    /// The period size to open the audio devices with. This is also used as the plugin's maximum
+    /// buffer size.
+    pub period_size: u32,
+}
+
+impl Default for WrapperConfig {
+    fn default() -> Self {
+        Self {
+            backend: None,
+            input_device: None,
+            output_device: None,
+            no_input: false,
+            midi_input: None,
+            sample_rate: 48000,
+            period_size: 512,
+        }
+    }
+}
+
+impl WrapperConfig {
+    /// Parse the command line options, excluding the program name. Options can be passed as either
+    /// `--option value` or `--option=value`. Returns an error message if the options could not be
+    /// parsed.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut config = Self::default();
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (option, inline_value) = match arg.split_once('=') {
+                Some((option, value)) => (option.to_owned(), Some(value.to_owned())),
+                None => (arg, None),
+            };
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("Missing value for '{option}'"))
+            };
+
+            match option.as_str() {
+                "--backend" => config.backend = Some(value()?),
+                "--input-device" => config.input_device = Some(value()?),
+                "--output-device" => config.output_device = Some(value()?),
+                "--no-input" => config.no_input = true,
+                "--midi-input" => config.midi_input = Some(value()?),
+                "--sample-rate" => config.sample_rate = parse_positive(&option, &value()?)?,
+                "--period-size" => config.period_size = parse_positive(&option, &value()?)?,
+                _ => return Err(format!("Unknown option '{option}'")),
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+/// Parse a positive integer passed to `option`.
+fn parse_positive(option: &str, value: &str) -> Result<u32, String> {
+    match value.parse() {
+        Ok(value) if value > 0 => Ok(value),
+        _ => Err(format!(
+            "'{value}' is not a valid value for '{option}', expected a positive integer"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<WrapperConfig, String> {
+        WrapperConfig::from_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn defaults() {
+        assert_eq!(parse(&[]), Ok(WrapperConfig::default()));
+    }
+
+    #[test]
+    fn options() {
+        let config = parse(&[
+            "--backend",
+            "jack",
+            "--output-device=Built-in Audio",
+            "--no-input",
+            "--midi-input",
+            "Keyboard",
+            "--sample-rate=44100",
+            "--period-size",
+            "128",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            config,
+            WrapperConfig {
+                backend: Some(String::from("jack")),
+                input_device: None,
+                output_device: Some(String::from("Built-in Audio")),
+                no_input: true,
+                midi_input: Some(String::from("Keyboard")),
+                sample_rate: 44100,
+                period_size: 128,
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_options() {
+        assert!(parse(&["--sample-rate"]).is_err());
+        assert!(parse(&["--sample-rate", "zero"]).is_err());
+        assert!(parse(&["--period-size=0"]).is_err());
+        assert!(parse(&["--volume", "11"]).is_err());
+    }
+}
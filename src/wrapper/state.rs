@@ -16,21 +16,34 @@
 
 //! Utilities for saving a [crate::plugin::Plugin]'s state.
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use crate::param::internals::StateFormat;
 
 /// A plain, unnormalized value for a parameter.
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
-pub(crate) enum ParamValue {
+pub enum ParamValue {
     F32(f32),
     I32(i32),
     Bool(bool),
 }
 
-/// A plugin's state so it can be restored at a later point.
+/// A plugin's state so it can be restored at a later point. Plugins get to modify older states in
+/// [crate::plugin::Plugin::migrate_state()].
 #[derive(Debug, Serialize, Deserialize)]
-pub(crate) struct State {
+pub struct State {
+    /// The plugin's [crate::plugin::Plugin::STATE_VERSION] at the time this state was saved. States
+    /// saved before this was stored are treated as version 1.
+    #[serde(default = "default_version")]
+    pub version: u32,
     /// The plugin's parameter values. These are stored unnormalized. This mean sthe old values will
     /// be recalled when when the parameter's range gets increased. Doing so may still mess with
     /// parmaeter automation though, depending on how the host impelments that.
@@ -39,7 +52,319 @@ pub(crate) struct State {
     /// on the [crate::param::internals::Params] struct that's annotated with `#[persist =
     /// "stable_name"]` will be persisted this way.
     ///
-    /// The individual fields are also serialized as JSON so they can safely be restored
-    /// independently of the other fields.
-    pub fields: HashMap<String, String>,
+    /// The individual fields are serialized separately in the state's [Self::format] so they can
+    /// safely be restored independently of the other fields. In JSON states these are stored as
+    /// JSON strings, and in binary states as raw bytes.
+    #[serde(with = "serialized_fields")]
+    pub fields: HashMap<String, Vec<u8>>,
+    /// The format this state is stored in. This is not part of the serialized state itself, it is
+    /// set when the state is deserialized and it determines how [Self::fields] are interpreted.
+    #[serde(skip)]
+    pub format: StateFormat,
+}
+
+/// The version for states that don't store a version.
+fn default_version() -> u32 {
+    1
+}
+
+/// (De)serialization for [State::fields]. JSON is human readable, so there the fields are stored as
+/// strings containing the fields' JSON, like they have always been. Binary formats store the raw
+/// bytes instead.
+mod serialized_fields {
+    use super::*;
+    use serde::ser::Error as _;
+
+    pub fn serialize<S: Serializer>(
+        fields: &HashMap<String, Vec<u8>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            let fields = fields
+                .iter()
+                .map(|(name, data)| {
+                    Ok((name, std::str::from_utf8(data).map_err(S::Error::custom)?))
+                })
+                .collect::<Result<HashMap<_, _>, S::Error>>()?;
+
+            fields.serialize(serializer)
+        } else {
+            fields.serialize(serializer)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<String, Vec<u8>>, D::Error> {
+        if deserializer.is_human_readable() {
+            let fields = HashMap::<String, String>::deserialize(deserializer)?;
+
+            Ok(fields
+                .into_iter()
+                .map(|(name, data)| (name, data.into_bytes()))
+                .collect())
+        } else {
+            HashMap::<String, Vec<u8>>::deserialize(deserializer)
+        }
+    }
+}
+
+/// Binary states start with this header, followed by a single byte containing the binary format's
+/// version. JSON states always start with a `{`, so the two can be told apart.
+const BINARY_STATE_MAGIC: &[u8] = b"NIH-plug";
+/// The version of the binary state format, stored after [BINARY_STATE_MAGIC].
+const BINARY_STATE_FORMAT_VERSION: u8 = 1;
+
+/// Serialize a plugin's state in the state's [State::format]. Every plugin format and the autosave
+/// files use this same representation, so a state saved by one wrapper can be loaded by another.
+pub(crate) fn serialize(state: &State) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    match state.format {
+        StateFormat::Json => Ok(serde_json::to_vec(state)?),
+        StateFormat::Binary => {
+            let mut serialized = BINARY_STATE_MAGIC.to_vec();
+            serialized.push(BINARY_STATE_FORMAT_VERSION);
+            bincode::serialize_into(&mut serialized, state)?;
+
+            Ok(serialized)
+        }
+    }
+}
+
+/// Deserialize a plugin's state previously serialized with [serialize()]. Both JSON and binary
+/// states are detected regardless of the plugin's [crate::plugin::Plugin::BINARY_STATE] setting,
+/// so existing states still load after switching formats.
+pub(crate) fn deserialize(serialized: &[u8]) -> Result<State, Box<dyn Error + Send + Sync>> {
+    match serialized.strip_prefix(BINARY_STATE_MAGIC) {
+        Some(binary) => match binary.split_first() {
+            Some((&BINARY_STATE_FORMAT_VERSION, data)) => {
+                let mut state: State = bincode::deserialize(data)?;
+                state.format = StateFormat::Binary;
+
+                Ok(state)
+            }
+            Some((version, _)) => {
+                Err(format!("Unsupported binary state format version {}", version).into())
+            }
+            None => Err("The binary state is missing its format version".into()),
+        },
+        None => {
+            let mut state: State = serde_json::from_slice(serialized)?;
+            state.format = StateFormat::Json;
+
+            Ok(state)
+        }
+    }
+}
+
+/// The extension used for autosave files. These contain a regular serialized state, so this does
+/// not say anything about the state's format.
+const AUTOSAVE_EXTENSION: &str = "state";
+
+/// Used to give every plugin instance within this process its own autosave file.
+static NEXT_AUTOSAVE_INSTANCE_ID: AtomicU32 = AtomicU32::new(0);
+
+/// The directory autosave files for a plugin are stored in. This is `nih-plug/<plugin
+/// name>/autosave` within the platform's configuration directory. Returns `None` if that directory
+/// cannot be determined.
+pub(crate) fn autosave_dir(plugin_name: &str) -> Option<PathBuf> {
+    let config_dir: Option<PathBuf> = {
+        cfg_if::cfg_if! {
+            if #[cfg(target_os = "windows")] {
+                std::env::var_os("APPDATA").map(PathBuf::from)
+            } else if #[cfg(target_os = "macos")] {
+                std::env::var_os("HOME")
+                    .map(|home| PathBuf::from(home).join("Library/Application Support"))
+            } else {
+                std::env::var_os("XDG_CONFIG_HOME")
+                    .map(PathBuf::from)
+                    .filter(|path| path.is_absolute())
+                    .or_else(|| {
+                        std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config"))
+                    })
+            }
+        }
+    };
+
+    // The plugin's name is used as a directory name, so it shouldn't contain any path separators
+    let plugin_dir_name: String = plugin_name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == ' ' || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    Some(
+        config_dir?
+            .join("nih-plug")
+            .join(plugin_dir_name)
+            .join("autosave"),
+    )
+}
+
+/// Get a new autosave file path for a plugin instance. The file name contains the current process
+/// ID so autosave files left behind by crashed sessions can be told apart from those written by
+/// other instances within this process.
+pub(crate) fn new_autosave_path(plugin_name: &str) -> Option<PathBuf> {
+    let instance_id = NEXT_AUTOSAVE_INSTANCE_ID.fetch_add(1, Ordering::Relaxed);
+
+    Some(autosave_dir(plugin_name)?.join(format!(
+        "{}-{}.{}",
+        process::id(),
+        instance_id,
+        AUTOSAVE_EXTENSION
+    )))
+}
+
+/// Find the most recent autosave file that was written by a process that's no longer running. If a
+/// session did not shut down cleanly, then this file contains the last autosaved state from that
+/// session. Files from this process and from other processes that are still running belong to
+/// plugin instances that are still alive, so those are never recovered.
+pub(crate) fn find_recovery_file(plugin_name: &str) -> Option<PathBuf> {
+    let own_pid = process::id();
+
+    fs::read_dir(autosave_dir(plugin_name)?)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let pid = autosave_file_pid(&entry.file_name().to_string_lossy());
+            matches!(pid, Some(pid) if pid != own_pid && !process_is_alive(pid))
+        })
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .max_by_key(|(modified, _)| *modified)
+        .map(|(_, path)| path)
+}
+
+/// Parse the ID of the process that wrote an autosave file from the file's name. See
+/// [new_autosave_path()].
+fn autosave_file_pid(file_name: &str) -> Option<u32> {
+    let (pid, _instance_id) = file_name
+        .strip_suffix(AUTOSAVE_EXTENSION)?
+        .strip_suffix('.')?
+        .split_once('-')?;
+
+    pid.parse().ok()
+}
+
+/// Check whether a process with this ID is still running. Process IDs can be reused, so this may
+/// also return `true` for an unrelated process. That only means that a recovery file is offered
+/// later.
+fn process_is_alive(pid: u32) -> bool {
+    cfg_if::cfg_if! {
+        if #[cfg(unix)] {
+            // Sending signal 0 only checks whether the process exists. `EPERM` means that the
+            // process exists, but that it belongs to another user.
+            unsafe { libc::kill(pid as libc::pid_t, 0) == 0 }
+                || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+        } else if #[cfg(windows)] {
+            use windows::Win32::Foundation::{CloseHandle, ERROR_ACCESS_DENIED};
+            use windows::Win32::System::Threading::{
+                GetExitCodeProcess, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION,
+            };
+
+            /// The exit code reported for processes that are still running.
+            const STILL_ACTIVE: u32 = 259;
+
+            unsafe {
+                let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid);
+                if handle.is_invalid() {
+                    // We may not be allowed to query processes running as another user
+                    return io::Error::last_os_error().raw_os_error()
+                        == Some(ERROR_ACCESS_DENIED.0 as i32);
+                }
+
+                let mut exit_code = 0;
+                let is_alive = GetExitCodeProcess(handle, &mut exit_code).as_bool()
+                    && exit_code == STILL_ACTIVE;
+                CloseHandle(handle);
+
+                is_alive
+            }
+        } else {
+            // Without a way to check this, it's safer to never recover state from other processes
+            let _ = pid;
+            true
+        }
+    }
+}
+
+/// Atomically write a plugin's state to an autosave file. The state is first written to a
+/// temporary file, so a crash during this function cannot leave behind a partially written
+/// autosave file.
+pub(crate) fn write_autosave(path: &Path, state: &State) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let serialized =
+        serialize(state).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    let temporary_path = path.with_extension(format!("{}.tmp", AUTOSAVE_EXTENSION));
+    fs::write(&temporary_path, serialized)?;
+    fs::rename(&temporary_path, path)
+}
+
+/// Read a plugin's state from an autosave file written by [write_autosave()].
+pub(crate) fn read_autosave(path: &Path) -> io::Result<State> {
+    let serialized = fs::read(path)?;
+
+    deserialize(&serialized).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn autosave_file_names() {
+        assert_eq!(autosave_file_pid("1234-0.state"), Some(1234));
+        assert_eq!(autosave_file_pid("1234-0.state.tmp"), None);
+        assert_eq!(autosave_file_pid("settings.state"), None);
+
+        // Autosave files from this process are never recovered
+        assert!(process_is_alive(process::id()));
+    }
+
+    #[test]
+    fn state_formats_round_trip() {
+        let impulse_response: Vec<f32> = (0..1024).map(|i| i as f32 / 1024.0).collect();
+        let new_state = |format| State {
+            version: 1,
+            params: HashMap::from([(String::from("gain"), ParamValue::F32(0.5))]),
+            fields: HashMap::from([(
+                String::from("impulse_response"),
+                crate::param::internals::serialize_field(&impulse_response, format).unwrap(),
+            )]),
+            format,
+        };
+
+        // JSON states store the fields as JSON strings, like states without a binary format did
+        let json_state = serialize(&new_state(StateFormat::Json)).unwrap();
+        assert!(json_state.starts_with(b"{"));
+        let value: serde_json::Value = serde_json::from_slice(&json_state).unwrap();
+        assert!(value["fields"]["impulse_response"].is_string());
+
+        // Binary states store the raw bincode data for the fields instead of encoding it again
+        let binary_state = serialize(&new_state(StateFormat::Binary)).unwrap();
+        assert!(binary_state.starts_with(BINARY_STATE_MAGIC));
+        assert!(binary_state.len() < impulse_response.len() * 5);
+
+        for (serialized, format) in [
+            (json_state, StateFormat::Json),
+            (binary_state, StateFormat::Binary),
+        ] {
+            let state = deserialize(&serialized).unwrap();
+            assert_eq!(state.format, format);
+            assert!(matches!(state.params["gain"], ParamValue::F32(gain) if gain == 0.5));
+
+            let restored: Vec<f32> = crate::param::internals::deserialize_field(
+                &state.fields["impulse_response"],
+                state.format,
+            )
+            .unwrap();
+            assert_eq!(restored, impulse_response);
+        }
+    }
 }
@@ -14,17 +14,34 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+use lazy_static::lazy_static;
 use std::cmp;
-use std::marker::PhantomData;
+use std::collections::VecDeque;
 use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
 use vst3_sys::vst::TChar;
 use widestring::U16CString;
 
+use crate::plugin::{NoteEvent, Plugin};
+use crate::util::ScopedFtz;
+
 #[cfg(all(debug_assertions, feature = "assert_process_allocs"))]
 #[global_allocator]
 static A: assert_no_alloc::AllocDisabler = assert_no_alloc::AllocDisabler;
 
+/// Right now the wrappers add their own bypass parameter. This is shared between all plugin
+/// formats so the bypass state is saved and restored the same way everywhere.
+pub const BYPASS_PARAM_ID: &str = "bypass";
+lazy_static! {
+    pub static ref BYPASS_PARAM_HASH: u32 = hash_param_id(BYPASS_PARAM_ID);
+}
+
 /// A Rabin fingerprint based string hash for parameter ID strings.
+///
+/// Hosts store these hashes in their project files to refer to parameters, for instance for
+/// automation, so this function must produce the same hash for the same ID across nih-plug
+/// versions, builds, and platforms. It only depends on the ID's UTF-8 bytes and never on anything
+/// like a randomly seeded hasher. Changing this function would break every saved project.
 pub fn hash_param_id(id: &str) -> u32 {
     let mut has_overflown = false;
     let mut hash: u32 = 0;
@@ -53,7 +70,8 @@ pub fn hash_param_id(id: &str) -> u32 {
 
 /// The equivalent of the `strlcpy()` C function. Copy `src` to `dest` as a null-terminated
 /// C-string. If `dest` does not have enough capacity, add a null terminator at the end to prevent
-/// buffer overflows.
+/// buffer overflows. The string is only ever truncated at a character boundary so `dest` always
+/// contains valid UTF-8.
 pub fn strlcpy(dest: &mut [c_char], src: &str) {
     if dest.is_empty() {
         return;
@@ -62,13 +80,17 @@ pub fn strlcpy(dest: &mut [c_char], src: &str) {
     let src_bytes: &[u8] = src.as_bytes();
     let src_bytes_signed: &[i8] = unsafe { &*(src_bytes as *const [u8] as *const [i8]) };
 
-    // Make sure there's always room for a null terminator
-    let copy_len = cmp::min(dest.len() - 1, src.len());
+    // Make sure there's always room for a null terminator, and don't split multibyte characters
+    let mut copy_len = cmp::min(dest.len() - 1, src.len());
+    while !src.is_char_boundary(copy_len) {
+        copy_len -= 1;
+    }
     dest[..copy_len].copy_from_slice(&src_bytes_signed[..copy_len]);
     dest[copy_len] = 0;
 }
 
-/// The same as [strlcpy()], but for VST3's fun UTF-16 strings instead.
+/// The same as [strlcpy()], but for VST3's fun UTF-16 strings instead. Surrogate pairs are never
+/// split up when the string gets truncated.
 pub fn u16strlcpy(dest: &mut [TChar], src: &str) {
     if dest.is_empty() {
         return;
@@ -85,19 +107,143 @@ pub fn u16strlcpy(dest: &mut [TChar], src: &str) {
     let src_utf16_chars_signed: &[TChar] =
         unsafe { &*(src_utf16_chars as *const [u16] as *const [TChar]) };
 
-    // Make sure there's always room for a null terminator
-    let copy_len = cmp::min(dest.len() - 1, src_utf16_chars_signed.len());
+    // Make sure there's always room for a null terminator. If the last code unit we'd copy is the
+    // first half of a surrogate pair, then we'll need to leave out the entire pair.
+    let mut copy_len = cmp::min(dest.len() - 1, src_utf16_chars.len());
+    if copy_len > 0
+        && copy_len < src_utf16_chars.len()
+        && (0xD800..=0xDBFF).contains(&src_utf16_chars[copy_len - 1])
+    {
+        copy_len -= 1;
+    }
     dest[..copy_len].copy_from_slice(&src_utf16_chars_signed[..copy_len]);
     dest[copy_len] = 0;
 }
 
-/// A wrapper around the entire process function, including the plugin wrapper parts. This sets up
-/// `assert_no_alloc` if needed, while also making sure that things like FTZ are set up correctly if
-/// the host has not already done so.
-pub fn process_wrapper<T, F: FnOnce() -> T>(f: F) -> T {
-    // Make sure FTZ is always enabled, even if the host doesn't do it for us
-    let _ftz_guard = ScopedFtz::enable();
+/// Get a slice for one of the host's input channels that stays intact while the plugin writes to
+/// its outputs. If the input channel shares memory with one of the output channels, then it's
+/// copied to `scratch_buffer` first. Null input channels are treated as silence. The scratch
+/// buffer is only resized, and thus allocated, if the host exceeds the maximum buffer size.
+///
+/// # Safety
+///
+/// `input_channel_ptr` must either be null or point to `num_samples` samples, and
+/// `output_channel_ptrs` must contain `num_output_channels` valid channel pointers. The returned
+/// slice is only valid until the scratch buffer gets resized or the host's buffers are freed.
+pub unsafe fn stable_input_slice<'a>(
+    input_channel_ptr: *const f32,
+    output_channel_ptrs: *const *const f32,
+    num_output_channels: usize,
+    scratch_buffer: &mut Vec<f32>,
+    num_samples: usize,
+) -> &'a [f32] {
+    let aliases_output = (0..num_output_channels).any(|output_channel_idx| {
+        *output_channel_ptrs.add(output_channel_idx) == input_channel_ptr
+    });
+    if input_channel_ptr.is_null() || aliases_output {
+        nih_debug_assert!(num_samples <= scratch_buffer.len());
+        if scratch_buffer.len() < num_samples {
+            scratch_buffer.resize(num_samples, 0.0);
+        }
+
+        let scratch_slice = &mut scratch_buffer[..num_samples];
+        if input_channel_ptr.is_null() {
+            scratch_slice.fill(0.0);
+        } else {
+            std::ptr::copy_nonoverlapping(
+                input_channel_ptr,
+                scratch_slice.as_mut_ptr(),
+                num_samples,
+            );
+        }
+
+        std::slice::from_raw_parts(scratch_slice.as_ptr(), scratch_slice.len())
+    } else {
+        std::slice::from_raw_parts(input_channel_ptr, num_samples)
+    }
+}
+
+/// The number of events and automation points the wrapper's event queues have room for. These
+/// queues are allocated up front and they are filled from the audio thread, so they should never
+/// grow beyond this. See [queue_event()].
+pub const EVENT_QUEUE_CAPACITY: usize = 512;
 
+/// Add an event or an automation point to one of the wrapper's event queues, unless the queue
+/// already contains [EVENT_QUEUE_CAPACITY] items. Growing the queue would allocate on the audio
+/// thread, so any items beyond that are dropped instead. This can happen when the host sends an
+/// unusual amount of events, or when several parameter flushes happen before the next block.
+/// Returns whether the item was added to the queue.
+pub fn queue_event<T>(queue: &mut VecDeque<T>, event: T) -> bool {
+    if queue.len() >= EVENT_QUEUE_CAPACITY {
+        nih_debug_assert_failure!("The event queue is full, dropping event");
+        false
+    } else {
+        queue.push_back(event);
+        true
+    }
+}
+
+/// Sort note events by their timing while keeping events with the same timing in their original
+/// order. This is an insertion sort since the events are almost always sorted already, and unlike
+/// the standard library's stable sort it doesn't allocate.
+pub fn sort_note_events(events: &mut VecDeque<NoteEvent>) {
+    let events = events.make_contiguous();
+    for event_idx in 1..events.len() {
+        let mut idx = event_idx;
+        while idx > 0 && events[idx - 1].timing() > events[idx].timing() {
+            events.swap(idx - 1, idx);
+            idx -= 1;
+        }
+    }
+}
+
+/// A wrapper around the entire process function, including the plugin wrapper parts. This combines
+/// [with_ftz()] and [with_alloc_assertions()]. FTZ and DAZ are enabled according to
+/// [Plugin::ENABLE_FTZ], regardless of whether the `assert_process_allocs` feature is enabled.
+/// Panics are caught with [catch_plugin_panic()] so they don't unwind into the host, in which case
+/// this returns `None`. The wrapper should then stop processing the plugin.
+pub fn process_wrapper<P: Plugin, T, F: FnOnce() -> T>(f: F) -> Option<T> {
+    catch_plugin_panic("processing audio", || {
+        with_ftz(P::ENABLE_FTZ, || with_alloc_assertions(f))
+    })
+}
+
+/// Run `f` and catch any panics instead of letting them unwind into the host, since unwinding
+/// across the FFI boundary is undefined behavior. If `f` panics, then the panic message gets logged
+/// together with `description`, which should describe what the plugin was doing at the time, and
+/// `None` is returned.
+pub fn catch_plugin_panic<T, F: FnOnce() -> T>(description: &str, f: F) -> Option<T> {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(result) => Some(result),
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("unknown panic");
+            nih_error!("The plugin panicked while {}: {}", description, message);
+
+            None
+        }
+    }
+}
+
+/// Run `f` with the CPU's Flush To Zero and Denormals Are Zero modes enabled if `enable` is set
+/// and the host has not already enabled them. The old modes are restored afterwards. If `enable`
+/// is not set, then the host's modes are left untouched.
+pub fn with_ftz<T, F: FnOnce() -> T>(enable: bool, f: F) -> T {
+    let _ftz_guard = if enable {
+        Some(ScopedFtz::enable())
+    } else {
+        None
+    };
+
+    f()
+}
+
+/// Run `f` while panicking on allocations. This only does something in debug builds with the
+/// `assert_process_allocs` feature enabled.
+pub fn with_alloc_assertions<T, F: FnOnce() -> T>(f: F) -> T {
     cfg_if::cfg_if! {
         if #[cfg(all(debug_assertions, feature = "assert_process_allocs"))] {
             assert_no_alloc::assert_no_alloc(f)
@@ -107,53 +253,181 @@ pub fn process_wrapper<T, F: FnOnce() -> T>(f: F) -> T {
     }
 }
 
-/// Enable the CPU's Flush To Zero flag while this object is in scope. If the flag was not already
-/// set, it will be restored to its old value when this gets dropped.
-struct ScopedFtz {
-    /// The old FTZ mode to restore to, if FTZ was not already set.
-    old_ftz_mode: Option<u32>,
-    /// We can't directly implement !Send and !Sync, but this will do the same thing. This object
-    /// affects the current thread's floating point registers, so it may only be dropped on the
-    /// current thread.
-    send_sync_marker: PhantomData<*const ()>,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Convert a null terminated C-string buffer back to a Rust string, panicking if it's not
+    /// valid UTF-8.
+    fn c_str_to_string(buffer: &[c_char]) -> String {
+        let bytes: Vec<u8> = buffer
+            .iter()
+            .take_while(|&&c| c != 0)
+            .map(|&c| c as u8)
+            .collect();
+
+        String::from_utf8(bytes).expect("Invalid UTF-8")
+    }
 
-impl ScopedFtz {
-    fn enable() -> Self {
-        cfg_if::cfg_if! {
-            if #[cfg(target_feature = "sse")] {
-                let mode = unsafe { std::arch::x86_64::_MM_GET_FLUSH_ZERO_MODE() };
-                if mode != std::arch::x86_64::_MM_FLUSH_ZERO_ON {
-                    unsafe { std::arch::x86_64::_MM_SET_FLUSH_ZERO_MODE(std::arch::x86_64::_MM_FLUSH_ZERO_ON) };
-
-                    Self {
-                        old_ftz_mode: Some(mode),
-                        send_sync_marker: PhantomData,
-                    }
-                } else {
-                    Self {
-                        old_ftz_mode: None,
-                        send_sync_marker: PhantomData,
-                    }
-                }
-            } else {
-                Self {
-                    old_ftz_mode: None,
-                    send_sync_marker: PhantomData,
-                }
-            }
+    /// The same as [c_str_to_string()], but for UTF-16 buffers.
+    fn u16_str_to_string(buffer: &[TChar]) -> String {
+        let chars: Vec<u16> = buffer
+            .iter()
+            .take_while(|&&c| c != 0)
+            .map(|&c| c as u16)
+            .collect();
+
+        String::from_utf16(&chars).expect("Invalid UTF-16")
+    }
+
+    #[test]
+    fn hash_param_id_is_stable() {
+        // These values are stored in hosts' project files and must never change
+        assert_eq!(hash_param_id(""), 0);
+        assert_eq!(hash_param_id("gain"), 3165055);
+        assert_eq!(hash_param_id("bypass"), 773352680);
+    }
+
+    #[test]
+    fn hash_param_id_clears_sign_bit() {
+        assert_eq!(hash_param_id("stereo_width"), 1751535295);
+        assert_eq!(hash_param_id("stereo_width") & (1 << 31), 0);
+    }
+
+    #[test]
+    fn strlcpy_fits() {
+        let mut buffer = [1 as c_char; 17];
+        strlcpy(&mut buffer, "Café Compressor");
+        assert_eq!(c_str_to_string(&buffer), "Café Compressor");
+    }
+
+    #[test]
+    fn strlcpy_truncates_ascii() {
+        let mut buffer = [1 as c_char; 4];
+        strlcpy(&mut buffer, "Compressor");
+        assert_eq!(c_str_to_string(&buffer), "Com");
+    }
+
+    #[test]
+    fn strlcpy_truncates_accented() {
+        // The 'é' takes up two bytes, and only the first one would fit
+        let mut buffer = [1 as c_char; 5];
+        strlcpy(&mut buffer, "Café Compressor");
+        assert_eq!(c_str_to_string(&buffer), "Caf");
+    }
+
+    #[test]
+    fn strlcpy_truncates_emoji() {
+        // The emoji takes up four bytes
+        for len in 3..=6 {
+            let mut buffer = [1 as c_char; 8];
+            strlcpy(&mut buffer[..len], "ab🎹");
+            assert_eq!(c_str_to_string(&buffer), "ab");
         }
+
+        let mut buffer = [1 as c_char; 7];
+        strlcpy(&mut buffer, "ab🎹");
+        assert_eq!(c_str_to_string(&buffer), "ab🎹");
+    }
+
+    #[test]
+    fn u16strlcpy_fits() {
+        let mut buffer = [1 as TChar; 16];
+        u16strlcpy(&mut buffer, "Café Compressor");
+        assert_eq!(u16_str_to_string(&buffer), "Café Compressor");
     }
-}
 
-impl Drop for ScopedFtz {
-    fn drop(&mut self) {
-        if let Some(mode) = self.old_ftz_mode {
-            cfg_if::cfg_if! {
-                if #[cfg(target_feature = "sse")] {
-                    unsafe { std::arch::x86_64::_MM_SET_FLUSH_ZERO_MODE(mode) };
-                }
-            };
+    #[test]
+    fn u16strlcpy_truncates_accented() {
+        // 'é' is a single UTF-16 code unit
+        let mut buffer = [1 as TChar; 5];
+        u16strlcpy(&mut buffer, "Café Compressor");
+        assert_eq!(u16_str_to_string(&buffer), "Café");
+    }
+
+    #[test]
+    fn u16strlcpy_truncates_emoji() {
+        // The emoji is encoded as a surrogate pair, so it takes up two code units
+        let mut buffer = [1 as TChar; 4];
+        u16strlcpy(&mut buffer, "ab🎹");
+        assert_eq!(u16_str_to_string(&buffer), "ab");
+
+        let mut buffer = [1 as TChar; 5];
+        u16strlcpy(&mut buffer, "ab🎹");
+        assert_eq!(u16_str_to_string(&buffer), "ab🎹");
+    }
+
+    #[test]
+    #[cfg(all(target_arch = "x86_64", target_feature = "sse2"))]
+    fn ftz_is_scoped() {
+        use crate::util::{get_mxcsr, MXCSR_FTZ_DAZ};
+
+        let modes = || unsafe { get_mxcsr() } & MXCSR_FTZ_DAZ;
+        let original_modes = modes();
+
+        with_ftz(true, || assert_eq!(modes(), MXCSR_FTZ_DAZ));
+        assert_eq!(modes(), original_modes);
+
+        // When disabled the current modes are left as is, both inside and outside of the closure
+        with_ftz(false, || assert_eq!(modes(), original_modes));
+        assert_eq!(modes(), original_modes);
+        with_ftz(true, || {
+            with_ftz(false, || assert_eq!(modes(), MXCSR_FTZ_DAZ))
+        });
+        assert_eq!(modes(), original_modes);
+    }
+
+    #[test]
+    fn stable_input_slices() {
+        unsafe {
+            let mut output = [1.0f32, 2.0, 3.0];
+            let separate_input = [4.0f32, 5.0, 6.0];
+            let output_ptrs = [output.as_mut_ptr() as *const f32];
+            let mut scratch_buffer = vec![0.0; 3];
+
+            // Inputs that don't alias the outputs are used directly
+            let slice = stable_input_slice(
+                separate_input.as_ptr(),
+                output_ptrs.as_ptr(),
+                1,
+                &mut scratch_buffer,
+                3,
+            );
+            assert_eq!(slice.as_ptr(), separate_input.as_ptr());
+
+            // In place inputs are copied so they survive the plugin writing to its outputs
+            let slice = stable_input_slice(
+                output_ptrs[0],
+                output_ptrs.as_ptr(),
+                1,
+                &mut scratch_buffer,
+                3,
+            );
+            output.fill(0.0);
+            assert_eq!(slice, [1.0, 2.0, 3.0]);
+
+            // And missing inputs are silent
+            let slice = stable_input_slice(
+                std::ptr::null(),
+                output_ptrs.as_ptr(),
+                1,
+                &mut scratch_buffer,
+                3,
+            );
+            assert_eq!(slice, [0.0, 0.0, 0.0]);
         }
     }
+
+    #[test]
+    fn event_queues_dont_grow() {
+        let mut queue = VecDeque::with_capacity(EVENT_QUEUE_CAPACITY);
+        for i in 0..EVENT_QUEUE_CAPACITY {
+            assert!(queue_event(&mut queue, i));
+        }
+
+        // Events that don't fit anymore are dropped instead of reallocating the queue
+        assert!(!queue_event(&mut queue, EVENT_QUEUE_CAPACITY));
+        assert_eq!(queue.len(), EVENT_QUEUE_CAPACITY);
+        assert_eq!(queue.back(), Some(&(EVENT_QUEUE_CAPACITY - 1)));
+    }
 }
@@ -15,9 +15,7 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use std::cmp;
-use std::collections::HashMap;
 use std::ffi::c_void;
-use std::marker::PhantomData;
 use std::mem::{self, MaybeUninit};
 use std::ptr;
 use std::sync::atomic::Ordering;
@@ -26,27 +24,40 @@ use vst3_sys::base::{kInvalidArgument, kNoInterface, kResultFalse, kResultOk, tr
 use vst3_sys::base::{IBStream, IPluginBase, IPluginFactory, IPluginFactory2, IPluginFactory3};
 use vst3_sys::utils::SharedVstPtr;
 use vst3_sys::vst::{
-    IAudioProcessor, IComponent, IEditController, IEventList, IParamValueQueue, IParameterChanges,
-    TChar,
+    IAudioProcessor, IComponent, IEditController, IEventList, IMidiMapping,
+    INoteExpressionController, IParamValueQueue, IParameterChanges, IUnitInfo,
+    NoteExpressionTypeInfo, NoteExpressionValueDescription, TChar,
 };
 use vst3_sys::VST3;
 use widestring::U16CStr;
 
-mod context;
-mod inner;
+mod interfaces;
+pub(crate) mod param_units;
 #[macro_use]
-mod util;
-mod view;
-
-use self::inner::WrapperInner;
-use self::util::{VstPtr, BYPASS_PARAM_HASH};
+pub(crate) mod util;
+pub(crate) mod view;
+
+use self::interfaces::{IPluginCompatibility, IProcessContextRequirements};
+use self::util::{
+    clear_outputs, midi_cc_default_normalized_value, midi_cc_param_name,
+    note_expression_string_to_value, note_expression_value_to_string, process_context_requirements,
+    sample_precision_from_vst3, subcategories_string, transport_from_vst3, SinglePrecisionBuffers,
+    VstPtr, K_DISTRIBUTABLE, K_SIMPLE_MODE_SUPPORTED, VST3_MIDI_CCS, VST3_MIDI_CHANNELS,
+    VST3_MIDI_PARAMS_END, VST3_MIDI_PARAMS_START, VST3_NOTE_EXPRESSIONS,
+    VST3_NOTE_EXPRESSION_BRIGHTNESS_TYPE_ID, VST3_NOTE_EXPRESSION_TUNING_TYPE_ID,
+};
 use self::view::WrapperView;
+use crate::context::AutomationPoint;
 use crate::param::internals::ParamPtr;
-use crate::param::range::Range;
-use crate::param::Param;
-use crate::plugin::{BufferConfig, BusConfig, NoteEvent, Plugin, ProcessStatus, Vst3Plugin};
-use crate::wrapper::state::{ParamValue, State};
-use crate::wrapper::util::{process_wrapper, strlcpy, u16strlcpy};
+use crate::plugin::{
+    BufferConfig, BusConfig, NoteEvent, Plugin, ProcessStatus, SamplePrecision, Vst3Plugin,
+};
+use crate::wrapper::inner::WrapperInner;
+use crate::wrapper::state;
+use crate::wrapper::util::{
+    process_wrapper, queue_event, sort_note_events, stable_input_slice, strlcpy, u16strlcpy,
+    BYPASS_PARAM_HASH,
+};
 
 // Alias needed for the VST3 attribute macro
 use vst3_sys as vst3_com;
@@ -57,7 +68,15 @@ pub use vst3_sys::sys::GUID;
 /// The VST3 SDK version this is roughtly based on.
 const VST3_SDK_VERSION: &str = "VST 3.6.14";
 
-#[VST3(implements(IComponent, IEditController, IAudioProcessor))]
+#[VST3(implements(
+    IComponent,
+    IEditController,
+    IAudioProcessor,
+    IProcessContextRequirements,
+    IUnitInfo,
+    IMidiMapping,
+    INoteExpressionController
+))]
 struct Wrapper<P: Plugin> {
     inner: Arc<WrapperInner<P>>,
 }
@@ -66,6 +85,16 @@ impl<P: Plugin> Wrapper<P> {
     pub fn new() -> Box<Self> {
         Self::allocate(WrapperInner::new())
     }
+
+    /// Whether the wrapper can process audio at this precision. Double precision is only ever
+    /// offered to plugins that prefer it. The plugin still processes single precision buffers in
+    /// that case, see [SinglePrecisionBuffers].
+    fn supports_sample_precision(sample_precision: SamplePrecision) -> bool {
+        match sample_precision {
+            SamplePrecision::Single => true,
+            SamplePrecision::Double => P::PREFERRED_SAMPLE_PRECISION == SamplePrecision::Double,
+        }
+    }
 }
 
 impl<P: Plugin> IPluginBase for Wrapper<P> {
@@ -95,8 +124,14 @@ impl<P: Plugin> IComponent for Wrapper<P> {
         type_: vst3_sys::vst::MediaType,
         dir: vst3_sys::vst::BusDirection,
     ) -> i32 {
-        // All plugins currently only have a single input and a single output bus
+        // All plugins currently have a single main input and output bus, with optional auxiliary
+        // input busses after the main input bus
         match type_ {
+            x if x == vst3_sys::vst::MediaTypes::kAudio as i32
+                && dir == vst3_sys::vst::BusDirections::kInput as i32 =>
+            {
+                1 + P::AUX_INPUT_CHANNELS.len() as i32
+            }
             x if x == vst3_sys::vst::MediaTypes::kAudio as i32 => 1,
             x if x == vst3_sys::vst::MediaTypes::kEvent as i32
                 && dir == vst3_sys::vst::BusDirections::kInput as i32
@@ -104,6 +139,12 @@ impl<P: Plugin> IComponent for Wrapper<P> {
             {
                 1
             }
+            x if x == vst3_sys::vst::MediaTypes::kEvent as i32
+                && dir == vst3_sys::vst::BusDirections::kOutput as i32
+                && P::PRODUCES_MIDI =>
+            {
+                1
+            }
             _ => 0,
         }
     }
@@ -129,7 +170,7 @@ impl<P: Plugin> IComponent for Wrapper<P> {
                     (d, 0) if d == vst3_sys::vst::BusDirections::kInput as i32 => {
                         info.direction = vst3_sys::vst::BusDirections::kInput as i32;
                         info.channel_count =
-                            self.inner.current_bus_config.load().num_input_channels as i32;
+                            self.inner.current_bus_config.read().num_input_channels as i32;
                         u16strlcpy(&mut info.name, "Input");
 
                         kResultOk
@@ -137,11 +178,30 @@ impl<P: Plugin> IComponent for Wrapper<P> {
                     (d, 0) if d == vst3_sys::vst::BusDirections::kOutput as i32 => {
                         info.direction = vst3_sys::vst::BusDirections::kOutput as i32;
                         info.channel_count =
-                            self.inner.current_bus_config.load().num_output_channels as i32;
+                            self.inner.current_bus_config.read().num_output_channels as i32;
                         u16strlcpy(&mut info.name, "Output");
 
                         kResultOk
                     }
+                    (d, n)
+                        if d == vst3_sys::vst::BusDirections::kInput as i32
+                            && n > 0
+                            && n as usize <= P::AUX_INPUT_CHANNELS.len() =>
+                    {
+                        // Hosts should not connect these busses automatically
+                        info.direction = vst3_sys::vst::BusDirections::kInput as i32;
+                        info.channel_count = self.inner.current_bus_config.read().aux_input_channels
+                            [n as usize - 1] as i32;
+                        info.bus_type = vst3_sys::vst::BusTypes::kAux as i32;
+                        info.flags = 0;
+                        if P::AUX_INPUT_CHANNELS.len() == 1 {
+                            u16strlcpy(&mut info.name, "Sidechain Input");
+                        } else {
+                            u16strlcpy(&mut info.name, &format!("Sidechain Input {}", n));
+                        }
+
+                        kResultOk
+                    }
                     _ => kInvalidArgument,
                 }
             }
@@ -161,6 +221,22 @@ impl<P: Plugin> IComponent for Wrapper<P> {
                 info.flags = vst3_sys::vst::BusFlags::kDefaultActive as u32;
                 kResultOk
             }
+            (t, d, 0)
+                if t == vst3_sys::vst::MediaTypes::kEvent as i32
+                    && d == vst3_sys::vst::BusDirections::kOutput as i32
+                    && P::PRODUCES_MIDI =>
+            {
+                *info = mem::zeroed();
+
+                let info = &mut *info;
+                info.media_type = vst3_sys::vst::MediaTypes::kEvent as i32;
+                info.direction = vst3_sys::vst::BusDirections::kOutput as i32;
+                info.channel_count = 16;
+                u16strlcpy(&mut info.name, "MIDI Output");
+                info.bus_type = vst3_sys::vst::BusTypes::kMain as i32;
+                info.flags = vst3_sys::vst::BusFlags::kDefaultActive as u32;
+                kResultOk
+            }
             _ => kInvalidArgument,
         }
     }
@@ -198,6 +274,14 @@ impl<P: Plugin> IComponent for Wrapper<P> {
         // We don't need any special handling here
         match (type_, dir, index) {
             (t, _, 0) if t == vst3_sys::vst::MediaTypes::kAudio as i32 => kResultOk,
+            (t, d, n)
+                if t == vst3_sys::vst::MediaTypes::kAudio as i32
+                    && d == vst3_sys::vst::BusDirections::kInput as i32
+                    && n > 0
+                    && n as usize <= P::AUX_INPUT_CHANNELS.len() =>
+            {
+                kResultOk
+            }
             (t, d, 0)
                 if t == vst3_sys::vst::MediaTypes::kEvent as i32
                     && d == vst3_sys::vst::BusDirections::kInput as i32
@@ -205,6 +289,13 @@ impl<P: Plugin> IComponent for Wrapper<P> {
             {
                 kResultOk
             }
+            (t, d, 0)
+                if t == vst3_sys::vst::MediaTypes::kEvent as i32
+                    && d == vst3_sys::vst::BusDirections::kOutput as i32
+                    && P::PRODUCES_MIDI =>
+            {
+                kResultOk
+            }
             _ => kInvalidArgument,
         }
     }
@@ -250,7 +341,7 @@ impl<P: Plugin> IComponent for Wrapper<P> {
             return kResultFalse;
         }
 
-        let state: State = match serde_json::from_slice(&read_buffer) {
+        let state = match state::deserialize(&read_buffer) {
             Ok(s) => s,
             Err(err) => {
                 nih_debug_assert_failure!("Error while deserializing state: {}", err);
@@ -258,77 +349,11 @@ impl<P: Plugin> IComponent for Wrapper<P> {
             }
         };
 
-        let sample_rate = self
-            .inner
-            .current_buffer_config
-            .load()
-            .map(|c| c.sample_rate);
-        for (param_id_str, param_value) in state.params {
-            // Handle the bypass parameter separately
-            if param_id_str == util::BYPASS_PARAM_ID {
-                match param_value {
-                    ParamValue::Bool(b) => self.inner.bypass_state.store(b, Ordering::SeqCst),
-                    _ => nih_debug_assert_failure!(
-                        "Invalid serialized value {:?} for parameter \"{}\"",
-                        param_value,
-                        param_id_str,
-                    ),
-                };
-                continue;
-            }
-
-            let param_ptr = match self
-                .inner
-                .param_id_to_hash
-                .get(param_id_str.as_str())
-                .and_then(|hash| self.inner.param_by_hash.get(hash))
-            {
-                Some(ptr) => ptr,
-                None => {
-                    nih_debug_assert_failure!("Unknown parameter: {}", param_id_str);
-                    continue;
-                }
-            };
-
-            match (param_ptr, param_value) {
-                (ParamPtr::FloatParam(p), ParamValue::F32(v)) => (**p).set_plain_value(v),
-                (ParamPtr::IntParam(p), ParamValue::I32(v)) => (**p).set_plain_value(v),
-                (ParamPtr::BoolParam(p), ParamValue::Bool(v)) => (**p).set_plain_value(v),
-                (param_ptr, param_value) => {
-                    nih_debug_assert_failure!(
-                        "Invalid serialized value {:?} for parameter \"{}\" ({:?})",
-                        param_value,
-                        param_id_str,
-                        param_ptr,
-                    );
-                }
-            }
-
-            // Make sure everything starts out in sync
-            if let Some(sample_rate) = sample_rate {
-                param_ptr.update_smoother(sample_rate, true);
-            }
-        }
-
-        // The plugin can also persist arbitrary fields alongside its parameters. This is useful for
-        // storing things like sample data.
-        self.inner
-            .plugin
-            .read()
-            .params()
-            .deserialize_fields(&state.fields);
-
-        // Reinitialize the plugin after loading state so it can respond to the new parmaeters
-        let bus_config = self.inner.current_bus_config.load();
-        if let Some(buffer_config) = self.inner.current_buffer_config.load() {
-            self.inner.plugin.write().initialize(
-                &bus_config,
-                &buffer_config,
-                &mut self.inner.make_process_context(),
-            );
+        if self.inner.set_state_object(state) {
+            kResultOk
+        } else {
+            kResultFalse
         }
-
-        kResultOk
     }
 
     unsafe fn get_state(&self, state: SharedVstPtr<dyn IBStream>) -> tresult {
@@ -336,43 +361,8 @@ impl<P: Plugin> IComponent for Wrapper<P> {
 
         let state = state.upgrade().unwrap();
 
-        // We'll serialize parmaeter values as a simple `string_param_id: display_value` map.
-        let mut params: HashMap<_, _> = self
-            .inner
-            .param_id_to_hash
-            .iter()
-            .filter_map(|(param_id_str, hash)| {
-                let param_ptr = self.inner.param_by_hash.get(hash)?;
-                Some((param_id_str, param_ptr))
-            })
-            .map(|(&param_id_str, &param_ptr)| match param_ptr {
-                ParamPtr::FloatParam(p) => (
-                    param_id_str.to_string(),
-                    ParamValue::F32((*p).plain_value()),
-                ),
-                ParamPtr::IntParam(p) => (
-                    param_id_str.to_string(),
-                    ParamValue::I32((*p).plain_value()),
-                ),
-                ParamPtr::BoolParam(p) => (
-                    param_id_str.to_string(),
-                    ParamValue::Bool((*p).plain_value()),
-                ),
-            })
-            .collect();
-
-        // Don't forget about the bypass parameter
-        params.insert(
-            util::BYPASS_PARAM_ID.to_string(),
-            ParamValue::Bool(self.inner.bypass_state.load(Ordering::SeqCst)),
-        );
-
-        // The plugin can also persist arbitrary fields alongside its parameters. This is useful for
-        // storing things like sample data.
-        let fields = self.inner.plugin.read().params().serialize_fields();
-
-        let plugin_state = State { params, fields };
-        match serde_json::to_vec(&plugin_state) {
+        let plugin_state = self.inner.get_state_object();
+        match state::serialize(&plugin_state) {
             Ok(serialized) => {
                 let mut num_bytes_written = 0;
                 let result = state.write(
@@ -413,8 +403,8 @@ impl<P: Plugin> IEditController for Wrapper<P> {
 
     unsafe fn get_parameter_count(&self) -> i32 {
         // NOTE: We add a bypass parameter ourselves on index `self.param_ids.len()`, so these
-        //       indices are all off by one
-        self.inner.param_hashes.len() as i32 + 1
+        //       indices are all off by one. The hidden MIDI controller parameters come after that.
+        self.inner.param_hashes.len() as i32 + 1 + self.inner.midi_cc_values.len() as i32
     }
 
     unsafe fn get_parameter_info(
@@ -424,8 +414,9 @@ impl<P: Plugin> IEditController for Wrapper<P> {
     ) -> tresult {
         check_null_ptr!(info);
 
-        // Parameter index `self.param_ids.len()` is our own bypass parameter
-        if param_index < 0 || param_index > self.inner.param_hashes.len() as i32 {
+        // Parameter index `self.param_ids.len()` is our own bypass parameter, and the indices after
+        // that belong to the MIDI controller parameters for `IMidiMapping`
+        if param_index < 0 || param_index >= self.get_parameter_count() {
             return kInvalidArgument;
         }
 
@@ -442,6 +433,21 @@ impl<P: Plugin> IEditController for Wrapper<P> {
             info.unit_id = vst3_sys::vst::kRootUnitId;
             info.flags = vst3_sys::vst::ParameterFlags::kCanAutomate as i32
                 | vst3_sys::vst::ParameterFlags::kIsBypass as i32;
+        } else if param_index > self.inner.param_hashes.len() as i32 {
+            // The host needs to know about these parameters to be able to automate the MIDI CCs
+            // mapped to them, but they should not show up in its parameter lists
+            let midi_param_idx = (param_index - self.inner.param_hashes.len() as i32 - 1) as u32;
+            let name = midi_cc_param_name(midi_param_idx);
+
+            info.id = VST3_MIDI_PARAMS_START + midi_param_idx;
+            u16strlcpy(&mut info.title, &name);
+            u16strlcpy(&mut info.short_title, &name);
+            u16strlcpy(&mut info.units, "");
+            info.step_count = 0;
+            info.default_normalized_value = midi_cc_default_normalized_value(midi_param_idx) as f64;
+            info.unit_id = vst3_sys::vst::kRootUnitId;
+            info.flags = vst3_sys::vst::ParameterFlags::kCanAutomate as i32
+                | vst3_sys::vst::ParameterFlags::kIsHidden as i32;
         } else {
             let param_hash = &self.inner.param_hashes[param_index as usize];
             let default_value = &self.inner.param_defaults_normalized[param_index as usize];
@@ -451,18 +457,18 @@ impl<P: Plugin> IEditController for Wrapper<P> {
             u16strlcpy(&mut info.title, param_ptr.name());
             u16strlcpy(&mut info.short_title, param_ptr.name());
             u16strlcpy(&mut info.units, param_ptr.unit());
-            info.step_count = match param_ptr {
-                ParamPtr::FloatParam(_) => 0,
-                ParamPtr::IntParam(p) => match (**p).range {
-                    Range::Linear { min, max } => max - min,
-                    Range::Skewed { min, max, .. } => max - min,
-                    Range::SymmetricalSkewed { min, max, .. } => max - min,
-                },
-                ParamPtr::BoolParam(_) => 1,
-            };
+            info.step_count = param_ptr.step_count();
             info.default_normalized_value = *default_value as f64;
-            info.unit_id = vst3_sys::vst::kRootUnitId;
-            info.flags = vst3_sys::vst::ParameterFlags::kCanAutomate as i32;
+            info.unit_id = self.inner.param_units.param_unit_id(*param_hash);
+            info.flags = if param_ptr.is_read_only() {
+                vst3_sys::vst::ParameterFlags::kIsReadOnly as i32
+            } else {
+                vst3_sys::vst::ParameterFlags::kCanAutomate as i32
+            };
+            // Hosts can show these parameters as a dropdown containing all of the values
+            if self.inner.param_value_strings.contains_key(param_hash) {
+                info.flags |= vst3_sys::vst::ParameterFlags::kIsList as i32;
+            }
         }
 
         kResultOk
@@ -486,6 +492,12 @@ impl<P: Plugin> IEditController for Wrapper<P> {
                 u16strlcpy(dest, "Enabled")
             }
 
+            kResultOk
+        } else if let Some(value_strings) = self.inner.param_value_strings.get(&id) {
+            let step =
+                (value_normalized.clamp(0.0, 1.0) * (value_strings.len() - 1) as f64).round();
+            u16strlcpy(dest, &value_strings[step as usize]);
+
             kResultOk
         } else if let Some(param_ptr) = self.inner.param_by_hash.get(&id) {
             u16strlcpy(
@@ -493,6 +505,10 @@ impl<P: Plugin> IEditController for Wrapper<P> {
                 &param_ptr.normalized_value_to_string(value_normalized as f32, false),
             );
 
+            kResultOk
+        } else if self.inner.midi_cc_param_value(id).is_some() {
+            u16strlcpy(dest, &format!("{:.3}", value_normalized.clamp(0.0, 1.0)));
+
             kResultOk
         } else {
             kInvalidArgument
@@ -539,6 +555,8 @@ impl<P: Plugin> IEditController for Wrapper<P> {
             value_normalized
         } else if let Some(param_ptr) = self.inner.param_by_hash.get(&id) {
             param_ptr.preview_plain(value_normalized as f32) as f64
+        } else if self.inner.midi_cc_param_value(id).is_some() {
+            value_normalized.clamp(0.0, 1.0)
         } else {
             0.5
         }
@@ -549,6 +567,8 @@ impl<P: Plugin> IEditController for Wrapper<P> {
             plain_value.clamp(0.0, 1.0)
         } else if let Some(param_ptr) = self.inner.param_by_hash.get(&id) {
             param_ptr.preview_normalized(plain_value as f32) as f64
+        } else if self.inner.midi_cc_param_value(id).is_some() {
+            plain_value.clamp(0.0, 1.0)
         } else {
             0.5
         }
@@ -563,6 +583,8 @@ impl<P: Plugin> IEditController for Wrapper<P> {
             }
         } else if let Some(param_ptr) = self.inner.param_by_hash.get(&id) {
             param_ptr.normalized_value() as f64
+        } else if let Some(value) = self.inner.midi_cc_param_value(id) {
+            value.load() as f64
         } else {
             0.5
         }
@@ -575,13 +597,24 @@ impl<P: Plugin> IEditController for Wrapper<P> {
             return kResultOk;
         }
 
+        // There's no way to send note events to the plugin outside of the process function, so
+        // changes to the MIDI controller parameters only update their values here
+        if let Some(midi_cc_value) = self.inner.midi_cc_param_value(id) {
+            midi_cc_value.store((value as f32).clamp(0.0, 1.0));
+            return kResultOk;
+        }
+
         let sample_rate = self
             .inner
             .current_buffer_config
             .load()
             .map(|c| c.sample_rate);
-        self.inner
-            .set_normalized_value_by_hash(id, value as f32, sample_rate)
+        self.inner.set_normalized_value_by_hash(
+            id,
+            value as f32,
+            sample_rate,
+            self.inner.host_change_source(id),
+        )
     }
 
     unsafe fn set_component_handler(
@@ -590,20 +623,259 @@ impl<P: Plugin> IEditController for Wrapper<P> {
     ) -> tresult {
         *self.inner.component_handler.write() = handler.upgrade().map(VstPtr::from);
 
+        // The editor may have already been used before the host set the component handler
+        self.inner.flush_pending_edits();
+
         kResultOk
     }
 
     unsafe fn create_view(&self, _name: vst3_sys::base::FIDString) -> *mut c_void {
         // Without specialization this is the least redundant way to check if the plugin has an
-        // editor. The default implementation returns a None here.
+        // editor. The default implementation returns a None here. The editor may also be
+        // temporarily unavailable, in which case we'll also tell the host there is no editor.
         match &self.inner.editor {
-            Some(editor) => Box::into_raw(WrapperView::new(self.inner.clone(), editor.clone()))
-                as *mut vst3_sys::c_void,
-            None => ptr::null_mut(),
+            Some(editor) if editor.is_available() => {
+                Box::into_raw(WrapperView::new(self.inner.clone(), editor.clone()))
+                    as *mut vst3_sys::c_void
+            }
+            _ => ptr::null_mut(),
+        }
+    }
+}
+
+impl<P: Plugin> IUnitInfo for Wrapper<P> {
+    unsafe fn get_unit_count(&self) -> i32 {
+        // The root unit is always there, the parameter groups are added on top of that
+        self.inner.param_units.len() as i32 + 1
+    }
+
+    unsafe fn get_unit_info(&self, unit_index: i32, info: *mut vst3_sys::vst::UnitInfo) -> tresult {
+        check_null_ptr!(info);
+
+        if unit_index < 0 || unit_index > self.inner.param_units.len() as i32 {
+            return kInvalidArgument;
+        }
+
+        *info = mem::zeroed();
+
+        let info = &mut *info;
+        info.program_list_id = vst3_sys::vst::kNoProgramListId;
+        if unit_index == 0 {
+            info.id = vst3_sys::vst::kRootUnitId;
+            info.parent_unit_id = vst3_sys::vst::kNoParentUnitId;
+            u16strlcpy(&mut info.name, "Root");
+        } else {
+            // This was checked above
+            let unit = self.inner.param_units.get(unit_index as usize - 1).unwrap();
+            info.id = unit.id;
+            info.parent_unit_id = unit.parent_id;
+            u16strlcpy(&mut info.name, &unit.name);
+        }
+
+        kResultOk
+    }
+
+    // We don't have any program lists, so the rest of this interface only needs to report that
+
+    unsafe fn get_program_list_count(&self) -> i32 {
+        0
+    }
+
+    unsafe fn get_program_list_info(
+        &self,
+        _list_index: i32,
+        _info: *mut vst3_sys::vst::ProgramListInfo,
+    ) -> tresult {
+        kInvalidArgument
+    }
+
+    unsafe fn get_program_name(
+        &self,
+        _list_id: i32,
+        _program_index: i32,
+        _name: *mut vst3_sys::vst::String128,
+    ) -> tresult {
+        kInvalidArgument
+    }
+
+    unsafe fn get_program_info(
+        &self,
+        _list_id: i32,
+        _program_index: i32,
+        _attribute_id: vst3_sys::vst::CString,
+        _attribute_value: *mut vst3_sys::vst::String128,
+    ) -> tresult {
+        kInvalidArgument
+    }
+
+    unsafe fn has_program_pitch_names(&self, _id: i32, _index: i32) -> tresult {
+        kResultFalse
+    }
+
+    unsafe fn get_program_pitch_name(
+        &self,
+        _id: i32,
+        _index: i32,
+        _pitch: i16,
+        _name: *mut vst3_sys::vst::String128,
+    ) -> tresult {
+        kInvalidArgument
+    }
+
+    unsafe fn get_selected_unit(&self) -> i32 {
+        vst3_sys::vst::kRootUnitId
+    }
+
+    unsafe fn select_unit(&self, _id: i32) -> tresult {
+        kResultFalse
+    }
+
+    unsafe fn get_unit_by_bus(
+        &self,
+        _type_: vst3_sys::vst::MediaType,
+        _dir: vst3_sys::vst::BusDirection,
+        _bus_index: i32,
+        _channel: i32,
+        _unit_id: *mut i32,
+    ) -> tresult {
+        kResultFalse
+    }
+
+    unsafe fn set_unit_program_data(
+        &self,
+        _list_or_unit: i32,
+        _program_index: i32,
+        _data: SharedVstPtr<dyn IBStream>,
+    ) -> tresult {
+        kInvalidArgument
+    }
+}
+
+impl<P: Plugin> IMidiMapping for Wrapper<P> {
+    unsafe fn get_midi_controller_assignment(
+        &self,
+        bus_index: i32,
+        channel: i16,
+        midi_cc_number: i16,
+        id: *mut u32,
+    ) -> tresult {
+        check_null_ptr!(id);
+
+        // The MIDI CCs are mapped to parameters in a reserved range of parameter IDs. Changes to
+        // those parameters are then sent to the plugin as MIDI CC events during processing.
+        if !P::ACCEPTS_MIDI
+            || bus_index != 0
+            || !(0..VST3_MIDI_CHANNELS as i16).contains(&channel)
+            || !(0..VST3_MIDI_CCS as i16).contains(&midi_cc_number)
+        {
+            return kResultFalse;
+        }
+
+        *id = VST3_MIDI_PARAMS_START + (channel as u32 * VST3_MIDI_CCS) + midi_cc_number as u32;
+
+        kResultOk
+    }
+}
+
+impl<P: Plugin> INoteExpressionController for Wrapper<P> {
+    unsafe fn get_note_expression_count(&self, bus_index: i32, _channel: i16) -> i32 {
+        // Hosts only send the note expressions that are listed here, even the predefined ones
+        if P::ACCEPTS_MIDI && bus_index == 0 {
+            VST3_NOTE_EXPRESSIONS.len() as i32
+        } else {
+            0
+        }
+    }
+
+    unsafe fn get_note_expression_info(
+        &self,
+        bus_index: i32,
+        _channel: i16,
+        note_expression_index: i32,
+        info: *mut NoteExpressionTypeInfo,
+    ) -> tresult {
+        check_null_ptr!(info);
+
+        if !P::ACCEPTS_MIDI
+            || bus_index != 0
+            || !(0..VST3_NOTE_EXPRESSIONS.len() as i32).contains(&note_expression_index)
+        {
+            return kInvalidArgument;
+        }
+
+        *info = mem::zeroed();
+
+        let info = &mut *info;
+        let note_expression = &VST3_NOTE_EXPRESSIONS[note_expression_index as usize];
+        info.type_id = note_expression.type_id;
+        u16strlcpy(&mut info.title, note_expression.title);
+        u16strlcpy(&mut info.short_title, note_expression.title);
+        u16strlcpy(&mut info.units, note_expression.unit);
+        info.unit_id = vst3_sys::vst::kRootUnitId;
+        info.value_desc = NoteExpressionValueDescription {
+            default_value: note_expression.default_value,
+            min: 0.0,
+            max: 1.0,
+            step_count: 0,
+        };
+        // These note expressions are not associated with any parameter
+        info.id = u32::MAX;
+        info.flags = note_expression.flags;
+
+        kResultOk
+    }
+
+    unsafe fn get_note_expression_string_by_value(
+        &self,
+        _bus_index: i32,
+        _channel: i16,
+        id: u32,
+        value: f64,
+        string: *mut TChar,
+    ) -> tresult {
+        check_null_ptr!(string);
+
+        // Somehow there's no length there, so we'll assume our own maximum
+        let dest = &mut *(string as *mut [TChar; 128]);
+        match note_expression_value_to_string(id, value) {
+            Some(value_string) => {
+                u16strlcpy(dest, &value_string);
+                kResultOk
+            }
+            None => kInvalidArgument,
+        }
+    }
+
+    unsafe fn get_note_expression_value_by_string(
+        &self,
+        _bus_index: i32,
+        _channel: i16,
+        id: u32,
+        string: *const TChar,
+        value: *mut f64,
+    ) -> tresult {
+        check_null_ptr!(string, value);
+
+        let string = match U16CStr::from_ptr_str(string as *const u16).to_string() {
+            Ok(s) => s,
+            Err(_) => return kInvalidArgument,
+        };
+        match note_expression_string_to_value(id, &string) {
+            Some(v) => {
+                *value = v;
+                kResultOk
+            }
+            None => kResultFalse,
         }
     }
 }
 
+impl<P: Plugin> IProcessContextRequirements for Wrapper<P> {
+    unsafe fn get_process_context_requirements(&self) -> u32 {
+        process_context_requirements(&P::TRANSPORT_REQUIREMENTS)
+    }
+}
+
 impl<P: Plugin> IAudioProcessor for Wrapper<P> {
     unsafe fn set_bus_arrangements(
         &self,
@@ -614,16 +886,23 @@ impl<P: Plugin> IAudioProcessor for Wrapper<P> {
     ) -> tresult {
         check_null_ptr!(inputs, outputs);
 
-        // We currently only do single audio bus IO configurations
-        if num_ins != 1 || num_outs != 1 {
+        // We currently only do single audio bus IO configurations, with optional auxiliary input
+        // busses. The number of auxiliary busses is fixed, but their channel counts aren't.
+        if num_ins != 1 + P::AUX_INPUT_CHANNELS.len() as i32 || num_outs != 1 {
             return kInvalidArgument;
         }
 
         let input_channel_map = &*inputs;
         let output_channel_map = &*outputs;
+        let aux_input_channel_maps =
+            std::slice::from_raw_parts(inputs.add(1), P::AUX_INPUT_CHANNELS.len());
         let proposed_config = BusConfig {
             num_input_channels: input_channel_map.count_ones(),
             num_output_channels: output_channel_map.count_ones(),
+            aux_input_channels: aux_input_channel_maps
+                .iter()
+                .map(|channel_map| channel_map.count_ones())
+                .collect(),
         };
         if self
             .inner
@@ -631,10 +910,20 @@ impl<P: Plugin> IAudioProcessor for Wrapper<P> {
             .read()
             .accepts_bus_config(&proposed_config)
         {
-            self.inner.current_bus_config.store(proposed_config);
+            *self.inner.current_bus_config.write() = proposed_config;
 
             kResultOk
         } else {
+            // The host will fall back to the current configuration it can query through
+            // `get_bus_arrangement()`, which is always a configuration the plugin has accepted
+            if cfg!(debug_assertions) {
+                nih_log!(
+                    "The plugin rejected the host's proposed bus config {:?}, keeping {:?}",
+                    proposed_config,
+                    self.inner.current_bus_config.read()
+                );
+            }
+
             kResultFalse
         }
     }
@@ -664,12 +953,19 @@ impl<P: Plugin> IAudioProcessor for Wrapper<P> {
             }
         };
 
-        let config = self.inner.current_bus_config.load();
+        let config = self.inner.current_bus_config.read();
         let num_channels = match (dir, index) {
             (d, 0) if d == vst3_sys::vst::BusDirections::kInput as i32 => config.num_input_channels,
             (d, 0) if d == vst3_sys::vst::BusDirections::kOutput as i32 => {
                 config.num_output_channels
             }
+            (d, n)
+                if d == vst3_sys::vst::BusDirections::kInput as i32
+                    && n > 0
+                    && n as usize <= P::AUX_INPUT_CHANNELS.len() =>
+            {
+                config.aux_input_channels[n as usize - 1]
+            }
             _ => return kInvalidArgument,
         };
         let channel_map = channel_count_to_map(num_channels);
@@ -681,14 +977,17 @@ impl<P: Plugin> IAudioProcessor for Wrapper<P> {
     }
 
     unsafe fn can_process_sample_size(&self, symbolic_sample_size: i32) -> tresult {
-        if symbolic_sample_size == vst3_sys::vst::SymbolicSampleSizes::kSample32 as i32 {
-            kResultOk
-        } else {
-            kResultFalse
+        match sample_precision_from_vst3(symbolic_sample_size) {
+            Some(sample_precision) if Self::supports_sample_precision(sample_precision) => {
+                kResultOk
+            }
+            _ => kResultFalse,
         }
     }
 
     unsafe fn get_latency_samples(&self) -> u32 {
+        // Any changes to the latency after this point need to be reported with a restart
+        self.inner.latency_reported.store(true, Ordering::SeqCst);
         self.inner.current_latency.load(Ordering::SeqCst)
     }
 
@@ -697,37 +996,41 @@ impl<P: Plugin> IAudioProcessor for Wrapper<P> {
 
         // There's no special handling for offline processing at the moment
         let setup = &*setup;
-        nih_debug_assert_eq!(
-            setup.symbolic_sample_size,
-            vst3_sys::vst::SymbolicSampleSizes::kSample32 as i32
-        );
 
-        let bus_config = self.inner.current_bus_config.load();
+        // Hosts should only use sample sizes we said we can process, but if they don't then we'll
+        // refuse the setup so the host falls back to single precision
+        let sample_precision = match sample_precision_from_vst3(setup.symbolic_sample_size) {
+            Some(sample_precision) if Self::supports_sample_precision(sample_precision) => {
+                sample_precision
+            }
+            _ => {
+                nih_debug_assert_failure!(
+                    "The host tried to use an unsupported sample size: {}",
+                    setup.symbolic_sample_size
+                );
+                return kResultFalse;
+            }
+        };
+
         let buffer_config = BufferConfig {
             sample_rate: setup.sample_rate as f32,
             max_buffer_size: setup.max_samples_per_block as u32,
+            sample_precision,
         };
 
-        // Befure initializing the plugin, make sure all smoothers are set the the default values
-        for param in self.inner.param_by_hash.values() {
-            param.update_smoother(buffer_config.sample_rate, true);
-        }
-
-        if self.inner.plugin.write().initialize(
-            &bus_config,
-            &buffer_config,
-            &mut self.inner.make_process_context(),
-        ) {
-            // Preallocate enough room in the output slices vector so we can convert a `*mut *mut
-            // f32` to a `&mut [&mut f32]` in the process call
-            self.inner
-                .output_buffer
-                .write()
-                .as_raw_vec()
-                .resize_with(bus_config.num_output_channels as usize, || &mut []);
-
-            // Also store this for later, so we can reinitialize the plugin after restoring state
-            self.inner.current_buffer_config.store(Some(buffer_config));
+        if self.inner.initialize_plugin(buffer_config) {
+            if sample_precision == SamplePrecision::Double {
+                let bus_config = self.inner.current_bus_config.read();
+                let input_bus_channels: Vec<u32> = [bus_config.num_input_channels]
+                    .into_iter()
+                    .chain(bus_config.aux_input_channels.iter().copied())
+                    .collect();
+                self.inner.single_precision_buffers.write().resize(
+                    &input_bus_channels,
+                    &[bus_config.num_output_channels],
+                    buffer_config.max_buffer_size as usize,
+                );
+            }
 
             kResultOk
         } else {
@@ -736,9 +1039,7 @@ impl<P: Plugin> IAudioProcessor for Wrapper<P> {
     }
 
     unsafe fn set_processing(&self, state: TBool) -> tresult {
-        // Always reset the processing status when the plugin gets activated or deactivated
-        self.inner.last_process_status.store(ProcessStatus::Normal);
-        self.inner.is_processing.store(state != 0, Ordering::SeqCst);
+        self.inner.set_processing(state != 0);
 
         // We don't have any special handling for suspending and resuming plugins, yet
         kResultOk
@@ -747,11 +1048,35 @@ impl<P: Plugin> IAudioProcessor for Wrapper<P> {
     unsafe fn process(&self, data: *mut vst3_sys::vst::ProcessData) -> tresult {
         check_null_ptr!(data);
 
+        // A plugin that panicked before is likely in an inconsistent state, so it won't be
+        // processed anymore and it only outputs silence
+        if self.inner.panicked.load(Ordering::SeqCst) {
+            clear_outputs(&*data);
+            return kResultFalse;
+        }
+
         // Panic on allocations if the `assert_process_allocs` feature has been enabled, and make
-        // sure that FTZ is set up correctly
-        process_wrapper(|| {
-            // We need to handle incoming automation first
+        // sure that FTZ is set up correctly unless the plugin opted out of that. Panics are caught
+        // here so they don't unwind into the host.
+        let result = process_wrapper::<P, _, _>(|| {
             let data = &*data;
+
+            // The host may only want to send new parameter values (and possibly events) without
+            // processing any audio. MIDI effects without any audio channels still get regular
+            // process calls without any output busses, so those should not be mistaken for
+            // parameter flushes.
+            let num_buffer_channels = {
+                let bus_config = self.inner.current_bus_config.read();
+                if P::ANALYSIS_ONLY {
+                    bus_config.num_input_channels
+                } else {
+                    bus_config.num_output_channels
+                }
+            };
+            let is_parameter_flush =
+                data.num_samples == 0 || (data.num_outputs == 0 && num_buffer_channels > 0);
+
+            // We need to handle incoming automation first
             let sample_rate = self
                 .inner
                 .current_buffer_config
@@ -766,6 +1091,71 @@ impl<P: Plugin> IAudioProcessor for Wrapper<P> {
                         let param_hash = param_change_queue.get_parameter_id();
                         let num_changes = param_change_queue.get_point_count();
 
+                        // The MIDI CCs mapped through `IMidiMapping` don't correspond to real
+                        // parameters, and every point in their queues becomes a note event instead.
+                        // Sorting the events below takes care of their order.
+                        if P::ACCEPTS_MIDI
+                            && (VST3_MIDI_PARAMS_START..VST3_MIDI_PARAMS_END).contains(&param_hash)
+                        {
+                            let mut input_events = self.inner.input_events.write();
+                            let mut sample_offset = 0i32;
+                            let mut value = 0.0f64;
+                            for point_idx in 0..num_changes {
+                                if param_change_queue.get_point(
+                                    point_idx,
+                                    &mut sample_offset,
+                                    &mut value,
+                                ) == kResultOk
+                                {
+                                    let timing = if is_parameter_flush {
+                                        0
+                                    } else {
+                                        sample_offset.max(0) as u32
+                                    };
+                                    if let Some(note_event) =
+                                        self.inner.midi_cc_param_to_note_event(
+                                            timing,
+                                            param_hash,
+                                            value as f32,
+                                        )
+                                    {
+                                        queue_event(&mut input_events, note_event);
+                                    }
+                                }
+                            }
+
+                            continue;
+                        }
+
+                        // The plugin also gets to see the individual automation points, with the
+                        // plain values computed the same way as when the parameter gets set. These
+                        // are only cleared after the plugin has processed a block, and parameter
+                        // flushes don't have any samples for these points to belong to.
+                        let param_ptr = self.inner.param_by_hash.get(&param_hash);
+                        if let (Some(param_ptr), false) = (param_ptr, is_parameter_flush) {
+                            let mut input_automation = self.inner.input_automation.write();
+                            let mut sample_offset = 0i32;
+                            let mut value = 0.0f64;
+                            for point_idx in 0..num_changes {
+                                if param_change_queue.get_point(
+                                    point_idx,
+                                    &mut sample_offset,
+                                    &mut value,
+                                ) == kResultOk
+                                {
+                                    queue_event(
+                                        &mut input_automation,
+                                        AutomationPoint {
+                                            timing: sample_offset.max(0) as u32,
+                                            param: *param_ptr,
+                                            normalized: value as f32,
+                                            plain: param_ptr.preview_plain(value as f32),
+                                        },
+                                    );
+                                }
+                            }
+                        }
+
                         // TODO: Handle sample accurate parameter changes, possibly in a similar way
                         //       to the smoothing
                         let mut sample_offset = 0i32;
@@ -781,129 +1171,486 @@ impl<P: Plugin> IAudioProcessor for Wrapper<P> {
                                 param_hash,
                                 value as f32,
                                 sample_rate,
+                                self.inner.host_change_source(param_hash),
                             );
+
+                            // Smoothing dense automation would only make the parameter lag behind
+                            if P::BYPASS_SMOOTHING_FOR_DENSE_AUTOMATION && num_changes > 1 {
+                                if let (Some(param_ptr), Some(sample_rate)) =
+                                    (self.inner.param_by_hash.get(&param_hash), sample_rate)
+                                {
+                                    param_ptr.update_smoother(sample_rate, true);
+                                }
+                            }
                         }
                     }
                 }
             }
 
-            // And also incoming note events if the plugin accepts MDII
+            // And also incoming note events if the plugin accepts MDII. These are only cleared
+            // after the plugin has processed a block, so events sent during a parameter flush are
+            // delivered at the start of the next block instead of being dropped.
             if P::ACCEPTS_MIDI {
                 let mut input_events = self.inner.input_events.write();
                 if let Some(events) = data.input_events.upgrade() {
                     let num_events = events.get_event_count();
 
-                    input_events.clear();
                     let mut event: MaybeUninit<_> = MaybeUninit::uninit();
                     for i in 0..num_events {
                         nih_debug_assert_eq!(events.get_event(i, event.as_mut_ptr()), kResultOk);
                         let event = event.assume_init();
-                        let timing = event.sample_offset as u32;
-                        if event.type_ == vst3_sys::vst::EventTypes::kNoteOnEvent as u16 {
-                            let event = event.event.note_on;
-                            input_events.push_back(NoteEvent::NoteOn {
-                                timing,
-                                channel: event.channel as u8,
-                                note: event.pitch as u8,
-                                velocity: (event.velocity * 127.0).round() as u8,
-                            });
-                        } else if event.type_ == vst3_sys::vst::EventTypes::kNoteOffEvent as u16 {
-                            let event = event.event.note_off;
-                            input_events.push_back(NoteEvent::NoteOff {
-                                timing,
-                                channel: event.channel as u8,
-                                note: event.pitch as u8,
-                                velocity: (event.velocity * 127.0).round() as u8,
-                            });
+                        let timing = if is_parameter_flush {
+                            0
+                        } else {
+                            event.sample_offset.max(0) as u32
+                        };
+                        if let Some(note_event) =
+                            self.inner.vst3_event_to_note_event(timing, &event)
+                        {
+                            queue_event(&mut input_events, note_event);
                         }
                     }
                 }
+
+                // Hosts are supposed to send events in order, but not all of them do. Plugins can
+                // rely on the events being sorted by their timing.
+                sort_note_events(&mut input_events);
             }
 
-            // It's possible the host only wanted to send new parameter values
-            if data.num_outputs == 0 {
+            // It's possible the host only wanted to send new parameter values. Those have all been
+            // applied above and the smoothers already have their new targets, so the next regular
+            // block simply continues from there. The host may not have provided any audio buffers
+            // here, so nothing below this point can be done for a parameter flush.
+            if is_parameter_flush {
                 nih_log!("VST3 parameter flush");
                 return kResultOk;
             }
 
-            // The setups we suppport are:
-            // - 1 input bus
-            // - 1 output bus
-            // - 1 input bus and 1 output bus
+            // At double precision the plugin processes single precision copies of the host's
+            // buffers, and everything below only sees those copies. The outputs are copied back to
+            // the host's buffers after the plugin has processed audio.
+            let host_data = data;
+            let is_double_precision = host_data.symbolic_sample_size
+                == vst3_sys::vst::SymbolicSampleSizes::kSample64 as i32;
+
+            // All of the wrapper's buffers are allocated for the maximum block size from
+            // `IAudioProcessor::setupProcessing()`, and hosts are not allowed to exceed it. If a
+            // host does so anyways, then only the first part of the block is processed.
+            let max_buffer_size = self
+                .inner
+                .current_buffer_config
+                .load()
+                .map_or(0, |c| c.max_buffer_size as i32);
             nih_debug_assert!(
-                data.num_inputs >= 0
-                    && data.num_inputs <= 1
-                    && data.num_outputs >= 0
-                    && data.num_outputs <= 1,
-                "The host provides more than one input or output bus"
+                host_data.num_samples <= max_buffer_size,
+                "The host passed {} samples, but the maximum block size is {}",
+                host_data.num_samples,
+                max_buffer_size
             );
-            nih_debug_assert_eq!(
-                data.symbolic_sample_size,
-                vst3_sys::vst::SymbolicSampleSizes::kSample32 as i32
-            );
-            nih_debug_assert!(data.num_samples >= 0);
 
-            let num_output_channels = (*data.outputs).num_channels as usize;
-            check_null_ptr_msg!(
-                "Process output pointer is null",
-                data.outputs,
-                (*data.outputs).buffers,
-            );
+            let mut single_precision_buffers = self.inner.single_precision_buffers.write();
+            let clamped_data;
+            let data = if is_double_precision {
+                clamped_data = single_precision_buffers.read_double_precision(host_data);
+                &clamped_data
+            } else if host_data.num_samples > max_buffer_size {
+                // SAFETY: `ProcessData` only contains plain values and pointers it doesn't own
+                clamped_data = vst3_sys::vst::ProcessData {
+                    num_samples: max_buffer_size,
+                    ..ptr::read(host_data)
+                };
+                &clamped_data
+            } else {
+                host_data
+            };
 
             // This vector has been reallocated to contain enough slices as there are output
-            // channels
+            // channels (or input channels for analysis-only plugins)
             let mut output_buffer = self.inner.output_buffer.write();
-            {
-                let output_slices = output_buffer.as_raw_vec();
-                nih_debug_assert_eq!(num_output_channels, output_slices.len());
-                for (output_channel_idx, output_channel_slice) in
-                    output_slices.iter_mut().enumerate()
-                {
-                    // SAFETY: These pointers may not be valid outside of this function even though
-                    // their lifetime is equal to this structs. This is still safe because they are
-                    // only dereferenced here later as part of this process function.
-                    *output_channel_slice = std::slice::from_raw_parts_mut(
-                        *((*data.outputs).buffers as *mut *mut f32).add(output_channel_idx),
-                        data.num_samples as usize,
-                    );
-                }
-            }
-
-            // Most hosts process data in place, in which case we don't need to do any copying
-            // ourselves. If the pointers do not alias, then we'll do the copy here and then the
-            // plugin can just do normal in place processing.
-            if !data.inputs.is_null() {
-                let num_input_channels = (*data.inputs).num_channels as usize;
+            if data.num_outputs > 0 {
+                // The setups we suppport are:
+                // - 1 input bus
+                // - 1 output bus
+                // - 1 input bus and 1 output bus
+                // Any auxiliary input busses come after the main input bus
                 nih_debug_assert!(
-                    num_input_channels <= num_output_channels,
-                    "Stereo to mono and similar configurations are not supported"
+                    data.num_inputs >= 0
+                        && data.num_inputs <= 1 + P::AUX_INPUT_CHANNELS.len() as i32
+                        && data.num_outputs >= 0
+                        && data.num_outputs <= 1,
+                    "The host provides more than one input or output bus"
+                );
+                nih_debug_assert_eq!(
+                    data.symbolic_sample_size,
+                    vst3_sys::vst::SymbolicSampleSizes::kSample32 as i32
                 );
-                for input_channel_idx in 0..cmp::min(num_input_channels, num_output_channels) {
-                    let output_channel_ptr =
-                        *((*data.outputs).buffers as *mut *mut f32).add(input_channel_idx);
-                    let input_channel_ptr =
-                        *((*data.inputs).buffers as *const *const f32).add(input_channel_idx);
-                    if input_channel_ptr != output_channel_ptr {
-                        ptr::copy_nonoverlapping(
+                nih_debug_assert!(data.num_samples >= 0);
+
+                let num_output_channels = (*data.outputs).num_channels as usize;
+                check_null_ptr_msg!(
+                    "Process output pointer is null",
+                    data.outputs,
+                    (*data.outputs).buffers,
+                );
+
+                // Analysis-only plugins don't get any output slices, they only get to read the
+                // host's inputs. Those are set up after the inputs have been copied to the outputs
+                // below.
+                if !P::ANALYSIS_ONLY {
+                    let output_slices = output_buffer.as_raw_vec();
+                    nih_debug_assert_eq!(num_output_channels, output_slices.len());
+                    for (output_channel_idx, output_channel_slice) in
+                        output_slices.iter_mut().enumerate()
+                    {
+                        // SAFETY: These pointers may not be valid outside of this function even
+                        // though their lifetime is equal to this structs. This is still safe
+                        // because they are only dereferenced here later as part of this process
+                        // function.
+                        *output_channel_slice = std::slice::from_raw_parts_mut(
+                            *((*data.outputs).buffers as *mut *mut f32).add(output_channel_idx),
+                            data.num_samples as usize,
+                        );
+                    }
+                }
+
+                // The plugin may also need to read the unmodified inputs after writing to the
+                // outputs. This has to happen before the inputs get copied to the outputs below.
+                if P::SEPARATE_INPUT_BUFFERS && !P::ANALYSIS_ONLY {
+                    let mut input_scratch_buffers = self.inner.input_scratch_buffers.write();
+                    let input_slices = output_buffer.as_raw_input_vec();
+                    let num_input_channels = if data.inputs.is_null() {
+                        0
+                    } else {
+                        (*data.inputs).num_channels as usize
+                    };
+                    for (input_channel_idx, (input_channel_slice, scratch_buffer)) in input_slices
+                        .iter_mut()
+                        .zip(input_scratch_buffers.iter_mut())
+                        .enumerate()
+                    {
+                        let input_channel_ptr = if input_channel_idx < num_input_channels {
+                            *((*data.inputs).buffers as *const *const f32).add(input_channel_idx)
+                        } else {
+                            ptr::null()
+                        };
+
+                        // SAFETY: `stable_input_slice()` only resizes this scratch buffer, which
+                        //         happens if the host exceeds the maximum buffer size, before
+                        //         creating the slice. The scratch buffers are not touched again
+                        //         until the next process call, so the slice stays valid while the
+                        //         plugin processes this block.
+                        *input_channel_slice = stable_input_slice(
                             input_channel_ptr,
-                            output_channel_ptr,
+                            (*data.outputs).buffers as *const *const f32,
+                            num_output_channels,
+                            scratch_buffer,
                             data.num_samples as usize,
                         );
                     }
                 }
+
+                // The same goes for the auxiliary inputs, which come after the main input bus.
+                // Busses that aren't active or that the host left out are passed as silence.
+                {
+                    let mut aux_input_scratch_buffers =
+                        self.inner.aux_input_scratch_buffers.write();
+                    let aux_input_slices = output_buffer.as_raw_aux_input_vec();
+                    for (aux_input_idx, (channel_slices, scratch_buffers)) in aux_input_slices
+                        .iter_mut()
+                        .zip(aux_input_scratch_buffers.iter_mut())
+                        .enumerate()
+                    {
+                        let bus_idx = 1 + aux_input_idx;
+                        let aux_input = if !data.inputs.is_null()
+                            && bus_idx < data.num_inputs as usize
+                            && !(*data.inputs.add(bus_idx)).buffers.is_null()
+                        {
+                            Some(&*data.inputs.add(bus_idx))
+                        } else {
+                            None
+                        };
+                        let num_aux_input_channels =
+                            aux_input.map_or(0, |aux_input| aux_input.num_channels as usize);
+
+                        for (channel_idx, (channel_slice, scratch_buffer)) in channel_slices
+                            .iter_mut()
+                            .zip(scratch_buffers.iter_mut())
+                            .enumerate()
+                        {
+                            let input_channel_ptr = match aux_input {
+                                Some(aux_input) if channel_idx < num_aux_input_channels => {
+                                    *(aux_input.buffers as *const *const f32).add(channel_idx)
+                                }
+                                _ => ptr::null(),
+                            };
+
+                            // SAFETY: `stable_input_slice()` only resizes this scratch buffer,
+                            //         which happens if the host exceeds the maximum buffer size,
+                            //         before creating the slice. The scratch buffers are not
+                            //         touched again until the next process call, so the slice stays
+                            //         valid while the plugin processes this block.
+                            *channel_slice = stable_input_slice(
+                                input_channel_ptr,
+                                (*data.outputs).buffers as *const *const f32,
+                                num_output_channels,
+                                scratch_buffer,
+                                data.num_samples as usize,
+                            );
+                        }
+                    }
+                }
+
+                // Most hosts process data in place, in which case we don't need to do any
+                // copying ourselves. If the pointers do not alias, then we'll do the copy here and
+                // then the plugin can just do normal in place processing. When the plugin has more
+                // output channels than input channels the remaining outputs are cleared, and any
+                // extra input channels are dropped.
+                if !data.inputs.is_null() {
+                    let num_input_channels = (*data.inputs).num_channels as usize;
+                    let num_copied_channels = cmp::min(num_input_channels, num_output_channels);
+                    for input_channel_idx in 0..num_copied_channels {
+                        let output_channel_ptr =
+                            *((*data.outputs).buffers as *mut *mut f32).add(input_channel_idx);
+                        let input_channel_ptr =
+                            *((*data.inputs).buffers as *const *const f32).add(input_channel_idx);
+                        if input_channel_ptr != output_channel_ptr {
+                            ptr::copy_nonoverlapping(
+                                input_channel_ptr,
+                                output_channel_ptr,
+                                data.num_samples as usize,
+                            );
+                        }
+                    }
+
+                    for output_channel_idx in num_copied_channels..num_output_channels {
+                        let output_channel_ptr =
+                            *((*data.outputs).buffers as *mut *mut f32).add(output_channel_idx);
+                        ptr::write_bytes(output_channel_ptr, 0, data.num_samples as usize);
+                    }
+                }
+
+                // Plugins that only analyze their input directly get to read the host's input
+                // buffers. Nothing writes to the outputs while the plugin is processing audio, so
+                // this is fine even when the host processes audio in place.
+                if P::ANALYSIS_ONLY {
+                    let input_slices = output_buffer.as_raw_input_vec();
+                    let num_input_channels = if data.inputs.is_null() {
+                        0
+                    } else {
+                        (*data.inputs).num_channels as usize
+                    };
+                    nih_debug_assert_eq!(num_input_channels, input_slices.len());
+                    for (input_channel_idx, input_channel_slice) in
+                        input_slices.iter_mut().enumerate()
+                    {
+                        *input_channel_slice = if input_channel_idx < num_input_channels {
+                            std::slice::from_raw_parts(
+                                *((*data.inputs).buffers as *const *const f32)
+                                    .add(input_channel_idx),
+                                data.num_samples as usize,
+                            )
+                        } else {
+                            &[]
+                        };
+                    }
+                }
             }
 
             let plugin = &mut *self.inner.plugin.data_ptr();
-            let mut context = self.inner.make_process_context();
-            match plugin.process(&mut output_buffer, &mut context) {
+            // The buffer config is always set during `IAudioProcessor::setupProcessing()`
+            let transport = transport_from_vst3(data.context, sample_rate.unwrap_or_default());
+            let mut context = self.inner.make_process_context(transport);
+            let status = self.inner.process_bypassable(
+                &mut output_buffer,
+                data.num_samples as usize,
+                |buffer| plugin.process(buffer, &mut context),
+            );
+            // This is used to report the plugin's tail length to the host
+            self.inner.last_process_status.store(status);
+            let result = match status {
                 ProcessStatus::Error(err) => {
                     nih_debug_assert_failure!("Process error: {}", err);
 
                     kResultFalse
                 }
                 _ => kResultOk,
+            };
+            // Any events and automation points the plugin did not consume are meant for this block
+            // only
+            context.input_events_guard.clear();
+            context.input_automation_guard.clear();
+            drop(context);
+
+            self.inner
+                .apply_activation_fade_in(&mut output_buffer, data.num_samples as u32);
+            if is_double_precision {
+                single_precision_buffers.write_double_precision(host_data);
+            }
+
+            // Note events sent by the plugin are written to the host's output event list. Events
+            // that don't belong to this block are kept around for the next one.
+            if P::PRODUCES_MIDI {
+                let output_events = data.output_events.upgrade();
+                self.inner
+                    .drain_output_events(data.num_samples as u32, |event| {
+                        let output_events = match &output_events {
+                            Some(output_events) => output_events,
+                            None => return,
+                        };
+
+                        let new_event = |type_: vst3_sys::vst::EventTypes| {
+                            let mut vst3_event: vst3_sys::vst::Event = mem::zeroed();
+                            vst3_event.sample_offset = event.timing() as i32;
+                            vst3_event.type_ = type_ as u16;
+                            vst3_event
+                        };
+                        let add_legacy_midi_cc =
+                            |channel: u8, control: u8, value: u8, value2: u8| {
+                                let mut vst3_event =
+                                    new_event(vst3_sys::vst::EventTypes::kLegacyMIDICCOutEvent);
+                                vst3_event.event.legacy_midi_cc_out.channel = channel as i8;
+                                vst3_event.event.legacy_midi_cc_out.control_number = control;
+                                vst3_event.event.legacy_midi_cc_out.value = value as i8;
+                                vst3_event.event.legacy_midi_cc_out.value2 = value2 as i8;
+                                output_events.add_event(&mut vst3_event);
+                            };
+
+                        match *event {
+                            NoteEvent::NoteOn {
+                                note_id,
+                                channel,
+                                note,
+                                velocity,
+                                ..
+                            } => {
+                                let mut vst3_event =
+                                    new_event(vst3_sys::vst::EventTypes::kNoteOnEvent);
+                                vst3_event.event.note_on.channel = channel as i16;
+                                vst3_event.event.note_on.pitch = note as i16;
+                                vst3_event.event.note_on.velocity = velocity as f32 / 127.0;
+                                vst3_event.event.note_on.note_id = note_id.unwrap_or(-1);
+                                output_events.add_event(&mut vst3_event);
+                            }
+                            NoteEvent::NoteOff {
+                                note_id,
+                                channel,
+                                note,
+                                velocity,
+                                ..
+                            } => {
+                                let mut vst3_event =
+                                    new_event(vst3_sys::vst::EventTypes::kNoteOffEvent);
+                                vst3_event.event.note_off.channel = channel as i16;
+                                vst3_event.event.note_off.pitch = note as i16;
+                                vst3_event.event.note_off.velocity = velocity as f32 / 127.0;
+                                vst3_event.event.note_off.note_id = note_id.unwrap_or(-1);
+                                output_events.add_event(&mut vst3_event);
+                            }
+                            NoteEvent::PolyPressure {
+                                note_id,
+                                channel,
+                                note,
+                                pressure,
+                                ..
+                            } => {
+                                let mut vst3_event =
+                                    new_event(vst3_sys::vst::EventTypes::kPolyPressureEvent);
+                                vst3_event.event.poly_pressure.channel = channel as i16;
+                                vst3_event.event.poly_pressure.pitch = note as i16;
+                                vst3_event.event.poly_pressure.pressure = pressure;
+                                vst3_event.event.poly_pressure.note_id = note_id.unwrap_or(-1);
+                                output_events.add_event(&mut vst3_event);
+                            }
+                            NoteEvent::PolyPitchBend {
+                                note_id, semitones, ..
+                            } => {
+                                let mut vst3_event =
+                                    new_event(vst3_sys::vst::EventTypes::kNoteExpressionValueEvent);
+                                vst3_event.event.note_expression_value.type_id =
+                                    VST3_NOTE_EXPRESSION_TUNING_TYPE_ID;
+                                vst3_event.event.note_expression_value.note_id = note_id;
+                                vst3_event.event.note_expression_value.value =
+                                    (semitones as f64 / 240.0 + 0.5).clamp(0.0, 1.0);
+                                output_events.add_event(&mut vst3_event);
+                            }
+                            NoteEvent::PolyBrightness {
+                                note_id,
+                                brightness,
+                                ..
+                            } => {
+                                let mut vst3_event =
+                                    new_event(vst3_sys::vst::EventTypes::kNoteExpressionValueEvent);
+                                vst3_event.event.note_expression_value.type_id =
+                                    VST3_NOTE_EXPRESSION_BRIGHTNESS_TYPE_ID;
+                                vst3_event.event.note_expression_value.note_id = note_id;
+                                vst3_event.event.note_expression_value.value = brightness as f64;
+                                output_events.add_event(&mut vst3_event);
+                            }
+                            // VST3 only supports sending these as legacy MIDI CC events. Hosts send
+                            // the plugin's input MIDI CCs through `IMidiMapping` instead.
+                            NoteEvent::MidiChannelPressure {
+                                channel, pressure, ..
+                            } => add_legacy_midi_cc(
+                                channel,
+                                128,
+                                (pressure * 127.0).round() as u8,
+                                0,
+                            ),
+                            NoteEvent::MidiPitchBend { channel, value, .. } => {
+                                let value = (value * 16383.0).round() as u16;
+                                add_legacy_midi_cc(
+                                    channel,
+                                    129,
+                                    (value & 0x7F) as u8,
+                                    ((value >> 7) & 0x7F) as u8,
+                                );
+                            }
+                            NoteEvent::MidiProgramChange {
+                                channel, program, ..
+                            } => add_legacy_midi_cc(channel, 130, program & 0x7F, 0),
+                            NoteEvent::MidiBankSelect { channel, bank, .. } => {
+                                add_legacy_midi_cc(channel, 0, ((bank >> 7) & 0x7F) as u8, 0);
+                                add_legacy_midi_cc(channel, 32, (bank & 0x7F) as u8, 0);
+                            }
+                            NoteEvent::MidiCC {
+                                channel, cc, value, ..
+                            } => add_legacy_midi_cc(channel, cc, (value * 127.0).round() as u8, 0),
+                        }
+                    });
+            }
+
+            // The plugin may have updated its meters during the process call, so we'll send their
+            // current values to the host as output parameter changes
+            if !self.inner.meter_param_hashes.is_empty() {
+                if let Some(output_param_changes) = data.output_param_changes.upgrade() {
+                    for param_hash in &self.inner.meter_param_hashes {
+                        let mut queue_index = 0;
+                        if let Some(param_value_queue) = output_param_changes
+                            .add_parameter_data(param_hash, &mut queue_index)
+                            .upgrade()
+                        {
+                            let normalized_value =
+                                self.inner.param_by_hash[param_hash].normalized_value() as f64;
+                            let mut point_index = 0;
+                            param_value_queue.add_point(0, normalized_value, &mut point_index);
+                        }
+                    }
+                }
+            }
+
+            result
+        });
+
+        match result {
+            Some(result) => result,
+            None => {
+                // The plugin may have left anything in the output buffers, and the host should not
+                // get to play that back
+                self.inner.panicked.store(true, Ordering::SeqCst);
+                clear_outputs(&*data);
+                kResultFalse
             }
-        })
+        }
     }
 
     unsafe fn get_tail_samples(&self) -> u32 {
@@ -916,27 +1663,96 @@ impl<P: Plugin> IAudioProcessor for Wrapper<P> {
     }
 }
 
+/// Everything the [Factory] needs to know about a plugin, with the plugin's type erased so a single
+/// factory can export multiple plugins.
 #[doc(hidden)]
-#[VST3(implements(IPluginFactory, IPluginFactory2, IPluginFactory3))]
-pub struct Factory<P: Vst3Plugin> {
-    /// The type will be used for constructing plugin instances later.
-    _phantom: PhantomData<P>,
+pub struct PluginDescriptor {
+    name: &'static str,
+    vendor: &'static str,
+    url: &'static str,
+    email: &'static str,
+    version: &'static str,
+    /// The plugin's subcategories, joined by pipe characters.
+    subcategories: String,
+    class_flags: u32,
+    class_id: [u8; 16],
+    compatibility_ids: &'static [[u8; 16]],
+    /// Creates a new [Wrapper] for the plugin and returns it as a raw pointer.
+    create_instance: fn() -> *mut vst3_sys::c_void,
 }
 
-impl<P: Vst3Plugin> Factory<P> {
-    pub fn new() -> Box<Self> {
-        Self::allocate(PhantomData::default())
+impl PluginDescriptor {
+    pub fn for_plugin<P: Vst3Plugin>() -> Self {
+        let mut class_flags = 0;
+        if P::VST3_DISTRIBUTABLE {
+            class_flags |= K_DISTRIBUTABLE;
+        }
+        if P::VST3_SIMPLE_MODE_SUPPORTED {
+            class_flags |= K_SIMPLE_MODE_SUPPORTED;
+        }
+
+        Self {
+            name: P::NAME,
+            vendor: P::VENDOR,
+            url: P::URL,
+            email: P::EMAIL,
+            version: P::VERSION,
+            subcategories: subcategories_string(P::VST3_SUBCATEGORIES),
+            class_flags,
+            class_id: P::VST3_CLASS_ID,
+            compatibility_ids: P::VST3_COMPATIBILITY_IDS,
+            create_instance: || Box::into_raw(Wrapper::<P>::new()) as *mut vst3_sys::c_void,
+        }
+    }
+}
+
+#[doc(hidden)]
+#[VST3(implements(IPluginFactory, IPluginFactory2, IPluginFactory3, IPluginCompatibility))]
+pub struct Factory {
+    /// The plugins exported by this factory. The factory's own information is taken from the first
+    /// plugin.
+    plugins: Vec<PluginDescriptor>,
+}
+
+impl Factory {
+    pub fn new(plugins: Vec<PluginDescriptor>) -> Box<Self> {
+        nih_debug_assert!(
+            !plugins.is_empty(),
+            "The factory does not contain any plugins"
+        );
+        for (i, plugin) in plugins.iter().enumerate() {
+            nih_debug_assert!(
+                plugins[i + 1..]
+                    .iter()
+                    .all(|other| other.class_id != plugin.class_id),
+                "Multiple plugins use the same VST3 class ID as '{}'",
+                plugin.name
+            );
+        }
+
+        Self::allocate(plugins)
+    }
+
+    /// Get the plugin at the index used in the `get_class_info*()` functions.
+    fn plugin_at(&self, index: i32) -> Option<&PluginDescriptor> {
+        usize::try_from(index)
+            .ok()
+            .and_then(|index| self.plugins.get(index))
     }
 }
 
-impl<P: Vst3Plugin> IPluginFactory for Factory<P> {
+impl IPluginFactory for Factory {
     unsafe fn get_factory_info(&self, info: *mut vst3_sys::base::PFactoryInfo) -> tresult {
+        check_null_ptr!(info);
+
         *info = mem::zeroed();
 
         let info = &mut *info;
-        strlcpy(&mut info.vendor, P::VENDOR);
-        strlcpy(&mut info.url, P::URL);
-        strlcpy(&mut info.email, P::EMAIL);
+        if let Some(plugin) = self.plugins.first() {
+            strlcpy(&mut info.vendor, plugin.vendor);
+            strlcpy(&mut info.url, plugin.url);
+            strlcpy(&mut info.email, plugin.email);
+        }
         info.flags = vst3_sys::base::FactoryFlags::kUnicode as i32;
 
         kResultOk
@@ -945,22 +1761,25 @@ impl<P: Vst3Plugin> IPluginFactory for Factory<P> {
     unsafe fn count_classes(&self) -> i32 {
         // We don't do shell plugins, and good of an idea having separated components and edit
         // controllers in theory is, few software can use it, and doing that would make our simple
-        // microframework a lot less simple
-        1
+        // microframework a lot less simple. So every plugin is exactly one class.
+        self.plugins.len() as i32
     }
 
     unsafe fn get_class_info(&self, index: i32, info: *mut vst3_sys::base::PClassInfo) -> tresult {
-        if index != 0 {
-            return kInvalidArgument;
-        }
+        check_null_ptr!(info);
+
+        let plugin = match self.plugin_at(index) {
+            Some(plugin) => plugin,
+            None => return kInvalidArgument,
+        };
 
         *info = mem::zeroed();
 
         let info = &mut *info;
-        info.cid.data = P::VST3_CLASS_ID;
+        info.cid.data = plugin.class_id;
         info.cardinality = vst3_sys::base::ClassCardinality::kManyInstances as i32;
         strlcpy(&mut info.category, "Audio Module Class");
-        strlcpy(&mut info.name, P::NAME);
+        strlcpy(&mut info.name, plugin.name);
 
         kResultOk
     }
@@ -973,64 +1792,84 @@ impl<P: Vst3Plugin> IPluginFactory for Factory<P> {
     ) -> tresult {
         check_null_ptr!(cid, obj);
 
-        if (*cid).data != P::VST3_CLASS_ID {
-            return kNoInterface;
-        }
-
-        *obj = Box::into_raw(Wrapper::<P>::new()) as *mut vst3_sys::c_void;
+        // Hosts that don't support `IPluginCompatibility` may still try to instantiate the plugin
+        // using one of its old class IDs. The current class IDs take precedence in case another
+        // plugin in the factory used to have the same class ID.
+        let cid = (*cid).data;
+        let plugin = self
+            .plugins
+            .iter()
+            .find(|plugin| plugin.class_id == cid)
+            .or_else(|| {
+                self.plugins
+                    .iter()
+                    .find(|plugin| plugin.compatibility_ids.contains(&cid))
+            });
+        match plugin {
+            Some(plugin) => {
+                *obj = (plugin.create_instance)();
 
-        kResultOk
+                kResultOk
+            }
+            None => kNoInterface,
+        }
     }
 }
 
-impl<P: Vst3Plugin> IPluginFactory2 for Factory<P> {
+impl IPluginFactory2 for Factory {
     unsafe fn get_class_info2(
         &self,
         index: i32,
         info: *mut vst3_sys::base::PClassInfo2,
     ) -> tresult {
-        if index != 0 {
-            return kInvalidArgument;
-        }
+        check_null_ptr!(info);
+
+        let plugin = match self.plugin_at(index) {
+            Some(plugin) => plugin,
+            None => return kInvalidArgument,
+        };
 
         *info = mem::zeroed();
 
         let info = &mut *info;
-        info.cid.data = P::VST3_CLASS_ID;
+        info.cid.data = plugin.class_id;
         info.cardinality = vst3_sys::base::ClassCardinality::kManyInstances as i32;
         strlcpy(&mut info.category, "Audio Module Class");
-        strlcpy(&mut info.name, P::NAME);
-        info.class_flags = 1 << 1; // kSimpleModeSupported
-        strlcpy(&mut info.subcategories, P::VST3_CATEGORIES);
-        strlcpy(&mut info.vendor, P::VENDOR);
-        strlcpy(&mut info.version, P::VERSION);
+        strlcpy(&mut info.name, plugin.name);
+        info.class_flags = plugin.class_flags;
+        strlcpy(&mut info.subcategories, &plugin.subcategories);
+        strlcpy(&mut info.vendor, plugin.vendor);
+        strlcpy(&mut info.version, plugin.version);
         strlcpy(&mut info.sdk_version, VST3_SDK_VERSION);
 
         kResultOk
     }
 }
 
-impl<P: Vst3Plugin> IPluginFactory3 for Factory<P> {
+impl IPluginFactory3 for Factory {
     unsafe fn get_class_info_unicode(
         &self,
         index: i32,
         info: *mut vst3_sys::base::PClassInfoW,
     ) -> tresult {
-        if index != 0 {
-            return kInvalidArgument;
-        }
+        check_null_ptr!(info);
+
+        let plugin = match self.plugin_at(index) {
+            Some(plugin) => plugin,
+            None => return kInvalidArgument,
+        };
 
         *info = mem::zeroed();
 
         let info = &mut *info;
-        info.cid.data = P::VST3_CLASS_ID;
+        info.cid.data = plugin.class_id;
         info.cardinality = vst3_sys::base::ClassCardinality::kManyInstances as i32;
         strlcpy(&mut info.category, "Audio Module Class");
-        u16strlcpy(&mut info.name, P::NAME);
-        info.class_flags = 1 << 1; // kSimpleModeSupported
-        strlcpy(&mut info.subcategories, P::VST3_CATEGORIES);
-        u16strlcpy(&mut info.vendor, P::VENDOR);
-        u16strlcpy(&mut info.version, P::VERSION);
+        u16strlcpy(&mut info.name, plugin.name);
+        info.class_flags = plugin.class_flags;
+        strlcpy(&mut info.subcategories, &plugin.subcategories);
+        u16strlcpy(&mut info.vendor, plugin.vendor);
+        u16strlcpy(&mut info.version, plugin.version);
         u16strlcpy(&mut info.sdk_version, VST3_SDK_VERSION);
 
         kResultOk
@@ -1042,13 +1881,66 @@ impl<P: Vst3Plugin> IPluginFactory3 for Factory<P> {
     }
 }
 
-/// Export a VST3 plugin from this library using the provided plugin type.
+impl IPluginCompatibility for Factory {
+    unsafe fn get_compatibility_json(&self, stream: SharedVstPtr<dyn IBStream>) -> tresult {
+        check_null_ptr!(stream);
+
+        // The class IDs are written as 32 character uppercase hexadecimal strings
+        let format_class_id = |class_id: &[u8; 16]| -> String {
+            class_id
+                .iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect()
+        };
+        let compatibility_entries: Vec<serde_json::Value> = self
+            .plugins
+            .iter()
+            .filter(|plugin| !plugin.compatibility_ids.is_empty())
+            .map(|plugin| {
+                let old_class_ids: Vec<String> = plugin
+                    .compatibility_ids
+                    .iter()
+                    .map(format_class_id)
+                    .collect();
+
+                serde_json::json!({
+                    "New": format_class_id(&plugin.class_id),
+                    "Old": old_class_ids,
+                })
+            })
+            .collect();
+        if compatibility_entries.is_empty() {
+            return kResultFalse;
+        }
+
+        let stream = stream.upgrade().unwrap();
+        let compatibility_json = serde_json::Value::Array(compatibility_entries).to_string();
+
+        let mut num_bytes_written = 0;
+        let result = stream.write(
+            compatibility_json.as_ptr() as *const c_void,
+            compatibility_json.len() as i32,
+            &mut num_bytes_written,
+        );
+
+        nih_debug_assert_eq!(result, kResultOk);
+        nih_debug_assert_eq!(num_bytes_written as usize, compatibility_json.len());
+        kResultOk
+    }
+}
+
+/// Export one or more VST3 plugins from this library using the provided plugin types. Multiple
+/// plugins can be bundled in a single library using `nih_export_vst3!(PluginA, PluginB)`. The
+/// plugins should all have their own class IDs, and the first plugin's vendor information is used
+/// for the library itself.
 #[macro_export]
 macro_rules! nih_export_vst3 {
-    ($plugin_ty:ty) => {
+    ($($plugin_ty:ty),+ $(,)?) => {
         #[no_mangle]
         pub extern "system" fn GetPluginFactory() -> *mut ::std::ffi::c_void {
-            let factory = ::nih_plug::wrapper::vst3::Factory::<$plugin_ty>::new();
+            let factory = ::nih_plug::wrapper::vst3::Factory::new(vec![
+                $(::nih_plug::wrapper::vst3::PluginDescriptor::for_plugin::<$plugin_ty>()),+
+            ]);
 
             Box::into_raw(factory) as *mut ::std::ffi::c_void
         }
@@ -1100,3 +1992,2207 @@ macro_rules! nih_export_vst3 {
         }
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use crossbeam::atomic::AtomicCell;
+    use parking_lot::{Mutex, RwLock};
+    use std::any::Any;
+    use std::cell::Cell;
+    use std::collections::HashMap;
+    use std::ffi::CStr;
+    use std::pin::Pin;
+    use vst3_sys::base::{kIBSeekCur, kIBSeekEnd, kIBSeekSet};
+    use vst3_sys::gui::{IPlugFrame, IPlugView, IPlugViewContentScaleSupport, ViewRect};
+
+    use super::*;
+    use crate::buffer::Buffer;
+    use crate::context::{
+        GuiContext, MainThreadExecutor, ParamSetter, ProcessContext, Transport,
+        TransportRequirements,
+    };
+    use crate::param::internals::{deserialize_field, serialize_field, Params, StateFormat};
+    use crate::param::midi_learn::MidiCcMap;
+    use crate::param::range::Range;
+    use crate::param::smoothing::{Smoother, SmoothingStyle};
+    use crate::param::{BoolParam, FloatParam, IntParam, MeterParam, Param};
+    use crate::plugin::{Editor, Vst3SubCategory};
+    use crate::wrapper::inner::{
+        ParamChangeSource, PendingEdit, Task, PENDING_EDITS_CAPACITY,
+        PENDING_GESTURE_EDITS_RESERVED,
+    };
+    use crate::wrapper::util::hash_param_id;
+    use crate::ParentWindowHandle;
+
+    /// An in-memory [IBStream] implementation so we can test the state saving and restoring
+    /// without a host.
+    #[VST3(implements(IBStream))]
+    struct MemoryStream {
+        data: Mutex<Vec<u8>>,
+        position: Mutex<usize>,
+    }
+
+    impl MemoryStream {
+        fn new(data: Vec<u8>) -> Box<Self> {
+            Self::allocate(Mutex::new(data), Mutex::new(0))
+        }
+
+        /// Get a pointer to this object's `IBStream` interface. The pointer is only valid for as
+        /// long as this object is alive.
+        unsafe fn as_shared_ptr(&self) -> SharedVstPtr<dyn IBStream> {
+            // This object only implements a single interface, so the pointer to the object is also
+            // the pointer to its `IBStream` vtable
+            mem::transmute(self as *const Self)
+        }
+    }
+
+    impl IBStream for MemoryStream {
+        unsafe fn read(
+            &self,
+            buffer: *mut c_void,
+            num_bytes: i32,
+            num_bytes_read: *mut i32,
+        ) -> tresult {
+            check_null_ptr!(buffer);
+
+            let data = self.data.lock();
+            let mut position = self.position.lock();
+            let num_bytes = cmp::min(
+                num_bytes.max(0) as usize,
+                data.len().saturating_sub(*position),
+            );
+            ptr::copy_nonoverlapping(data[*position..].as_ptr(), buffer as *mut u8, num_bytes);
+            *position += num_bytes;
+
+            if !num_bytes_read.is_null() {
+                *num_bytes_read = num_bytes as i32;
+            }
+
+            kResultOk
+        }
+
+        unsafe fn write(
+            &self,
+            buffer: *const c_void,
+            num_bytes: i32,
+            num_bytes_written: *mut i32,
+        ) -> tresult {
+            check_null_ptr!(buffer);
+
+            let mut data = self.data.lock();
+            let mut position = self.position.lock();
+            let buffer = std::slice::from_raw_parts(buffer as *const u8, num_bytes.max(0) as usize);
+            let end_position = *position + buffer.len();
+            if data.len() < end_position {
+                data.resize(end_position, 0);
+            }
+            data[*position..end_position].copy_from_slice(buffer);
+            *position = end_position;
+
+            if !num_bytes_written.is_null() {
+                *num_bytes_written = buffer.len() as i32;
+            }
+
+            kResultOk
+        }
+
+        unsafe fn seek(&self, pos: i64, mode: i32, result: *mut i64) -> tresult {
+            let data_len = self.data.lock().len() as i64;
+            let mut position = self.position.lock();
+            let new_position = match mode {
+                m if m == kIBSeekSet => pos,
+                m if m == kIBSeekCur => *position as i64 + pos,
+                m if m == kIBSeekEnd => data_len + pos,
+                _ => return kInvalidArgument,
+            };
+            if new_position < 0 || new_position > data_len {
+                return kResultFalse;
+            }
+
+            *position = new_position as usize;
+            if !result.is_null() {
+                *result = new_position;
+            }
+
+            kResultOk
+        }
+
+        unsafe fn tell(&self, pos: *mut i64) -> tresult {
+            check_null_ptr!(pos);
+
+            *pos = *self.position.lock() as i64;
+
+            kResultOk
+        }
+    }
+
+    /// A single parameter's [IParamValueQueue] with a fixed list of `(sample_offset, value)`
+    /// points, so we can send parameter changes to the wrapper without a host.
+    #[VST3(implements(IParamValueQueue))]
+    struct TestParamValueQueue {
+        param_hash: u32,
+        points: Vec<(i32, f64)>,
+    }
+
+    impl TestParamValueQueue {
+        fn new(param_hash: u32, points: Vec<(i32, f64)>) -> Box<Self> {
+            Self::allocate(param_hash, points)
+        }
+    }
+
+    impl IParamValueQueue for TestParamValueQueue {
+        unsafe fn get_parameter_id(&self) -> u32 {
+            self.param_hash
+        }
+
+        unsafe fn get_point_count(&self) -> i32 {
+            self.points.len() as i32
+        }
+
+        unsafe fn get_point(
+            &self,
+            index: i32,
+            sample_offset: *mut i32,
+            value: *mut f64,
+        ) -> tresult {
+            check_null_ptr!(sample_offset, value);
+
+            match usize::try_from(index)
+                .ok()
+                .and_then(|index| self.points.get(index))
+            {
+                Some(&(point_sample_offset, point_value)) => {
+                    *sample_offset = point_sample_offset;
+                    *value = point_value;
+
+                    kResultOk
+                }
+                None => kInvalidArgument,
+            }
+        }
+
+        unsafe fn add_point(&self, _sample_offset: i32, _value: f64, _index: *mut i32) -> tresult {
+            // These queues are only used as inputs
+            kResultFalse
+        }
+    }
+
+    /// An [IParameterChanges] implementation containing a fixed set of [TestParamValueQueue]s.
+    #[VST3(implements(IParameterChanges))]
+    struct TestParameterChanges {
+        queues: Vec<Box<TestParamValueQueue>>,
+    }
+
+    impl TestParameterChanges {
+        fn new(queues: Vec<Box<TestParamValueQueue>>) -> Box<Self> {
+            Self::allocate(queues)
+        }
+
+        /// Get a pointer to this object's `IParameterChanges` interface. The pointer is only valid
+        /// for as long as this object is alive.
+        unsafe fn as_shared_ptr(&self) -> SharedVstPtr<dyn IParameterChanges> {
+            mem::transmute(self as *const Self)
+        }
+    }
+
+    impl IParameterChanges for TestParameterChanges {
+        unsafe fn get_parameter_count(&self) -> i32 {
+            self.queues.len() as i32
+        }
+
+        unsafe fn get_parameter_data(&self, index: i32) -> SharedVstPtr<dyn IParamValueQueue> {
+            match usize::try_from(index)
+                .ok()
+                .and_then(|index| self.queues.get(index))
+            {
+                Some(queue) => mem::transmute(&**queue as *const TestParamValueQueue),
+                None => mem::transmute(ptr::null::<TestParamValueQueue>()),
+            }
+        }
+
+        unsafe fn add_parameter_data(
+            &self,
+            _id: *const u32,
+            _index: *mut i32,
+        ) -> SharedVstPtr<dyn IParamValueQueue> {
+            // These changes are only used as inputs
+            mem::transmute(ptr::null::<TestParamValueQueue>())
+        }
+    }
+
+    /// A plugin with one parameter of every type and a couple persistent fields. The [Params]
+    /// trait is implemented by hand because the derive macro can't be used from within this crate.
+    struct TestPlugin {
+        params: Pin<Box<TestParams>>,
+    }
+
+    struct TestParams {
+        float: FloatParam,
+        int: IntParam,
+        bool: BoolParam,
+        meter: MeterParam,
+        mode: IntParam,
+        field: RwLock<Vec<u32>>,
+        impulse_response: RwLock<Vec<f32>>,
+        midi_cc_map: RwLock<MidiCcMap>,
+    }
+
+    impl Default for TestPlugin {
+        fn default() -> Self {
+            Self {
+                params: Box::pin(TestParams::default()),
+            }
+        }
+    }
+
+    impl Default for TestParams {
+        fn default() -> Self {
+            Self {
+                float: FloatParam {
+                    value: 0.0,
+                    range: Range::Linear {
+                        min: -10.0,
+                        max: 10.0,
+                    },
+                    smoothed: Smoother::new(SmoothingStyle::Linear(100.0)),
+                    name: "Float",
+                    ..Default::default()
+                },
+                int: IntParam {
+                    value: 0,
+                    range: Range::Linear { min: -5, max: 5 },
+                    name: "Int",
+                    ..Default::default()
+                },
+                bool: BoolParam {
+                    value: false,
+                    name: "Bool",
+                    ..Default::default()
+                },
+                meter: MeterParam {
+                    range: Range::Linear { min: 0.0, max: 2.0 },
+                    name: "Meter",
+                    ..Default::default()
+                },
+                mode: IntParam {
+                    value: 0,
+                    range: Range::Linear { min: 0, max: 3 },
+                    name: "Mode",
+                    value_to_string: Some(Arc::new(|value| {
+                        String::from(
+                            ["Low-pass", "High-pass", "Band-pass", "Notch"][value as usize],
+                        )
+                    })),
+                    ..Default::default()
+                },
+                field: RwLock::new(Vec::new()),
+                impulse_response: RwLock::new(Vec::new()),
+                midi_cc_map: RwLock::new(MidiCcMap::new()),
+            }
+        }
+    }
+
+    impl Params for TestParams {
+        fn param_map(self: Pin<&Self>) -> HashMap<&'static str, ParamPtr> {
+            let mut param_map = HashMap::new();
+            param_map.insert("float", self.float.as_ptr());
+            param_map.insert("int", self.int.as_ptr());
+            param_map.insert("bool", self.bool.as_ptr());
+            param_map.insert("meter", self.meter.as_ptr());
+            param_map.insert("mode", self.mode.as_ptr());
+
+            param_map
+        }
+
+        fn param_ids(self: Pin<&Self>) -> &'static [&'static str] {
+            &["float", "int", "bool", "meter", "mode"]
+        }
+
+        fn param_groups(self: Pin<&Self>) -> HashMap<&'static str, &'static str> {
+            let mut param_groups = HashMap::new();
+            param_groups.insert("float", "Filter/Advanced");
+            param_groups.insert("mode", "Filter");
+
+            param_groups
+        }
+
+        fn serialize_fields(&self, format: StateFormat) -> HashMap<String, Vec<u8>> {
+            let mut serialized = HashMap::new();
+            serialized.insert(
+                String::from("field"),
+                serialize_field(&*self.field.read(), format).unwrap(),
+            );
+            serialized.insert(
+                String::from("impulse_response"),
+                serialize_field(&*self.impulse_response.read(), format).unwrap(),
+            );
+            serialized.insert(
+                String::from("midi_cc_map"),
+                serialize_field(&*self.midi_cc_map.read(), format).unwrap(),
+            );
+
+            serialized
+        }
+
+        fn deserialize_fields(&self, serialized: &HashMap<String, Vec<u8>>, format: StateFormat) {
+            if let Some(data) = serialized.get("field") {
+                *self.field.write() = deserialize_field(data, format).unwrap();
+            }
+            if let Some(data) = serialized.get("impulse_response") {
+                *self.impulse_response.write() = deserialize_field(data, format).unwrap();
+            }
+            if let Some(data) = serialized.get("midi_cc_map") {
+                *self.midi_cc_map.write() = deserialize_field(data, format).unwrap();
+            }
+        }
+    }
+
+    impl Plugin for TestPlugin {
+        const NAME: &'static str = "Test Plugin";
+        const VENDOR: &'static str = "NIH-plug";
+        const URL: &'static str = "https://example.com";
+        const EMAIL: &'static str = "info@example.com";
+
+        const VERSION: &'static str = "0.0.1";
+
+        const STATE_VERSION: u32 = 2;
+
+        const ACCEPTS_MIDI: bool = true;
+        const TRANSPORT_REQUIREMENTS: TransportRequirements = TransportRequirements {
+            transport_state: true,
+            tempo: true,
+            time_signature: true,
+            ..TransportRequirements::NONE
+        };
+
+        fn params(&self) -> Pin<&dyn Params> {
+            self.params.as_ref()
+        }
+
+        fn migrate_state(&self, from_version: u32, state: &mut state::State) {
+            // Version 1 stored `float` and `field` under different names
+            if from_version < 2 {
+                if let Some(value) = state.params.remove("old_float") {
+                    state.params.insert(String::from("float"), value);
+                }
+                if let Some(data) = state.fields.remove("values") {
+                    state.fields.insert(String::from("field"), data);
+                }
+            }
+        }
+
+        fn process(
+            &mut self,
+            _buffer: &mut Buffer,
+            _context: &mut impl ProcessContext,
+        ) -> ProcessStatus {
+            ProcessStatus::Normal
+        }
+    }
+
+    impl Vst3Plugin for TestPlugin {
+        const VST3_CLASS_ID: [u8; 16] = *b"NihPlugTestPlugn";
+        const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] =
+            &[Vst3SubCategory::Instrument, Vst3SubCategory::Synth];
+        const VST3_DISTRIBUTABLE: bool = true;
+    }
+
+    /// A plugin that panics while processing audio, and optionally while being initialized.
+    struct PanickingPlugin {
+        params: Pin<Box<TestParams>>,
+        panic_on_initialize: bool,
+        num_process_calls: u32,
+    }
+
+    impl Default for PanickingPlugin {
+        fn default() -> Self {
+            Self {
+                params: Box::pin(TestParams::default()),
+                panic_on_initialize: false,
+                num_process_calls: 0,
+            }
+        }
+    }
+
+    impl Plugin for PanickingPlugin {
+        const NAME: &'static str = "Panicking Plugin";
+        const VENDOR: &'static str = "NIH-plug";
+        const URL: &'static str = "https://example.com";
+        const EMAIL: &'static str = "info@example.com";
+
+        const VERSION: &'static str = "0.0.1";
+
+        fn params(&self) -> Pin<&dyn Params> {
+            self.params.as_ref()
+        }
+
+        fn initialize(
+            &mut self,
+            _bus_config: &BusConfig,
+            _buffer_config: &BufferConfig,
+            _context: &mut impl ProcessContext,
+        ) -> bool {
+            if self.panic_on_initialize {
+                panic!("Panicking during initialization");
+            }
+
+            true
+        }
+
+        fn process(
+            &mut self,
+            _buffer: &mut Buffer,
+            _context: &mut impl ProcessContext,
+        ) -> ProcessStatus {
+            self.num_process_calls += 1;
+            panic!("Panicking during processing");
+        }
+    }
+
+    impl Vst3Plugin for PanickingPlugin {
+        const VST3_CLASS_ID: [u8; 16] = *b"NihPlugPanicPlug";
+        const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[Vst3SubCategory::Fx];
+    }
+
+    /// The normalized values for all parameters, including the bypass parameter, in a stable
+    /// order.
+    unsafe fn param_values(wrapper: &Wrapper<TestPlugin>) -> Vec<f64> {
+        ["float", "int", "bool"]
+            .iter()
+            .map(|id| hash_param_id(id))
+            .chain([*BYPASS_PARAM_HASH])
+            .map(|hash| wrapper.get_param_normalized(hash))
+            .collect()
+    }
+
+    #[test]
+    fn state_round_trip() {
+        unsafe {
+            let wrapper = Wrapper::<TestPlugin>::new();
+            let default_values = param_values(&wrapper);
+
+            // Normalized values that map exactly to values within these parameters' ranges
+            wrapper.set_param_normalized(hash_param_id("float"), 0.75);
+            wrapper.set_param_normalized(hash_param_id("int"), 0.2);
+            wrapper.set_param_normalized(hash_param_id("bool"), 1.0);
+            wrapper.set_param_normalized(*BYPASS_PARAM_HASH, 1.0);
+            *wrapper.inner.plugin.read().params.field.write() = vec![1, 2, 3];
+            let expected_values = param_values(&wrapper);
+            assert_ne!(expected_values, default_values);
+
+            let stream = MemoryStream::new(Vec::new());
+            assert_eq!(
+                IComponent::get_state(&*wrapper, stream.as_shared_ptr()),
+                kResultOk
+            );
+            assert!(!stream.data.lock().is_empty());
+
+            // The state should be restored into a fresh instance
+            stream.seek(0, kIBSeekSet, ptr::null_mut());
+            let restored_wrapper = Wrapper::<TestPlugin>::new();
+            assert_eq!(
+                IComponent::set_state(&*restored_wrapper, stream.as_shared_ptr()),
+                kResultOk
+            );
+
+            assert_eq!(param_values(&restored_wrapper), expected_values);
+            assert_eq!(
+                *restored_wrapper.inner.plugin.read().params.field.read(),
+                vec![1, 2, 3]
+            );
+        }
+    }
+
+    #[test]
+    fn large_field_round_trip() {
+        unsafe {
+            let impulse_response: Vec<f32> = (0..48000)
+                .map(|i| (i as f32 * 0.01).sin() * (-(i as f32) / 10000.0).exp())
+                .collect();
+            let wrapper = Wrapper::<TestPlugin>::new();
+            *wrapper.inner.plugin.read().params.impulse_response.write() = impulse_response.clone();
+
+            let stream = MemoryStream::new(Vec::new());
+            assert_eq!(
+                IComponent::get_state(&*wrapper, stream.as_shared_ptr()),
+                kResultOk
+            );
+
+            let json_state = stream.data.lock().clone();
+
+            stream.seek(0, kIBSeekSet, ptr::null_mut());
+            let restored_wrapper = Wrapper::<TestPlugin>::new();
+            assert_eq!(
+                IComponent::set_state(&*restored_wrapper, stream.as_shared_ptr()),
+                kResultOk
+            );
+            assert_eq!(
+                *restored_wrapper
+                    .inner
+                    .plugin
+                    .read()
+                    .params
+                    .impulse_response
+                    .read(),
+                impulse_response
+            );
+
+            // The same state stored in the binary format, as plugins with `BINARY_STATE` write it,
+            // should take up far less space than the JSON representation and load just the same
+            let mut binary_state = state::deserialize(&json_state).unwrap();
+            binary_state.fields = wrapper
+                .inner
+                .plugin
+                .read()
+                .params
+                .serialize_fields(StateFormat::Binary);
+            binary_state.format = StateFormat::Binary;
+            let serialized = state::serialize(&binary_state).unwrap();
+            assert!(serialized.starts_with(b"NIH-plug"));
+            assert!(serialized.len() < impulse_response.len() * 6);
+            assert!(serialized.len() < json_state.len());
+
+            let stream = MemoryStream::new(serialized);
+            let restored_wrapper = Wrapper::<TestPlugin>::new();
+            assert_eq!(
+                IComponent::set_state(&*restored_wrapper, stream.as_shared_ptr()),
+                kResultOk
+            );
+            assert_eq!(
+                *restored_wrapper
+                    .inner
+                    .plugin
+                    .read()
+                    .params
+                    .impulse_response
+                    .read(),
+                impulse_response
+            );
+        }
+    }
+
+    #[test]
+    fn midi_cc_map_round_trip() {
+        unsafe {
+            let wrapper = Wrapper::<TestPlugin>::new();
+            {
+                let plugin = wrapper.inner.plugin.read();
+                let mut midi_cc_map = plugin.params.midi_cc_map.write();
+                midi_cc_map.assign(1, "float");
+                midi_cc_map.assign(74, "int");
+                midi_cc_map.assign(75, "int");
+            }
+
+            let stream = MemoryStream::new(Vec::new());
+            assert_eq!(
+                IComponent::get_state(&*wrapper, stream.as_shared_ptr()),
+                kResultOk
+            );
+
+            stream.seek(0, kIBSeekSet, ptr::null_mut());
+            let restored_wrapper = Wrapper::<TestPlugin>::new();
+            assert_eq!(
+                IComponent::set_state(&*restored_wrapper, stream.as_shared_ptr()),
+                kResultOk
+            );
+
+            let plugin = restored_wrapper.inner.plugin.read();
+            let midi_cc_map = plugin.params.midi_cc_map.read();
+            assert_eq!(
+                *midi_cc_map,
+                *wrapper.inner.plugin.read().params.midi_cc_map.read()
+            );
+            assert_eq!(midi_cc_map.get(1), Some("float"));
+            assert_eq!(
+                midi_cc_map.ccs_for_param("int").collect::<Vec<_>>(),
+                [74, 75]
+            );
+            assert_eq!(midi_cc_map.get(2), None);
+        }
+    }
+
+    #[test]
+    fn state_with_leading_data() {
+        unsafe {
+            let wrapper = Wrapper::<TestPlugin>::new();
+            wrapper.set_param_normalized(hash_param_id("float"), 0.25);
+            let expected_values = param_values(&wrapper);
+
+            // Some hosts, like Bitwig, put their own data in the stream before the plugin's state
+            let stream = MemoryStream::new(b"HEADER".to_vec());
+            stream.seek(0, kIBSeekEnd, ptr::null_mut());
+            assert_eq!(
+                IComponent::get_state(&*wrapper, stream.as_shared_ptr()),
+                kResultOk
+            );
+
+            stream.seek(6, kIBSeekSet, ptr::null_mut());
+            let restored_wrapper = Wrapper::<TestPlugin>::new();
+            assert_eq!(
+                IComponent::set_state(&*restored_wrapper, stream.as_shared_ptr()),
+                kResultOk
+            );
+            assert_eq!(param_values(&restored_wrapper), expected_values);
+        }
+    }
+
+    #[test]
+    fn state_with_unknown_parameter() {
+        unsafe {
+            let stream = MemoryStream::new(
+                br#"{"params":{"float":{"f32":5.0},"does_not_exist":{"f32":1.0}},"fields":{}}"#
+                    .to_vec(),
+            );
+            let wrapper = Wrapper::<TestPlugin>::new();
+            assert_eq!(
+                IComponent::set_state(&*wrapper, stream.as_shared_ptr()),
+                kResultOk
+            );
+
+            // The known parameters should still be restored
+            assert_eq!(wrapper.get_param_normalized(hash_param_id("float")), 0.75);
+        }
+    }
+
+    #[test]
+    fn state_load_resets_smoothers() {
+        unsafe {
+            let wrapper = Wrapper::<TestPlugin>::new();
+            wrapper
+                .inner
+                .current_buffer_config
+                .store(Some(BufferConfig {
+                    sample_rate: 100.0,
+                    max_buffer_size: 64,
+                    sample_precision: SamplePrecision::Single,
+                }));
+
+            // This starts a 10 sample ramp from 0.0 to 5.0, and the loaded state then contains the
+            // smoother's target value
+            let float_hash = hash_param_id("float");
+            wrapper.set_param_normalized(float_hash, 0.75);
+            {
+                let plugin = wrapper.inner.plugin.read();
+                plugin.params.float.smoothed.next();
+                plugin.params.float.smoothed.next();
+                assert!(plugin.params.float.smoothed.is_smoothing());
+            }
+
+            let stream =
+                MemoryStream::new(br#"{"params":{"float":{"f32":5.0}},"fields":{}}"#.to_vec());
+            assert_eq!(
+                IComponent::set_state(&*wrapper, stream.as_shared_ptr()),
+                kResultOk
+            );
+
+            let plugin = wrapper.inner.plugin.read();
+            assert!(!plugin.params.float.smoothed.is_smoothing());
+            assert_eq!(plugin.params.float.smoothed.next(), 5.0);
+
+            // Parameters that aren't part of the state should also not keep smoothing
+            drop(plugin);
+            wrapper.set_param_normalized(float_hash, 0.25);
+            let stream = MemoryStream::new(br#"{"params":{},"fields":{}}"#.to_vec());
+            assert_eq!(
+                IComponent::set_state(&*wrapper, stream.as_shared_ptr()),
+                kResultOk
+            );
+
+            let plugin = wrapper.inner.plugin.read();
+            assert!(!plugin.params.float.smoothed.is_smoothing());
+            assert_eq!(plugin.params.float.smoothed.next(), -5.0);
+        }
+    }
+
+    #[test]
+    fn state_migration() {
+        unsafe {
+            let old_state = state::State {
+                version: 1,
+                params: HashMap::from([(String::from("old_float"), state::ParamValue::F32(5.0))]),
+                fields: HashMap::from([(String::from("values"), b"[1,2,3]".to_vec())]),
+                format: StateFormat::Json,
+            };
+            let stream = MemoryStream::new(state::serialize(&old_state).unwrap());
+            let wrapper = Wrapper::<TestPlugin>::new();
+            assert_eq!(
+                IComponent::set_state(&*wrapper, stream.as_shared_ptr()),
+                kResultOk
+            );
+
+            assert_eq!(wrapper.get_param_normalized(hash_param_id("float")), 0.75);
+            assert_eq!(
+                *wrapper.inner.plugin.read().params.field.read(),
+                vec![1, 2, 3]
+            );
+
+            // Saving the state again should store it in the current format
+            let stream = MemoryStream::new(Vec::new());
+            assert_eq!(
+                IComponent::get_state(&*wrapper, stream.as_shared_ptr()),
+                kResultOk
+            );
+            let new_state = state::deserialize(&stream.data.lock()).unwrap();
+            assert_eq!(new_state.version, 2);
+            assert!(new_state.fields.contains_key("field"));
+            assert!(!new_state.fields.contains_key("values"));
+        }
+    }
+
+    #[test]
+    fn state_from_newer_version() {
+        unsafe {
+            let wrapper = Wrapper::<TestPlugin>::new();
+            let default_values = param_values(&wrapper);
+
+            let stream = MemoryStream::new(
+                br#"{"version":3,"params":{"float":{"f32":5.0}},"fields":{}}"#.to_vec(),
+            );
+            assert_eq!(
+                IComponent::set_state(&*wrapper, stream.as_shared_ptr()),
+                kResultFalse
+            );
+            assert_eq!(param_values(&wrapper), default_values);
+        }
+    }
+
+    #[test]
+    fn empty_state() {
+        unsafe {
+            let wrapper = Wrapper::<TestPlugin>::new();
+            let default_values = param_values(&wrapper);
+
+            let stream = MemoryStream::new(Vec::new());
+            assert_eq!(
+                IComponent::set_state(&*wrapper, stream.as_shared_ptr()),
+                kResultFalse
+            );
+            assert_eq!(param_values(&wrapper), default_values);
+        }
+    }
+
+    #[test]
+    fn meters_are_read_only() {
+        unsafe {
+            let wrapper = Wrapper::<TestPlugin>::new();
+            let meter_hash = hash_param_id("meter");
+            wrapper.inner.plugin.read().params.meter.set(1.0);
+            assert_eq!(wrapper.get_param_normalized(meter_hash), 0.5);
+
+            // The host should not be able to change the meter's value
+            assert_eq!(wrapper.set_param_normalized(meter_hash, 1.0), kResultFalse);
+            assert_eq!(wrapper.get_param_normalized(meter_hash), 0.5);
+
+            // But the plugin's own updates are still reported to the host
+            wrapper.inner.plugin.read().params.meter.set(0.5);
+            assert_eq!(wrapper.get_param_normalized(meter_hash), 0.25);
+
+            let mut info: vst3_sys::vst::ParameterInfo = mem::zeroed();
+            assert_eq!(wrapper.get_parameter_info(3, &mut info), kResultOk);
+            assert_eq!(info.id, meter_hash);
+            assert_eq!(
+                info.flags,
+                vst3_sys::vst::ParameterFlags::kIsReadOnly as i32
+            );
+
+            // And it should not end up in the plugin's state
+            let stream = MemoryStream::new(Vec::new());
+            assert_eq!(
+                IComponent::get_state(&*wrapper, stream.as_shared_ptr()),
+                kResultOk
+            );
+            let state = state::deserialize(&stream.data.lock()).unwrap();
+            assert!(!state.params.contains_key("meter"));
+        }
+    }
+
+    #[test]
+    fn discrete_params_with_value_strings_are_lists() {
+        unsafe {
+            let wrapper = Wrapper::<TestPlugin>::new();
+            let mode_hash = hash_param_id("mode");
+
+            let mut info: vst3_sys::vst::ParameterInfo = mem::zeroed();
+            assert_eq!(wrapper.get_parameter_info(4, &mut info), kResultOk);
+            assert_eq!(info.id, mode_hash);
+            assert_eq!(info.step_count, 3);
+            assert_ne!(
+                info.flags & vst3_sys::vst::ParameterFlags::kIsList as i32,
+                0
+            );
+
+            // Integer parameters without value strings are not lists
+            assert_eq!(wrapper.get_parameter_info(1, &mut info), kResultOk);
+            assert_eq!(
+                info.flags & vst3_sys::vst::ParameterFlags::kIsList as i32,
+                0
+            );
+
+            let mut string = [0 as TChar; 128];
+            assert_eq!(
+                wrapper.get_param_string_by_value(mode_hash, 1.0 / 3.0, string.as_mut_ptr()),
+                kResultOk
+            );
+            assert_eq!(
+                U16CStr::from_ptr_str(string.as_ptr() as *const u16).to_string_lossy(),
+                "High-pass"
+            );
+        }
+    }
+
+    #[test]
+    fn param_groups_are_units() {
+        unsafe {
+            let wrapper = Wrapper::<TestPlugin>::new();
+            let unit_info = |unit_index: i32| {
+                let mut info: vst3_sys::vst::UnitInfo = mem::zeroed();
+                assert_eq!(wrapper.get_unit_info(unit_index, &mut info), kResultOk);
+                let name =
+                    U16CStr::from_ptr_str(info.name.as_ptr() as *const u16).to_string_lossy();
+
+                (info.id, info.parent_unit_id, name)
+            };
+
+            // The root unit comes first, and the parent group is added before its child group
+            assert_eq!(wrapper.get_unit_count(), 3);
+            let (root_id, _, _) = unit_info(0);
+            let (filter_id, filter_parent_id, filter_name) = unit_info(1);
+            let (advanced_id, advanced_parent_id, advanced_name) = unit_info(2);
+            assert_eq!(root_id, vst3_sys::vst::kRootUnitId);
+            assert_eq!(filter_parent_id, root_id);
+            assert_eq!(filter_name, "Filter");
+            assert_eq!(advanced_parent_id, filter_id);
+            assert_eq!(advanced_name, "Advanced");
+            let mut info: vst3_sys::vst::UnitInfo = mem::zeroed();
+            assert_eq!(wrapper.get_unit_info(3, &mut info), kInvalidArgument);
+
+            let param_unit_id = |param_index: i32| {
+                let mut info: vst3_sys::vst::ParameterInfo = mem::zeroed();
+                assert_eq!(
+                    wrapper.get_parameter_info(param_index, &mut info),
+                    kResultOk
+                );
+
+                info.unit_id
+            };
+            assert_eq!(param_unit_id(0), advanced_id);
+            assert_eq!(param_unit_id(1), root_id);
+            assert_eq!(param_unit_id(4), filter_id);
+            // The bypass parameter is always at the top level
+            assert_eq!(param_unit_id(5), root_id);
+        }
+    }
+
+    #[test]
+    fn editor_edits_are_throttled() {
+        unsafe {
+            let wrapper = Wrapper::<TestPlugin>::new();
+            let float_ptr = wrapper.inner.plugin.read().params.float.as_ptr();
+            let float_hash = hash_param_id("float");
+
+            // At one edit per second, only the first and the last edit of this gesture are sent
+            let setter = ParamSetter::new(&*wrapper.inner);
+            setter.set_max_edit_rate(Some(1.0));
+            wrapper.inner.raw_begin_set_parameter(float_ptr);
+            wrapper.inner.raw_set_parameter_normalized(float_ptr, 0.25);
+            wrapper.inner.raw_set_parameter_normalized(float_ptr, 0.5);
+            wrapper.inner.raw_set_parameter_normalized(float_ptr, 0.75);
+            wrapper.inner.raw_end_set_parameter(float_ptr);
+
+            let edits: Vec<PendingEdit> =
+                std::iter::from_fn(|| wrapper.inner.pending_edits.pop()).collect();
+            assert!(matches!(
+                edits[..],
+                [
+                    PendingEdit::Begin(begin_hash),
+                    PendingEdit::Perform(first_hash, first),
+                    PendingEdit::Perform(last_hash, last),
+                    PendingEdit::End(end_hash),
+                ] if [begin_hash, first_hash, last_hash, end_hash] == [float_hash; 4]
+                    && first == 0.25
+                    && last == 0.75
+            ));
+
+            // The parameter itself is always updated right away
+            assert_eq!(wrapper.get_param_normalized(float_hash), 0.75);
+        }
+    }
+
+    #[test]
+    fn gestures_survive_a_full_pending_edits_queue() {
+        unsafe {
+            let wrapper = Wrapper::<TestPlugin>::new();
+            let float_ptr = wrapper.inner.plugin.read().params.float.as_ptr();
+            let float_hash = hash_param_id("float");
+
+            // Without a component handler these edits pile up until the queue is full, but the end
+            // of the gesture should still make it through
+            wrapper.inner.raw_begin_set_parameter(float_ptr);
+            for _ in 0..PENDING_EDITS_CAPACITY {
+                wrapper.inner.raw_set_parameter_normalized(float_ptr, 0.5);
+            }
+            wrapper.inner.raw_end_set_parameter(float_ptr);
+
+            let edits: Vec<PendingEdit> =
+                std::iter::from_fn(|| wrapper.inner.pending_edits.pop()).collect();
+            assert_eq!(
+                edits.len(),
+                PENDING_EDITS_CAPACITY - PENDING_GESTURE_EDITS_RESERVED + 1
+            );
+            assert!(matches!(edits.first(), Some(PendingEdit::Begin(hash)) if *hash == float_hash));
+            assert!(matches!(edits.last(), Some(PendingEdit::End(hash)) if *hash == float_hash));
+        }
+    }
+
+    #[test]
+    fn smoothers_reset_on_restart() {
+        unsafe {
+            let wrapper = Wrapper::<TestPlugin>::new();
+            let float_hash = hash_param_id("float");
+            assert_eq!(IAudioProcessor::set_processing(&*wrapper, 1), kResultOk);
+
+            // A parameter that has already reached its target should stay there
+            let target = {
+                let plugin = wrapper.inner.plugin.read();
+                plugin.params.float.smoothed.next()
+            };
+            assert_eq!(IAudioProcessor::set_processing(&*wrapper, 0), kResultOk);
+            assert_eq!(IAudioProcessor::set_processing(&*wrapper, 1), kResultOk);
+            {
+                let plugin = wrapper.inner.plugin.read();
+                assert!(!plugin.params.float.smoothed.is_smoothing());
+                assert_eq!(plugin.params.float.smoothed.next(), target);
+            }
+
+            // And a parameter that was still being smoothed when processing stopped should jump
+            // straight to its target when processing resumes
+            wrapper.inner.set_normalized_value_by_hash(
+                float_hash,
+                1.0,
+                Some(44_100.0),
+                ParamChangeSource::Host,
+            );
+            assert!(wrapper
+                .inner
+                .plugin
+                .read()
+                .params
+                .float
+                .smoothed
+                .is_smoothing());
+            assert_eq!(IAudioProcessor::set_processing(&*wrapper, 0), kResultOk);
+            assert_eq!(IAudioProcessor::set_processing(&*wrapper, 1), kResultOk);
+            {
+                let plugin = wrapper.inner.plugin.read();
+                assert!(!plugin.params.float.smoothed.is_smoothing());
+                assert_eq!(
+                    plugin.params.float.smoothed.next(),
+                    plugin.params.float.plain_value()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn raw_midi_to_note_events() {
+        let wrapper = Wrapper::<TestPlugin>::new();
+        let inner = &wrapper.inner;
+
+        assert_eq!(
+            inner.midi_to_note_event(3, [0x91, 60, 100]),
+            Some(NoteEvent::NoteOn {
+                timing: 3,
+                note_id: None,
+                channel: 1,
+                note: 60,
+                velocity: 100
+            })
+        );
+        // Note on events with a zero velocity are note off events
+        assert_eq!(
+            inner.midi_to_note_event(0, [0x90, 60, 0]),
+            Some(NoteEvent::NoteOff {
+                timing: 0,
+                note_id: None,
+                channel: 0,
+                note: 60,
+                velocity: 0
+            })
+        );
+        assert_eq!(
+            inner.midi_to_note_event(0, [0xA3, 64, 127]),
+            Some(NoteEvent::PolyPressure {
+                timing: 0,
+                note_id: None,
+                channel: 3,
+                note: 64,
+                pressure: 1.0
+            })
+        );
+        assert_eq!(
+            inner.midi_to_note_event(0, [0xE0, 0x00, 0x40]),
+            Some(NoteEvent::MidiPitchBend {
+                timing: 0,
+                channel: 0,
+                value: 8192.0 / 16383.0
+            })
+        );
+
+        // Both halves of the bank number are remembered per channel
+        inner.midi_to_note_event(0, [0xB2, 0, 1]);
+        assert_eq!(
+            inner.midi_to_note_event(0, [0xB2, 32, 5]),
+            Some(NoteEvent::MidiBankSelect {
+                timing: 0,
+                channel: 2,
+                bank: (1 << 7) | 5
+            })
+        );
+
+        // System messages can't be represented as note events
+        assert_eq!(inner.midi_to_note_event(0, [0xF8, 0, 0]), None);
+    }
+
+    #[test]
+    fn vst3_events_to_note_events() {
+        unsafe {
+            let wrapper = Wrapper::<TestPlugin>::new();
+            let inner = &wrapper.inner;
+            let new_event = |type_: vst3_sys::vst::EventTypes| {
+                let mut event: vst3_sys::vst::Event = mem::zeroed();
+                event.type_ = type_ as u16;
+                event
+            };
+
+            // The note ID is passed on so expression events can be matched to this note
+            let mut event = new_event(vst3_sys::vst::EventTypes::kNoteOnEvent);
+            event.event.note_on.channel = 1;
+            event.event.note_on.pitch = 60;
+            event.event.note_on.velocity = 1.0;
+            event.event.note_on.note_id = 42;
+            assert_eq!(
+                inner.vst3_event_to_note_event(5, &event),
+                Some(NoteEvent::NoteOn {
+                    timing: 5,
+                    note_id: Some(42),
+                    channel: 1,
+                    note: 60,
+                    velocity: 127
+                })
+            );
+
+            let mut event = new_event(vst3_sys::vst::EventTypes::kPolyPressureEvent);
+            event.event.poly_pressure.channel = 1;
+            event.event.poly_pressure.pitch = 60;
+            event.event.poly_pressure.pressure = 0.5;
+            event.event.poly_pressure.note_id = -1;
+            assert_eq!(
+                inner.vst3_event_to_note_event(0, &event),
+                Some(NoteEvent::PolyPressure {
+                    timing: 0,
+                    note_id: None,
+                    channel: 1,
+                    note: 60,
+                    pressure: 0.5
+                })
+            );
+
+            let mut event = new_event(vst3_sys::vst::EventTypes::kNoteExpressionValueEvent);
+            event.event.note_expression_value.type_id = VST3_NOTE_EXPRESSION_TUNING_TYPE_ID;
+            event.event.note_expression_value.note_id = 42;
+            event.event.note_expression_value.value = 0.5 + 2.0 / 240.0;
+            assert!(matches!(
+                inner.vst3_event_to_note_event(0, &event),
+                Some(NoteEvent::PolyPitchBend { note_id: 42, semitones, .. })
+                    if (semitones - 2.0).abs() < 1e-4
+            ));
+
+            event.event.note_expression_value.type_id = VST3_NOTE_EXPRESSION_BRIGHTNESS_TYPE_ID;
+            event.event.note_expression_value.value = 0.25;
+            assert_eq!(
+                inner.vst3_event_to_note_event(0, &event),
+                Some(NoteEvent::PolyBrightness {
+                    timing: 0,
+                    note_id: 42,
+                    brightness: 0.25
+                })
+            );
+
+            // Other note expressions are not supported
+            event.event.note_expression_value.type_id = 0;
+            assert_eq!(inner.vst3_event_to_note_event(0, &event), None);
+        }
+    }
+
+    #[test]
+    fn note_expressions_are_listed() {
+        unsafe {
+            let wrapper = Wrapper::<TestPlugin>::new();
+
+            // Hosts only send the note expressions the plugin lists, and only on the event bus
+            assert_eq!(wrapper.get_note_expression_count(0, 0), 2);
+            assert_eq!(wrapper.get_note_expression_count(1, 0), 0);
+
+            let mut info: NoteExpressionTypeInfo = mem::zeroed();
+            assert_eq!(
+                wrapper.get_note_expression_info(0, 0, 0, &mut info),
+                kResultOk
+            );
+            assert_eq!(info.type_id, VST3_NOTE_EXPRESSION_TUNING_TYPE_ID);
+            assert_eq!(info.value_desc.default_value, 0.5);
+            assert_eq!(
+                wrapper.get_note_expression_info(0, 0, 1, &mut info),
+                kResultOk
+            );
+            assert_eq!(info.type_id, VST3_NOTE_EXPRESSION_BRIGHTNESS_TYPE_ID);
+            assert_eq!(
+                wrapper.get_note_expression_info(0, 0, 2, &mut info),
+                kInvalidArgument
+            );
+
+            let mut string = [0 as TChar; 128];
+            assert_eq!(
+                wrapper.get_note_expression_string_by_value(
+                    0,
+                    0,
+                    VST3_NOTE_EXPRESSION_TUNING_TYPE_ID,
+                    0.5 + 12.0 / 240.0,
+                    string.as_mut_ptr()
+                ),
+                kResultOk
+            );
+            let mut value = 0.0;
+            assert_eq!(
+                wrapper.get_note_expression_value_by_string(
+                    0,
+                    0,
+                    VST3_NOTE_EXPRESSION_TUNING_TYPE_ID,
+                    string.as_ptr(),
+                    &mut value
+                ),
+                kResultOk
+            );
+            assert!((value - (0.5 + 12.0 / 240.0)).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn sample_precision_negotiation() {
+        unsafe {
+            // The test plugin doesn't prefer double precision, so it's never offered to the host
+            let wrapper = Wrapper::<TestPlugin>::new();
+            assert_eq!(
+                wrapper
+                    .can_process_sample_size(vst3_sys::vst::SymbolicSampleSizes::kSample32 as i32),
+                kResultOk
+            );
+            assert_eq!(
+                wrapper
+                    .can_process_sample_size(vst3_sys::vst::SymbolicSampleSizes::kSample64 as i32),
+                kResultFalse
+            );
+        }
+    }
+
+    #[test]
+    fn double_precision_buffers_are_converted() {
+        unsafe {
+            let mut buffers = SinglePrecisionBuffers::default();
+            buffers.resize(&[2, 1], &[2], 4);
+
+            // The host's stereo main input, a mono auxiliary input, and a stereo output
+            let mut inputs: Vec<Vec<f64>> = vec![
+                vec![0.1, 0.2, 0.3],
+                vec![-0.1, -0.2, -0.3],
+                vec![1.0, 2.0, 3.0],
+            ];
+            let mut outputs: Vec<Vec<f64>> = vec![vec![0.0; 3], vec![0.0; 3]];
+            let mut input_ptrs: Vec<*mut c_void> = inputs
+                .iter_mut()
+                .map(|channel| channel.as_mut_ptr() as *mut c_void)
+                .collect();
+            let mut output_ptrs: Vec<*mut c_void> = outputs
+                .iter_mut()
+                .map(|channel| channel.as_mut_ptr() as *mut c_void)
+                .collect();
+            let mut input_busses = [
+                vst3_sys::vst::AudioBusBuffers {
+                    num_channels: 2,
+                    silence_flags: 0,
+                    buffers: input_ptrs.as_mut_ptr(),
+                },
+                vst3_sys::vst::AudioBusBuffers {
+                    num_channels: 1,
+                    silence_flags: 0,
+                    buffers: input_ptrs.as_mut_ptr().add(2),
+                },
+            ];
+            let mut output_busses = [vst3_sys::vst::AudioBusBuffers {
+                num_channels: 2,
+                silence_flags: 0,
+                buffers: output_ptrs.as_mut_ptr(),
+            }];
+
+            let mut host_data: vst3_sys::vst::ProcessData = mem::zeroed();
+            host_data.symbolic_sample_size = vst3_sys::vst::SymbolicSampleSizes::kSample64 as i32;
+            host_data.num_samples = 3;
+            host_data.num_inputs = 2;
+            host_data.num_outputs = 1;
+            host_data.inputs = input_busses.as_mut_ptr();
+            host_data.outputs = output_busses.as_mut_ptr();
+
+            let data = buffers.read_double_precision(&host_data);
+            assert_eq!(
+                data.symbolic_sample_size,
+                vst3_sys::vst::SymbolicSampleSizes::kSample32 as i32
+            );
+            assert_eq!(data.num_samples, 3);
+            assert_eq!((data.num_inputs, data.num_outputs), (2, 1));
+            assert_eq!((*data.inputs).num_channels, 2);
+            assert_eq!((*data.inputs.add(1)).num_channels, 1);
+
+            let channel = |bus: *const vst3_sys::vst::AudioBusBuffers, channel_idx: usize| {
+                std::slice::from_raw_parts_mut(
+                    *((*bus).buffers as *mut *mut f32).add(channel_idx),
+                    3,
+                )
+            };
+            assert_eq!(channel(data.inputs, 0), [0.1, 0.2, 0.3]);
+            assert_eq!(channel(data.inputs, 1), [-0.1, -0.2, -0.3]);
+            assert_eq!(channel(data.inputs.add(1), 0), [1.0, 2.0, 3.0]);
+
+            // Only the outputs get written back to the host's buffers
+            channel(data.outputs, 0).copy_from_slice(&[0.5, 0.25, 0.125]);
+            channel(data.outputs, 1).copy_from_slice(&[-0.5, -0.25, -0.125]);
+            channel(data.inputs, 0).fill(0.0);
+            buffers.write_double_precision(&host_data);
+            assert_eq!(outputs, [[0.5, 0.25, 0.125], [-0.5, -0.25, -0.125]]);
+            assert_eq!(inputs[0], [0.1, 0.2, 0.3]);
+        }
+    }
+
+    #[test]
+    fn oversized_double_precision_blocks_are_clamped() {
+        unsafe {
+            let mut buffers = SinglePrecisionBuffers::default();
+            buffers.resize(&[], &[1], 2);
+
+            let mut output = vec![0.0f64; 4];
+            let mut output_ptrs = [output.as_mut_ptr() as *mut c_void];
+            let mut output_busses = [vst3_sys::vst::AudioBusBuffers {
+                num_channels: 1,
+                silence_flags: 0,
+                buffers: output_ptrs.as_mut_ptr(),
+            }];
+
+            let mut host_data: vst3_sys::vst::ProcessData = mem::zeroed();
+            host_data.symbolic_sample_size = vst3_sys::vst::SymbolicSampleSizes::kSample64 as i32;
+            host_data.num_samples = 4;
+            host_data.num_outputs = 1;
+            host_data.outputs = output_busses.as_mut_ptr();
+
+            // The buffers only have room for two samples
+            let data = buffers.read_double_precision(&host_data);
+            assert_eq!(data.num_samples, 2);
+            std::slice::from_raw_parts_mut(*((*data.outputs).buffers as *mut *mut f32), 2)
+                .fill(1.0);
+
+            buffers.write_double_precision(&host_data);
+            assert_eq!(output, [1.0, 1.0, 0.0, 0.0]);
+        }
+    }
+
+    #[test]
+    fn parameter_flush_before_audio() {
+        unsafe {
+            let wrapper = Wrapper::<TestPlugin>::new();
+            let float_hash = hash_param_id("float");
+
+            let mut setup: vst3_sys::vst::ProcessSetup = mem::zeroed();
+            setup.symbolic_sample_size = vst3_sys::vst::SymbolicSampleSizes::kSample32 as i32;
+            setup.sample_rate = 44_100.0;
+            setup.max_samples_per_block = 64;
+            assert_eq!(
+                IAudioProcessor::setup_processing(&*wrapper, &setup),
+                kResultOk
+            );
+            assert_eq!(IAudioProcessor::set_processing(&*wrapper, 1), kResultOk);
+
+            // The host only sends new parameter values, without any output busses. Only the last
+            // point for each parameter gets applied.
+            let param_changes = TestParameterChanges::new(vec![TestParamValueQueue::new(
+                float_hash,
+                vec![(0, 0.25), (0, 0.75)],
+            )]);
+            let mut flush_data: vst3_sys::vst::ProcessData = mem::zeroed();
+            flush_data.symbolic_sample_size = vst3_sys::vst::SymbolicSampleSizes::kSample32 as i32;
+            flush_data.input_param_changes = param_changes.as_shared_ptr();
+            assert_eq!(
+                IAudioProcessor::process(&*wrapper, &mut flush_data),
+                kResultOk
+            );
+            {
+                let plugin = wrapper.inner.plugin.read();
+                assert_eq!(plugin.params.float.plain_value(), 5.0);
+                // The smoother only starts moving towards its new target once audio is processed
+                assert!(plugin.params.float.smoothed.is_smoothing());
+            }
+            assert!(wrapper.inner.input_automation.read().is_empty());
+
+            // The next regular block then gets processed as usual, starting from those values
+            let mut channels = vec![vec![0.5f32; 32]; 2];
+            let mut channel_ptrs: Vec<*mut c_void> = channels
+                .iter_mut()
+                .map(|channel| channel.as_mut_ptr() as *mut c_void)
+                .collect();
+            let mut output_bus = vst3_sys::vst::AudioBusBuffers {
+                num_channels: 2,
+                silence_flags: 0,
+                buffers: channel_ptrs.as_mut_ptr(),
+            };
+            let mut audio_data: vst3_sys::vst::ProcessData = mem::zeroed();
+            audio_data.symbolic_sample_size = vst3_sys::vst::SymbolicSampleSizes::kSample32 as i32;
+            audio_data.num_samples = 32;
+            audio_data.num_outputs = 1;
+            audio_data.outputs = &mut output_bus;
+            assert_eq!(
+                IAudioProcessor::process(&*wrapper, &mut audio_data),
+                kResultOk
+            );
+            assert_eq!(wrapper.inner.plugin.read().params.float.plain_value(), 5.0);
+            assert_eq!(channels, vec![vec![0.5f32; 32]; 2]);
+        }
+    }
+
+    #[test]
+    fn plugin_panics_are_caught() {
+        unsafe {
+            let mut setup: vst3_sys::vst::ProcessSetup = mem::zeroed();
+            setup.symbolic_sample_size = vst3_sys::vst::SymbolicSampleSizes::kSample32 as i32;
+            setup.sample_rate = 44_100.0;
+            setup.max_samples_per_block = 64;
+
+            // A panic during initialization makes the setup call fail
+            let wrapper = Wrapper::<PanickingPlugin>::new();
+            wrapper.inner.plugin.write().panic_on_initialize = true;
+            assert_eq!(
+                IAudioProcessor::setup_processing(&*wrapper, &setup),
+                kResultFalse
+            );
+            assert!(wrapper.inner.panicked.load(Ordering::SeqCst));
+
+            // A panic while processing audio makes the process call fail, and after that the
+            // plugin is not processed anymore
+            let wrapper = Wrapper::<PanickingPlugin>::new();
+            assert_eq!(
+                IAudioProcessor::setup_processing(&*wrapper, &setup),
+                kResultOk
+            );
+            assert_eq!(IAudioProcessor::set_processing(&*wrapper, 1), kResultOk);
+
+            let mut channels = vec![vec![0.5f32; 32]; 2];
+            let mut channel_ptrs: Vec<*mut c_void> = channels
+                .iter_mut()
+                .map(|channel| channel.as_mut_ptr() as *mut c_void)
+                .collect();
+            let mut output_bus = vst3_sys::vst::AudioBusBuffers {
+                num_channels: 2,
+                silence_flags: 0,
+                buffers: channel_ptrs.as_mut_ptr(),
+            };
+            let mut audio_data: vst3_sys::vst::ProcessData = mem::zeroed();
+            audio_data.symbolic_sample_size = vst3_sys::vst::SymbolicSampleSizes::kSample32 as i32;
+            audio_data.num_samples = 32;
+            audio_data.num_outputs = 1;
+            audio_data.outputs = &mut output_bus;
+            // Both for the call that panicked and for every call after that, the outputs are silent
+            for _ in 0..2 {
+                for channel in &mut channels {
+                    channel.fill(0.5);
+                }
+                assert_eq!(
+                    IAudioProcessor::process(&*wrapper, &mut audio_data),
+                    kResultFalse
+                );
+                assert_eq!(channels, vec![vec![0.0f32; 32]; 2]);
+            }
+            assert!(wrapper.inner.panicked.load(Ordering::SeqCst));
+            assert_eq!(wrapper.inner.plugin.read().num_process_calls, 1);
+
+            // The plugin also won't be initialized again
+            assert_eq!(IAudioProcessor::set_processing(&*wrapper, 0), kResultOk);
+            assert_eq!(
+                IAudioProcessor::setup_processing(&*wrapper, &setup),
+                kResultFalse
+            );
+        }
+    }
+
+    #[test]
+    fn unhandled_sample_rate_changes_reset_smoothers() {
+        unsafe {
+            let wrapper = Wrapper::<TestPlugin>::new();
+            let mut setup: vst3_sys::vst::ProcessSetup = mem::zeroed();
+            setup.symbolic_sample_size = vst3_sys::vst::SymbolicSampleSizes::kSample32 as i32;
+            setup.sample_rate = 100.0;
+            setup.max_samples_per_block = 64;
+            assert_eq!(
+                IAudioProcessor::setup_processing(&*wrapper, &setup),
+                kResultOk
+            );
+
+            // This starts a ramp towards 5.0
+            let float_hash = hash_param_id("float");
+            wrapper.set_param_normalized(float_hash, 0.75);
+            assert!(wrapper
+                .inner
+                .plugin
+                .read()
+                .params
+                .float
+                .smoothed
+                .is_smoothing());
+
+            // The test plugin doesn't handle sample rate changes, so it gets reinitialized and the
+            // smoother should not keep ramping at the new sample rate
+            setup.sample_rate = 200.0;
+            assert_eq!(
+                IAudioProcessor::setup_processing(&*wrapper, &setup),
+                kResultOk
+            );
+            let plugin = wrapper.inner.plugin.read();
+            assert!(!plugin.params.float.smoothed.is_smoothing());
+            assert_eq!(plugin.params.float.smoothed.next(), 5.0);
+        }
+    }
+
+    #[test]
+    fn bypass_crossfades_to_input() {
+        unsafe {
+            let wrapper = Wrapper::<TestPlugin>::new();
+            let mut setup: vst3_sys::vst::ProcessSetup = mem::zeroed();
+            setup.symbolic_sample_size = vst3_sys::vst::SymbolicSampleSizes::kSample32 as i32;
+            setup.sample_rate = 48_000.0;
+            setup.max_samples_per_block = 64;
+            assert_eq!(
+                IAudioProcessor::setup_processing(&*wrapper, &setup),
+                kResultOk
+            );
+            assert_eq!(IAudioProcessor::set_processing(&*wrapper, 1), kResultOk);
+
+            // The 'plugin' used here turns the input into silence, and the input is always 1.0
+            let mut channels = vec![vec![0.0f32; 64]; 2];
+            let num_process_calls = Cell::new(0);
+            let process_block = |channels: &mut Vec<Vec<f32>>| {
+                let mut buffer = Buffer::default();
+                for channel in channels.iter_mut() {
+                    channel.fill(1.0);
+                    buffer
+                        .as_raw_vec()
+                        .push(std::slice::from_raw_parts_mut(channel.as_mut_ptr(), 64));
+                }
+
+                wrapper.inner.process_bypassable(&mut buffer, 64, |buffer| {
+                    num_process_calls.set(num_process_calls.get() + 1);
+                    for channel in buffer.as_raw() {
+                        channel.fill(0.0);
+                    }
+
+                    ProcessStatus::Tail(1000)
+                })
+            };
+            assert_eq!(process_block(&mut channels), ProcessStatus::Tail(1000));
+            assert_eq!(channels, vec![vec![0.0f32; 64]; 2]);
+
+            // The 10 ms crossfade takes 480 samples, and there should not be any jumps in the
+            // output during that time
+            wrapper.inner.set_normalized_value_by_hash(
+                *BYPASS_PARAM_HASH,
+                1.0,
+                Some(48_000.0),
+                ParamChangeSource::Host,
+            );
+            let mut previous_sample = 0.0;
+            for _ in 0..8 {
+                assert_eq!(process_block(&mut channels), ProcessStatus::Tail(1000));
+                assert_eq!(channels[0], channels[1]);
+                for &sample in &channels[0] {
+                    assert!(sample >= previous_sample);
+                    assert!(sample - previous_sample < 0.0025);
+                    previous_sample = sample;
+                }
+            }
+            assert_eq!(channels[0][63], 1.0);
+            assert_eq!(num_process_calls.get(), 9);
+
+            // Once the plugin is fully bypassed it no longer processes audio, and the output
+            // equals the input
+            assert_eq!(process_block(&mut channels), ProcessStatus::Normal);
+            assert_eq!(channels, vec![vec![1.0f32; 64]; 2]);
+            assert_eq!(num_process_calls.get(), 9);
+
+            // Unbypassing crossfades back to the plugin's output
+            wrapper.inner.set_normalized_value_by_hash(
+                *BYPASS_PARAM_HASH,
+                0.0,
+                Some(48_000.0),
+                ParamChangeSource::Host,
+            );
+            assert_eq!(process_block(&mut channels), ProcessStatus::Tail(1000));
+            assert_eq!(num_process_calls.get(), 10);
+            assert!(channels[0][0] > 0.99 && channels[0][0] < 1.0);
+            assert!(channels[0][63] < channels[0][0]);
+        }
+    }
+
+    #[test]
+    fn bypass_delays_the_input_by_the_latency() {
+        unsafe {
+            let wrapper = Wrapper::<TestPlugin>::new();
+            let mut setup: vst3_sys::vst::ProcessSetup = mem::zeroed();
+            setup.symbolic_sample_size = vst3_sys::vst::SymbolicSampleSizes::kSample32 as i32;
+            setup.sample_rate = 48_000.0;
+            setup.max_samples_per_block = 64;
+            wrapper.inner.current_latency.store(16, Ordering::SeqCst);
+            assert_eq!(
+                IAudioProcessor::setup_processing(&*wrapper, &setup),
+                kResultOk
+            );
+            wrapper.inner.set_normalized_value_by_hash(
+                *BYPASS_PARAM_HASH,
+                1.0,
+                Some(48_000.0),
+                ParamChangeSource::Host,
+            );
+            assert_eq!(IAudioProcessor::set_processing(&*wrapper, 1), kResultOk);
+
+            // The bypassed output should line up with the plugin's delayed output, so an impulse
+            // at the start of the input comes out 16 samples later
+            let mut channels = vec![vec![0.0f32; 64]; 2];
+            for channel in &mut channels {
+                channel[0] = 1.0;
+            }
+            let mut buffer = Buffer::default();
+            for channel in channels.iter_mut() {
+                buffer
+                    .as_raw_vec()
+                    .push(std::slice::from_raw_parts_mut(channel.as_mut_ptr(), 64));
+            }
+            let status = wrapper
+                .inner
+                .process_bypassable(&mut buffer, 64, |_| unreachable!());
+            assert_eq!(status, ProcessStatus::Normal);
+            drop(buffer);
+
+            for channel in &channels {
+                assert_eq!(channel[16], 1.0);
+                assert_eq!(channel.iter().sum::<f32>(), 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn midi_ccs_are_mapped_to_params() {
+        unsafe {
+            let wrapper = Wrapper::<TestPlugin>::new();
+            let float_hash = hash_param_id("float");
+
+            let mut mod_wheel_id = 0;
+            let mut pitch_bend_id = 0;
+            assert_eq!(
+                wrapper.get_midi_controller_assignment(0, 2, 1, &mut mod_wheel_id),
+                kResultOk
+            );
+            assert_eq!(
+                wrapper.get_midi_controller_assignment(0, 2, 129, &mut pitch_bend_id),
+                kResultOk
+            );
+            assert_ne!(mod_wheel_id, pitch_bend_id);
+            for id in [mod_wheel_id, pitch_bend_id] {
+                assert!(!wrapper.inner.param_by_hash.contains_key(&id));
+                assert_ne!(id, *BYPASS_PARAM_HASH);
+            }
+
+            // The host can only automate these if they're listed as hidden parameters after the
+            // plugin's own parameters and the bypass parameter
+            let num_params = wrapper.inner.param_hashes.len() as i32 + 1;
+            assert_eq!(
+                wrapper.get_parameter_count(),
+                num_params + (VST3_MIDI_CHANNELS * VST3_MIDI_CCS) as i32
+            );
+            let mut info: vst3_sys::vst::ParameterInfo = mem::zeroed();
+            assert_eq!(
+                wrapper.get_parameter_info(num_params + (2 * VST3_MIDI_CCS) as i32 + 1, &mut info),
+                kResultOk
+            );
+            assert_eq!(info.id, mod_wheel_id);
+            assert_eq!(
+                info.flags,
+                vst3_sys::vst::ParameterFlags::kCanAutomate as i32
+                    | vst3_sys::vst::ParameterFlags::kIsHidden as i32
+            );
+            assert_eq!(
+                wrapper.get_parameter_info(wrapper.get_parameter_count(), &mut info),
+                kInvalidArgument
+            );
+            assert_eq!(wrapper.get_param_normalized(mod_wheel_id), 0.0);
+            assert_eq!(wrapper.get_param_normalized(pitch_bend_id), 0.5);
+            assert_eq!(wrapper.set_param_normalized(mod_wheel_id, 0.75), kResultOk);
+            assert_eq!(wrapper.get_param_normalized(mod_wheel_id), 0.75);
+
+            // Only the 130 controllers on the 16 channels of the single event bus can be mapped
+            let mut id = 0;
+            assert_eq!(
+                wrapper.get_midi_controller_assignment(1, 0, 1, &mut id),
+                kResultFalse
+            );
+            assert_eq!(
+                wrapper.get_midi_controller_assignment(0, 16, 1, &mut id),
+                kResultFalse
+            );
+            assert_eq!(
+                wrapper.get_midi_controller_assignment(0, 0, 130, &mut id),
+                kResultFalse
+            );
+
+            assert_eq!(
+                wrapper
+                    .inner
+                    .midi_cc_param_to_note_event(5, mod_wheel_id, 0.5),
+                Some(NoteEvent::MidiCC {
+                    timing: 5,
+                    channel: 2,
+                    cc: 1,
+                    value: 0.5
+                })
+            );
+            assert_eq!(
+                wrapper
+                    .inner
+                    .midi_cc_param_to_note_event(5, float_hash, 0.5),
+                None
+            );
+
+            // The host then automates these like any other parameter, and every point becomes a
+            // note event instead of a parameter change
+            let mut setup: vst3_sys::vst::ProcessSetup = mem::zeroed();
+            setup.symbolic_sample_size = vst3_sys::vst::SymbolicSampleSizes::kSample32 as i32;
+            setup.sample_rate = 44_100.0;
+            setup.max_samples_per_block = 64;
+            assert_eq!(
+                IAudioProcessor::setup_processing(&*wrapper, &setup),
+                kResultOk
+            );
+            let param_changes = TestParameterChanges::new(vec![
+                TestParamValueQueue::new(mod_wheel_id, vec![(0, 0.25), (16, 1.0)]),
+                TestParamValueQueue::new(pitch_bend_id, vec![(8, 0.0)]),
+            ]);
+            let mut flush_data: vst3_sys::vst::ProcessData = mem::zeroed();
+            flush_data.symbolic_sample_size = vst3_sys::vst::SymbolicSampleSizes::kSample32 as i32;
+            flush_data.input_param_changes = param_changes.as_shared_ptr();
+            assert_eq!(
+                IAudioProcessor::process(&*wrapper, &mut flush_data),
+                kResultOk
+            );
+            assert_eq!(
+                wrapper
+                    .inner
+                    .input_events
+                    .read()
+                    .iter()
+                    .copied()
+                    .collect::<Vec<_>>(),
+                [
+                    NoteEvent::MidiCC {
+                        timing: 0,
+                        channel: 2,
+                        cc: 1,
+                        value: 0.25
+                    },
+                    NoteEvent::MidiCC {
+                        timing: 0,
+                        channel: 2,
+                        cc: 1,
+                        value: 1.0
+                    },
+                    NoteEvent::MidiPitchBend {
+                        timing: 0,
+                        channel: 2,
+                        value: 0.0
+                    },
+                ]
+            );
+            assert!(wrapper.inner.input_automation.read().is_empty());
+            assert_eq!(wrapper.get_param_normalized(mod_wheel_id), 1.0);
+            assert_eq!(wrapper.get_param_normalized(pitch_bend_id), 0.0);
+        }
+    }
+
+    #[test]
+    fn factory_class_info() {
+        unsafe {
+            let factory = Factory::new(vec![PluginDescriptor::for_plugin::<TestPlugin>()]);
+
+            let mut info: vst3_sys::base::PClassInfo2 = mem::zeroed();
+            assert_eq!(factory.get_class_info2(0, &mut info), kResultOk);
+            assert_eq!(info.class_flags, K_DISTRIBUTABLE | K_SIMPLE_MODE_SUPPORTED);
+            assert_eq!(
+                CStr::from_ptr(info.subcategories.as_ptr()).to_str(),
+                Ok("Instrument|Synth")
+            );
+
+            let mut info: vst3_sys::base::PClassInfoW = mem::zeroed();
+            assert_eq!(factory.get_class_info_unicode(0, &mut info), kResultOk);
+            assert_eq!(info.class_flags, K_DISTRIBUTABLE | K_SIMPLE_MODE_SUPPORTED);
+            assert_eq!(
+                CStr::from_ptr(info.subcategories.as_ptr()).to_str(),
+                Ok("Instrument|Synth")
+            );
+        }
+    }
+
+    #[test]
+    fn subcategories_are_not_cut_off() {
+        // Together with the separators these don't fit in the 127 characters the host allows, so
+        // the last category should be left out entirely instead of being truncated
+        let subcategories = subcategories_string(&[
+            Vst3SubCategory::Fx,
+            Vst3SubCategory::Custom(
+                "Pretty Long Category Name That Takes Up Half Of The Space Abcd",
+            ),
+            Vst3SubCategory::Custom(
+                "Another Long Category Name That Takes Up Half Of The Space Abc",
+            ),
+        ]);
+        assert_eq!(
+            subcategories,
+            "Fx|Pretty Long Category Name That Takes Up Half Of The Space Abcd"
+        );
+        assert!(subcategories.len() <= util::MAX_SUBCATEGORIES_LEN);
+    }
+
+    #[test]
+    fn latency_changes_are_debounced() {
+        let wrapper = Wrapper::<TestPlugin>::new();
+        let latency_changed = vst3_sys::vst::RestartFlags::kLatencyChanged as i32;
+
+        // Changes made before the host has queried the latency don't need a restart
+        wrapper
+            .inner
+            .make_process_context(Transport::new(44100.0))
+            .set_latency_samples(64);
+        assert_eq!(
+            wrapper.inner.pending_restart_flags.load(Ordering::SeqCst),
+            0
+        );
+        assert_eq!(unsafe { wrapper.get_latency_samples() }, 64);
+
+        // There is no component handler, so the restart request ends up in the pending flags
+        wrapper
+            .inner
+            .make_process_context(Transport::new(44100.0))
+            .set_latency_samples(128);
+        assert_eq!(
+            wrapper
+                .inner
+                .pending_restart_flags
+                .swap(0, Ordering::SeqCst),
+            latency_changed
+        );
+
+        // Setting the same value again, or setting a new value before the host has queried the
+        // latency again, doesn't spam the host with restart requests
+        for samples in [128, 128, 256] {
+            wrapper
+                .inner
+                .make_process_context(Transport::new(44100.0))
+                .set_latency_samples(samples);
+            assert_eq!(
+                wrapper.inner.pending_restart_flags.load(Ordering::SeqCst),
+                0
+            );
+        }
+
+        assert_eq!(unsafe { wrapper.get_latency_samples() }, 256);
+        wrapper
+            .inner
+            .make_process_context(Transport::new(44100.0))
+            .set_latency_samples(256);
+        assert_eq!(
+            wrapper.inner.pending_restart_flags.load(Ordering::SeqCst),
+            0
+        );
+        wrapper
+            .inner
+            .make_process_context(Transport::new(44100.0))
+            .set_latency_samples(32);
+        assert_eq!(
+            wrapper
+                .inner
+                .pending_restart_flags
+                .swap(0, Ordering::SeqCst),
+            latency_changed
+        );
+
+        // Notifications that could not be posted because the task queue was full are retried at
+        // the start of the next process call, and the new latency is kept in the meantime
+        wrapper
+            .inner
+            .latency_notification_pending
+            .store(true, Ordering::SeqCst);
+        let _context = wrapper.inner.make_process_context(Transport::new(44100.0));
+        assert!(!wrapper
+            .inner
+            .latency_notification_pending
+            .load(Ordering::SeqCst));
+        assert_eq!(
+            wrapper.inner.pending_restart_flags.load(Ordering::SeqCst),
+            latency_changed
+        );
+        assert_eq!(wrapper.inner.current_latency.load(Ordering::SeqCst), 32);
+    }
+
+    #[test]
+    fn process_edits_are_coalesced() {
+        unsafe {
+            let wrapper = Wrapper::<TestPlugin>::new();
+            let float_ptr = wrapper.inner.plugin.read().params.float.as_ptr();
+            let float_hash = hash_param_id("float");
+
+            // While a task is still waiting to be executed, setting parameters doesn't post any
+            // more of them
+            wrapper
+                .inner
+                .process_edits_task_posted
+                .store(true, Ordering::SeqCst);
+            {
+                let context = wrapper.inner.make_process_context(Transport::new(44100.0));
+                for normalized in [0.25, 0.5, 0.75] {
+                    context.set_parameter(float_ptr, normalized);
+                }
+            }
+            assert!(wrapper.inner.pending_edits.is_empty());
+
+            // And when that task runs the host only gets to see the latest value
+            wrapper.inner.execute(Task::PerformEdits);
+            let edits: Vec<PendingEdit> =
+                std::iter::from_fn(|| wrapper.inner.pending_edits.pop()).collect();
+            assert!(matches!(
+                edits[..],
+                [
+                    PendingEdit::Begin(begin_hash),
+                    PendingEdit::Perform(perform_hash, normalized),
+                    PendingEdit::End(end_hash),
+                ] if [begin_hash, perform_hash, end_hash] == [float_hash; 3]
+                    && (normalized - 0.75).abs() < 1e-6
+            ));
+            assert!(!wrapper
+                .inner
+                .process_edits_task_posted
+                .load(Ordering::SeqCst));
+        }
+    }
+
+    #[test]
+    fn output_events_are_sorted_and_deferred() {
+        let wrapper = Wrapper::<TestPlugin>::new();
+        let note_on = |timing: u32, note: u8| NoteEvent::NoteOn {
+            timing,
+            note_id: None,
+            channel: 0,
+            note,
+            velocity: 100,
+        };
+
+        wrapper.inner.output_events.write().extend([
+            note_on(12, 0),
+            note_on(3, 1),
+            note_on(40, 2),
+            note_on(3, 3),
+            note_on(32, 4),
+        ]);
+
+        // Events with the same timing keep their order, and events past the end of the 32 sample
+        // block are moved to the next block
+        let mut sent_events = Vec::new();
+        wrapper
+            .inner
+            .drain_output_events(32, |event| sent_events.push(*event));
+        assert_eq!(sent_events, [note_on(3, 1), note_on(3, 3), note_on(12, 0)]);
+
+        sent_events.clear();
+        wrapper
+            .inner
+            .drain_output_events(32, |event| sent_events.push(*event));
+        assert_eq!(sent_events, [note_on(0, 4), note_on(8, 2)]);
+        assert!(wrapper.inner.output_events.read().is_empty());
+    }
+
+    #[test]
+    fn next_event_is_sample_accurate() {
+        let wrapper = Wrapper::<TestPlugin>::new();
+        let note_on = |timing: u32, note: u8| NoteEvent::NoteOn {
+            timing,
+            note_id: None,
+            channel: 0,
+            note,
+            velocity: 100,
+        };
+
+        let mut input_events = wrapper.inner.input_events.write();
+        input_events.extend([note_on(7, 0), note_on(0, 1), note_on(3, 2), note_on(0, 3)]);
+        sort_note_events(&mut input_events);
+        drop(input_events);
+
+        // Every event is delivered once the sample position reaches its timing, and never earlier
+        let mut context = wrapper.inner.make_process_context(Transport::new(44100.0));
+        let mut received_events = Vec::new();
+        for sample_idx in 0..8 {
+            while let Some(event) = context.next_event(sample_idx) {
+                assert!(event.timing() as usize <= sample_idx);
+                received_events.push((sample_idx, event));
+            }
+            assert_eq!(
+                context.num_consumed_events(),
+                received_events.len(),
+                "sample {}",
+                sample_idx
+            );
+        }
+
+        assert_eq!(
+            received_events,
+            [
+                (0, note_on(0, 1)),
+                (0, note_on(0, 3)),
+                (3, note_on(3, 2)),
+                (7, note_on(7, 0)),
+            ]
+        );
+        assert_eq!(context.next_midi_event(), None);
+        assert_eq!(context.num_consumed_events(), 4);
+    }
+
+    #[test]
+    fn process_context_requirements_match_plugin() {
+        assert_eq!(
+            process_context_requirements(&TransportRequirements::NONE),
+            0
+        );
+        assert_eq!(
+            process_context_requirements(&TransportRequirements::ALL),
+            (1 << 2) | (1 << 3) | (1 << 4) | (1 << 6) | (1 << 7) | (1 << 10)
+        );
+
+        // The test plugin only needs the transport state, the tempo, and the time signature
+        let wrapper = Wrapper::<TestPlugin>::new();
+        let flags = unsafe { wrapper.get_process_context_requirements() };
+        assert_eq!(flags, (1 << 6) | (1 << 7) | (1 << 10));
+
+        // A host that respects these flags only fills in the requested fields, and those should
+        // all end up in the transport
+        unsafe {
+            let mut context: vst3_sys::vst::ProcessContext = mem::zeroed();
+            context.state = (1 << 1) | (1 << 10) | (1 << 13);
+            context.project_time_samples = 44100;
+            context.tempo = 90.0;
+            context.time_sig_num = 3;
+            context.time_sig_den = 4;
+            let transport = transport_from_vst3(&context, 44100.0);
+            assert!(transport.playing);
+            assert_eq!(transport.tempo, Some(90.0));
+            assert_eq!(transport.time_sig_numerator, Some(3));
+            assert_eq!(transport.time_sig_denominator, Some(4));
+            assert_eq!(transport.pos_samples, Some(44100));
+            assert_eq!(transport.bar_start_pos_beats, None);
+            assert_eq!(transport.loop_range_beats, None);
+        }
+    }
+
+    #[test]
+    fn vst3_transport() {
+        unsafe {
+            assert_eq!(
+                transport_from_vst3(ptr::null(), 44100.0),
+                Transport::new(44100.0)
+            );
+
+            // Playing, with a valid tempo and time signature, but without the musical position
+            let mut context: vst3_sys::vst::ProcessContext = mem::zeroed();
+            context.state = (1 << 1) | (1 << 10) | (1 << 13);
+            context.project_time_samples = 88200;
+            context.tempo = 120.0;
+            context.time_sig_num = 6;
+            context.time_sig_den = 8;
+            let transport = transport_from_vst3(&context, 44100.0);
+            assert!(transport.playing);
+            assert_eq!(transport.pos_seconds(), Some(2.0));
+            assert_eq!(transport.pos_beats, Some(4.0));
+            assert_eq!(transport.bar_length_beats(), Some(3.0));
+            assert_eq!(transport.loop_range_beats, None);
+
+            // Some hosts report a zero tempo when they don't have one
+            context.tempo = 0.0;
+            context.time_sig_den = 0;
+            let transport = transport_from_vst3(&context, 44100.0);
+            assert_eq!(transport.tempo, None);
+            assert_eq!(transport.pos_beats, None);
+            assert_eq!(transport.time_sig_numerator, None);
+            assert_eq!(transport.bar_length_beats(), None);
+        }
+    }
+
+    /// An editor that doesn't draw anything, used to test the [WrapperView].
+    struct TestEditor {
+        size: AtomicCell<(u32, u32)>,
+        supports_scaling: bool,
+    }
+
+    impl TestEditor {
+        fn new(supports_scaling: bool) -> Self {
+            Self {
+                size: AtomicCell::new((200, 100)),
+                supports_scaling,
+            }
+        }
+    }
+
+    impl Editor for TestEditor {
+        fn spawn(
+            &self,
+            _parent: ParentWindowHandle,
+            _context: Arc<dyn GuiContext>,
+        ) -> Box<dyn Any> {
+            Box::new(())
+        }
+
+        fn size(&self) -> (u32, u32) {
+            self.size.load()
+        }
+
+        fn set_scale_factor(&self, _factor: f32) -> bool {
+            self.supports_scaling
+        }
+    }
+
+    /// An [IPlugFrame] that either accepts or rejects all resize requests, and that records the
+    /// sizes it has been asked to resize the view to.
+    #[VST3(implements(IPlugFrame))]
+    struct TestPlugFrame {
+        accept: bool,
+        requested_sizes: Mutex<Vec<(i32, i32)>>,
+    }
+
+    impl TestPlugFrame {
+        fn new(accept: bool) -> Box<Self> {
+            Self::allocate(accept, Mutex::new(Vec::new()))
+        }
+
+        /// Get a pointer to this object's `IPlugFrame` interface. The pointer is only valid for as
+        /// long as this object is alive.
+        unsafe fn as_ptr(&self) -> *mut c_void {
+            self as *const Self as *mut c_void
+        }
+    }
+
+    impl IPlugFrame for TestPlugFrame {
+        unsafe fn resize_view(
+            &self,
+            _view: SharedVstPtr<dyn IPlugView>,
+            new_size: *mut ViewRect,
+        ) -> tresult {
+            check_null_ptr!(new_size);
+
+            let new_size = &*new_size;
+            self.requested_sizes.lock().push((
+                new_size.right - new_size.left,
+                new_size.bottom - new_size.top,
+            ));
+
+            if self.accept {
+                kResultOk
+            } else {
+                kResultFalse
+            }
+        }
+    }
+
+    /// The size the view reports to the host as a `(width, height)` pair.
+    unsafe fn view_size(view: &WrapperView<TestPlugin>) -> (i32, i32) {
+        let mut rect: vst3_sys::gui::ViewRect = mem::zeroed();
+        assert_eq!(view.get_size(&mut rect), kResultOk);
+
+        (rect.right - rect.left, rect.bottom - rect.top)
+    }
+
+    #[test]
+    #[cfg(not(target_os = "macos"))]
+    fn view_scaling() {
+        unsafe {
+            let wrapper = Wrapper::<TestPlugin>::new();
+
+            // Without a scale factor from the host the editor's size is reported as is
+            let view = WrapperView::new(wrapper.inner.clone(), Arc::new(TestEditor::new(true)));
+            assert_eq!(view_size(&view), (200, 100));
+            assert_eq!(view.set_content_scale_factor(1.5), kResultOk);
+            assert_eq!(view_size(&view), (300, 150));
+
+            // Editors that can't be scaled keep their original size
+            let view = WrapperView::new(wrapper.inner.clone(), Arc::new(TestEditor::new(false)));
+            assert_eq!(view.set_content_scale_factor(2.0), kResultFalse);
+            assert_eq!(view_size(&view), (200, 100));
+        }
+    }
+
+    #[test]
+    #[cfg(all(target_family = "unix", not(target_os = "macos")))]
+    fn view_resizing() {
+        unsafe {
+            let wrapper = Wrapper::<TestPlugin>::new();
+            let editor = Arc::new(TestEditor::new(false));
+            let view = WrapperView::new(wrapper.inner.clone(), editor.clone());
+
+            // Resizing is not possible while the editor is closed
+            assert!(!wrapper.inner.request_resize());
+
+            let plug_frame = TestPlugFrame::new(true);
+            assert_eq!(view.set_frame(plug_frame.as_ptr()), kResultOk);
+            assert_eq!(
+                view.attached(
+                    ptr::null_mut(),
+                    b"X11EmbedWindowID\0".as_ptr() as vst3_sys::base::FIDString
+                ),
+                kResultOk
+            );
+
+            // The host should be asked to resize the view to the editor's new size, and it should
+            // only accept that size afterwards
+            editor.size.store((400, 300));
+            assert!(wrapper.inner.request_resize());
+            assert_eq!(*plug_frame.requested_sizes.lock(), vec![(400, 300)]);
+            assert_eq!(view_size(&view), (400, 300));
+
+            let mut rect: ViewRect = mem::zeroed();
+            rect.right = 500;
+            rect.bottom = 500;
+            assert_eq!(view.check_size_constraint(&mut rect), kResultOk);
+            assert_eq!((rect.right, rect.bottom), (400, 300));
+            assert_eq!(view.on_size(&mut rect), kResultOk);
+
+            // A host that rejects the new size leaves it up to the editor to go back to its old
+            // size
+            let plug_frame = TestPlugFrame::new(false);
+            assert_eq!(view.set_frame(plug_frame.as_ptr()), kResultOk);
+            editor.size.store((800, 600));
+            assert!(!wrapper.inner.request_resize());
+            assert_eq!(*plug_frame.requested_sizes.lock(), vec![(800, 600)]);
+
+            // And after closing the editor the view is no longer used
+            assert_eq!(view.removed(), kResultOk);
+            assert!(!wrapper.inner.request_resize());
+
+            // The view would otherwise release the frame after it has already been freed
+            assert_eq!(view.set_frame(ptr::null_mut()), kResultOk);
+        }
+    }
+}
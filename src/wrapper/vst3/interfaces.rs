@@ -0,0 +1,42 @@
+// nih-plug: plugins, but rewritten in Rust
+// Copyright (C) 2022 Robbert van der Helm
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! VST3 interfaces that are missing from vst3-sys. These are defined the same way vst3-sys defines
+//! its own interfaces.
+
+use vst3_com::interfaces::IUnknown;
+use vst3_sys::base::{tresult, IBStream};
+use vst3_sys::utils::SharedVstPtr;
+
+// Alias needed for the COM interface macro
+use vst3_sys as vst3_com;
+
+/// `Steinberg::IPluginCompatibility`, added in VST 3.7.5. The host can query this on the plugin
+/// factory to find out which old class IDs a plugin can replace. The plugin writes a JSON array to
+/// the stream of the form `[{"New": "<class ID>", "Old": ["<class ID>", ...]}]`.
+#[vst3_com::com_interface("4AFD4B6A-35D7-C240-A5C3-1414FB7D15E6")]
+pub trait IPluginCompatibility: IUnknown {
+    unsafe fn get_compatibility_json(&self, stream: SharedVstPtr<dyn IBStream>) -> tresult;
+}
+
+/// `Steinberg::Vst::IProcessContextRequirements`, added in VST 3.7.0. The host queries this on the
+/// audio processor to find out which parts of the process context the plugin needs, so it can skip
+/// computing the rest. The returned value is a combination of the
+/// `IProcessContextRequirements::Flags`.
+#[vst3_com::com_interface("2A654303-EF76-4E3D-95B5-FE83730EF6D0")]
+pub trait IProcessContextRequirements: IUnknown {
+    unsafe fn get_process_context_requirements(&self) -> u32;
+}
@@ -0,0 +1,185 @@
+// nih-plug: plugins, but rewritten in Rust
+// Copyright (C) 2022 Robbert van der Helm
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! VST3 groups parameters into units. Every unit has an ID, a name, and the ID of its parent unit,
+//! and the host gets to see these units through `IUnitInfo`. This converts the slash separated
+//! group paths from [crate::param::internals::Params::param_groups()] to a flat list of units.
+
+use std::collections::{BTreeMap, HashMap};
+use vst3_sys::vst::kRootUnitId;
+
+use crate::wrapper::util::hash_param_id;
+
+/// The units for all of a plugin's parameter groups, and the unit each parameter belongs to.
+#[derive(Debug, Default)]
+pub struct ParamUnits {
+    /// All units except for the root unit. Parents always come before their children.
+    units: Vec<ParamUnit>,
+    /// The ID of the unit each parameter belongs to, indexed by the parameter's hash. Parameters
+    /// that are not in this map belong to the root unit.
+    unit_id_by_param_hash: HashMap<u32, i32>,
+}
+
+/// A single unit in [ParamUnits].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParamUnit {
+    /// The unit's ID. This is computed from the unit's full group path, so the IDs stay the same
+    /// when groups are added or removed.
+    pub id: i32,
+    /// The unit's name, which is the last component of its group path.
+    pub name: String,
+    /// The ID of the unit's parent, or `kRootUnitId` for top level groups.
+    pub parent_id: i32,
+}
+
+impl ParamUnits {
+    /// Create units for the groups in `param_groups`, which contains pairs of parameter hashes and
+    /// the group paths those parameters belong to. Group paths consist of group names separated by
+    /// slashes. Empty group paths correspond to the root unit, and every parent group also gets
+    /// its own unit.
+    pub fn from_param_groups<'a>(param_groups: impl IntoIterator<Item = (u32, &'a str)>) -> Self {
+        // Sorting by the group paths makes sure that parents always come before their children
+        let mut unit_ids_by_group_path: BTreeMap<String, i32> = BTreeMap::new();
+        let mut unit_id_by_param_hash = HashMap::new();
+        for (param_hash, group_path) in param_groups {
+            let components: Vec<&str> = group_path
+                .split('/')
+                .map(|component| component.trim())
+                .filter(|component| !component.is_empty())
+                .collect();
+            if components.is_empty() {
+                continue;
+            }
+
+            for num_components in 1..=components.len() {
+                let group_path = components[..num_components].join("/");
+                unit_ids_by_group_path
+                    .entry(group_path)
+                    .or_insert_with_key(|group_path| unit_id(group_path));
+            }
+
+            unit_id_by_param_hash.insert(param_hash, unit_id(&components.join("/")));
+        }
+
+        let units: Vec<ParamUnit> = unit_ids_by_group_path
+            .iter()
+            .map(|(group_path, &id)| {
+                let (parent_id, name) = match group_path.rsplit_once('/') {
+                    Some((parent_path, name)) => (unit_ids_by_group_path[parent_path], name),
+                    None => (kRootUnitId, group_path.as_str()),
+                };
+
+                ParamUnit {
+                    id,
+                    name: name.to_string(),
+                    parent_id,
+                }
+            })
+            .collect();
+        if cfg!(debug_assertions) {
+            let mut seen_ids: HashMap<i32, &str> = HashMap::with_capacity(units.len());
+            for (group_path, &id) in &unit_ids_by_group_path {
+                if let Some(other_group_path) = seen_ids.insert(id, group_path.as_str()) {
+                    nih_debug_assert_failure!(
+                        "The parameter groups \"{}\" and \"{}\" have the same unit ID, one of them should be renamed",
+                        other_group_path,
+                        group_path
+                    );
+                }
+            }
+        }
+
+        Self {
+            units,
+            unit_id_by_param_hash,
+        }
+    }
+
+    /// The number of units, excluding the root unit.
+    pub fn len(&self) -> usize {
+        self.units.len()
+    }
+
+    /// Get the unit at the given index, excluding the root unit.
+    pub fn get(&self, index: usize) -> Option<&ParamUnit> {
+        self.units.get(index)
+    }
+
+    /// The ID of the unit a parameter belongs to. This is `kRootUnitId` for parameters that are
+    /// not part of a group.
+    pub fn param_unit_id(&self, param_hash: u32) -> i32 {
+        self.unit_id_by_param_hash
+            .get(&param_hash)
+            .copied()
+            .unwrap_or(kRootUnitId)
+    }
+}
+
+/// Compute a unit ID from a normalized group path. Unit IDs are positive, since negative IDs are
+/// reserved and zero is the root unit.
+fn unit_id(group_path: &str) -> i32 {
+    match (hash_param_id(group_path) & 0x7FFF_FFFF) as i32 {
+        0 => 1,
+        id => id,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_groups() {
+        let units = ParamUnits::from_param_groups([
+            (1, "Band 2"),
+            (2, "Band 1/Filter"),
+            (3, ""),
+            (4, " Band 1 / Filter "),
+            (5, "Band 1"),
+        ]);
+
+        // The parent groups get their own units even if they don't contain any parameters
+        assert_eq!(units.len(), 3);
+        let band_1 = units.get(0).unwrap();
+        let band_1_filter = units.get(1).unwrap();
+        let band_2 = units.get(2).unwrap();
+        assert_eq!(band_1.name, "Band 1");
+        assert_eq!(band_1.parent_id, kRootUnitId);
+        assert_eq!(band_1_filter.name, "Filter");
+        assert_eq!(band_1_filter.parent_id, band_1.id);
+        assert_eq!(band_2.name, "Band 2");
+        assert_eq!(band_2.parent_id, kRootUnitId);
+        assert!(units.get(3).is_none());
+
+        assert_eq!(units.param_unit_id(1), band_2.id);
+        assert_eq!(units.param_unit_id(2), band_1_filter.id);
+        assert_eq!(units.param_unit_id(3), kRootUnitId);
+        assert_eq!(units.param_unit_id(4), band_1_filter.id);
+        assert_eq!(units.param_unit_id(5), band_1.id);
+        assert_eq!(units.param_unit_id(6), kRootUnitId);
+    }
+
+    #[test]
+    fn stable_unit_ids() {
+        let units = ParamUnits::from_param_groups([(1, "Band 1"), (2, "Band 2")]);
+        let more_units = ParamUnits::from_param_groups([(1, "Band 0"), (2, "Band 2")]);
+
+        // Adding or removing other groups doesn't change a group's ID
+        assert_eq!(units.param_unit_id(2), more_units.param_unit_id(2));
+        assert_ne!(units.param_unit_id(1), units.param_unit_id(2));
+        assert!(units.param_unit_id(1) > 0);
+    }
+}
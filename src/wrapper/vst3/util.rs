@@ -14,16 +14,474 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use lazy_static::lazy_static;
+use crossbeam::atomic::AtomicCell;
+use std::ffi::c_void;
+use std::ptr;
+use vst3_sys::vst::{AudioBusBuffers, EventTypes, ProcessData, SymbolicSampleSizes};
 
-use crate::wrapper::util::hash_param_id;
+use crate::context::{Transport, TransportRequirements};
+use crate::plugin::{NoteEvent, Plugin, SamplePrecision, Vst3SubCategory};
+use crate::wrapper::inner::WrapperInner;
 
-/// Right now the wrapper adds its own bypass parameter.
+// These are the `ProcessContext::StatesAndFlags` values the transport information depends on
+const K_PLAYING: u32 = 1 << 1;
+const K_CYCLE_ACTIVE: u32 = 1 << 2;
+const K_RECORDING: u32 = 1 << 3;
+const K_PROJECT_TIME_MUSIC_VALID: u32 = 1 << 9;
+const K_TEMPO_VALID: u32 = 1 << 10;
+const K_BAR_POSITION_VALID: u32 = 1 << 11;
+const K_CYCLE_VALID: u32 = 1 << 12;
+const K_TIME_SIG_VALID: u32 = 1 << 13;
+
+// These are the `IProcessContextRequirements::Flags` values for the transport information we use
+const K_NEED_PROJECT_TIME_MUSIC: u32 = 1 << 2;
+const K_NEED_BAR_POSITION_MUSIC: u32 = 1 << 3;
+const K_NEED_CYCLE_MUSIC: u32 = 1 << 4;
+const K_NEED_TEMPO: u32 = 1 << 6;
+const K_NEED_TIME_SIGNATURE: u32 = 1 << 7;
+const K_NEED_TRANSPORT_STATE: u32 = 1 << 10;
+
+// These are the `ComponentFlags` values used for a class's `class_flags`
+pub const K_DISTRIBUTABLE: u32 = 1 << 0;
+pub const K_SIMPLE_MODE_SUPPORTED: u32 = 1 << 1;
+
+/// The `kTuningTypeID` note expression type, used for [NoteEvent::PolyPitchBend].
+pub const VST3_NOTE_EXPRESSION_TUNING_TYPE_ID: u32 = 2;
+/// The `kBrightnessTypeID` note expression type, used for [NoteEvent::PolyBrightness].
+pub const VST3_NOTE_EXPRESSION_BRIGHTNESS_TYPE_ID: u32 = 5;
+
+/// A note expression type plugins that accept MIDI support, so hosts know which note expressions
+/// they can send through `INoteExpressionController`.
+pub struct Vst3NoteExpression {
+    pub type_id: u32,
+    pub title: &'static str,
+    pub unit: &'static str,
+    /// The normalized value at which the note expression doesn't change the note.
+    pub default_value: f64,
+    /// The `NoteExpressionTypeFlags` for this type.
+    pub flags: i32,
+}
+
+/// The note expressions that are converted to [NoteEvent]s, see
+/// [WrapperInner::vst3_event_to_note_event()]. Tuning is bipolar (`kIsBipolar`), and both are
+/// absolute values (`kIsAbsolute`).
+pub const VST3_NOTE_EXPRESSIONS: [Vst3NoteExpression; 2] = [
+    Vst3NoteExpression {
+        type_id: VST3_NOTE_EXPRESSION_TUNING_TYPE_ID,
+        title: "Tuning",
+        unit: "st",
+        default_value: 0.5,
+        flags: (1 << 0) | (1 << 2),
+    },
+    Vst3NoteExpression {
+        type_id: VST3_NOTE_EXPRESSION_BRIGHTNESS_TYPE_ID,
+        title: "Brightness",
+        unit: "%",
+        default_value: 0.5,
+        flags: 1 << 2,
+    },
+];
+
+/// Format the normalized value of the note expression with type `type_id` for display. Returns
+/// `None` for unsupported note expression types.
+pub fn note_expression_value_to_string(type_id: u32, value: f64) -> Option<String> {
+    let value = value.clamp(0.0, 1.0);
+    match type_id {
+        VST3_NOTE_EXPRESSION_TUNING_TYPE_ID => Some(format!("{:.2}", 240.0 * (value - 0.5))),
+        VST3_NOTE_EXPRESSION_BRIGHTNESS_TYPE_ID => Some(format!("{:.0}", value * 100.0)),
+        _ => None,
+    }
+}
+
+/// The inverse of [note_expression_value_to_string()].
+pub fn note_expression_string_to_value(type_id: u32, string: &str) -> Option<f64> {
+    let value: f64 = string.trim().parse().ok()?;
+    match type_id {
+        VST3_NOTE_EXPRESSION_TUNING_TYPE_ID => Some((value / 240.0 + 0.5).clamp(0.0, 1.0)),
+        VST3_NOTE_EXPRESSION_BRIGHTNESS_TYPE_ID => Some((value / 100.0).clamp(0.0, 1.0)),
+        _ => None,
+    }
+}
+
+/// The number of MIDI controllers per channel the host can map to parameters through
+/// `IMidiMapping`. These are the 128 regular MIDI CCs, followed by `kAfterTouch` and `kPitchBend`.
+pub const VST3_MIDI_CCS: u32 = 130;
+/// The number of MIDI channels the host can map to parameters through `IMidiMapping`.
+pub const VST3_MIDI_CHANNELS: u32 = 16;
+/// The first of the parameter IDs reserved for the MIDI controllers from `IMidiMapping`. Changes to
+/// these parameters are sent to the plugin as note events, and they are not listed as regular
+/// parameters. Instead, they are exposed to the host as hidden automatable parameters. The parameter
+/// for controller `cc` on `channel` has the ID `VST3_MIDI_PARAMS_START + (channel * VST3_MIDI_CCS)
+/// + cc`.
+pub const VST3_MIDI_PARAMS_START: u32 = VST3_MIDI_PARAMS_END - (VST3_MIDI_CCS * VST3_MIDI_CHANNELS);
+/// The end of the reserved range of MIDI controller parameter IDs, exclusive. [crate::wrapper::util::hash_param_id()]
+/// never sets the sign bit, so the reserved range sits at the very top of the range parameter
+/// hashes can occupy.
+pub const VST3_MIDI_PARAMS_END: u32 = 1 << 31;
+
+/// The default normalized value for the MIDI controller parameter at index `midi_param_idx`,
+/// counted from [VST3_MIDI_PARAMS_START]. Pitch bend starts at its center position, everything else
+/// starts at zero.
+pub fn midi_cc_default_normalized_value(midi_param_idx: u32) -> f32 {
+    if midi_param_idx % VST3_MIDI_CCS == 129 {
+        0.5
+    } else {
+        0.0
+    }
+}
+
+/// The name of the MIDI controller parameter at index `midi_param_idx`, counted from
+/// [VST3_MIDI_PARAMS_START].
+pub fn midi_cc_param_name(midi_param_idx: u32) -> String {
+    let channel = (midi_param_idx / VST3_MIDI_CCS) + 1;
+    match midi_param_idx % VST3_MIDI_CCS {
+        128 => format!("MIDI Channel {} Aftertouch", channel),
+        129 => format!("MIDI Channel {} Pitch Bend", channel),
+        cc => format!("MIDI Channel {} CC {}", channel, cc),
+    }
+}
+
+/// The maximum length of the subcategories string in `PClassInfo2` and `PClassInfoW`, excluding
+/// the null terminator.
+pub const MAX_SUBCATEGORIES_LEN: usize = 127;
+
+/// Join a plugin's subcategories into the pipe separated string VST3 hosts expect. Categories that
+/// would make the string longer than [MAX_SUBCATEGORIES_LEN] are left out, since truncating the
+/// string would leave the host with a partial category.
+pub fn subcategories_string(subcategories: &[Vst3SubCategory]) -> String {
+    nih_debug_assert!(
+        !subcategories.is_empty(),
+        "A VST3 plugin should have at least one subcategory"
+    );
+
+    let mut result = String::new();
+    for subcategory in subcategories {
+        let subcategory = subcategory.as_str();
+        nih_debug_assert!(
+            !subcategory.is_empty() && !subcategory.contains('|'),
+            "Invalid VST3 subcategory '{}'",
+            subcategory
+        );
+
+        let separator_len = if result.is_empty() { 0 } else { 1 };
+        if result.len() + separator_len + subcategory.len() > MAX_SUBCATEGORIES_LEN {
+            nih_debug_assert_failure!(
+                "The VST3 subcategories are longer than {} characters, leaving out '{}' and \
+                 everything after it",
+                MAX_SUBCATEGORIES_LEN,
+                subcategory
+            );
+            break;
+        }
+
+        if !result.is_empty() {
+            result.push('|');
+        }
+        result.push_str(subcategory);
+    }
+
+    result
+}
+
+/// Convert a VST3 `SymbolicSampleSizes` value to a [SamplePrecision]. Returns `None` for unknown
+/// sample sizes.
+pub fn sample_precision_from_vst3(symbolic_sample_size: i32) -> Option<SamplePrecision> {
+    match symbolic_sample_size {
+        x if x == vst3_sys::vst::SymbolicSampleSizes::kSample32 as i32 => {
+            Some(SamplePrecision::Single)
+        }
+        x if x == vst3_sys::vst::SymbolicSampleSizes::kSample64 as i32 => {
+            Some(SamplePrecision::Double)
+        }
+        _ => None,
+    }
+}
+
+/// Fill all of the host's output channels in `data` with silence. This is used when the plugin
+/// can't be processed because it panicked.
+///
+/// # Safety
 ///
-/// TODO: Actually use this parameter.
-pub const BYPASS_PARAM_ID: &str = "bypass";
-lazy_static! {
-    pub static ref BYPASS_PARAM_HASH: u32 = hash_param_id(BYPASS_PARAM_ID);
+/// The output busses in `data` need to be valid for `data.num_samples` samples.
+pub unsafe fn clear_outputs(data: &ProcessData) {
+    if data.outputs.is_null() || data.num_samples <= 0 {
+        return;
+    }
+
+    let num_samples = data.num_samples as usize;
+    let is_double_precision = data.symbolic_sample_size == SymbolicSampleSizes::kSample64 as i32;
+    let busses = std::slice::from_raw_parts_mut(data.outputs, data.num_outputs.max(0) as usize);
+    for bus in busses {
+        if bus.buffers.is_null() {
+            continue;
+        }
+
+        for channel_idx in 0..bus.num_channels.max(0) as usize {
+            let channel_ptr = *bus.buffers.add(channel_idx);
+            if channel_ptr.is_null() {
+                continue;
+            }
+
+            if is_double_precision {
+                ptr::write_bytes(channel_ptr as *mut f64, 0, num_samples);
+            } else {
+                ptr::write_bytes(channel_ptr as *mut f32, 0, num_samples);
+            }
+        }
+        bus.silence_flags = u64::MAX;
+    }
+}
+
+/// Single precision copies of the host's audio buffers, used when the host processes audio at double
+/// precision. nih-plug's buffers only contain `f32` samples, so the wrapper converts the host's
+/// `f64` buffers to these buffers before the plugin processes audio, and it converts the outputs
+/// back afterwards. These buffers are allocated in the setup call.
+#[derive(Default)]
+pub struct SinglePrecisionBuffers {
+    inputs: SinglePrecisionBusses,
+    outputs: SinglePrecisionBusses,
+    /// The number of samples allocated for every channel in [Self::resize()].
+    max_buffer_size: usize,
+}
+
+/// The channels for either the input or the output busses in [SinglePrecisionBuffers].
+#[derive(Default)]
+struct SinglePrecisionBusses {
+    /// The samples for every channel of every bus.
+    channels: Vec<Vec<Vec<f32>>>,
+    /// Pointers to the channels in `channels`. The host's `AudioBusBuffers` point to an array of
+    /// channel pointers, so these need to be stored somewhere.
+    channel_ptrs: Vec<Vec<*mut c_void>>,
+    /// The bus descriptions pointing to `channel_ptrs`, passed to the process function in place of
+    /// the host's busses.
+    busses: Vec<AudioBusBuffers>,
+}
+
+/// SAFETY: The pointers only point to the data owned by this object, and they're only dereferenced
+/// in the process function while the wrapper holds a lock on this object.
+unsafe impl Send for SinglePrecisionBuffers {}
+unsafe impl Sync for SinglePrecisionBuffers {}
+
+impl SinglePrecisionBuffers {
+    /// Allocate room for `max_buffer_size` samples for every channel. `input_bus_channels` and
+    /// `output_bus_channels` contain the number of channels for each bus, in the same order the
+    /// host passes them to the process function.
+    pub fn resize(
+        &mut self,
+        input_bus_channels: &[u32],
+        output_bus_channels: &[u32],
+        max_buffer_size: usize,
+    ) {
+        self.inputs.resize(input_bus_channels, max_buffer_size);
+        self.outputs.resize(output_bus_channels, max_buffer_size);
+        self.max_buffer_size = max_buffer_size;
+    }
+
+    /// Copy the host's double precision buffers to the single precision buffers, and return a copy
+    /// of `data` that points to the single precision buffers instead. Any channels that don't fit
+    /// in the buffers allocated in [Self::resize()] are dropped, and the returned process data
+    /// never contains more samples than those buffers can hold.
+    ///
+    /// # Safety
+    ///
+    /// `data` needs to contain double precision buffers with at least `data.num_samples` samples.
+    /// The returned process data may only be used while these buffers are not resized.
+    pub unsafe fn read_double_precision(&mut self, data: &ProcessData) -> ProcessData {
+        let num_samples = (data.num_samples.max(0) as usize).min(self.max_buffer_size);
+
+        // SAFETY: `ProcessData` only contains plain values and pointers it doesn't own
+        let mut single_precision_data = ptr::read(data);
+        single_precision_data.symbolic_sample_size = SymbolicSampleSizes::kSample32 as i32;
+        single_precision_data.num_samples = num_samples as i32;
+        let (num_inputs, inputs) =
+            self.inputs
+                .read_double_precision(data.inputs, data.num_inputs, num_samples);
+        single_precision_data.num_inputs = num_inputs;
+        single_precision_data.inputs = inputs;
+        let (num_outputs, outputs) =
+            self.outputs
+                .read_double_precision(data.outputs, data.num_outputs, num_samples);
+        single_precision_data.num_outputs = num_outputs;
+        single_precision_data.outputs = outputs;
+
+        single_precision_data
+    }
+
+    /// Copy the single precision output buffers back to the host's double precision output
+    /// buffers after the plugin has processed audio.
+    ///
+    /// # Safety
+    ///
+    /// `data` needs to be the same process data that was passed to [Self::read_double_precision()].
+    pub unsafe fn write_double_precision(&self, data: &ProcessData) {
+        let num_samples = (data.num_samples.max(0) as usize).min(self.max_buffer_size);
+        if data.outputs.is_null() {
+            return;
+        }
+
+        for (bus_idx, bus) in self
+            .outputs
+            .busses
+            .iter()
+            .enumerate()
+            .take(data.num_outputs.max(0) as usize)
+        {
+            let host_bus = &*data.outputs.add(bus_idx);
+            if host_bus.buffers.is_null() {
+                continue;
+            }
+
+            for (channel_idx, channel) in self.outputs.channels[bus_idx]
+                .iter()
+                .enumerate()
+                .take(bus.num_channels as usize)
+            {
+                let host_channel = std::slice::from_raw_parts_mut(
+                    *(host_bus.buffers as *const *mut f64).add(channel_idx),
+                    num_samples,
+                );
+                for (host_sample, sample) in host_channel.iter_mut().zip(channel) {
+                    *host_sample = *sample as f64;
+                }
+            }
+        }
+    }
+}
+
+impl SinglePrecisionBusses {
+    fn resize(&mut self, bus_channels: &[u32], max_buffer_size: usize) {
+        self.channels = bus_channels
+            .iter()
+            .map(|&num_channels| vec![vec![0.0; max_buffer_size]; num_channels as usize])
+            .collect();
+        self.channel_ptrs = self
+            .channels
+            .iter_mut()
+            .map(|channels| {
+                channels
+                    .iter_mut()
+                    .map(|channel| channel.as_mut_ptr() as *mut c_void)
+                    .collect()
+            })
+            .collect();
+        self.busses = self
+            .channel_ptrs
+            .iter_mut()
+            .map(|channel_ptrs| AudioBusBuffers {
+                num_channels: channel_ptrs.len() as i32,
+                silence_flags: 0,
+                buffers: channel_ptrs.as_mut_ptr(),
+            })
+            .collect();
+    }
+
+    /// Copy the samples from the host's busses to these busses, and return the number of busses
+    /// and a pointer to the array of busses to pass to the plugin in place of the host's busses.
+    unsafe fn read_double_precision(
+        &mut self,
+        host_busses: *const AudioBusBuffers,
+        num_host_busses: i32,
+        num_samples: usize,
+    ) -> (i32, *mut AudioBusBuffers) {
+        if host_busses.is_null() {
+            return (0, ptr::null_mut());
+        }
+
+        let num_busses = (num_host_busses.max(0) as usize).min(self.busses.len());
+        for bus_idx in 0..num_busses {
+            let host_bus = &*host_busses.add(bus_idx);
+            let bus = &mut self.busses[bus_idx];
+            if host_bus.buffers.is_null() {
+                bus.num_channels = 0;
+                bus.buffers = ptr::null_mut();
+                continue;
+            }
+
+            let channels = &mut self.channels[bus_idx];
+            let num_channels = (host_bus.num_channels.max(0) as usize).min(channels.len());
+            bus.num_channels = num_channels as i32;
+            bus.silence_flags = host_bus.silence_flags;
+            bus.buffers = self.channel_ptrs[bus_idx].as_mut_ptr();
+
+            for (channel_idx, channel) in channels.iter_mut().enumerate().take(num_channels) {
+                let host_channel = std::slice::from_raw_parts(
+                    *(host_bus.buffers as *const *const f64).add(channel_idx),
+                    num_samples,
+                );
+                for (sample, host_sample) in channel.iter_mut().zip(host_channel) {
+                    *sample = *host_sample as f32;
+                }
+            }
+        }
+
+        (num_busses as i32, self.busses.as_mut_ptr())
+    }
+}
+
+/// Convert a plugin's transport requirements to the flags returned from
+/// `IProcessContextRequirements::getProcessContextRequirements()`.
+pub fn process_context_requirements(requirements: &TransportRequirements) -> u32 {
+    let mut flags = 0;
+    if requirements.transport_state {
+        flags |= K_NEED_TRANSPORT_STATE;
+    }
+    if requirements.tempo {
+        flags |= K_NEED_TEMPO;
+    }
+    if requirements.time_signature {
+        flags |= K_NEED_TIME_SIGNATURE;
+    }
+    if requirements.position_beats {
+        flags |= K_NEED_PROJECT_TIME_MUSIC;
+    }
+    if requirements.bar_position {
+        flags |= K_NEED_BAR_POSITION_MUSIC;
+    }
+    if requirements.loop_range {
+        flags |= K_NEED_CYCLE_MUSIC;
+    }
+
+    flags
+}
+
+/// Convert the host's VST3 process context to a [Transport]. The context is optional, so `context`
+/// may be a null pointer, in which case the transport won't contain any information from the host.
+///
+/// # Safety
+///
+/// `context` needs to be either a null pointer or a valid pointer to a process context.
+pub unsafe fn transport_from_vst3(
+    context: *const vst3_sys::vst::ProcessContext,
+    sample_rate: f32,
+) -> Transport {
+    let mut transport = Transport::new(sample_rate);
+    let context = match context.as_ref() {
+        Some(context) => context,
+        None => return transport,
+    };
+
+    transport.playing = context.state & K_PLAYING != 0;
+    transport.recording = context.state & K_RECORDING != 0;
+    // The position in samples is the only value that's always valid
+    transport.pos_samples = Some(context.project_time_samples);
+    if context.state & K_TEMPO_VALID != 0 {
+        transport.tempo = Some(context.tempo);
+    }
+    if context.state & K_TIME_SIG_VALID != 0 {
+        transport.time_sig_numerator = Some(context.time_sig_num);
+        transport.time_sig_denominator = Some(context.time_sig_den);
+    }
+    if context.state & K_PROJECT_TIME_MUSIC_VALID != 0 {
+        transport.pos_beats = Some(context.project_time_music);
+    }
+    if context.state & K_BAR_POSITION_VALID != 0 {
+        transport.bar_start_pos_beats = Some(context.bar_position_music);
+    }
+    if context.state & K_CYCLE_ACTIVE != 0 && context.state & K_CYCLE_VALID != 0 {
+        transport.loop_range_beats = Some((context.cycle_start_music, context.cycle_end_music));
+    }
+
+    transport.sanitize()
 }
 
 /// Early exit out of a VST3 function when one of the passed pointers is null
@@ -67,3 +525,131 @@ impl<T: vst3_sys::ComInterface + ?Sized> From<vst3_sys::VstPtr<T>> for VstPtr<T>
 /// counting, so as long as a `VstPtr<T>` handle exists the object will stay alive.
 unsafe impl<T: vst3_sys::ComInterface + ?Sized> Send for VstPtr<T> {}
 unsafe impl<T: vst3_sys::ComInterface + ?Sized> Sync for VstPtr<T> {}
+
+/// The VST3 specific event conversions. These live here instead of in [WrapperInner]'s own module
+/// since the other wrappers don't need them.
+impl<P: Plugin> WrapperInner<P> {
+    /// Get the value of the MIDI controller parameter with the hash `param_hash` from
+    /// `midi_cc_values`. Returns `None` if this is not one of those parameters, or if the plugin
+    /// doesn't accept MIDI.
+    pub fn midi_cc_param_value(&self, param_hash: u32) -> Option<&AtomicCell<f32>> {
+        param_hash
+            .checked_sub(VST3_MIDI_PARAMS_START)
+            .and_then(|midi_param_idx| self.midi_cc_values.get(midi_param_idx as usize))
+    }
+
+    /// Convert a change to one of the MIDI controller parameters the host maps MIDI CCs to through
+    /// `IMidiMapping` to a [NoteEvent]. Returns `None` if `param_hash` is not in the range starting
+    /// at [VST3_MIDI_PARAMS_START]. This also updates the parameter's value in `midi_cc_values`.
+    pub fn midi_cc_param_to_note_event(
+        &self,
+        timing: u32,
+        param_hash: u32,
+        normalized_value: f32,
+    ) -> Option<NoteEvent> {
+        if !(VST3_MIDI_PARAMS_START..VST3_MIDI_PARAMS_END).contains(&param_hash) {
+            return None;
+        }
+
+        let midi_param_idx = param_hash - VST3_MIDI_PARAMS_START;
+        let channel = (midi_param_idx / VST3_MIDI_CCS) as u8;
+        let normalized_value = normalized_value.clamp(0.0, 1.0);
+        if let Some(value) = self.midi_cc_param_value(param_hash) {
+            value.store(normalized_value);
+        }
+
+        match midi_param_idx % VST3_MIDI_CCS {
+            // `kAfterTouch`
+            128 => Some(NoteEvent::MidiChannelPressure {
+                timing,
+                channel,
+                pressure: normalized_value,
+            }),
+            // `kPitchBend`
+            129 => Some(NoteEvent::MidiPitchBend {
+                timing,
+                channel,
+                value: normalized_value,
+            }),
+            // Bank selects combine the last received values for CC 0 and CC 32
+            cc @ (0 | 32) => Some(NoteEvent::MidiBankSelect {
+                timing,
+                channel,
+                bank: self.update_midi_bank(
+                    channel,
+                    cc as u8,
+                    (normalized_value * 127.0).round() as u8,
+                ),
+            }),
+            cc => Some(NoteEvent::MidiCC {
+                timing,
+                channel,
+                cc: cc as u8,
+                value: normalized_value,
+            }),
+        }
+    }
+
+    /// Convert an event from a VST3 host's input event list to a [NoteEvent]. Returns `None` for
+    /// events that don't have a [NoteEvent] equivalent.
+    ///
+    /// # Safety
+    ///
+    /// `event.type_` must match the variant of the `event.event` union that has been initialized.
+    pub unsafe fn vst3_event_to_note_event(
+        &self,
+        timing: u32,
+        event: &vst3_sys::vst::Event,
+    ) -> Option<NoteEvent> {
+        // VST3 uses -1 for notes that don't have an identifier
+        let note_id = |note_id: i32| if note_id >= 0 { Some(note_id) } else { None };
+
+        if event.type_ == EventTypes::kNoteOnEvent as u16 {
+            let event = event.event.note_on;
+            Some(NoteEvent::NoteOn {
+                timing,
+                note_id: note_id(event.note_id),
+                channel: event.channel as u8,
+                note: event.pitch as u8,
+                velocity: (event.velocity * 127.0).round() as u8,
+            })
+        } else if event.type_ == EventTypes::kNoteOffEvent as u16 {
+            let event = event.event.note_off;
+            Some(NoteEvent::NoteOff {
+                timing,
+                note_id: note_id(event.note_id),
+                channel: event.channel as u8,
+                note: event.pitch as u8,
+                velocity: (event.velocity * 127.0).round() as u8,
+            })
+        } else if event.type_ == EventTypes::kPolyPressureEvent as u16 {
+            let event = event.event.poly_pressure;
+            Some(NoteEvent::PolyPressure {
+                timing,
+                note_id: note_id(event.note_id),
+                channel: event.channel as u8,
+                note: event.pitch as u8,
+                pressure: event.pressure.clamp(0.0, 1.0),
+            })
+        } else if event.type_ == EventTypes::kNoteExpressionValueEvent as u16 {
+            let event = event.event.note_expression_value;
+            match event.type_id {
+                // Tuning is normalized so that 0.5 means no change, and the full range covers 120
+                // semitones in either direction
+                VST3_NOTE_EXPRESSION_TUNING_TYPE_ID => Some(NoteEvent::PolyPitchBend {
+                    timing,
+                    note_id: event.note_id,
+                    semitones: (240.0 * (event.value - 0.5)) as f32,
+                }),
+                VST3_NOTE_EXPRESSION_BRIGHTNESS_TYPE_ID => Some(NoteEvent::PolyBrightness {
+                    timing,
+                    note_id: event.note_id,
+                    brightness: event.value as f32,
+                }),
+                _ => None,
+            }
+        } else {
+            None
+        }
+    }
+}
@@ -14,18 +14,23 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+use atomic_float::AtomicF32;
 use parking_lot::RwLock;
 use raw_window_handle::RawWindowHandle;
 use std::any::Any;
 use std::ffi::{c_void, CStr};
 use std::mem;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use vst3_sys::base::{kInvalidArgument, kResultFalse, kResultOk, tresult, TBool};
-use vst3_sys::gui::IPlugView;
+use vst3_sys::gui::{IPlugFrame, IPlugView, IPlugViewContentScaleSupport, ViewRect};
+use vst3_sys::utils::SharedVstPtr;
 use vst3_sys::VST3;
 
-use super::inner::WrapperInner;
+use super::util::VstPtr;
 use crate::plugin::{Editor, Plugin};
+use crate::wrapper::inner::WrapperInner;
+use crate::wrapper::util::catch_plugin_panic;
 use crate::ParentWindowHandle;
 
 // Alias needed for the VST3 attribute macro
@@ -45,19 +50,104 @@ const VST3_PLATFORM_X11_WINDOW: &str = "X11EmbedWindowID";
 
 /// The plugin's [IPlugView] instance created in [IEditController::create_view] if `P` has an
 /// editor. This is managed separately so the lifetime bounds match up.
-#[VST3(implements(IPlugView))]
+#[VST3(implements(IPlugView, IPlugViewContentScaleSupport))]
 pub(crate) struct WrapperView<P: Plugin> {
     inner: Arc<WrapperInner<P>>,
     editor: Arc<dyn Editor>,
-    editor_handle: RwLock<Option<Box<dyn Any>>>,
+    editor_handle: RwLock<Option<OpenEditor<P>>>,
+    /// The host's [IPlugFrame] passed through [IPlugView::set_frame()]. This is used to ask the
+    /// host to resize the view when the editor's size changes.
+    plug_frame: RwLock<Option<VstPtr<dyn IPlugFrame>>>,
+    /// The DPI scaling factor set by the host through
+    /// [IPlugViewContentScaleSupport::set_content_scale_factor()]. This stays at 1.0 if the host
+    /// never sets it or if the editor doesn't support scaling. The editor's size is multiplied by
+    /// this factor when reporting it to the host.
+    scaling_factor: AtomicF32,
 }
 
 impl<P: Plugin> WrapperView<P> {
     pub fn new(inner: Arc<WrapperInner<P>>, editor: Arc<dyn Editor>) -> Box<Self> {
-        Self::allocate(inner, editor, RwLock::new(None))
+        Self::allocate(
+            inner,
+            editor,
+            RwLock::new(None),
+            RwLock::new(None),
+            AtomicF32::new(1.0),
+        )
+    }
+
+    /// The editor's size as reported to the host, with the DPI scaling factor applied.
+    fn scaled_size(&self) -> ViewRect {
+        let (width, height) = self.editor.size();
+        let scaling_factor = self.scaling_factor.load(Ordering::Relaxed);
+
+        // SAFETY: This is a plain C struct
+        let mut size: ViewRect = unsafe { mem::zeroed() };
+        size.right = (width as f32 * scaling_factor).round() as i32;
+        size.bottom = (height as f32 * scaling_factor).round() as i32;
+
+        size
+    }
+
+    /// Ask the host to resize the view to the editor's current size. Returns `false` if the host
+    /// rejected the new size or if it did not pass an [IPlugFrame] to the view.
+    pub unsafe fn request_resize(&self) -> bool {
+        let plug_frame = self.plug_frame.read();
+        let plug_frame = match &*plug_frame {
+            Some(plug_frame) => plug_frame,
+            None => return false,
+        };
+
+        // `IPlugView` is the first interface this object implements, so the pointer to this object
+        // is also a pointer to its `IPlugView` vtable
+        let plug_view: SharedVstPtr<dyn IPlugView> = mem::transmute(self as *const Self);
+        let mut size = self.scaled_size();
+        plug_frame.resize_view(plug_view, &mut size) == kResultOk
+    }
+}
+
+/// The handle of an editor that's currently open in a [WrapperView].
+struct OpenEditor<P: Plugin> {
+    inner: Arc<WrapperInner<P>>,
+    _handle: Box<dyn Any>,
+}
+
+impl<P: Plugin> Drop for OpenEditor<P> {
+    fn drop(&mut self) {
+        // This happens both when the editor gets closed and when the host releases the view
+        // without closing the editor first, so the wrapper can never end up with a dangling pointer
+        // to the view
+        *self.inner.plug_view.write() = None;
+    }
+}
+
+/// A non-owning pointer to the [WrapperView] of an open editor, stored in
+/// [WrapperInner::plug_view] so the editor can ask the host to resize it. The view creates this
+/// when the editor gets opened, and it's removed again when the editor's [OpenEditor] handle gets
+/// dropped. Both of those happen while holding the write lock, so the pointer stays valid for as
+/// long as a read lock on [WrapperInner::plug_view] is held.
+pub(crate) struct ViewPtr<P: Plugin>(*const WrapperView<P>);
+
+impl<P: Plugin> ViewPtr<P> {
+    fn new(view: &WrapperView<P>) -> Self {
+        Self(view)
+    }
+}
+
+impl<P: Plugin> std::ops::Deref for ViewPtr<P> {
+    type Target = WrapperView<P>;
+
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: See the struct's docstring
+        unsafe { &*self.0 }
     }
 }
 
+/// SAFETY: The view is only ever accessed from the GUI thread, and the pointer is only dereferenced
+/// while the view is alive.
+unsafe impl<P: Plugin> Send for ViewPtr<P> {}
+unsafe impl<P: Plugin> Sync for ViewPtr<P> {}
+
 impl<P: Plugin> IPlugView for WrapperView<P> {
     #[cfg(all(target_family = "unix", not(target_os = "macos")))]
     unsafe fn is_platform_type_supported(&self, type_: vst3_sys::base::FIDString) -> tresult {
@@ -124,10 +214,22 @@ impl<P: Plugin> IPlugView for WrapperView<P> {
                 }
             };
 
-            *editor_handle = Some(
+            // A panicking editor shouldn't take down the host, and the plugin can still process
+            // audio without its editor
+            match catch_plugin_panic("opening its editor", || {
                 self.editor
-                    .spawn(ParentWindowHandle { handle }, self.inner.clone()),
-            );
+                    .spawn(ParentWindowHandle { handle }, self.inner.clone())
+            }) {
+                Some(spawned_editor) => {
+                    *editor_handle = Some(OpenEditor {
+                        inner: self.inner.clone(),
+                        _handle: spawned_editor,
+                    })
+                }
+                None => return kResultFalse,
+            }
+            *self.inner.plug_view.write() = Some(ViewPtr::new(self));
+
             kResultOk
         } else {
             kResultFalse
@@ -137,7 +239,9 @@ impl<P: Plugin> IPlugView for WrapperView<P> {
     unsafe fn removed(&self) -> tresult {
         let mut editor_handle = self.editor_handle.write();
         if editor_handle.is_some() {
+            // This also removes the view's pointer from the wrapper
             *editor_handle = None;
+
             kResultOk
         } else {
             kResultFalse
@@ -171,43 +275,74 @@ impl<P: Plugin> IPlugView for WrapperView<P> {
     unsafe fn get_size(&self, size: *mut vst3_sys::gui::ViewRect) -> tresult {
         check_null_ptr!(size);
 
-        *size = mem::zeroed();
-
-        let (width, height) = self.editor.size();
-        let size = &mut *size;
-        size.left = 0;
-        size.right = width as i32;
-        size.top = 0;
-        size.bottom = height as i32;
+        *size = self.scaled_size();
 
         kResultOk
     }
 
-    unsafe fn on_size(&self, _new_size: *mut vst3_sys::gui::ViewRect) -> tresult {
-        // TODO: Implement resizing
-        kResultOk
+    unsafe fn on_size(&self, new_size: *mut vst3_sys::gui::ViewRect) -> tresult {
+        check_null_ptr!(new_size);
+
+        // Only the editor itself can change its size. The host calls this after resizing the
+        // window, either when the editor gets opened or after the editor requested a new size, and
+        // in both cases the size should match the editor's current size.
+        let new_size = &*new_size;
+        let size = self.scaled_size();
+        if new_size.right - new_size.left == size.right
+            && new_size.bottom - new_size.top == size.bottom
+        {
+            kResultOk
+        } else {
+            kResultFalse
+        }
     }
 
     unsafe fn on_focus(&self, _state: TBool) -> tresult {
         kResultOk
     }
 
-    unsafe fn set_frame(&self, _frame: *mut c_void) -> tresult {
-        // TODO: Implement resizing. We don't implement that right now, so we also don't need the
-        //       plug frame.
+    unsafe fn set_frame(&self, frame: *mut c_void) -> tresult {
+        // The correct argument type is missing from the bindings
+        let frame: SharedVstPtr<dyn IPlugFrame> = mem::transmute(frame);
+        *self.plug_frame.write() = frame.upgrade().map(VstPtr::from);
+
         kResultOk
     }
 
     unsafe fn can_resize(&self) -> tresult {
-        // TODO: Implement resizing
+        // The user can't resize the editor, but the editor can still request a new size through
+        // `GuiContext::request_resize()`
         kResultFalse
     }
 
     unsafe fn check_size_constraint(&self, rect: *mut vst3_sys::gui::ViewRect) -> tresult {
         check_null_ptr!(rect);
 
-        // TODO: Add this with the resizing
-        if (*rect).right - (*rect).left > 0 && (*rect).bottom - (*rect).top > 0 {
+        // The only valid size is the editor's current size
+        let size = self.scaled_size();
+        let rect = &mut *rect;
+        rect.right = rect.left + size.right;
+        rect.bottom = rect.top + size.bottom;
+
+        kResultOk
+    }
+}
+
+impl<P: Plugin> IPlugViewContentScaleSupport for WrapperView<P> {
+    unsafe fn set_content_scale_factor(&self, factor: f32) -> tresult {
+        // macOS scales the window by itself, and hosts are not supposed to call this function there
+        if cfg!(target_os = "macos") {
+            nih_debug_assert_failure!("Ignoring host request to set explicit DPI scaling factor");
+            return kResultFalse;
+        }
+
+        if factor > 0.0
+            && catch_plugin_panic("setting its editor's scale factor", || {
+                self.editor.set_scale_factor(factor)
+            })
+            .unwrap_or(false)
+        {
+            self.scaling_factor.store(factor, Ordering::Relaxed);
             kResultOk
         } else {
             kResultFalse
@@ -0,0 +1,81 @@
+// nih-plug: plugins, but rewritten in Rust
+// Copyright (C) 2022 Robbert van der Helm
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Tests for [EnumParam] using the `#[derive(Enum)]` macro, which can only be used from outside of
+//! the `nih_plug` crate.
+
+use nih_plug::param::internals::ParamPtr;
+use nih_plug::{Enum, EnumParam, Param};
+
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+enum Waveform {
+    Sine,
+    #[name = "Saw tooth"]
+    Saw,
+    Square,
+}
+
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Stereo,
+}
+
+#[test]
+fn derived_variants() {
+    assert_eq!(Waveform::variants(), ["Sine", "Saw tooth", "Square"]);
+    assert_eq!(Waveform::Square.to_index(), 2);
+    assert_eq!(Waveform::from_index(1), Waveform::Saw);
+    // Out of range indices map to the last variant
+    assert_eq!(Waveform::from_index(10), Waveform::Square);
+}
+
+#[test]
+fn out_of_range_indices_are_clamped() {
+    let param = EnumParam::new("Waveform", Waveform::Saw);
+    assert_eq!(param.to_string(), "Saw tooth");
+    assert_eq!(param.string_to_normalized_value("square"), Some(1.0));
+
+    // The wrappers set the type-erased index directly when restoring state, and that state may
+    // come from a version of the plugin with more variants
+    let inner = match param.as_ptr() {
+        ParamPtr::EnumParam(p) => p,
+        _ => unreachable!(),
+    };
+    unsafe {
+        (*inner).set_plain_value(7);
+        assert_eq!((*inner).plain_value(), 2);
+        assert_eq!((*inner).normalized_value(), 1.0);
+    }
+    assert_eq!(param.value(), Waveform::Square);
+    assert_eq!(param.to_string(), "Square");
+
+    unsafe { (*inner).set_plain_value(-1) };
+    assert_eq!(param.value(), Waveform::Sine);
+    assert_eq!(param.to_string(), "Sine");
+}
+
+#[test]
+fn single_variant() {
+    let mut param = EnumParam::new("Mode", Mode::Stereo);
+    assert_eq!(param.normalized_value(), 0.0);
+    assert_eq!(param.default_normalized_value(), 0.0);
+    assert_eq!(param.string_to_normalized_value("stereo"), Some(0.0));
+    assert_eq!(param.normalized_value_to_string(0.5, true), "Stereo");
+
+    param.set_normalized_value(1.0);
+    assert_eq!(param.value(), Mode::Stereo);
+    assert_eq!(unsafe { param.as_ptr().step_count() }, 0);
+}
@@ -1,9 +1,14 @@
 use anyhow::{bail, Context, Result};
 use std::fs;
 use std::path::Path;
+use std::path::PathBuf;
 use std::process::Command;
 
-const USAGE_STRING: &str = "Usage: cargo xtask bundle <target> [--release] [--bundle-vst3]";
+mod validate;
+
+const USAGE_STRING: &str = "Usage:
+  cargo xtask bundle <target> [--release] [--bundle-vst3] [--bundle-clap]
+  cargo xtask validate <target> [--release]";
 
 fn main() -> Result<()> {
     let project_root = Path::new(env!("CARGO_MANIFEST_DIR"))
@@ -25,43 +30,37 @@ fn main() -> Result<()> {
 
             bundle(&target, other_args)
         }
+        "validate" => {
+            let target = args
+                .next()
+                .context(format!("Missing target name\n\n{USAGE_STRING}"))?;
+            let other_args: Vec<_> = args.collect();
+
+            let lib_path = build(&target, other_args)?;
+            validate::validate(&lib_path)?;
+
+            eprintln!("\nValidated '{target}'");
+            Ok(())
+        }
         _ => bail!("Unknown command '{command}'\n\n{USAGE_STRING}"),
     }
 }
 
 // TODO: This probably needs more work for macOS. I don't know, I don't have a Mac.
 fn bundle(target: &str, mut args: Vec<String>) -> Result<()> {
-    let mut is_release_build = false;
     let mut bundle_vst3 = false;
+    let mut bundle_clap = false;
     for arg_idx in (0..args.len()).rev() {
-        let arg = &args[arg_idx];
-        match arg.as_str() {
-            "--bundle-vst3" => {
-                bundle_vst3 = true;
-                args.remove(arg_idx);
-            }
-            "--release" => is_release_build = true,
-            _ => (),
+        if args[arg_idx] == "--bundle-vst3" {
+            bundle_vst3 = true;
+            args.remove(arg_idx);
+        } else if args[arg_idx] == "--bundle-clap" {
+            bundle_clap = true;
+            args.remove(arg_idx);
         }
     }
 
-    let status = Command::new("cargo")
-        .arg("build")
-        .arg("-p")
-        .arg(target)
-        .args(args)
-        .status()
-        .context(format!("Could not call cargo to build {target}"))?;
-    if !status.success() {
-        bail!("Could not build {}", target);
-    }
-
-    let lib_path = Path::new("target")
-        .join(if is_release_build { "release" } else { "debug" })
-        .join(library_name(target));
-    if !lib_path.exists() {
-        bail!("Could not find built library at {}", lib_path.display());
-    }
+    let lib_path = build(target, args)?;
 
     eprintln!();
     if bundle_vst3 {
@@ -79,13 +78,48 @@ fn bundle(target: &str, mut args: Vec<String>) -> Result<()> {
         fs::copy(&lib_path, &vst3_lib_path).context("Could not copy library to bundle")?;
 
         eprintln!("Created a VST3 bundle at '{}'", vst3_bundle_home.display());
-    } else {
+    }
+    if bundle_clap {
+        // CLAP plugins are just the plugin library with a `.clap` extension
+        let clap_lib_path = Path::new("target").join(clap_bundle_library_name(target));
+
+        fs::copy(&lib_path, &clap_lib_path).context("Could not copy library to bundle")?;
+
+        eprintln!("Created a CLAP bundle at '{}'", clap_lib_path.display());
+    }
+    if !bundle_vst3 && !bundle_clap {
         eprintln!("Not creating any plugin bundles")
     }
 
     Ok(())
 }
 
+/// Build the plugin library for `target`, passing any other arguments through to `cargo build`.
+/// Returns the path to the built library.
+fn build(target: &str, args: Vec<String>) -> Result<PathBuf> {
+    let is_release_build = args.iter().any(|arg| arg == "--release");
+
+    let status = Command::new("cargo")
+        .arg("build")
+        .arg("-p")
+        .arg(target)
+        .args(args)
+        .status()
+        .context(format!("Could not call cargo to build {target}"))?;
+    if !status.success() {
+        bail!("Could not build {}", target);
+    }
+
+    let lib_path = Path::new("target")
+        .join(if is_release_build { "release" } else { "debug" })
+        .join(library_name(target));
+    if !lib_path.exists() {
+        bail!("Could not find built library at {}", lib_path.display());
+    }
+
+    Ok(lib_path)
+}
+
 #[cfg(target_os = "linux")]
 fn library_name(target: &str) -> String {
     format!("lib{target}.so")
@@ -101,6 +135,11 @@ fn library_name(target: &str) -> String {
     format!("{target}.dll")
 }
 
+// TODO: This should also be a Mach-O bundle on macOS
+fn clap_bundle_library_name(target: &str) -> String {
+    format!("{target}.clap")
+}
+
 // See https://developer.steinberg.help/display/VST/Plug-in+Format+Structure
 
 #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
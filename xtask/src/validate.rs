@@ -0,0 +1,708 @@
+//! A minimal VST3 host for validating a plugin's FFI surface. This loads the compiled library,
+//! enumerates the plugin factory's classes, and exercises every plugin through its raw COM
+//! interfaces the same way a host would. The interfaces are declared by hand instead of using
+//! `vst3-sys` so any mismatch between nih-plug's use of `vst3-sys` and the actual ABI shows up here.
+
+use anyhow::{bail, Context, Result};
+use std::ffi::{c_void, CStr};
+use std::os::raw::c_char;
+use std::path::Path;
+use std::ptr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+
+type TResult = i32;
+type Tuid = [u8; 16];
+
+const K_RESULT_OK: TResult = 0;
+
+const K_REALTIME: i32 = 0;
+const K_SAMPLE_32: i32 = 0;
+const K_AUDIO: i32 = 0;
+const K_INPUT: i32 = 0;
+const K_OUTPUT: i32 = 1;
+
+const K_IB_SEEK_SET: i32 = 0;
+const K_IB_SEEK_CUR: i32 = 1;
+const K_IB_SEEK_END: i32 = 2;
+
+const SAMPLE_RATE: f64 = 44100.0;
+const BLOCK_SIZE: usize = 512;
+const NUM_BLOCKS: usize = 16;
+
+/// The equivalent of the VST3 SDK's `INLINE_UID` macro. Windows uses the COM compatible layout.
+const fn inline_uid(l1: u32, l2: u32, l3: u32, l4: u32) -> Tuid {
+    inline_uid_with_layout(cfg!(target_os = "windows"), l1, l2, l3, l4)
+}
+
+/// [inline_uid()], but with an explicit layout. The COM compatible layout stores the first 32-bit
+/// value as little endian, and the two 16-bit halves of the second value as little endian.
+const fn inline_uid_with_layout(com_compatible: bool, l1: u32, l2: u32, l3: u32, l4: u32) -> Tuid {
+    let l1 = if com_compatible {
+        l1.to_le_bytes()
+    } else {
+        l1.to_be_bytes()
+    };
+    let l2 = if com_compatible {
+        // This matches the SDK's `(l2 >> 16, l2 >> 24, l2, l2 >> 8)`
+        let bytes = l2.to_be_bytes();
+        [bytes[1], bytes[0], bytes[3], bytes[2]]
+    } else {
+        l2.to_be_bytes()
+    };
+    let l3 = l3.to_be_bytes();
+    let l4 = l4.to_be_bytes();
+
+    [
+        l1[0], l1[1], l1[2], l1[3], l2[0], l2[1], l2[2], l2[3], l3[0], l3[1], l3[2], l3[3], l4[0],
+        l4[1], l4[2], l4[3],
+    ]
+}
+
+const FUNKNOWN_IID: Tuid = inline_uid(0x00000000, 0x00000000, 0xC0000000, 0x00000046);
+const IBSTREAM_IID: Tuid = inline_uid(0xC3BF6EA2, 0x30994752, 0x9B6BF990, 0x1EE33E9B);
+const ICOMPONENT_IID: Tuid = inline_uid(0xE831FF31, 0xF2D54301, 0x928EBBEE, 0x25697802);
+const IAUDIO_PROCESSOR_IID: Tuid = inline_uid(0x42043F99, 0xB7DA453C, 0xA569E79D, 0x9AAEC33D);
+
+#[repr(C)]
+struct FUnknownVtbl {
+    query_interface: unsafe extern "system" fn(
+        this: *mut c_void,
+        iid: *const Tuid,
+        obj: *mut *mut c_void,
+    ) -> TResult,
+    add_ref: unsafe extern "system" fn(this: *mut c_void) -> u32,
+    release: unsafe extern "system" fn(this: *mut c_void) -> u32,
+}
+
+#[repr(C)]
+struct IPluginFactoryVtbl {
+    unknown: FUnknownVtbl,
+    get_factory_info:
+        unsafe extern "system" fn(this: *mut c_void, info: *mut PFactoryInfo) -> TResult,
+    count_classes: unsafe extern "system" fn(this: *mut c_void) -> i32,
+    get_class_info:
+        unsafe extern "system" fn(this: *mut c_void, index: i32, info: *mut PClassInfo) -> TResult,
+    create_instance: unsafe extern "system" fn(
+        this: *mut c_void,
+        cid: *const Tuid,
+        iid: *const Tuid,
+        obj: *mut *mut c_void,
+    ) -> TResult,
+}
+
+#[repr(C)]
+struct IComponentVtbl {
+    unknown: FUnknownVtbl,
+    // `IPluginBase`
+    initialize: unsafe extern "system" fn(this: *mut c_void, context: *mut c_void) -> TResult,
+    terminate: unsafe extern "system" fn(this: *mut c_void) -> TResult,
+    // `IComponent`
+    get_controller_class_id:
+        unsafe extern "system" fn(this: *mut c_void, cid: *mut Tuid) -> TResult,
+    set_io_mode: unsafe extern "system" fn(this: *mut c_void, mode: i32) -> TResult,
+    get_bus_count: unsafe extern "system" fn(this: *mut c_void, type_: i32, dir: i32) -> i32,
+    get_bus_info: unsafe extern "system" fn(
+        this: *mut c_void,
+        type_: i32,
+        dir: i32,
+        index: i32,
+        info: *mut BusInfo,
+    ) -> TResult,
+    get_routing_info: unsafe extern "system" fn(
+        this: *mut c_void,
+        in_info: *mut c_void,
+        out_info: *mut c_void,
+    ) -> TResult,
+    activate_bus: unsafe extern "system" fn(
+        this: *mut c_void,
+        type_: i32,
+        dir: i32,
+        index: i32,
+        state: u8,
+    ) -> TResult,
+    set_active: unsafe extern "system" fn(this: *mut c_void, state: u8) -> TResult,
+    set_state: unsafe extern "system" fn(this: *mut c_void, state: *mut c_void) -> TResult,
+    get_state: unsafe extern "system" fn(this: *mut c_void, state: *mut c_void) -> TResult,
+}
+
+#[repr(C)]
+struct IAudioProcessorVtbl {
+    unknown: FUnknownVtbl,
+    set_bus_arrangements: unsafe extern "system" fn(
+        this: *mut c_void,
+        inputs: *mut u64,
+        num_ins: i32,
+        outputs: *mut u64,
+        num_outs: i32,
+    ) -> TResult,
+    get_bus_arrangement: unsafe extern "system" fn(
+        this: *mut c_void,
+        dir: i32,
+        index: i32,
+        arr: *mut u64,
+    ) -> TResult,
+    can_process_sample_size:
+        unsafe extern "system" fn(this: *mut c_void, symbolic_sample_size: i32) -> TResult,
+    get_latency_samples: unsafe extern "system" fn(this: *mut c_void) -> u32,
+    setup_processing:
+        unsafe extern "system" fn(this: *mut c_void, setup: *mut ProcessSetup) -> TResult,
+    set_processing: unsafe extern "system" fn(this: *mut c_void, state: u8) -> TResult,
+    process: unsafe extern "system" fn(this: *mut c_void, data: *mut ProcessData) -> TResult,
+    get_tail_samples: unsafe extern "system" fn(this: *mut c_void) -> u32,
+}
+
+#[repr(C)]
+struct IBStreamVtbl {
+    unknown: FUnknownVtbl,
+    read: unsafe extern "system" fn(
+        this: *mut c_void,
+        buffer: *mut c_void,
+        num_bytes: i32,
+        num_bytes_read: *mut i32,
+    ) -> TResult,
+    write: unsafe extern "system" fn(
+        this: *mut c_void,
+        buffer: *mut c_void,
+        num_bytes: i32,
+        num_bytes_written: *mut i32,
+    ) -> TResult,
+    seek: unsafe extern "system" fn(
+        this: *mut c_void,
+        pos: i64,
+        mode: i32,
+        result: *mut i64,
+    ) -> TResult,
+    tell: unsafe extern "system" fn(this: *mut c_void, pos: *mut i64) -> TResult,
+}
+
+#[repr(C)]
+struct PFactoryInfo {
+    vendor: [c_char; 64],
+    url: [c_char; 256],
+    email: [c_char; 128],
+    flags: i32,
+}
+
+#[repr(C)]
+struct PClassInfo {
+    cid: Tuid,
+    cardinality: i32,
+    category: [c_char; 32],
+    name: [c_char; 64],
+}
+
+#[repr(C)]
+struct BusInfo {
+    media_type: i32,
+    direction: i32,
+    channel_count: i32,
+    name: [u16; 128],
+    bus_type: i32,
+    flags: u32,
+}
+
+#[repr(C)]
+struct ProcessSetup {
+    process_mode: i32,
+    symbolic_sample_size: i32,
+    max_samples_per_block: i32,
+    sample_rate: f64,
+}
+
+#[repr(C)]
+struct AudioBusBuffers {
+    num_channels: i32,
+    silence_flags: u64,
+    buffers: *mut *mut f32,
+}
+
+#[repr(C)]
+struct ProcessData {
+    process_mode: i32,
+    symbolic_sample_size: i32,
+    num_samples: i32,
+    num_inputs: i32,
+    num_outputs: i32,
+    inputs: *mut AudioBusBuffers,
+    outputs: *mut AudioBusBuffers,
+    input_param_changes: *mut c_void,
+    output_param_changes: *mut c_void,
+    input_events: *mut c_void,
+    output_events: *mut c_void,
+    context: *mut c_void,
+}
+
+/// An owned reference to a COM object. The reference is released when this is dropped.
+struct ComPtr<V> {
+    ptr: *mut *const V,
+}
+
+impl<V> ComPtr<V> {
+    /// Take ownership of a reference returned by the plugin. Returns `None` if the pointer is null.
+    unsafe fn from_raw(ptr: *mut c_void) -> Option<Self> {
+        if ptr.is_null() {
+            None
+        } else {
+            Some(Self {
+                ptr: ptr as *mut *const V,
+            })
+        }
+    }
+
+    fn as_raw(&self) -> *mut c_void {
+        self.ptr as *mut c_void
+    }
+
+    unsafe fn vtbl(&self) -> &V {
+        &**self.ptr
+    }
+
+    unsafe fn unknown(&self) -> &FUnknownVtbl {
+        // Every vtable starts with `FUnknown`'s functions
+        &*(*self.ptr as *const FUnknownVtbl)
+    }
+
+    /// Query the object for another interface.
+    unsafe fn cast<W>(&self, iid: &Tuid) -> Option<ComPtr<W>> {
+        let mut obj = ptr::null_mut();
+        if (self.unknown().query_interface)(self.as_raw(), iid, &mut obj) == K_RESULT_OK {
+            ComPtr::from_raw(obj)
+        } else {
+            None
+        }
+    }
+}
+
+impl<V> Drop for ComPtr<V> {
+    fn drop(&mut self) {
+        unsafe { (self.unknown().release)(self.as_raw()) };
+    }
+}
+
+/// An in-memory `IBStream` implementation the plugin's state can be written to and read from.
+#[repr(C)]
+struct MemoryStream {
+    vtbl: *const IBStreamVtbl,
+    ref_count: AtomicU32,
+    data: Mutex<(Vec<u8>, usize)>,
+}
+
+static MEMORY_STREAM_VTBL: IBStreamVtbl = IBStreamVtbl {
+    unknown: FUnknownVtbl {
+        query_interface: MemoryStream::query_interface,
+        add_ref: MemoryStream::add_ref,
+        release: MemoryStream::release,
+    },
+    read: MemoryStream::read,
+    write: MemoryStream::write,
+    seek: MemoryStream::seek,
+    tell: MemoryStream::tell,
+};
+
+impl MemoryStream {
+    fn new() -> Box<Self> {
+        Box::new(Self {
+            vtbl: &MEMORY_STREAM_VTBL,
+            // This object is owned by the `Box`, so the plugin's references are only borrowed
+            ref_count: AtomicU32::new(1),
+            data: Mutex::new((Vec::new(), 0)),
+        })
+    }
+
+    fn as_raw(&self) -> *mut c_void {
+        self as *const Self as *mut c_void
+    }
+
+    fn len(&self) -> usize {
+        self.data.lock().unwrap().0.len()
+    }
+
+    fn rewind(&self) {
+        self.data.lock().unwrap().1 = 0;
+    }
+
+    unsafe fn from_this<'a>(this: *mut c_void) -> &'a Self {
+        &*(this as *const Self)
+    }
+
+    unsafe extern "system" fn query_interface(
+        this: *mut c_void,
+        iid: *const Tuid,
+        obj: *mut *mut c_void,
+    ) -> TResult {
+        if *iid == FUNKNOWN_IID || *iid == IBSTREAM_IID {
+            Self::add_ref(this);
+            *obj = this;
+            K_RESULT_OK
+        } else {
+            *obj = ptr::null_mut();
+            // kNoInterface
+            -1
+        }
+    }
+
+    unsafe extern "system" fn add_ref(this: *mut c_void) -> u32 {
+        Self::from_this(this)
+            .ref_count
+            .fetch_add(1, Ordering::SeqCst)
+            + 1
+    }
+
+    unsafe extern "system" fn release(this: *mut c_void) -> u32 {
+        Self::from_this(this)
+            .ref_count
+            .fetch_sub(1, Ordering::SeqCst)
+            - 1
+    }
+
+    unsafe extern "system" fn read(
+        this: *mut c_void,
+        buffer: *mut c_void,
+        num_bytes: i32,
+        num_bytes_read: *mut i32,
+    ) -> TResult {
+        let mut guard = Self::from_this(this).data.lock().unwrap();
+        let (data, position) = &mut *guard;
+        let num_bytes = (num_bytes.max(0) as usize).min(data.len() - *position);
+        ptr::copy_nonoverlapping(data[*position..].as_ptr(), buffer as *mut u8, num_bytes);
+        *position += num_bytes;
+
+        if !num_bytes_read.is_null() {
+            *num_bytes_read = num_bytes as i32;
+        }
+
+        K_RESULT_OK
+    }
+
+    unsafe extern "system" fn write(
+        this: *mut c_void,
+        buffer: *mut c_void,
+        num_bytes: i32,
+        num_bytes_written: *mut i32,
+    ) -> TResult {
+        let mut guard = Self::from_this(this).data.lock().unwrap();
+        let (data, position) = &mut *guard;
+        let buffer = std::slice::from_raw_parts(buffer as *const u8, num_bytes.max(0) as usize);
+        let end_position = *position + buffer.len();
+        if data.len() < end_position {
+            data.resize(end_position, 0);
+        }
+        data[*position..end_position].copy_from_slice(buffer);
+        *position = end_position;
+
+        if !num_bytes_written.is_null() {
+            *num_bytes_written = buffer.len() as i32;
+        }
+
+        K_RESULT_OK
+    }
+
+    unsafe extern "system" fn seek(
+        this: *mut c_void,
+        pos: i64,
+        mode: i32,
+        result: *mut i64,
+    ) -> TResult {
+        let mut guard = Self::from_this(this).data.lock().unwrap();
+        let (data, position) = &mut *guard;
+        let new_position = match mode {
+            K_IB_SEEK_SET => pos,
+            K_IB_SEEK_CUR => *position as i64 + pos,
+            K_IB_SEEK_END => data.len() as i64 + pos,
+            // kInvalidArgument
+            _ => return 2,
+        };
+        if new_position < 0 || new_position > data.len() as i64 {
+            // kResultFalse
+            return 1;
+        }
+
+        *position = new_position as usize;
+        if !result.is_null() {
+            *result = new_position;
+        }
+
+        K_RESULT_OK
+    }
+
+    unsafe extern "system" fn tell(this: *mut c_void, pos: *mut i64) -> TResult {
+        *pos = Self::from_this(this).data.lock().unwrap().1 as i64;
+
+        K_RESULT_OK
+    }
+}
+
+/// Convert a null terminated C-string buffer to a Rust string.
+fn c_str(buffer: &[c_char]) -> String {
+    // SAFETY: The buffers are zeroed before they're passed to the plugin, and the last byte is
+    //         never overwritten by the plugin
+    unsafe { CStr::from_ptr(buffer.as_ptr()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Check a `tresult` returned by the plugin.
+fn check(result: TResult, function: &str) -> Result<()> {
+    if result != K_RESULT_OK {
+        bail!("{function}() returned {result}");
+    }
+
+    Ok(())
+}
+
+/// Load the plugin library at `lib_path` and validate every plugin in its factory.
+pub fn validate(lib_path: &Path) -> Result<()> {
+    // SAFETY: Loading a library runs its initializers. We just built it ourselves, so that's fine.
+    let library = unsafe { libloading::Library::new(lib_path) }
+        .with_context(|| format!("Could not load '{}'", lib_path.display()))?;
+
+    unsafe {
+        #[cfg(target_os = "linux")]
+        {
+            let module_entry: libloading::Symbol<unsafe extern "C" fn(*mut c_void) -> bool> =
+                library
+                    .get(b"ModuleEntry")
+                    .context("The library does not export 'ModuleEntry'")?;
+            if !module_entry(ptr::null_mut()) {
+                bail!("ModuleEntry() returned false");
+            }
+        }
+
+        let result = validate_factory(&library);
+
+        #[cfg(target_os = "linux")]
+        {
+            let module_exit: libloading::Symbol<unsafe extern "C" fn() -> bool> = library
+                .get(b"ModuleExit")
+                .context("The library does not export 'ModuleExit'")?;
+            if !module_exit() {
+                bail!("ModuleExit() returned false");
+            }
+        }
+
+        result
+    }
+}
+
+unsafe fn validate_factory(library: &libloading::Library) -> Result<()> {
+    let get_plugin_factory: libloading::Symbol<unsafe extern "system" fn() -> *mut c_void> =
+        library
+            .get(b"GetPluginFactory")
+            .context("The library does not export 'GetPluginFactory'")?;
+    let factory: ComPtr<IPluginFactoryVtbl> = ComPtr::from_raw(get_plugin_factory())
+        .context("GetPluginFactory() returned a null pointer")?;
+
+    let mut factory_info: PFactoryInfo = std::mem::zeroed();
+    check(
+        (factory.vtbl().get_factory_info)(factory.as_raw(), &mut factory_info),
+        "IPluginFactory::getFactoryInfo",
+    )?;
+    eprintln!(
+        "Factory: {} ({}, {})",
+        c_str(&factory_info.vendor),
+        c_str(&factory_info.url),
+        c_str(&factory_info.email)
+    );
+
+    let num_classes = (factory.vtbl().count_classes)(factory.as_raw());
+    if num_classes <= 0 {
+        bail!("The factory does not contain any classes");
+    }
+
+    for class_idx in 0..num_classes {
+        let mut class_info: PClassInfo = std::mem::zeroed();
+        check(
+            (factory.vtbl().get_class_info)(factory.as_raw(), class_idx, &mut class_info),
+            "IPluginFactory::getClassInfo",
+        )?;
+        let name = c_str(&class_info.name);
+        eprintln!(
+            "\nClass {class_idx}: {name} ({})",
+            c_str(&class_info.category)
+        );
+
+        validate_plugin(&factory, &class_info.cid)
+            .with_context(|| format!("Validation failed for '{name}'"))?;
+        eprintln!("  OK");
+    }
+
+    Ok(())
+}
+
+unsafe fn validate_plugin(factory: &ComPtr<IPluginFactoryVtbl>, cid: &Tuid) -> Result<()> {
+    let mut obj = ptr::null_mut();
+    check(
+        (factory.vtbl().create_instance)(factory.as_raw(), cid, &ICOMPONENT_IID, &mut obj),
+        "IPluginFactory::createInstance",
+    )?;
+    let component: ComPtr<IComponentVtbl> =
+        ComPtr::from_raw(obj).context("createInstance() returned a null pointer")?;
+    let processor: ComPtr<IAudioProcessorVtbl> = component
+        .cast(&IAUDIO_PROCESSOR_IID)
+        .context("The plugin does not implement IAudioProcessor")?;
+
+    check(
+        (component.vtbl().initialize)(component.as_raw(), ptr::null_mut()),
+        "IPluginBase::initialize",
+    )?;
+
+    // The host would normally use the bus info to decide how many channels to pass to the plugin
+    let mut num_channels = [0usize; 2];
+    for (dir, num_channels) in [K_INPUT, K_OUTPUT].into_iter().zip(num_channels.iter_mut()) {
+        if (component.vtbl().get_bus_count)(component.as_raw(), K_AUDIO, dir) > 0 {
+            let mut bus_info: BusInfo = std::mem::zeroed();
+            check(
+                (component.vtbl().get_bus_info)(component.as_raw(), K_AUDIO, dir, 0, &mut bus_info),
+                "IComponent::getBusInfo",
+            )?;
+            *num_channels = bus_info.channel_count.max(0) as usize;
+        }
+    }
+    let [num_input_channels, num_output_channels] = num_channels;
+    eprintln!("  {num_input_channels} input channels, {num_output_channels} output channels");
+
+    check(
+        (processor.vtbl().can_process_sample_size)(processor.as_raw(), K_SAMPLE_32),
+        "IAudioProcessor::canProcessSampleSize",
+    )?;
+    let mut setup = ProcessSetup {
+        process_mode: K_REALTIME,
+        symbolic_sample_size: K_SAMPLE_32,
+        max_samples_per_block: BLOCK_SIZE as i32,
+        sample_rate: SAMPLE_RATE,
+    };
+    check(
+        (processor.vtbl().setup_processing)(processor.as_raw(), &mut setup),
+        "IAudioProcessor::setupProcessing",
+    )?;
+    check(
+        (component.vtbl().set_active)(component.as_raw(), 1),
+        "IComponent::setActive",
+    )?;
+    check(
+        (processor.vtbl().set_processing)(processor.as_raw(), 1),
+        "IAudioProcessor::setProcessing",
+    )?;
+
+    // Process a couple of blocks of noise-like input and make sure the output stays finite
+    let mut input_channels = vec![vec![0.0f32; BLOCK_SIZE]; num_input_channels];
+    let mut output_channels = vec![vec![0.0f32; BLOCK_SIZE]; num_output_channels];
+    let mut seed = 1u32;
+    for _ in 0..NUM_BLOCKS {
+        for sample in input_channels.iter_mut().flatten() {
+            seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+            *sample = (seed >> 8) as f32 / (1u32 << 24) as f32 * 2.0 - 1.0;
+        }
+
+        let mut input_ptrs: Vec<*mut f32> =
+            input_channels.iter_mut().map(|c| c.as_mut_ptr()).collect();
+        let mut output_ptrs: Vec<*mut f32> =
+            output_channels.iter_mut().map(|c| c.as_mut_ptr()).collect();
+        let mut inputs = AudioBusBuffers {
+            num_channels: num_input_channels as i32,
+            silence_flags: 0,
+            buffers: input_ptrs.as_mut_ptr(),
+        };
+        let mut outputs = AudioBusBuffers {
+            num_channels: num_output_channels as i32,
+            silence_flags: 0,
+            buffers: output_ptrs.as_mut_ptr(),
+        };
+        let mut data = ProcessData {
+            process_mode: K_REALTIME,
+            symbolic_sample_size: K_SAMPLE_32,
+            num_samples: BLOCK_SIZE as i32,
+            num_inputs: (num_input_channels > 0) as i32,
+            num_outputs: (num_output_channels > 0) as i32,
+            inputs: if num_input_channels > 0 {
+                &mut inputs
+            } else {
+                ptr::null_mut()
+            },
+            outputs: if num_output_channels > 0 {
+                &mut outputs
+            } else {
+                ptr::null_mut()
+            },
+            input_param_changes: ptr::null_mut(),
+            output_param_changes: ptr::null_mut(),
+            input_events: ptr::null_mut(),
+            output_events: ptr::null_mut(),
+            context: ptr::null_mut(),
+        };
+        check(
+            (processor.vtbl().process)(processor.as_raw(), &mut data),
+            "IAudioProcessor::process",
+        )?;
+
+        if output_channels
+            .iter()
+            .flatten()
+            .any(|sample| !sample.is_finite())
+        {
+            bail!("The plugin produced non-finite output samples");
+        }
+    }
+
+    check(
+        (processor.vtbl().set_processing)(processor.as_raw(), 0),
+        "IAudioProcessor::setProcessing",
+    )?;
+    check(
+        (component.vtbl().set_active)(component.as_raw(), 0),
+        "IComponent::setActive",
+    )?;
+
+    // The plugin's state should survive a round trip
+    let stream = MemoryStream::new();
+    check(
+        (component.vtbl().get_state)(component.as_raw(), stream.as_raw()),
+        "IComponent::getState",
+    )?;
+    if stream.len() == 0 {
+        bail!("getState() did not write any data");
+    }
+    eprintln!("  {} bytes of state", stream.len());
+
+    stream.rewind();
+    check(
+        (component.vtbl().set_state)(component.as_raw(), stream.as_raw()),
+        "IComponent::setState",
+    )?;
+    if stream.ref_count.load(Ordering::SeqCst) != 1 {
+        bail!("The plugin did not release its references to the state stream");
+    }
+
+    check(
+        (component.vtbl().terminate)(component.as_raw()),
+        "IPluginBase::terminate",
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn icomponent_iid_layout() {
+        // `IComponent` is `E831FF31-F2D5-4301-928E-BBEE25697802`. These are the bytes the SDK's
+        // `INLINE_UID` produces for both layouts.
+        assert_eq!(
+            inline_uid_with_layout(true, 0xE831FF31, 0xF2D54301, 0x928EBBEE, 0x25697802),
+            [
+                0x31, 0xFF, 0x31, 0xE8, 0xD5, 0xF2, 0x01, 0x43, 0x92, 0x8E, 0xBB, 0xEE, 0x25, 0x69,
+                0x78, 0x02
+            ]
+        );
+        assert_eq!(
+            inline_uid_with_layout(false, 0xE831FF31, 0xF2D54301, 0x928EBBEE, 0x25697802),
+            [
+                0xE8, 0x31, 0xFF, 0x31, 0xF2, 0xD5, 0x43, 0x01, 0x92, 0x8E, 0xBB, 0xEE, 0x25, 0x69,
+                0x78, 0x02
+            ]
+        );
+    }
+}